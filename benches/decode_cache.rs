@@ -0,0 +1,57 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use narm::narmvm::NarmVM;
+
+// Tight counting loop, assembled by hand so that no ARM toolchain is needed:
+//
+//      movs    r0, #0x00
+//      movs    r1, #0xFF
+//      lsls    r1, r1, #0x08
+//  loop:
+//      adds    r0, #0x01
+//      cmp     r0, r1
+//      bne     loop
+//      svc     #0xFF
+const COUNT_LOOP: [u16; 7] = [0x2000, 0x21FF, 0x0209, 0x3001, 0x4288, 0xD1FC, 0xDFFF];
+// 3 instructions of setup, 3 per iteration, and the final svc
+const COUNT_LOOP_INSTRUCTIONS: u64 = 3 + 3 * 0xFF00 + 1;
+
+fn create_vm(cached: bool) -> NarmVM {
+    let mut vm = NarmVM::default();
+    vm.memory.add_memory(0x01_0000, 0x01_0000).unwrap();
+    let code: Vec<u8> = COUNT_LOOP.iter().flat_map(|op| op.to_le_bytes()).collect();
+    vm.copy_into_memory(0x01_0000, &code).unwrap();
+    vm.set_thumb_pc_address(0x01_0000);
+    vm.set_decode_cache(cached);
+    if cached {
+        // run once so that the timed runs start with a warm cache, as they would when a contract is called repeatedly
+        vm.gas_remaining = COUNT_LOOP_INSTRUCTIONS;
+        assert_eq!(vm.execute().unwrap(), 0xFF);
+        vm.set_thumb_pc_address(0x01_0000);
+    }
+    vm.gas_remaining = COUNT_LOOP_INSTRUCTIONS;
+    vm
+}
+
+fn bench_decode_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count_loop");
+    group.throughput(Throughput::Elements(COUNT_LOOP_INSTRUCTIONS));
+    for cached in [false, true].iter() {
+        let name = if *cached { "cached" } else { "uncached" };
+        let vm = create_vm(*cached);
+        // only execution is timed, not creating the VM
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter_batched(
+                || vm.clone(),
+                |mut vm| {
+                    assert_eq!(vm.execute().unwrap(), 0xFF);
+                    vm
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode_cache);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+@ Arithmetic loop: register-only data processing with no memory access
+
+    .syntax unified
+    .section .text
+    .thumb_func
+    .globl _start
+_start:
+    ldr     r0, =20000          @ iterations
+    movs    r1, #0x00
+    movs    r2, #0x07
+    movs    r3, #0x00
+loop:
+    adds    r1, r1, r2
+    eors    r3, r1
+    lsls    r4, r3, #0x03
+    lsrs    r5, r3, #0x05
+    subs    r3, r4, r5
+    muls    r3, r2, r3
+    subs    r0, #0x01
+    bne     loop
+    svc     #0xFF
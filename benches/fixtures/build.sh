@@ -0,0 +1,12 @@
+#!/bin/sh
+# Rebuilds the benchmark fixtures from their assembly sources
+# Each fixture is the raw .text section of a program linked at 0x10000, which begins execution at its first byte
+set -e
+cd "$(dirname "$0")"
+for source in *.s; do
+    name="${source%.s}"
+    arm-none-eabi-as -march=armv6s-m -o"$name.o" "$source"
+    arm-none-eabi-ld -T link.ld -o"$name.elf" "$name.o"
+    arm-none-eabi-objcopy -O binary -j .text "$name.elf" "$name.bin"
+    rm "$name.o" "$name.elf"
+done
@@ -0,0 +1,30 @@
+@ Hash function: FNV-1a over 1Kb of data, repeated 16 times
+
+    .syntax unified
+    .section .text
+    .thumb_func
+    .globl _start
+_start:
+    movs    r7, #16             @ repetitions
+    ldr     r6, =0x01000193     @ FNV prime
+outer:
+    ldr     r0, =0x811C9DC5     @ FNV offset basis
+    ldr     r1, =data
+    ldr     r2, =data_end
+byte:
+    ldrb    r3, [r1]
+    adds    r1, #0x01
+    eors    r0, r3
+    muls    r0, r6, r0
+    cmp     r1, r2
+    bne     byte
+    subs    r7, #0x01
+    bne     outer
+    svc     #0xFF
+
+    .ltorg
+data:
+    .rept 64
+    .ascii "The quick brown "
+    .endr
+data_end:
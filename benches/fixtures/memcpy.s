@@ -0,0 +1,28 @@
+@ memcpy-style copy of 1Kb from code memory into writeable memory with LDM/STM, repeated 64 times
+
+    .syntax unified
+    .section .text
+    .thumb_func
+    .globl _start
+_start:
+    movs    r7, #64             @ repetitions
+outer:
+    ldr     r0, =source
+    ldr     r1, =0x81000000
+    ldr     r2, =source_end
+copy:
+    ldm     r0!, {r3, r4, r5, r6}
+    stm     r1!, {r3, r4, r5, r6}
+    cmp     r0, r2
+    bne     copy
+    subs    r7, #0x01
+    bne     outer
+    svc     #0xFF
+
+    .ltorg
+    .balign 4
+source:
+    .rept 64
+    .word 0x03020100, 0x07060504, 0x0B0A0908, 0x0F0E0D0C
+    .endr
+source_end:
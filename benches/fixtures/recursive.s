@@ -0,0 +1,27 @@
+@ Call-heavy recursive function: naive fibonacci(18)
+
+    .syntax unified
+    .section .text
+    .thumb_func
+    .globl _start
+_start:
+    ldr     r0, =0x81008000
+    mov     sp, r0
+    movs    r0, #18
+    bl      fib
+    svc     #0xFF
+
+@ r0 = n, returns fib(n) in r0
+fib:
+    push    {r4, r5, lr}
+    cmp     r0, #0x02
+    blt     fib_done
+    movs    r4, r0
+    subs    r0, r4, #0x01
+    bl      fib
+    movs    r5, r0
+    subs    r0, r4, #0x02
+    bl      fib
+    adds    r0, r0, r5
+fib_done:
+    pop     {r4, r5, pc}
@@ -0,0 +1,69 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use narm::narmvm::NarmVM;
+
+// Prebuilt guest programs, so that no ARM toolchain is needed to run the benchmarks
+// The sources are next to them in benches/fixtures and can be rebuilt with benches/fixtures/build.sh
+const WORKLOADS: [(&str, &[u8]); 5] = [
+    ("arith", include_bytes!("fixtures/arith.bin")),
+    ("memcpy", include_bytes!("fixtures/memcpy.bin")),
+    ("recursive", include_bytes!("fixtures/recursive.bin")),
+    ("hash", include_bytes!("fixtures/hash.bin")),
+    ("helloworld", include_bytes!("fixtures/helloworld.bin")),
+];
+
+const CODE_START: u32 = 0x01_0000;
+const STACK_START: u32 = 0x8100_0000;
+const GAS_LIMIT: u64 = 100_000_000;
+// An example price for memory, so that gas usage includes memory gas as well as instructions
+const GAS_PER_TOUCHED_PAGE: u64 = 100;
+
+fn create_vm(code: &[u8]) -> NarmVM {
+    let mut vm = NarmVM::default();
+    vm.memory.add_memory(CODE_START, 0x01_0000).unwrap();
+    vm.copy_into_memory(CODE_START, code).unwrap();
+    vm.memory.add_memory(STACK_START, 0xFFFF).unwrap();
+    vm.memory.limits.gas_per_touched_page = GAS_PER_TOUCHED_PAGE;
+    vm.set_thumb_pc_address(CODE_START);
+    vm.gas_remaining = GAS_LIMIT;
+    vm
+}
+
+// Runs the workload once to count the instructions executed and the gas used
+fn measure(code: &[u8]) -> (u64, u64) {
+    let mut vm = create_vm(code);
+    let mut instructions = 0;
+    loop {
+        instructions += 1;
+        let result = vm.cycle().unwrap();
+        if result != 0 {
+            assert_eq!(result, 0xFF);
+            break;
+        }
+    }
+    (instructions, GAS_LIMIT - vm.gas_remaining)
+}
+
+// Each workload is reported both in instructions per second and in gas per second
+fn bench_workloads(c: &mut Criterion) {
+    for (name, code) in WORKLOADS.iter() {
+        let (instructions, gas) = measure(code);
+        let mut group = c.benchmark_group(*name);
+        for (unit, amount) in [("instructions", instructions), ("gas", gas)].iter() {
+            group.throughput(Throughput::Elements(*amount));
+            group.bench_function(*unit, |b| {
+                b.iter_batched(
+                    || create_vm(code),
+                    |mut vm| {
+                        assert_eq!(vm.execute().unwrap(), 0xFF);
+                        vm
+                    },
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_workloads);
+criterion_main!(benches);
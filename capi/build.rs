@@ -0,0 +1,13 @@
+// Generates include/narm.h from the extern "C" API in src/lib.rs
+fn main(){
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)).unwrap();
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate the narm C header")
+        .write_to_file(format!("{}/include/narm.h", crate_dir));
+}
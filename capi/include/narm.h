@@ -0,0 +1,166 @@
+#ifndef NARM_H
+#define NARM_H
+
+/* Generated by cbindgen from capi/src/lib.rs. Do not edit by hand */
+
+#include <stdint.h>
+#include <stddef.h>
+
+// Error codes, which mirror NarmError
+// Errors which carry a value, such as the address of a memory error, also set `NarmResult::value`
+typedef enum NarmErrorCode {
+  NARM_OK = 0,
+  NARM_ERROR_UNLOADED_MEMORY_READ,
+  NARM_ERROR_UNLOADED_MEMORY_WRITE,
+  NARM_ERROR_EMPTY_MEMORY_READ,
+  NARM_ERROR_EMPTY_MEMORY_WRITE,
+  NARM_ERROR_READ_ONLY_MEMORY_WRITE,
+  NARM_ERROR_UNALIGNED_MEMORY_ADDITION,
+  NARM_ERROR_CONFLICTING_MEMORY_ADDITION,
+  NARM_ERROR_INVALID_OPCODE,
+  NARM_ERROR_INVALID_OPCODE32,
+  NARM_ERROR_OUT_OF_GAS,
+  NARM_ERROR_INVALID_ARCHITECTURE_MODE,
+  NARM_ERROR_UNKNOWN_SERVICE_CALL,
+  NARM_ERROR_UNKNOWN_SYSTEM_CALL,
+  NARM_ERROR_EMPTY_COSTACK,
+  NARM_ERROR_MEMORY_LIMIT_EXCEEDED,
+  NARM_ERROR_OUT_OF_MEMORY_GAS,
+  NARM_ERROR_INVALID_IT_BLOCK_INSTRUCTION,
+  NARM_ERROR_INVALID_MEMORY_REGION,
+  NARM_ERROR_STACK_OVERFLOW,
+  NARM_ERROR_INVALID_CONTRACT_CALL,
+  NARM_ERROR_INVALID_GUEST_STRING,
+  NARM_ERROR_UNKNOWN_SEMIHOSTING_CALL,
+  // A pointer passed to the API was null
+  NARM_ERROR_NULL_POINTER,
+} NarmErrorCode;
+
+// Why narm_vm_run returned
+typedef enum NarmExitKind {
+  // An SVC was executed which had no handler, or whose handler asked to stop execution
+  NARM_EXIT_SVC = 0,
+  // Execution stopped with an error
+  NARM_EXIT_ERROR,
+  // A YIELD, WFE, WFI or SEV hint was executed while narm_vm_set_yield_on_hints is enabled
+  NARM_EXIT_HINT,
+} NarmExitKind;
+
+// A VM along with the host callbacks registered through the C API
+typedef struct NarmVM NarmVM;
+
+// The result of an API call, with the value carried by the error, if any
+typedef struct NarmResult {
+  enum NarmErrorCode code;
+  uint32_t value;
+} NarmResult;
+
+// Called for every SVC executed by narm_vm_run, with the user data given to narm_vm_set_svc_handler
+// The handler may read and modify the VM, such as to pass results back in r0
+// Returning 0 continues execution after the SVC, while any other value stops narm_vm_run with NARM_EXIT_SVC
+typedef int32_t (*NarmSvcHandler)(void *user_data, struct NarmVM *vm, uint32_t svc);
+
+// The exit reason returned by narm_vm_run
+typedef struct NarmExit {
+  enum NarmExitKind kind;
+  // The SVC number for NARM_EXIT_SVC, or the hint for NARM_EXIT_HINT, such as 0x30 for WFI
+  uint32_t svc;
+  // The error for NARM_EXIT_ERROR, otherwise NARM_OK
+  struct NarmResult error;
+} NarmExit;
+
+// Creates a new VM with no memory mapped and no gas, which must be freed with narm_vm_free
+struct NarmVM *narm_vm_new(void);
+
+// Frees a VM created by narm_vm_new. Passing null does nothing
+// # Safety
+// vm must be null or a VM which was not already freed
+void narm_vm_free(struct NarmVM *vm);
+
+// Maps a block of memory, see MemorySystem::add_memory
+// # Safety
+// vm must be a valid VM
+struct NarmResult narm_vm_add_memory(struct NarmVM *vm, uint32_t address, uint32_t size);
+
+// Copies len bytes from data into VM memory at address
+// # Safety
+// vm must be a valid VM and data must be valid for reading len bytes
+struct NarmResult narm_vm_copy_into_memory(struct NarmVM *vm,
+                                           uint32_t address,
+                                           const uint8_t *data,
+                                           uint32_t len);
+
+// Copies len bytes of VM memory at address into out
+// # Safety
+// vm must be a valid VM and out must be valid for writing len bytes
+struct NarmResult narm_vm_copy_from_memory(struct NarmVM *vm,
+                                           uint32_t address,
+                                           uint8_t *out,
+                                           uint32_t len);
+
+// Gets r0-r15. r15 is PC, including the thumb bit. Any other register reads as 0
+// # Safety
+// vm must be a valid VM
+uint32_t narm_vm_get_reg(const struct NarmVM *vm, uint32_t reg);
+
+// Sets r0-r15. r15 is PC, which must include the thumb bit. Any other register is ignored
+// # Safety
+// vm must be a valid VM
+void narm_vm_set_reg(struct NarmVM *vm, uint32_t reg, uint32_t value);
+
+// Sets PC to the given address of thumb code, adding the thumb bit
+// # Safety
+// vm must be a valid VM
+void narm_vm_set_pc(struct NarmVM *vm, uint32_t address);
+
+// Gets the condition flags, with N, Z, C and V in bits 31 to 28
+// # Safety
+// vm must be a valid VM
+uint32_t narm_vm_get_cpsr(const struct NarmVM *vm);
+
+// Sets the condition flags, with N, Z, C and V in bits 31 to 28
+// # Safety
+// vm must be a valid VM
+void narm_vm_set_cpsr(struct NarmVM *vm, uint32_t value);
+
+// # Safety
+// vm must be a valid VM
+uint64_t narm_vm_get_gas(const struct NarmVM *vm);
+
+// # Safety
+// vm must be a valid VM
+void narm_vm_set_gas(struct NarmVM *vm, uint64_t gas);
+
+// Sets the lowest address SP may be set to, or 0 to remove the limit
+// Going below it stops execution with NARM_ERROR_STACK_OVERFLOW
+// # Safety
+// vm must be a valid VM
+void narm_vm_set_stack_limit(struct NarmVM *vm, uint32_t limit);
+
+// Makes narm_vm_run return NARM_EXIT_HINT on YIELD, WFE, WFI and SEV when enabled is non-zero, instead of treating them as NOP
+// Running again resumes after the hint
+// # Safety
+// vm must be a valid VM
+void narm_vm_set_yield_on_hints(struct NarmVM *vm,
+                                int32_t enabled);
+
+// Returns the lowest value SP has had, which gives the peak stack usage
+// # Safety
+// vm must be a valid VM
+uint32_t narm_vm_get_lowest_sp(const struct NarmVM *vm);
+
+// Registers the handler called for every SVC, or removes it when handler is null
+// user_data is passed to the handler unchanged
+// # Safety
+// vm must be a valid VM, and handler must remain callable with user_data for as long as it is registered
+void narm_vm_set_svc_handler(struct NarmVM *vm,
+                             NarmSvcHandler handler,
+                             void *user_data);
+
+// Executes until an SVC which is not handled, or an error
+// Each SVC is passed to the SVC handler if one is registered, and execution only stops if the handler returns non-zero
+// # Safety
+// vm must be a valid VM
+struct NarmExit narm_vm_run(struct NarmVM *vm);
+
+#endif  /* NARM_H */
@@ -0,0 +1,306 @@
+//! C ABI for embedding narm in hosts which are not written in Rust
+//! The header for this API is generated by cbindgen into include/narm.h when this crate is built
+//!
+//! All functions taking a `NarmVM` pointer require it to be a valid pointer returned by `narm_vm_new` which has not yet been freed
+//! Data pointers must be valid for the given length
+
+extern crate narm;
+
+use narm::narmvm::{NarmVM as VM, HINT_EXIT};
+use narm::NarmError;
+use std::ffi::c_void;
+use std::slice;
+
+/// Error codes, which mirror NarmError
+/// Errors which carry a value, such as the address of a memory error, also set `NarmResult::value`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum NarmErrorCode{
+    NARM_OK = 0,
+    NARM_ERROR_UNLOADED_MEMORY_READ,
+    NARM_ERROR_UNLOADED_MEMORY_WRITE,
+    NARM_ERROR_EMPTY_MEMORY_READ,
+    NARM_ERROR_EMPTY_MEMORY_WRITE,
+    NARM_ERROR_READ_ONLY_MEMORY_WRITE,
+    NARM_ERROR_UNALIGNED_MEMORY_ADDITION,
+    NARM_ERROR_CONFLICTING_MEMORY_ADDITION,
+    NARM_ERROR_INVALID_OPCODE,
+    NARM_ERROR_INVALID_OPCODE32,
+    NARM_ERROR_OUT_OF_GAS,
+    NARM_ERROR_INVALID_ARCHITECTURE_MODE,
+    NARM_ERROR_UNKNOWN_SERVICE_CALL,
+    NARM_ERROR_UNKNOWN_SYSTEM_CALL,
+    NARM_ERROR_EMPTY_COSTACK,
+    NARM_ERROR_MEMORY_LIMIT_EXCEEDED,
+    NARM_ERROR_OUT_OF_MEMORY_GAS,
+    NARM_ERROR_INVALID_IT_BLOCK_INSTRUCTION,
+    NARM_ERROR_INVALID_MEMORY_REGION,
+    NARM_ERROR_STACK_OVERFLOW,
+    NARM_ERROR_INVALID_CONTRACT_CALL,
+    NARM_ERROR_INVALID_GUEST_STRING,
+    NARM_ERROR_UNKNOWN_SEMIHOSTING_CALL,
+    /// A pointer passed to the API was null
+    NARM_ERROR_NULL_POINTER,
+}
+
+/// The result of an API call, with the value carried by the error, if any
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NarmResult{
+    pub code: NarmErrorCode,
+    pub value: u32,
+}
+
+/// Why narm_vm_run returned
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(non_camel_case_types)]
+pub enum NarmExitKind{
+    /// An SVC was executed which had no handler, or whose handler asked to stop execution
+    NARM_EXIT_SVC = 0,
+    /// Execution stopped with an error
+    NARM_EXIT_ERROR,
+    /// A YIELD, WFE, WFI or SEV hint was executed while narm_vm_set_yield_on_hints is enabled
+    NARM_EXIT_HINT,
+}
+
+/// The exit reason returned by narm_vm_run
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct NarmExit{
+    pub kind: NarmExitKind,
+    /// The SVC number for NARM_EXIT_SVC, or the hint for NARM_EXIT_HINT, such as 0x30 for WFI
+    pub svc: u32,
+    /// The error for NARM_EXIT_ERROR, otherwise NARM_OK
+    pub error: NarmResult,
+}
+
+/// Called for every SVC executed by narm_vm_run, with the user data given to narm_vm_set_svc_handler
+/// The handler may read and modify the VM, such as to pass results back in r0
+/// Returning 0 continues execution after the SVC, while any other value stops narm_vm_run with NARM_EXIT_SVC
+pub type NarmSvcHandler = Option<extern "C" fn(user_data: *mut c_void, vm: *mut NarmVM, svc: u32) -> i32>;
+
+/// A VM along with the host callbacks registered through the C API
+pub struct NarmVM{
+    vm: VM,
+    svc_handler: NarmSvcHandler,
+    svc_user_data: *mut c_void,
+}
+
+const OK: NarmResult = NarmResult{code: NarmErrorCode::NARM_OK, value: 0};
+const NULL_POINTER: NarmResult = NarmResult{code: NarmErrorCode::NARM_ERROR_NULL_POINTER, value: 0};
+
+impl From<NarmError> for NarmResult{
+    fn from(error: NarmError) -> NarmResult{
+        use NarmErrorCode::*;
+        let (code, value) = match error{
+            NarmError::None => (NARM_OK, 0),
+            NarmError::UnloadedMemoryRead(a) => (NARM_ERROR_UNLOADED_MEMORY_READ, a),
+            NarmError::UnloadedMemoryWrite(a) => (NARM_ERROR_UNLOADED_MEMORY_WRITE, a),
+            NarmError::EmptyMemoryRead(a) => (NARM_ERROR_EMPTY_MEMORY_READ, a),
+            NarmError::EmptyMemoryWrite(a) => (NARM_ERROR_EMPTY_MEMORY_WRITE, a),
+            NarmError::ReadOnlyMemoryWrite(a) => (NARM_ERROR_READ_ONLY_MEMORY_WRITE, a),
+            NarmError::UnalignedMemoryAddition => (NARM_ERROR_UNALIGNED_MEMORY_ADDITION, 0),
+            NarmError::ConflictingMemoryAddition => (NARM_ERROR_CONFLICTING_MEMORY_ADDITION, 0),
+            NarmError::InvalidOpcode(o) => (NARM_ERROR_INVALID_OPCODE, o as u32),
+            NarmError::InvalidOpcode32(o) => (NARM_ERROR_INVALID_OPCODE32, o),
+            NarmError::OutOfGas => (NARM_ERROR_OUT_OF_GAS, 0),
+            NarmError::InvalidArchitectureMode => (NARM_ERROR_INVALID_ARCHITECTURE_MODE, 0),
+            NarmError::UnknownServiceCall(s) => (NARM_ERROR_UNKNOWN_SERVICE_CALL, s),
+            NarmError::UnknownSystemCall(s) => (NARM_ERROR_UNKNOWN_SYSTEM_CALL, s),
+            NarmError::EmptyCostack => (NARM_ERROR_EMPTY_COSTACK, 0),
+            NarmError::MemoryLimitExceeded(a) => (NARM_ERROR_MEMORY_LIMIT_EXCEEDED, a),
+            NarmError::OutOfMemoryGas => (NARM_ERROR_OUT_OF_MEMORY_GAS, 0),
+            NarmError::InvalidITBlockInstruction(o) => (NARM_ERROR_INVALID_IT_BLOCK_INSTRUCTION, o),
+            NarmError::InvalidMemoryRegion(a) => (NARM_ERROR_INVALID_MEMORY_REGION, a),
+            NarmError::StackOverflow(sp) => (NARM_ERROR_STACK_OVERFLOW, sp),
+            NarmError::InvalidContractCall => (NARM_ERROR_INVALID_CONTRACT_CALL, 0),
+            NarmError::InvalidGuestString(a) => (NARM_ERROR_INVALID_GUEST_STRING, a),
+            NarmError::UnknownSemihostingCall(o) => (NARM_ERROR_UNKNOWN_SEMIHOSTING_CALL, o),
+        };
+        NarmResult{code, value}
+    }
+}
+
+fn to_result(result: Result<(), NarmError>) -> NarmResult{
+    match result{
+        Ok(()) => OK,
+        Err(e) => e.into()
+    }
+}
+
+/// Creates a new VM with no memory mapped and no gas, which must be freed with narm_vm_free
+#[no_mangle]
+pub extern "C" fn narm_vm_new() -> *mut NarmVM{
+    Box::into_raw(Box::new(NarmVM{
+        vm: VM::default(),
+        svc_handler: None,
+        svc_user_data: std::ptr::null_mut(),
+    }))
+}
+
+/// Frees a VM created by narm_vm_new. Passing null does nothing
+/// # Safety
+/// vm must be null or a VM which was not already freed
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_free(vm: *mut NarmVM){
+    if !vm.is_null(){
+        drop(Box::from_raw(vm));
+    }
+}
+
+/// Maps a block of memory, see MemorySystem::add_memory
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_add_memory(vm: *mut NarmVM, address: u32, size: u32) -> NarmResult{
+    to_result((*vm).vm.memory.add_memory(address, size).map(|_| ()))
+}
+
+/// Copies len bytes from data into VM memory at address
+/// # Safety
+/// vm must be a valid VM and data must be valid for reading len bytes
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_copy_into_memory(vm: *mut NarmVM, address: u32, data: *const u8, len: u32) -> NarmResult{
+    if data.is_null() && len != 0{
+        return NULL_POINTER;
+    }
+    let data = if len == 0 {&[][..]} else {slice::from_raw_parts(data, len as usize)};
+    to_result((*vm).vm.copy_into_memory(address, data))
+}
+
+/// Copies len bytes of VM memory at address into out
+/// # Safety
+/// vm must be a valid VM and out must be valid for writing len bytes
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_copy_from_memory(vm: *mut NarmVM, address: u32, out: *mut u8, len: u32) -> NarmResult{
+    if out.is_null() && len != 0{
+        return NULL_POINTER;
+    }
+    match (*vm).vm.memory.get_sized_memory(address, len){
+        Ok(m) => {
+            if len != 0{
+                slice::from_raw_parts_mut(out, len as usize).copy_from_slice(m);
+            }
+            OK
+        },
+        Err(e) => e.into()
+    }
+}
+
+/// Gets r0-r15. r15 is PC, including the thumb bit. Any other register reads as 0
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_get_reg(vm: *const NarmVM, reg: u32) -> u32{
+    (*vm).vm.external_get_reg(reg as usize)
+}
+
+/// Sets r0-r15. r15 is PC, which must include the thumb bit. Any other register is ignored
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_set_reg(vm: *mut NarmVM, reg: u32, value: u32){
+    (*vm).vm.external_set_reg(reg as usize, value);
+}
+
+/// Sets PC to the given address of thumb code, adding the thumb bit
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_set_pc(vm: *mut NarmVM, address: u32){
+    (*vm).vm.set_thumb_pc_address(address);
+}
+
+/// Gets the condition flags, with N, Z, C and V in bits 31 to 28
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_get_cpsr(vm: *const NarmVM) -> u32{
+    (*vm).vm.cpsr.get_cpsr()
+}
+
+/// Sets the condition flags, with N, Z, C and V in bits 31 to 28
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_set_cpsr(vm: *mut NarmVM, value: u32){
+    (*vm).vm.cpsr.set_cpsr(value);
+}
+
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_get_gas(vm: *const NarmVM) -> u64{
+    (*vm).vm.gas_remaining
+}
+
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_set_gas(vm: *mut NarmVM, gas: u64){
+    (*vm).vm.gas_remaining = gas;
+}
+
+/// Sets the lowest address SP may be set to, or 0 to remove the limit
+/// Going below it stops execution with NARM_ERROR_STACK_OVERFLOW
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_set_stack_limit(vm: *mut NarmVM, limit: u32){
+    (*vm).vm.set_stack_limit(limit);
+}
+
+/// Makes narm_vm_run return NARM_EXIT_HINT on YIELD, WFE, WFI and SEV when enabled is non-zero, instead of treating them as NOP
+/// Running again resumes after the hint
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_set_yield_on_hints(vm: *mut NarmVM, enabled: i32){
+    (*vm).vm.set_yield_on_hints(enabled != 0);
+}
+
+/// Returns the lowest value SP has had, which gives the peak stack usage
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_get_lowest_sp(vm: *const NarmVM) -> u32{
+    (*vm).vm.get_lowest_sp()
+}
+
+/// Registers the handler called for every SVC, or removes it when handler is null
+/// user_data is passed to the handler unchanged
+/// # Safety
+/// vm must be a valid VM, and handler must remain callable with user_data for as long as it is registered
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_set_svc_handler(vm: *mut NarmVM, handler: NarmSvcHandler, user_data: *mut c_void){
+    (*vm).svc_handler = handler;
+    (*vm).svc_user_data = user_data;
+}
+
+/// Executes until an SVC which is not handled, or an error
+/// Each SVC is passed to the SVC handler if one is registered, and execution only stops if the handler returns non-zero
+/// # Safety
+/// vm must be a valid VM
+#[no_mangle]
+pub unsafe extern "C" fn narm_vm_run(vm: *mut NarmVM) -> NarmExit{
+    loop{
+        match (*vm).vm.execute(){
+            Ok(svc) if svc & HINT_EXIT != 0 => {
+                return NarmExit{kind: NarmExitKind::NARM_EXIT_HINT, svc: svc & 0xFF, error: OK};
+            },
+            Ok(svc) => {
+                if let Some(handler) = (*vm).svc_handler{
+                    if handler((*vm).svc_user_data, vm, svc) == 0{
+                        continue;
+                    }
+                }
+                return NarmExit{kind: NarmExitKind::NARM_EXIT_SVC, svc, error: OK};
+            },
+            Err(e) => {
+                return NarmExit{kind: NarmExitKind::NARM_EXIT_ERROR, svc: 0, error: e.into()};
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+/*
+ * Exercises the narm C API from C
+ * Built and run by tests/test_capi.rs, which links it against the narm_capi static library
+ * Exits with 0 on success, and prints the failed check otherwise
+ */
+#include <stdio.h>
+#include <string.h>
+#include "narm.h"
+
+#define CODE_ADDRESS 0x00010000
+#define DATA_ADDRESS 0x81000000
+
+#define CHECK(condition) do { \
+        if (!(condition)) { \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #condition); \
+            return 1; \
+        } \
+    } while (0)
+
+/* movs r0, #5; svc #1; adds r0, #1; str r0, [r1]; svc #0xFF */
+static const uint16_t PROGRAM[] = {0x2005, 0xDF01, 0x3001, 0x6008, 0xDFFF};
+
+/* Records every SVC, doubles r0 for SVC 1, and stops execution for any other SVC */
+typedef struct {
+    uint32_t calls;
+    uint32_t last_svc;
+} HandlerState;
+
+static int32_t handle_svc(void *user_data, NarmVM *vm, uint32_t svc) {
+    HandlerState *state = (HandlerState *)user_data;
+    state->calls++;
+    state->last_svc = svc;
+    if (svc == 1) {
+        narm_vm_set_reg(vm, 0, narm_vm_get_reg(vm, 0) * 2);
+        return 0;
+    }
+    return 1;
+}
+
+static NarmVM *create_vm(void) {
+    NarmVM *vm = narm_vm_new();
+    if (narm_vm_add_memory(vm, CODE_ADDRESS, 0x1000).code != NARM_OK) {
+        return NULL;
+    }
+    if (narm_vm_add_memory(vm, DATA_ADDRESS, 0x1000).code != NARM_OK) {
+        return NULL;
+    }
+    if (narm_vm_copy_into_memory(vm, CODE_ADDRESS, (const uint8_t *)PROGRAM, sizeof(PROGRAM)).code != NARM_OK) {
+        return NULL;
+    }
+    narm_vm_set_pc(vm, CODE_ADDRESS);
+    narm_vm_set_reg(vm, 1, DATA_ADDRESS);
+    narm_vm_set_gas(vm, 1000);
+    return vm;
+}
+
+/* Without a handler, execution stops at every SVC */
+static int test_run_without_handler(void) {
+    NarmVM *vm = create_vm();
+    CHECK(vm != NULL);
+    NarmExit exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_SVC);
+    CHECK(exit.svc == 1);
+    CHECK(narm_vm_get_reg(vm, 0) == 5);
+    CHECK(narm_vm_get_reg(vm, 15) == (CODE_ADDRESS + 4) + 1);
+    exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_SVC);
+    CHECK(exit.svc == 0xFF);
+    CHECK(narm_vm_get_reg(vm, 0) == 6);
+    CHECK(narm_vm_get_gas(vm) == 1000 - 5);
+    narm_vm_free(vm);
+    return 0;
+}
+
+/* The handler can modify the VM and decides when to stop */
+static int test_run_with_handler(void) {
+    HandlerState state = {0, 0};
+    uint8_t data[4];
+    NarmVM *vm = create_vm();
+    CHECK(vm != NULL);
+    narm_vm_set_svc_handler(vm, handle_svc, &state);
+    NarmExit exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_SVC);
+    CHECK(exit.svc == 0xFF);
+    CHECK(exit.error.code == NARM_OK);
+    CHECK(state.calls == 2);
+    CHECK(state.last_svc == 0xFF);
+    CHECK(narm_vm_get_reg(vm, 0) == 11);
+    CHECK(narm_vm_copy_from_memory(vm, DATA_ADDRESS, data, sizeof(data)).code == NARM_OK);
+    CHECK(data[0] == 11 && data[1] == 0 && data[2] == 0 && data[3] == 0);
+    /* flags from adds r0, #1 */
+    CHECK(narm_vm_get_cpsr(vm) == 0);
+    narm_vm_set_cpsr(vm, 0x40000000);
+    CHECK(narm_vm_get_cpsr(vm) == 0x40000000);
+
+    /* removing the handler stops at every SVC again */
+    narm_vm_set_svc_handler(vm, NULL, NULL);
+    narm_vm_set_pc(vm, CODE_ADDRESS);
+    exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_SVC && exit.svc == 1);
+    CHECK(state.calls == 2);
+    narm_vm_free(vm);
+    return 0;
+}
+
+/* Errors are reported with their code and value */
+static int test_errors(void) {
+    uint8_t data[4] = {1, 2, 3, 4};
+    NarmVM *vm = create_vm();
+    CHECK(vm != NULL);
+    NarmResult result = narm_vm_add_memory(vm, CODE_ADDRESS, 0x100);
+    CHECK(result.code == NARM_ERROR_CONFLICTING_MEMORY_ADDITION);
+    result = narm_vm_add_memory(vm, 0x20001, 0x100);
+    CHECK(result.code == NARM_ERROR_UNALIGNED_MEMORY_ADDITION);
+    result = narm_vm_copy_into_memory(vm, 0x50000000, data, sizeof(data));
+    CHECK(result.code == NARM_ERROR_UNLOADED_MEMORY_READ);
+    CHECK(result.value == 0x50000000);
+    result = narm_vm_copy_from_memory(vm, DATA_ADDRESS + 0xFFE, data, sizeof(data));
+    CHECK(result.code == NARM_ERROR_EMPTY_MEMORY_READ);
+    result = narm_vm_copy_from_memory(vm, DATA_ADDRESS, NULL, sizeof(data));
+    CHECK(result.code == NARM_ERROR_NULL_POINTER);
+
+    narm_vm_set_gas(vm, 1);
+    NarmExit exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_ERROR);
+    CHECK(exit.error.code == NARM_ERROR_OUT_OF_GAS);
+
+    narm_vm_set_gas(vm, 1000);
+    narm_vm_set_pc(vm, 0x40000000);
+    exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_ERROR);
+    CHECK(exit.error.code == NARM_ERROR_UNLOADED_MEMORY_READ);
+    CHECK(exit.error.value == 0x40000000);
+    narm_vm_free(vm);
+    narm_vm_free(NULL);
+    return 0;
+}
+
+/* push {r0, lr}; svc #1 */
+static const uint16_t PUSH_PROGRAM[] = {0xB501, 0xDF01};
+
+/* Pushing below the stack limit stops with a stack overflow, and the lowest SP is tracked */
+static int test_stack_limit(void) {
+    NarmVM *vm = create_vm();
+    CHECK(vm != NULL);
+    CHECK(narm_vm_copy_into_memory(vm, CODE_ADDRESS, (const uint8_t *)PUSH_PROGRAM, sizeof(PUSH_PROGRAM)).code == NARM_OK);
+    narm_vm_set_reg(vm, 13, DATA_ADDRESS + 0x100);
+    narm_vm_set_stack_limit(vm, DATA_ADDRESS + 0xFC);
+    NarmExit exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_ERROR);
+    CHECK(exit.error.code == NARM_ERROR_STACK_OVERFLOW);
+    CHECK(exit.error.value == DATA_ADDRESS + 0xF8);
+    CHECK(narm_vm_get_lowest_sp(vm) == DATA_ADDRESS + 0x100);
+
+    narm_vm_set_stack_limit(vm, 0);
+    narm_vm_set_pc(vm, CODE_ADDRESS);
+    exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_SVC && exit.svc == 1);
+    CHECK(narm_vm_get_lowest_sp(vm) == DATA_ADDRESS + 0xF8);
+    narm_vm_free(vm);
+    return 0;
+}
+
+/* wfi, then svc #1 */
+static const uint16_t WFI_PROGRAM[] = {0xBF30, 0xDF01};
+
+/* WFI is a NOP by default, and returns to the host with yielding enabled */
+static int test_yield_on_hints(void) {
+    NarmVM *vm = create_vm();
+    CHECK(vm != NULL);
+    CHECK(narm_vm_copy_into_memory(vm, CODE_ADDRESS, (const uint8_t *)WFI_PROGRAM, sizeof(WFI_PROGRAM)).code == NARM_OK);
+    NarmExit exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_SVC && exit.svc == 1);
+
+    narm_vm_set_yield_on_hints(vm, 1);
+    narm_vm_set_pc(vm, CODE_ADDRESS);
+    exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_HINT && exit.svc == 0x30);
+    CHECK(exit.error.code == NARM_OK);
+    exit = narm_vm_run(vm);
+    CHECK(exit.kind == NARM_EXIT_SVC && exit.svc == 1);
+    narm_vm_free(vm);
+    return 0;
+}
+
+int main(void) {
+    if (test_run_without_handler() || test_run_with_handler() || test_errors() || test_stack_limit() ||
+        test_yield_on_hints()) {
+        return 1;
+    }
+    printf("narm C API tests passed\n");
+    return 0;
+}
@@ -0,0 +1,55 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/*
+
+Integration test for the C API
+
+Compiles tests/c/test_capi.c with the system C compiler against the generated header and the static library,
+then runs it. The C program checks the API itself and exits with a non-zero status on failure.
+
+*/
+
+// The static library is built next to the test executable when the library is built for integration tests
+fn find_static_library() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    for dir in [deps, deps.parent().unwrap()] {
+        let lib = dir.join("libnarm_capi.a");
+        if lib.exists() {
+            return lib;
+        }
+    }
+    panic!("libnarm_capi.a was not found next to {}", exe.display());
+}
+
+#[test]
+pub fn test_capi_from_c() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = tempfile::tempdir().unwrap();
+    let exe = out_dir.path().join("test_capi");
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let output = Command::new(&compiler)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(crate_dir.join("include"))
+        .arg(crate_dir.join("tests/c/test_capi.c"))
+        .arg(find_static_library())
+        .args(["-lpthread", "-ldl", "-lm"])
+        .arg("-o")
+        .arg(&exe)
+        .output()
+        .expect("failed to run the C compiler");
+    assert!(output.status.success(), "compiling test_capi.c failed:\n{}", String::from_utf8_lossy(&output.stderr));
+
+    let output = Command::new(&exe).output().unwrap();
+    assert!(
+        output.status.success(),
+        "test_capi failed:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,375 @@
+use crate::loader::Image;
+use narm::instruction::Instruction;
+use narm::memory::HexDumpFormat;
+use narm::narmvm::NarmVM;
+use narm::NarmError;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+pub const HELP: &str = "\
+commands:
+  step [count]              execute count instructions (s)
+  next [count]              like step, but runs called functions to completion (n)
+  continue                  run until a breakpoint, SVC or error (c)
+  break [location]          set a breakpoint, or list breakpoints without a location (b)
+  delete [location]         remove a breakpoint, or all breakpoints without a location
+  regs                      show registers, flags and gas (r)
+  set <target> <value>      set r0-r15, sp, lr, pc, a flag (n, z, c, v) or gas
+  x <location> [length]     dump memory, 64 bytes by default and 4096 at most
+  write <location> <byte>.. write bytes to memory
+  disas [location] [count]  disassemble around pc, or from location
+  gas                       show gas remaining and used
+  trace [count]             show the last executed instructions
+  symbols                   list symbols
+  help                      show this help
+  quit                      exit (q)
+locations are numbers (0x for hex), registers, symbols, or symbol+offset";
+
+/// Number of instructions shown by disas
+const DISASSEMBLY_LENGTH: usize = 8;
+/// Number of already executed instructions shown by disas before pc
+const DISASSEMBLY_HISTORY: usize = 3;
+/// Largest number of bytes shown by x, so that a mistyped length doesn't print gigabytes of unloaded memory
+const DUMP_LIMIT: u64 = 4096;
+
+/// Whether the debugger should keep reading commands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow{
+    Continue,
+    Quit,
+}
+
+/// Why execution stopped
+enum Stop{
+    Done,
+    Breakpoint,
+    Svc(u32),
+    Error(NarmError),
+}
+
+pub struct Debugger{
+    pub vm: NarmVM,
+    symbols: Vec<(String, u32)>,
+    breakpoints: Vec<u32>,
+    /// Addresses of the most recently executed instructions, oldest first
+    trace: VecDeque<u32>,
+    trace_length: usize,
+    /// Gas remaining when it was last set, for showing the gas used
+    initial_gas: u64,
+}
+
+impl Debugger{
+    pub fn new(image: Image, trace_length: usize) -> Debugger{
+        Debugger{
+            initial_gas: image.vm.gas_remaining,
+            vm: image.vm,
+            symbols: image.symbols,
+            breakpoints: vec![],
+            trace: VecDeque::new(),
+            trace_length,
+        }
+    }
+
+    /// Executes a single command line, returning the text to show
+    pub fn execute_line(&mut self, line: &str) -> (Flow, String){
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() || words[0].starts_with('#'){
+            return (Flow::Continue, String::new());
+        }
+        let (command, args) = (words[0], &words[1..]);
+        let result = match command{
+            "quit" | "q" | "exit" => return (Flow::Quit, String::new()),
+            "help" | "h" | "?" => Ok(HELP.to_string()),
+            "step" | "s" => parse_count(args.first(), 1).map(|n| self.step(n, false)),
+            "next" | "n" => parse_count(args.first(), 1).map(|n| self.step(n, true)),
+            "continue" | "c" => Ok(self.continue_execution()),
+            "break" | "b" => self.set_breakpoint(args.first()),
+            "delete" | "d" => self.delete_breakpoint(args.first()),
+            "regs" | "r" | "registers" => Ok(self.vm.get_diagnostics_message().trim_end().to_string()),
+            "set" => self.set(args),
+            "x" => self.dump(args),
+            "write" => self.write(args),
+            "disas" | "disassemble" => self.disassemble(args),
+            "gas" => Ok(format!("gas remaining: {}\ngas used: {}", self.vm.gas_remaining, self.initial_gas.saturating_sub(self.vm.gas_remaining))),
+            "trace" => self.show_trace(args.first()),
+            "symbols" => Ok(self.symbols.iter().map(|(name, address)| format!("{:#010x} {}", address, name)).collect::<Vec<_>>().join("\n")),
+            _ => Err(format!("unknown command '{}', see help", command)),
+        };
+        match result{
+            Ok(text) => (Flow::Continue, text),
+            Err(e) => (Flow::Continue, format!("error: {}", e)),
+        }
+    }
+
+    /// Executes one instruction, recording it in the trace
+    fn cycle(&mut self) -> Result<u32, NarmError>{
+        if self.trace.len() == self.trace_length{
+            self.trace.pop_front();
+        }
+        if self.trace_length > 0{
+            self.trace.push_back(self.vm.get_pc_address());
+        }
+        self.vm.cycle()
+    }
+
+    /// Executes count instructions, or with step_over, count lines where a call and the function it calls count as one
+    fn step(&mut self, count: u64, step_over: bool) -> String{
+        for _ in 0..count{
+            let stop = match self.call_return_address(){
+                Some(return_address) if step_over => self.run_until(Some(return_address)),
+                _ => match self.cycle(){
+                    Ok(0) => Stop::Done,
+                    Ok(svc) => Stop::Svc(svc),
+                    Err(e) => Stop::Error(e),
+                },
+            };
+            if !matches!(stop, Stop::Done){
+                return self.describe_stop(stop);
+            }
+        }
+        self.describe_stop(Stop::Done)
+    }
+
+    fn continue_execution(&mut self) -> String{
+        let stop = self.run_until(None);
+        self.describe_stop(stop)
+    }
+
+    /// Runs until a breakpoint, SVC, error, or until pc reaches target with the stack no deeper than at the start
+    /// At least one instruction is executed, so that continuing from a breakpoint does not stop at it again
+    fn run_until(&mut self, target: Option<u32>) -> Stop{
+        let sp = self.vm.get_sp();
+        loop{
+            match self.cycle(){
+                Ok(0) => {},
+                Ok(svc) => return Stop::Svc(svc),
+                Err(e) => return Stop::Error(e),
+            }
+            let pc = self.vm.get_pc_address();
+            if target == Some(pc) && self.vm.get_sp() >= sp{
+                return Stop::Done;
+            }
+            if self.breakpoints.contains(&pc){
+                return Stop::Breakpoint;
+            }
+        }
+    }
+
+    /// The address a call at pc returns to, if the instruction at pc is a call
+    fn call_return_address(&self) -> Option<u32>{
+        let pc = self.vm.get_pc_address();
+        match self.vm.decode_at(pc){
+            Ok((Instruction::BlT1{..}, size)) | Ok((Instruction::BlxT1{..}, size)) => Some(pc + size),
+            _ => None,
+        }
+    }
+
+    fn describe_stop(&self, stop: Stop) -> String{
+        let mut text = String::new();
+        match stop{
+            Stop::Done => {},
+            Stop::Breakpoint => text.push_str("breakpoint hit\n"),
+            Stop::Svc(svc) => {
+                let _ = writeln!(text, "stopped after svc #{:#04x}", svc);
+            },
+            Stop::Error(e) => {
+                let _ = writeln!(text, "stopped with error {} at {}", describe_error(&e), self.describe_address(self.vm.get_last_pc() & !1));
+            },
+        }
+        text.push_str(&self.disassemble_line(self.vm.get_pc_address(), true));
+        text
+    }
+
+    fn set_breakpoint(&mut self, location: Option<&&str>) -> Result<String, String>{
+        let location = match location{
+            Some(l) => self.parse_location(l)? & !1,
+            None => {
+                if self.breakpoints.is_empty(){
+                    return Ok("no breakpoints".to_string());
+                }
+                return Ok(self.breakpoints.iter().map(|b| format!("breakpoint at {}", self.describe_address(*b))).collect::<Vec<_>>().join("\n"));
+            }
+        };
+        if !self.breakpoints.contains(&location){
+            self.breakpoints.push(location);
+        }
+        Ok(format!("breakpoint at {}", self.describe_address(location)))
+    }
+
+    fn delete_breakpoint(&mut self, location: Option<&&str>) -> Result<String, String>{
+        match location{
+            Some(l) => {
+                let location = self.parse_location(l)? & !1;
+                let count = self.breakpoints.len();
+                self.breakpoints.retain(|b| *b != location);
+                if self.breakpoints.len() == count{
+                    return Err(format!("no breakpoint at {}", self.describe_address(location)));
+                }
+                Ok(format!("deleted breakpoint at {}", self.describe_address(location)))
+            },
+            None => {
+                self.breakpoints.clear();
+                Ok("deleted all breakpoints".to_string())
+            }
+        }
+    }
+
+    fn set(&mut self, args: &[&str]) -> Result<String, String>{
+        if args.len() != 2{
+            return Err("usage: set <target> <value>".to_string());
+        }
+        let (target, value) = (args[0], self.parse_location(args[1])?);
+        match target{
+            "pc" | "r15" => self.vm.set_thumb_pc_address(value),
+            "n" => self.vm.cpsr.n = value != 0,
+            "z" => self.vm.cpsr.z = value != 0,
+            "c" => self.vm.cpsr.c = value != 0,
+            "v" => self.vm.cpsr.v = value != 0,
+            "gas" => {
+                self.vm.gas_remaining = value as u64;
+                self.initial_gas = value as u64;
+            },
+            _ => {
+                let register = parse_register(target).ok_or_else(|| format!("unknown target '{}'", target))?;
+                self.vm.external_set_reg(register, value);
+            }
+        }
+        Ok(format!("{} = {:#010x}", target, value))
+    }
+
+    fn dump(&mut self, args: &[&str]) -> Result<String, String>{
+        let address = self.parse_location(args.first().ok_or("usage: x <location> [length]")?)?;
+        let length = parse_count(args.get(1), 64)?.min(DUMP_LIMIT) as u32;
+        Ok(self.vm.memory.hex_dump(address, length, HexDumpFormat::default()).trim_end().to_string())
+    }
+
+    fn write(&mut self, args: &[&str]) -> Result<String, String>{
+        if args.len() < 2{
+            return Err("usage: write <location> <byte>..".to_string());
+        }
+        let address = self.parse_location(args[0])?;
+        let bytes = args[1..].iter().map(|b| parse_number(b).filter(|v| *v <= 0xFF).map(|v| v as u8).ok_or_else(|| format!("invalid byte '{}'", b)))
+            .collect::<Result<Vec<u8>, String>>()?;
+        self.vm.copy_into_memory(address, &bytes).map_err(|e| describe_error(&e))?;
+        Ok(format!("wrote {} bytes at {:#010x}", bytes.len(), address))
+    }
+
+    fn disassemble(&self, args: &[&str]) -> Result<String, String>{
+        let pc = self.vm.get_pc_address();
+        let mut lines = vec![];
+        let mut address = match args.first(){
+            Some(location) => self.parse_location(location)? & !1,
+            None => {
+                //instructions before pc can't be found by decoding backwards, so the ones which were executed are shown instead
+                let start = self.trace.len().saturating_sub(DISASSEMBLY_HISTORY + 1);
+                for a in self.trace.range(start..){
+                    if *a != pc{
+                        lines.push(self.disassemble_line(*a, false));
+                    }
+                }
+                pc
+            }
+        };
+        let count = parse_count(args.get(1), DISASSEMBLY_LENGTH as u64)?;
+        for _ in 0..count{
+            lines.push(self.disassemble_line(address, address == pc));
+            //stop at the end of the address space as well as at anything which can't be decoded
+            match self.vm.decode_at(address).ok().and_then(|(_, size)| address.checked_add(size)){
+                Some(next) => address = next,
+                None => break,
+            }
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn disassemble_line(&self, address: u32, current: bool) -> String{
+        let marker = if current {"=>"} else {"  "};
+        let location = self.describe_address(address);
+        match self.vm.decode_at(address){
+            Ok((instruction, size)) => {
+                let opcode = if size == 4{
+                    let high = self.vm.memory.get_u16(address).unwrap_or_default() as u32;
+                    format!("{:08x}", (high << 16) | self.vm.memory.get_u16(address.wrapping_add(2)).unwrap_or_default() as u32)
+                }else{
+                    format!("{:04x}    ", self.vm.memory.get_u16(address).unwrap_or_default())
+                };
+                format!("{} {:<30} {}  {:?}", marker, location + ":", opcode, instruction)
+            },
+            Err(e) => format!("{} {:<30} {}", marker, location + ":", describe_error(&e)),
+        }
+    }
+
+    fn show_trace(&self, count: Option<&&str>) -> Result<String, String>{
+        let count = parse_count(count, self.trace.len() as u64)? as usize;
+        if self.trace.is_empty(){
+            return Ok("no instructions executed".to_string());
+        }
+        let start = self.trace.len().saturating_sub(count);
+        Ok(self.trace.range(start..).map(|a| self.disassemble_line(*a, false)).collect::<Vec<_>>().join("\n"))
+    }
+
+    /// Formats an address along with the symbol it is in, if any
+    fn describe_address(&self, address: u32) -> String{
+        let symbol = self.symbols.iter().rev().find(|(_, a)| *a <= address);
+        match symbol{
+            Some((name, a)) if address - a < 0x1000 => {
+                if address == *a{
+                    format!("{:#010x} <{}>", address, name)
+                }else{
+                    format!("{:#010x} <{}+{}>", address, name, address - a)
+                }
+            },
+            _ => format!("{:#010x}", address),
+        }
+    }
+
+    /// Parses a number, register, symbol or symbol+offset
+    fn parse_location(&self, text: &str) -> Result<u32, String>{
+        if let Some(value) = parse_number(text){
+            return Ok(value);
+        }
+        if let Some(register) = parse_register(text){
+            //without the thumb bit, as locations are addresses
+            return Ok(if register == 15 {self.vm.get_pc_address()} else {self.vm.external_get_reg(register)});
+        }
+        let (name, offset) = match text.split_once('+'){
+            Some((name, offset)) => (name, parse_number(offset).ok_or_else(|| format!("invalid offset '{}'", offset))?),
+            None => (text, 0),
+        };
+        self.symbols.iter().find(|(n, _)| n == name).map(|(_, a)| a.wrapping_add(offset)).ok_or_else(|| format!("unknown symbol '{}'", name))
+    }
+}
+
+/// Formats an error, with addresses in hex
+fn describe_error(error: &NarmError) -> String{
+    use NarmError::*;
+    match error{
+        UnloadedMemoryRead(a) | UnloadedMemoryWrite(a) | EmptyMemoryRead(a) | EmptyMemoryWrite(a) | ReadOnlyMemoryWrite(a) | MemoryLimitExceeded(a) | StackOverflow(a) => {
+            format!("{} ({:#010x})", error, a)
+        },
+        _ => format!("{:?}", error),
+    }
+}
+
+fn parse_number(text: &str) -> Option<u32>{
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")){
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn parse_count(text: Option<&&str>, default: u64) -> Result<u64, String>{
+    match text{
+        Some(t) => parse_number(t).map(|v| v as u64).ok_or_else(|| format!("invalid count '{}'", t)),
+        None => Ok(default),
+    }
+}
+
+fn parse_register(text: &str) -> Option<usize>{
+    match text{
+        "sp" => Some(13),
+        "lr" => Some(14),
+        "pc" => Some(15),
+        _ => text.strip_prefix('r').and_then(|n| n.parse().ok()).filter(|n| *n <= 15),
+    }
+}
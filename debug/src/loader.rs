@@ -0,0 +1,90 @@
+use narm::narmvm::NarmVM;
+use std::io::Cursor;
+
+/// Flag of ELF sections which occupy memory when the program is loaded
+const SHF_ALLOC: u64 = 0x2;
+/// Type of ELF sections which occupy memory but have no data in the file, such as .bss
+const SHT_NOBITS: u32 = 8;
+/// The address raw images are loaded at by default, which is where the test suite links its programs
+pub const DEFAULT_LOAD_ADDRESS: u32 = 0x01_0000;
+
+/// A program loaded into a VM, along with its symbols
+pub struct Image{
+    pub vm: NarmVM,
+    pub entry: u32,
+    /// Symbol names and addresses, sorted by address. Thumb function symbols have the thumb bit cleared
+    pub symbols: Vec<(String, u32)>,
+}
+
+/// Loads an ELF file, or otherwise a raw image at load_address which begins execution at its first byte
+pub fn load(data: &[u8], load_address: u32) -> Result<Image, String>{
+    if data.starts_with(b"\x7fELF"){
+        load_elf(data)
+    }else{
+        load_raw(data, load_address)
+    }
+}
+
+fn load_raw(data: &[u8], address: u32) -> Result<Image, String>{
+    let mut vm = NarmVM::default();
+    map_range(&mut vm, address, data.len() as u32, "image")?;
+    vm.copy_into_memory(address, data).map_err(|e| format!("failed to load the image at {:#010x}: {}", address, e))?;
+    Ok(Image{
+        vm,
+        entry: address,
+        symbols: vec![],
+    })
+}
+
+fn load_elf(data: &[u8]) -> Result<Image, String>{
+    let file = elf::File::open_stream(&mut Cursor::new(data)).map_err(|e| format!("invalid ELF file: {:?}", e))?;
+    let mut vm = NarmVM::default();
+    for section in file.sections.iter(){
+        let header = &section.shdr;
+        if header.flags.0 & SHF_ALLOC == 0 || header.size == 0{
+            continue;
+        }
+        let address = header.addr as u32;
+        map_range(&mut vm, address, header.size as u32, &header.name)?;
+        if header.shtype.0 != SHT_NOBITS{
+            vm.copy_into_memory(address, &section.data)
+                .map_err(|e| format!("failed to load section {} at {:#010x}: {}", header.name, address, e))?;
+        }
+    }
+    let mut symbols = vec![];
+    if let Some(symtab) = file.get_section(".symtab"){
+        for symbol in file.get_symbols(symtab).map_err(|e| format!("invalid ELF symbol table: {:?}", e))?{
+            //mapping symbols such as $t and $d only mark code and data
+            if symbol.name.is_empty() || symbol.name.starts_with('$') || symbol.shndx == 0{
+                continue;
+            }
+            symbols.push((symbol.name, symbol.value as u32 & !1));
+        }
+    }
+    symbols.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(Image{
+        vm,
+        entry: file.ehdr.entry as u32 & !1,
+        symbols,
+    })
+}
+
+/// Maps whole 64Kb memory blocks covering the given range, skipping blocks which are already mapped
+/// Newly mapped blocks are named for the memory map
+pub fn map_range(vm: &mut NarmVM, address: u32, size: u32, name: &str) -> Result<(), String>{
+    let first = address & 0xFFFF_0000;
+    let last = address.saturating_add(size.max(1) - 1) & 0xFFFF_0000;
+    let mut block = first;
+    loop{
+        if !vm.memory.section_exists(block){
+            let memory = vm.memory.add_memory(block, 0x1_0000).map_err(|e| format!("failed to map memory at {:#010x}: {}", block, e))?;
+            //add_memory leaves a marker in the first byte
+            memory[0] = 0;
+            vm.memory.set_region_name(block, name).unwrap();
+        }
+        if block == last{
+            return Ok(());
+        }
+        block += 0x1_0000;
+    }
+}
@@ -0,0 +1,162 @@
+//! narm-debug, an interactive terminal debugger for programs running in the narm VM
+//! It loads an ELF file or a raw image and reads commands from the terminal, or from a command file with --script
+
+extern crate narm;
+
+mod debugger;
+mod loader;
+
+use debugger::{Debugger, Flow};
+use narm::instruction::IsaProfile;
+use std::io::{BufRead, Write};
+use std::process::exit;
+
+const USAGE: &str = "\
+usage: narm-debug [options] <image>
+
+Loads an ELF file, or a raw image which begins execution at its first byte, and debugs it
+
+options:
+  -x, --script <file>     run the commands in file, echoing each one, and exit
+  --load-address <addr>   address raw images are loaded at (default 0x10000)
+  --stack <addr>          64Kb of stack memory is mapped here, with sp at its end (default 0x81000000)
+  --gas <amount>          gas available to the program (default 10000000)
+  --profile <profile>     armv6m (default), armv8m-baseline or armv7m
+  --trace <count>         number of executed instructions kept for trace (default 64)";
+
+const PROMPT: &str = "(narm) ";
+
+struct Options{
+    image: String,
+    script: Option<String>,
+    load_address: u32,
+    stack: u32,
+    gas: u64,
+    profile: IsaProfile,
+    trace: usize,
+}
+
+fn parse_number(text: &str) -> Result<u64, String>{
+    let value = match text.strip_prefix("0x"){
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    value.map_err(|_| format!("invalid number '{}'", text))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String>{
+    let mut options = Options{
+        image: String::new(),
+        script: None,
+        load_address: loader::DEFAULT_LOAD_ADDRESS,
+        stack: 0x8100_0000,
+        gas: 10_000_000,
+        profile: IsaProfile::ARMv6M,
+        trace: 64,
+    };
+    let mut image = None;
+    while let Some(arg) = args.next(){
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str(){
+            "-x" | "--script" => options.script = Some(value()?),
+            "--load-address" => options.load_address = parse_number(&value()?)? as u32,
+            "--stack" => options.stack = parse_number(&value()?)? as u32,
+            "--gas" => options.gas = parse_number(&value()?)?,
+            "--trace" => options.trace = parse_number(&value()?)? as usize,
+            "--profile" => {
+                options.profile = match value()?.as_str(){
+                    "armv6m" => IsaProfile::ARMv6M,
+                    "armv8m-baseline" => IsaProfile::ARMv8MBaseline,
+                    "armv7m" => IsaProfile::ARMv7M,
+                    p => return Err(format!("unknown profile '{}'", p)),
+                }
+            },
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+            _ => {
+                if image.replace(arg).is_some(){
+                    return Err("only one image can be debugged".to_string());
+                }
+            }
+        }
+    }
+    options.image = image.ok_or("no image given")?;
+    Ok(options)
+}
+
+fn create_debugger(options: &Options) -> Result<Debugger, String>{
+    let data = std::fs::read(&options.image).map_err(|e| format!("failed to read {}: {}", options.image, e))?;
+    let mut image = loader::load(&data, options.load_address)?;
+    loader::map_range(&mut image.vm, options.stack, 0x1_0000, "stack")?;
+    image.vm.set_sp(options.stack.wrapping_add(0x1_0000)).map_err(|e| format!("invalid stack address: {}", e))?;
+    image.vm.set_isa_profile(options.profile);
+    image.vm.set_thumb_pc_address(image.entry);
+    image.vm.gas_remaining = options.gas;
+    println!("loaded {}: entry {:#010x}, {} symbols", options.image, image.entry, image.symbols.len());
+    Ok(Debugger::new(image, options.trace))
+}
+
+fn main(){
+    let options = match parse_args(std::env::args().skip(1)){
+        Ok(o) => o,
+        Err(e) => {
+            if !e.is_empty(){
+                eprintln!("error: {}\n", e);
+            }
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+    let mut debugger = match create_debugger(&options){
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            exit(1);
+        }
+    };
+    match &options.script{
+        Some(path) => {
+            let script = match std::fs::read_to_string(path){
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("error: failed to read {}: {}", path, e);
+                    exit(1);
+                }
+            };
+            for line in script.lines(){
+                //commands are echoed so that the output of a session can be read on its own
+                println!("{}{}", PROMPT, line);
+                if run_command(&mut debugger, line) == Flow::Quit{
+                    break;
+                }
+            }
+        },
+        None => {
+            let stdin = std::io::stdin();
+            let mut lines = stdin.lock().lines();
+            loop{
+                print!("{}", PROMPT);
+                let _ = std::io::stdout().flush();
+                match lines.next(){
+                    Some(Ok(line)) => {
+                        if run_command(&mut debugger, &line) == Flow::Quit{
+                            break;
+                        }
+                    },
+                    _ => {
+                        println!();
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn run_command(debugger: &mut Debugger, line: &str) -> Flow{
+    let (flow, text) = debugger.execute_line(line);
+    if !text.is_empty(){
+        println!("{}", text);
+    }
+    flow
+}
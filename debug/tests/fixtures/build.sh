@@ -0,0 +1,11 @@
+#!/bin/sh
+# Rebuilds the debugger test fixtures from their assembly sources
+# Each fixture is an ELF linked at 0x10000, keeping its symbols for breakpoints
+set -e
+cd "$(dirname "$0")"
+for source in *.s; do
+    name="${source%.s}"
+    arm-none-eabi-as -march=armv6s-m -o"$name.o" "$source"
+    arm-none-eabi-ld -T link.ld -o"$name.elf" "$name.o"
+    rm "$name.o"
+done
@@ -0,0 +1,28 @@
+@ Adds 5 + 4 + 3 + 2 + 1 by calling accumulate for each number, storing every partial sum
+@ Exits with the sum in r0
+
+    .syntax unified
+    .section .text
+    .thumb_func
+    .globl _start
+_start:
+    ldr     r0, =0x81008000
+    mov     sp, r0
+    movs    r4, #5
+    movs    r5, #0
+loop:
+    movs    r0, r4
+    bl      accumulate
+    subs    r4, #1
+    bne     loop
+    movs    r0, r5
+    svc     #0xFF
+
+@ r5 += r0, storing the result at 0x81000000
+    .thumb_func
+    .globl accumulate
+accumulate:
+    adds    r5, r5, r0
+    ldr     r1, =0x81000000
+    str     r5, [r1]
+    bx      lr
@@ -0,0 +1,12 @@
+# Mistakes are reported and the session continues
+bogus
+break nosymbol
+delete 0x10000
+x 0x50000000 16
+write 0x81000000 0x100
+set r16 1
+step 3
+set pc 0x40000000
+step
+quit
+step
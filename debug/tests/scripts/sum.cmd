@@ -0,0 +1,23 @@
+# Breaks in the function called by the loop, then steps and inspects the state
+symbols
+break accumulate
+break
+continue
+regs
+disas
+next
+next
+trace 3
+delete accumulate
+break loop+6
+continue
+x 0x81000000 20
+gas
+# The sum so far can be changed from the debugger
+write 0x81000000 0x10 0
+set r5 0x10
+continue
+x sp 4
+delete
+continue
+x 0x81000000 4
@@ -0,0 +1,133 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+/*
+
+Integration test for the narm-debug binary
+
+General test cases:
+
+- Each command file in tests/scripts, run against tests/fixtures/sum.elf, gives exactly the output in the matching .out file
+- Raw images are loaded and run
+- Disassembling at the end of the address space stops there
+- Commands are read from stdin when no script is given
+- Invalid arguments and unreadable images are reported with a non-zero exit status
+
+After intentionally changing the output of the debugger, regenerate an .out file from tests/fixtures with:
+    narm-debug -x ../scripts/<name>.cmd sum.elf > ../scripts/<name>.out
+
+*/
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn narm_debug(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_narm-debug"))
+        .args(args)
+        .current_dir(tests_dir().join("fixtures"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+// Each command file in tests/scripts gives exactly the output in the matching .out file
+#[test]
+pub fn test_narm_debug_scripts() {
+    let mut count = 0;
+    for entry in std::fs::read_dir(tests_dir().join("scripts")).unwrap() {
+        let script = entry.unwrap().path();
+        if script.extension() != Some("cmd".as_ref()) {
+            continue;
+        }
+        let expected = std::fs::read_to_string(script.with_extension("out")).unwrap();
+        let output = narm_debug(&["-x", script.to_str().unwrap(), "sum.elf"], "");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let actual = String::from_utf8(output.stdout).unwrap();
+        for (line, (a, e)) in actual.lines().zip(expected.lines()).enumerate() {
+            assert_eq!(a, e, "{} differs at line {}", script.display(), line + 1);
+        }
+        assert_eq!(actual.lines().count(), expected.lines().count(), "{} has a different number of lines", script.display());
+        count += 1;
+    }
+    assert!(count >= 2);
+}
+
+// Raw images are loaded and run
+#[test]
+pub fn test_narm_debug_raw_image() {
+    let image = tests_dir().join("../../benches/fixtures/arith.bin");
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("raw.cmd");
+    std::fs::write(&script, "disas 0x10000 1\ncontinue\ngas\n").unwrap();
+    let output = narm_debug(&["--gas", "0x100000", "-x", script.to_str().unwrap(), image.to_str().unwrap()], "");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("entry 0x00010000, 0 symbols"), "{}", stdout);
+    assert!(stdout.contains("=> 0x00010000:"), "{}", stdout);
+    assert!(stdout.contains("stopped after svc #0xff"), "{}", stdout);
+    assert!(stdout.contains("gas used: "), "{}", stdout);
+}
+
+// Disassembling at the end of the address space stops there
+#[test]
+pub fn test_narm_debug_disas_end() {
+    // nop, then the first half of ldrex, whose second half would be at address 0
+    let script = "write 0xfffffffa 0x00 0xbf 0x00 0xbf 0x5d 0xe8\ndisas 0xfffffffa 5\ndisas 0xfffffffc 5\n";
+    let output = narm_debug(&["--stack", "0xffff0000", "sum.elf"], script);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("0xfffffffc:                    bf00"), "{}", stdout);
+    assert!(stdout.contains("0xfffffffe:                    UnloadedMemoryRead (0x00000000)\n(narm) "), "{}", stdout);
+
+    let script = "write 0xfffffffe 0x00 0xbf\ndisas 0xfffffffe 5\n";
+    let output = narm_debug(&["--stack", "0xffff0000", "sum.elf"], script);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("0xfffffffe:                    bf00      Hint { hint: 0 }\n(narm) "), "{}", stdout);
+}
+
+// Dumping memory is limited in length and goes through unloaded memory and the end of the address space
+#[test]
+pub fn test_narm_debug_dump_limit() {
+    let output = narm_debug(&["sum.elf"], "x 0x50000000 0xffffffff\nx 0xfffffff8 0x100\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("0x50000ff0:  ?? ??"), "{}", stdout);
+    assert!(!stdout.contains("0x50001000:"), "{}", stdout);
+    assert!(stdout.contains("0xfffffff8:  ?? ?? ?? ?? ?? ?? ?? ??"), "{}", stdout);
+    assert!(!stdout.contains("0x00000000:"), "{}", stdout);
+}
+
+// Commands are read from stdin when no script is given
+#[test]
+pub fn test_narm_debug_stdin() {
+    let output = narm_debug(&["sum.elf"], "break accumulate\ncontinue\nquit\nregs\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("(narm) breakpoint at 0x00010016 <accumulate>"), "{}", stdout);
+    assert!(stdout.contains("breakpoint hit\n=> 0x00010016 <accumulate>:"), "{}", stdout);
+    // nothing after quit is executed
+    assert!(!stdout.contains("r0:"), "{}", stdout);
+
+    // the end of input also ends the session
+    let output = narm_debug(&["sum.elf"], "step\n");
+    assert!(output.status.success());
+}
+
+// Invalid arguments and unreadable images are reported with a non-zero exit status
+#[test]
+pub fn test_narm_debug_invalid_arguments() {
+    for args in [&[][..], &["--profile", "armv9", "sum.elf"], &["--bogus", "sum.elf"], &["sum.elf", "sum.s"]] {
+        let output = narm_debug(args, "");
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("usage: narm-debug"));
+    }
+    let output = narm_debug(&["missing.elf"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("failed to read missing.elf"));
+}
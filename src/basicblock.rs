@@ -0,0 +1,116 @@
+use crate::decode::*;
+use crate::instruction::*;
+use crate::memory::*;
+use alloc::vec::Vec;
+
+/// The maximum number of instructions translated into a single basic block
+pub const MAX_BLOCK_INSTRUCTIONS: usize = 64;
+
+/// A single instruction within a basic block
+#[derive(Debug, Clone, Copy)]
+pub struct BlockOp{
+    pub instruction: Instruction,
+    /// Address of the instruction
+    pub address: u32,
+    /// The first halfword of the opcode, for debug tracing
+    pub opcode: u16,
+    pub size: u8,
+    /// If the instruction reads PC, and so needs pc bookkeeping to be done before it is executed
+    pub reads_pc: bool,
+    /// If the instruction can write to memory, and so may invalidate code or accrue memory gas
+    pub writes_memory: bool,
+}
+
+/// A straight line sequence of instructions which is entered only at the top and can only branch away at the bottom
+/// Each instruction is decoded only once, when the block is translated
+#[derive(Debug, Clone)]
+pub struct BasicBlock{
+    /// Address of the first instruction
+    pub start: u32,
+    /// Address immediately after the last instruction
+    pub end: u32,
+    /// Total gas cost of all instructions in the block
+    pub gas: u64,
+    pub ops: Vec<BlockOp>,
+}
+
+impl BasicBlock{
+    /// Translates the basic block which begins at the given address
+    /// Translation stops at any instruction which can change control flow, or before any opcode which can not be fetched
+    /// Returns None if not even the first instruction can be fetched
+    pub fn translate(memory: &MemorySystem, start: u32, profile: IsaProfile) -> Option<BasicBlock>{
+        let mut ops = vec![];
+        let mut address = start;
+        while ops.len() < MAX_BLOCK_INSTRUCTIONS{
+            let opcode = match memory.get_u16(address){
+                Ok(o) => o,
+                Err(_) => break
+            };
+            let (instruction, size) = if is_32bit_opcode(opcode){
+                match memory.get_u16(address.wrapping_add(2)){
+                    Ok(second) => (decode_instruction32(((opcode as u32) << 16) | (second as u32), profile), 4),
+                    Err(_) => break
+                }
+            }else{
+                (decode_instruction(opcode, profile), 2)
+            };
+            let terminator = ends_block(&instruction);
+            ops.push(BlockOp{
+                instruction,
+                address,
+                opcode,
+                size,
+                reads_pc: terminator || reads_pc(&instruction),
+                writes_memory: writes_memory(&instruction),
+            });
+            address = address.wrapping_add(size as u32);
+            if terminator{
+                break;
+            }
+        }
+        if ops.is_empty(){
+            return None;
+        }
+        Some(BasicBlock{
+            start,
+            end: address,
+            gas: ops.iter().map(|op| op.instruction.gas_cost()).sum(),
+            ops
+        })
+    }
+    /// Checks if the block was translated from any memory within the given range
+    pub fn overlaps(&self, address: u32, size: u32) -> bool{
+        (address as u64) < (self.end as u64) && (address as u64) + (size as u64) > (self.start as u64)
+    }
+}
+
+/// Checks if an instruction can change control flow or stop execution, and so must be the last instruction of a block
+/// IT also ends a block, as the instructions following it are executed conditionally by cycle()
+/// Wait hints end a block, as they may yield to the host
+pub fn ends_block(instruction: &Instruction) -> bool{
+    use Instruction::*;
+    instruction.is_branch() || instruction.is_wait_hint() || matches!(instruction,
+        Svc{..} | Bkpt{..} | ItT1{..} | UdfT1{..} | Invalid(_) | Invalid32(_))
+}
+
+/// Checks if an instruction uses the value of PC
+fn reads_pc(instruction: &Instruction) -> bool{
+    use Instruction::*;
+    match instruction{
+        LdrLitT1{..} | AdrT1{..} | AdrT3{..} => true,
+        LdrImmT4{rn, ..} | LdrbImmT3{rn, ..} | LdrhImmT3{rn, ..} | LdrsbImmT2{rn, ..} | LdrshImmT2{rn, ..} | LdrdImmT1{rn, ..} => *rn == 15,
+        MovRegT1{rd: a, rm: b} | AddRegT2{rdn: a, rm: b} | CmpRegT2{rn: a, rm: b} => *a == 15 || *b == 15,
+        _ => false
+    }
+}
+
+/// Checks if an instruction can write to memory
+fn writes_memory(instruction: &Instruction) -> bool{
+    use Instruction::*;
+    matches!(instruction,
+        StrSpImmT2{..} | StmT1{..} | StrRegT1{..} | StrbRegT1{..} | StrhRegT1{..} |
+        StrImmT1{..} | StrbImmT1{..} | StrhImmT1{..} | PushT1{..} |
+        StrexT1{..} | StrexbT1{..} | StrexhT1{..} |
+        StrImmT4{..} | StrbImmT3{..} | StrhImmT3{..} | StrRegT2{..} | StrbRegT2{..} | StrhRegT2{..} |
+        StrdImmT1{..} | StmT2{..} | StmdbT1{..})
+}
@@ -0,0 +1,199 @@
+use crate::instruction::IsaProfile;
+use crate::memory::MemoryLimits;
+use crate::narmvm::NarmVM;
+use crate::neutron::*;
+use crate::{Map, NarmError};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+/*
+
+Contract calls run the callee in a new NarmVM, so that it can not read or modify the memory of its caller. A guest calls
+another contract with __system_call(CALL_FEATURE, 0) after pushing its input onto the costack, followed by the gas to
+forward to the callee as a u64, and the id of the contract to call as a u32, both little endian. The whole costack below
+these two items is moved to the callee as its input. The forwarded gas is limited to the gas the caller has remaining.
+
+The callee returns by calling __exit, where an exit code of 0 is success and any other code reverts the call. Once the call
+ends, the costack of the caller is replaced by the costack of the callee, which is empty if the callee faulted, the gas the
+callee did not use is returned to the caller, and r0 is set to the CallStatus code.
+
+The caller only ever sees the costack, r0 and its gas change, whatever the callee does. Hosts which keep state that
+contracts can modify through system calls should checkpoint it in SystemCallHandler::begin_call and discard changes made by
+reverted calls in SystemCallHandler::end_call.
+
+*/
+
+/// The system call feature used for calling another contract
+pub const CALL_FEATURE: u32 = 0x8000_0000;
+/// The maximum depth of nested calls by default, where the outermost call has a depth of 0
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 8;
+
+/// Address of the code of a contract, which is also its entry point
+pub const CODE_ADDRESS: u32 = 0x1_0000;
+/// Address of the scratch RAM given to each contract
+pub const SCRATCH_ADDRESS: u32 = 0x8100_0000;
+pub const SCRATCH_SIZE: u32 = 0x1_0000;
+/// Address of the stack given to each contract. SP starts at the end of it
+pub const STACK_ADDRESS: u32 = 0x8200_0000;
+pub const STACK_SIZE: u32 = 0x8000;
+
+/// How a contract call ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CallStatus{
+    /// The contract called __exit(0)
+    Success,
+    /// The contract called __exit with the given non-zero code
+    Revert(u32),
+    /// The contract stopped with an error, such as running out of gas
+    Fault(NarmError),
+    /// No contract was added with the called id
+    UnknownContract,
+    /// The call would have exceeded the maximum call depth
+    DepthExceeded,
+}
+
+impl CallStatus{
+    /// The value given to the caller in r0 when a contract calls another contract
+    pub fn code(&self) -> u32{
+        match self{
+            CallStatus::Success => 0,
+            CallStatus::Revert(_) => 1,
+            CallStatus::Fault(_) => 2,
+            CallStatus::UnknownContract => 3,
+            CallStatus::DepthExceeded => 4,
+        }
+    }
+}
+
+/// The outcome of a contract call
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallResult{
+    pub status: CallStatus,
+    /// The costack of the callee when it exited, which is empty if it did not exit
+    pub output: CoStack,
+    /// The gas which was not used by the callee
+    pub gas_remaining: u64,
+}
+
+/// A contract which can be called, given as a raw image which begins execution at its first byte
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractImage{
+    pub code: Vec<u8>,
+}
+
+/// Runs contracts in their own NarmVM, and handles calls from one contract to another
+#[derive(Debug, Clone)]
+pub struct CallManager{
+    contracts: Map<u32, ContractImage>,
+    /// The maximum depth of nested calls, where the outermost call has a depth of 0
+    pub max_depth: usize,
+    /// The memory limits of every VM created for a call. Mapping the memory of a new VM is charged to the gas forwarded to it
+    pub limits: MemoryLimits,
+    /// The instruction set of every VM created for a call
+    pub profile: IsaProfile,
+    /// The depth of the call currently executing
+    depth: usize,
+}
+
+impl Default for CallManager{
+    fn default() -> CallManager{
+        CallManager{
+            contracts: Map::default(),
+            max_depth: DEFAULT_MAX_CALL_DEPTH,
+            limits: MemoryLimits::default(),
+            profile: IsaProfile::default(),
+            depth: 0,
+        }
+    }
+}
+
+impl CallManager{
+    /// Adds a contract which can be called by the given id, replacing any contract already added with it
+    pub fn add_contract(&mut self, id: u32, image: ContractImage){
+        self.contracts.insert(id, image);
+    }
+    pub fn get_contract(&self, id: u32) -> Option<&ContractImage>{
+        self.contracts.get(&id)
+    }
+    /// Creates a VM with the contract image loaded at CODE_ADDRESS, scratch RAM and a stack, ready to execute from the start of the image
+    /// The memory is charged for from the given gas
+    pub fn create_vm(&self, image: &ContractImage, gas: u64) -> Result<NarmVM, NarmError>{
+        let mut vm = self.empty_vm(gas);
+        self.load_image(&mut vm, image)?;
+        Ok(vm)
+    }
+    /// Creates a VM with the ISA profile and memory limits of the manager, but no memory
+    fn empty_vm(&self, gas: u64) -> NarmVM{
+        let mut vm = NarmVM::default();
+        vm.set_isa_profile(self.profile);
+        vm.memory.limits = self.limits;
+        vm.gas_remaining = gas;
+        vm
+    }
+    /// Maps and loads the memory for a contract into a VM created by empty_vm. On failure, the VM keeps the gas which was not charged
+    fn load_image(&self, vm: &mut NarmVM, image: &ContractImage) -> Result<(), NarmError>{
+        for (i, chunk) in image.code.chunks(0x1_0000).enumerate(){
+            let address = CODE_ADDRESS + i as u32 * 0x1_0000;
+            vm.map_memory(address, chunk.len() as u32)?;
+            vm.memory.write_raw(address, chunk)?;
+        }
+        for (address, size) in [(SCRATCH_ADDRESS, SCRATCH_SIZE), (STACK_ADDRESS, STACK_SIZE)]{
+            vm.map_memory(address, size)?;
+            //add_memory leaves a marker in the first byte
+            vm.memory.write_raw(address, &[0])?;
+        }
+        vm.set_sp(STACK_ADDRESS + STACK_SIZE)?;
+        vm.set_thumb_pc_address(CODE_ADDRESS);
+        Ok(())
+    }
+    /// Calls a contract from the host with the given costack as its input, and runs it until it exits
+    /// System calls are passed to the handler, apart from calls to other contracts which are handled here
+    pub fn call(&mut self, handler: &mut dyn SystemCallHandler, id: u32, input: CoStack, gas: u64) -> CallResult{
+        let image = match self.contracts.get(&id){
+            Some(image) => image.clone(),
+            None => return CallResult{status: CallStatus::UnknownContract, output: CoStack::default(), gas_remaining: gas},
+        };
+        if self.depth > self.max_depth{
+            return CallResult{status: CallStatus::DepthExceeded, output: CoStack::default(), gas_remaining: gas};
+        }
+        handler.begin_call(id);
+        let mut neutron = Neutron{costack: input};
+        let mut vm = self.empty_vm(gas);
+        //if the memory of the VM could not be mapped, the callee never runs and only the gas charged for mapping is used
+        let status = match self.load_image(&mut vm, &image).and_then(|_| self.execute(&mut vm, &mut neutron, handler)){
+            Ok(0) => CallStatus::Success,
+            Ok(code) => CallStatus::Revert(code),
+            Err(e) => CallStatus::Fault(e),
+        };
+        let gas_remaining = vm.gas_remaining;
+        handler.end_call(status != CallStatus::Success);
+        let output = if matches!(status, CallStatus::Fault(_)) {CoStack::default()} else {neutron.costack};
+        CallResult{status, output, gas_remaining}
+    }
+    /// Executes a VM until it exits, handling calls to other contracts
+    fn execute(&mut self, vm: &mut NarmVM, neutron: &mut Neutron, handler: &mut dyn SystemCallHandler) -> Result<u32, NarmError>{
+        loop{
+            let svc = vm.execute()?;
+            if svc == SVC_SYSTEM_CALL && vm.external_get_reg(0) == CALL_FEATURE{
+                self.call_from_guest(vm, neutron, handler)?;
+            }else if let Some(code) = neutron.handle_service_call(vm, handler, svc)?{
+                return Ok(code);
+            }
+        }
+    }
+    /// Handles a call made by the contract running in vm to another contract
+    fn call_from_guest(&mut self, vm: &mut NarmVM, neutron: &mut Neutron, handler: &mut dyn SystemCallHandler) -> Result<(), NarmError>{
+        let id = neutron.costack.pop().and_then(|id| id.as_slice().try_into().ok()).ok_or(NarmError::InvalidContractCall)?;
+        let gas = neutron.costack.pop().and_then(|gas| gas.as_slice().try_into().ok()).ok_or(NarmError::InvalidContractCall)?;
+        let gas = u64::from_le_bytes(gas).min(vm.gas_remaining);
+        vm.gas_remaining -= gas;
+        let input = core::mem::take(&mut neutron.costack);
+        self.depth += 1;
+        let result = self.call(handler, u32::from_le_bytes(id), input, gas);
+        self.depth -= 1;
+        vm.gas_remaining += result.gas_remaining;
+        neutron.costack = result.output;
+        vm.external_set_reg(0, result.status.code());
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::*;
+use crate::instruction::ShiftType;
 
 //argument masks
 pub const MASK_R3_R3:u16        = 0b0000_0000_0011_1111;
@@ -13,6 +14,11 @@ pub const MASK_X1_RL8:u16       = 0b0000_0001_1111_1111;
 pub const MASK_IMM7:u16         = 0b0000_0000_0111_1111;
 
 pub const MASK32_X1_IMM10_X1_X1_IMM11:u32 = 0b0000_0111_1111_1111_0010_1111_1111_1111;
+pub const MASK32_X1_IMM4_IMM3_RD4_IMM8:u32 = 0b0000_0100_0000_1111_0111_1111_1111_1111;
+pub const MASK32_RN4_RT4_RD4_IMM8:u32 = 0b0000_0000_0000_1111_1111_1111_1111_1111;
+pub const MASK32_RN4_RT4_RD4:u32 = 0b0000_0000_0000_1111_1111_0000_0000_1111;
+pub const MASK32_RN4_RA4_RD4_RM4:u32 = 0b0000_0000_0000_1111_1111_1111_0000_1111;
+pub const MASK32_RN4_RD4_RM4:u32 = 0b0000_0000_0000_1111_0000_1111_0000_1111;
 
 
 
@@ -88,6 +94,152 @@ pub fn decode32_x1_imm10_x1_x1_imm11(opcode: u32) -> (bool, u32, bool, bool, u32
     ) 
 }
 
+pub fn decode32_rn4_rd4_rm4(opcode: u32) -> (usize, usize, usize){
+    (
+        ((opcode & 0b0000_0000_0000_1111_0000_0000_0000_0000) >> 16) as usize,
+        ((opcode & 0b0000_0000_0000_0000_0000_1111_0000_0000) >> 8) as usize,
+        (opcode & 0b0000_0000_0000_0000_0000_0000_0000_1111) as usize
+    )
+}
+
+pub fn decode32_rn4_ra4_rd4_rm4(opcode: u32) -> (usize, usize, usize, usize){
+    (
+        ((opcode & 0b0000_0000_0000_1111_0000_0000_0000_0000) >> 16) as usize,
+        ((opcode & 0b0000_0000_0000_0000_1111_0000_0000_0000) >> 12) as usize,
+        ((opcode & 0b0000_0000_0000_0000_0000_1111_0000_0000) >> 8) as usize,
+        (opcode & 0b0000_0000_0000_0000_0000_0000_0000_1111) as usize
+    )
+}
+
+/// Decodes the registers of the exclusive access instructions. Rd is in bits 8-11 for the word variants only
+pub fn decode32_rn4_rt4_rd4_imm8(opcode: u32) -> (usize, usize, usize, u32){
+    (
+        ((opcode & 0b0000_0000_0000_1111_0000_0000_0000_0000) >> 16) as usize,
+        ((opcode & 0b0000_0000_0000_0000_1111_0000_0000_0000) >> 12) as usize,
+        ((opcode & 0b0000_0000_0000_0000_0000_1111_0000_0000) >> 8) as usize,
+        opcode & 0b0000_0000_0000_0000_0000_0000_1111_1111
+    )
+}
+
+/// Decodes the 16 bit immediate of MOVW and MOVT, which is imm4:i:imm3:imm8
+pub fn decode32_x1_imm4_imm3_imm8(opcode: u32) -> u32{
+    ((opcode & 0b0000_0000_0000_1111_0000_0000_0000_0000) >> 4) |
+    ((opcode & 0b0000_0100_0000_0000_0000_0000_0000_0000) >> 15) |
+    ((opcode & 0b0000_0000_0000_0000_0111_0000_0000_0000) >> 4) |
+    (opcode & 0b0000_0000_0000_0000_0000_0000_1111_1111)
+}
+
+/// Decodes the 12 bit immediate of the Thumb-2 data processing instructions, which is i:imm3:imm8
+pub fn decode32_x1_imm3_imm8(opcode: u32) -> u32{
+    ((opcode & 0b0000_0100_0000_0000_0000_0000_0000_0000) >> 15) |
+    ((opcode & 0b0000_0000_0000_0000_0111_0000_0000_0000) >> 4) |
+    (opcode & 0b0000_0000_0000_0000_0000_0000_1111_1111)
+}
+
+/// Decodes the registers and shift of a Thumb-2 shifted register operand, returning (Rn, Rd, Rm, shift type, shift amount)
+pub fn decode32_rn4_imm3_rd4_imm2_type2_rm4(opcode: u32) -> (usize, usize, usize, ShiftType, u32){
+    let imm5 = ((opcode & 0b0000_0000_0000_0000_0111_0000_0000_0000) >> 10) |
+        ((opcode & 0b0000_0000_0000_0000_0000_0000_1100_0000) >> 6);
+    let (shift_type, shift) = decode_imm_shift((opcode & 0b0000_0000_0000_0000_0000_0000_0011_0000) >> 4, imm5);
+    (
+        ((opcode & 0b0000_0000_0000_1111_0000_0000_0000_0000) >> 16) as usize,
+        ((opcode & 0b0000_0000_0000_0000_0000_1111_0000_0000) >> 8) as usize,
+        (opcode & 0b0000_0000_0000_0000_0000_0000_0000_1111) as usize,
+        shift_type,
+        shift
+    )
+}
+
+/// ThumbExpandImm_C, expands the 12 bit modified immediate of the Thumb-2 data processing instructions
+/// Returns the carry out along with the immediate, or None if the carry flag is left unchanged
+pub fn thumb_expand_imm(imm12: u32) -> (u32, Option<bool>){
+    let imm8 = imm12 & 0xFF;
+    if imm12 & 0b1100_0000_0000 == 0{
+        let imm32 = match (imm12 >> 8) & 0b11{
+            0b00 => imm8,
+            0b01 => imm8 << 16 | imm8,
+            0b10 => imm8 << 24 | imm8 << 8,
+            _ => imm8 << 24 | imm8 << 16 | imm8 << 8 | imm8
+        };
+        (imm32, None)
+    }else{
+        let unrotated = 0x80 | (imm12 & 0x7F);
+        let imm32 = unrotated.rotate_right(imm12 >> 7);
+        (imm32, Some(imm32 & 0x8000_0000 != 0))
+    }
+}
+
+/// DecodeImmShift, converts the type and 5 bit immediate of a shifted register operand into a shift type and amount
+pub fn decode_imm_shift(shift_type: u32, imm5: u32) -> (ShiftType, u32){
+    match shift_type{
+        0b00 => (ShiftType::Lsl, imm5),
+        0b01 => (ShiftType::Lsr, if imm5 == 0 {32} else {imm5}),
+        0b10 => (ShiftType::Asr, if imm5 == 0 {32} else {imm5}),
+        _ => if imm5 == 0 {(ShiftType::Rrx, 1)} else {(ShiftType::Ror, imm5)}
+    }
+}
+
+/// Shift_C, shifts a value returning the result and the carry out. A shift amount of 0 returns the value and carry unchanged
+/// Amounts larger than 32 are only possible for shifts by a register, and shift out every bit
+pub fn shift_c(value: u32, shift_type: ShiftType, amount: u32, carry_in: bool) -> (u32, bool){
+    if amount == 0{
+        return (value, carry_in);
+    }
+    match shift_type{
+        ShiftType::Lsl => match amount{
+            1..=31 => (value << amount, (value >> (32 - amount)) & 1 != 0),
+            32 => (0, value & 1 != 0),
+            _ => (0, false)
+        },
+        ShiftType::Lsr => match amount{
+            1..=31 => (value >> amount, (value >> (amount - 1)) & 1 != 0),
+            32 => (0, value & 0x8000_0000 != 0),
+            _ => (0, false)
+        },
+        ShiftType::Asr => {
+            let amount = amount.min(32);
+            let result = ((value as i32) >> (amount.min(31))) as u32;
+            (result, ((value as i32) >> (amount - 1).min(31)) & 1 != 0)
+        },
+        ShiftType::Ror => {
+            let result = value.rotate_right(amount % 32);
+            (result, result & 0x8000_0000 != 0)
+        },
+        ShiftType::Rrx => ((value >> 1) | ((carry_in as u32) << 31), value & 1 != 0)
+    }
+}
+
 pub fn decode_imm7(opcode: u16) -> u32{
     (opcode & 0b0000_0000_0111_1111) as u32
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_thumb_expand_imm() {
+        assert_eq!(thumb_expand_imm(0x0AB), (0x0000_00AB, None));
+        assert_eq!(thumb_expand_imm(0x1AB), (0x00AB_00AB, None));
+        assert_eq!(thumb_expand_imm(0x2AB), (0xAB00_AB00, None));
+        assert_eq!(thumb_expand_imm(0x3AB), (0xABAB_ABAB, None));
+        //rotated forms always have the top bit of the unrotated value set
+        assert_eq!(thumb_expand_imm(0x400), (0x8000_0000, Some(true)));
+        assert_eq!(thumb_expand_imm(0x47F), (0xFF00_0000, Some(true)));
+        assert_eq!(thumb_expand_imm(0xFFF), (0x0000_01FE, Some(false)));
+    }
+    #[test]
+    fn test_shift_c() {
+        assert_eq!(decode_imm_shift(0b01, 0), (ShiftType::Lsr, 32));
+        assert_eq!(decode_imm_shift(0b11, 0), (ShiftType::Rrx, 1));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Lsl, 0, true), (0x8000_0001, true));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Lsl, 1, false), (0x0000_0002, true));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Lsl, 32, false), (0, true));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Lsl, 33, true), (0, false));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Lsr, 1, false), (0x4000_0000, true));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Lsr, 32, false), (0, true));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Asr, 4, false), (0xF800_0000, false));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Asr, 200, false), (0xFFFF_FFFF, true));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Ror, 1, false), (0xC000_0000, true));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Ror, 32, false), (0x8000_0001, true));
+        assert_eq!(shift_c(0x8000_0001, ShiftType::Rrx, 1, false), (0x4000_0000, true));
+    }
+}
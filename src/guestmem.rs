@@ -0,0 +1,238 @@
+use crate::narmvm::NarmVM;
+use crate::memory::WRITEABLE_MEMORY;
+use crate::NarmError;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+/*
+
+Helpers for host code which moves data in and out of guest memory, such as system call handlers reading arguments given
+as pointers. All of them check that the whole range is mapped before reading or writing anything, so a failed write leaves
+memory unchanged. Ranges may span several memory blocks, as long as the blocks are contiguous. Writes go through the same
+path as guest stores, so touched pages are charged gas and cached code is invalidated. Host code is only allowed to write
+to the writeable part of the address space, at or above WRITEABLE_MEMORY.
+
+Values are read and written in their little endian representation with the alignment rules of the ARM C ABI, so that a
+struct declared with impl_guest_pod! has the same layout as the equivalent C struct in the guest.
+
+*/
+
+/// Plain old data which can be copied to and from guest memory
+/// Implemented for integers and arrays of them, and for structs with impl_guest_pod!
+pub trait GuestPod: Sized{
+    /// The size in guest memory, including any padding at the end
+    const SIZE: usize;
+    /// The alignment in guest memory, which decides the padding between fields of a struct
+    const ALIGN: usize;
+    /// Reads the value from exactly SIZE bytes
+    fn read_guest(bytes: &[u8]) -> Self;
+    /// Writes the value to exactly SIZE bytes. Padding is left unchanged
+    fn write_guest(&self, bytes: &mut [u8]);
+}
+
+macro_rules! impl_guest_pod_integer{
+    ($($ty:ty),*) => {
+        $(
+            impl GuestPod for $ty{
+                const SIZE: usize = core::mem::size_of::<$ty>();
+                const ALIGN: usize = core::mem::size_of::<$ty>();
+                fn read_guest(bytes: &[u8]) -> Self{
+                    <$ty>::from_le_bytes(bytes.try_into().unwrap())
+                }
+                fn write_guest(&self, bytes: &mut [u8]){
+                    bytes.copy_from_slice(&self.to_le_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_guest_pod_integer!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+impl<T: GuestPod + Copy + Default, const N: usize> GuestPod for [T; N]{
+    const SIZE: usize = T::SIZE * N;
+    const ALIGN: usize = T::ALIGN;
+    fn read_guest(bytes: &[u8]) -> Self{
+        let mut values = [T::default(); N];
+        for (value, b) in values.iter_mut().zip(bytes.chunks_exact(T::SIZE)){
+            *value = T::read_guest(b);
+        }
+        values
+    }
+    fn write_guest(&self, bytes: &mut [u8]){
+        for (value, b) in self.iter().zip(bytes.chunks_exact_mut(T::SIZE)){
+            value.write_guest(b);
+        }
+    }
+}
+
+/// Rounds offset up to a multiple of align, for laying out the fields of a GuestPod struct
+pub const fn align_up(offset: usize, align: usize) -> usize{
+    offset.div_ceil(align) * align
+}
+
+/// Implements GuestPod for a struct, given its fields and their types in declaration order
+/// The layout matches a C struct in the guest, with each field aligned to its type and padding at the end up to the largest alignment
+/// For example `impl_guest_pod!(Header{ kind: u8, length: u32 });` for a struct Header with those two fields
+#[macro_export]
+macro_rules! impl_guest_pod{
+    ($name:ident { $($field:ident: $ty:ty),* $(,)? }) => {
+        impl $crate::guestmem::GuestPod for $name{
+            const ALIGN: usize = {
+                let mut align = 1;
+                $(
+                    if <$ty as $crate::guestmem::GuestPod>::ALIGN > align{
+                        align = <$ty as $crate::guestmem::GuestPod>::ALIGN;
+                    }
+                )*
+                align
+            };
+            const SIZE: usize = {
+                let mut offset = 0;
+                $(
+                    offset = $crate::guestmem::align_up(offset, <$ty as $crate::guestmem::GuestPod>::ALIGN);
+                    offset += <$ty as $crate::guestmem::GuestPod>::SIZE;
+                )*
+                $crate::guestmem::align_up(offset, <Self as $crate::guestmem::GuestPod>::ALIGN)
+            };
+            #[allow(unused_assignments)]
+            fn read_guest(bytes: &[u8]) -> Self{
+                let mut offset = 0;
+                $(
+                    offset = $crate::guestmem::align_up(offset, <$ty as $crate::guestmem::GuestPod>::ALIGN);
+                    let $field = <$ty as $crate::guestmem::GuestPod>::read_guest(&bytes[offset..offset + <$ty as $crate::guestmem::GuestPod>::SIZE]);
+                    offset += <$ty as $crate::guestmem::GuestPod>::SIZE;
+                )*
+                $name{ $($field),* }
+            }
+            #[allow(unused_assignments)]
+            fn write_guest(&self, bytes: &mut [u8]){
+                let mut offset = 0;
+                $(
+                    offset = $crate::guestmem::align_up(offset, <$ty as $crate::guestmem::GuestPod>::ALIGN);
+                    $crate::guestmem::GuestPod::write_guest(&self.$field, &mut bytes[offset..offset + <$ty as $crate::guestmem::GuestPod>::SIZE]);
+                    offset += <$ty as $crate::guestmem::GuestPod>::SIZE;
+                )*
+            }
+        }
+    };
+}
+
+/// Splits a range of guest memory into the parts within each 64Kb memory block
+/// A range which goes past the end of the address space fails at its last address, with the given error
+fn block_ranges(address: u32, size: u64, error: fn(u32) -> NarmError) -> Result<Vec<(u32, u32)>, NarmError>{
+    if address as u64 + size > 1 << 32{
+        return Err(error(u32::MAX));
+    }
+    let mut ranges = Vec::new();
+    let mut address = address;
+    let mut remaining = size;
+    while remaining > 0{
+        let size = remaining.min(0x1_0000 - (address & 0xFFFF) as u64);
+        ranges.push((address, size as u32));
+        address = address.wrapping_add(size as u32);
+        remaining -= size;
+    }
+    Ok(ranges)
+}
+
+impl NarmVM{
+    /// Reads size bytes of guest memory, which may span several contiguous memory blocks
+    /// Nothing is allocated unless all of the memory is mapped, so a size given by the guest can't cause a large allocation
+    pub fn read_bytes(&self, address: u32, size: usize) -> Result<Vec<u8>, NarmError>{
+        let parts = block_ranges(address, size as u64, NarmError::EmptyMemoryRead)?.into_iter()
+            .map(|(a, s)| self.memory.get_sized_memory(a, s))
+            .collect::<Result<Vec<&[u8]>, NarmError>>()?;
+        let mut data = Vec::with_capacity(size);
+        for part in parts{
+            data.extend_from_slice(part);
+        }
+        Ok(data)
+    }
+    /// Writes data to guest memory, which may span several contiguous memory blocks
+    /// Nothing is written unless all of the memory is mapped and writeable. Fails with ReadOnlyMemoryWrite for any range which starts below WRITEABLE_MEMORY
+    pub fn write_bytes(&mut self, address: u32, data: &[u8]) -> Result<(), NarmError>{
+        let ranges = block_ranges(address, data.len() as u64, NarmError::EmptyMemoryWrite)?;
+        if !data.is_empty() && address < WRITEABLE_MEMORY{
+            return Err(NarmError::ReadOnlyMemoryWrite(address));
+        }
+        for &(a, s) in ranges.iter(){
+            if !self.memory.section_exists(a){
+                return Err(NarmError::UnloadedMemoryWrite(a));
+            }
+            if self.memory.get_sized_memory(a, s).is_err(){
+                return Err(NarmError::EmptyMemoryWrite(a + s - 1));
+            }
+        }
+        let mut data = data;
+        for (a, s) in ranges{
+            let (part, rest) = data.split_at(s as usize);
+            self.memory.get_mut_sized_memory(a, s)?.copy_from_slice(part);
+            data = rest;
+        }
+        Ok(())
+    }
+    /// Reads a NUL terminated string of at most max_length bytes before the NUL. The NUL is not included in the result
+    /// Fails with InvalidGuestString if there is no NUL within max_length bytes
+    pub fn read_cstring(&self, address: u32, max_length: usize) -> Result<Vec<u8>, NarmError>{
+        let mut string = Vec::new();
+        //the NUL may be one byte past max_length, but not past the end of the address space
+        let size = (max_length as u64).saturating_add(1).min((1 << 32) - address as u64);
+        for (a, s) in block_ranges(address, size, NarmError::EmptyMemoryRead)?{
+            let memory = self.memory.get_memory(a)?;
+            let part = &memory[..memory.len().min(s as usize)];
+            match part.iter().position(|b| *b == 0){
+                Some(end) => {
+                    string.extend_from_slice(&part[..end]);
+                    return Ok(string);
+                },
+                None if part.len() < s as usize => return Err(NarmError::EmptyMemoryRead(a + part.len() as u32)),
+                None => string.extend_from_slice(part),
+            }
+        }
+        Err(NarmError::InvalidGuestString(address))
+    }
+    /// Writes a string followed by a NUL. Fails with InvalidGuestString if the string itself contains a NUL
+    pub fn write_cstring(&mut self, address: u32, string: &[u8]) -> Result<(), NarmError>{
+        if string.contains(&0){
+            return Err(NarmError::InvalidGuestString(address));
+        }
+        let mut data = Vec::with_capacity(string.len() + 1);
+        data.extend_from_slice(string);
+        data.push(0);
+        self.write_bytes(address, &data)
+    }
+    /// Reads a UTF-8 string of length bytes, such as one given as a pointer and length. Fails with InvalidGuestString if it is not valid UTF-8
+    pub fn read_utf8(&self, address: u32, length: usize) -> Result<String, NarmError>{
+        String::from_utf8(self.read_bytes(address, length)?).map_err(|_| NarmError::InvalidGuestString(address))
+    }
+    /// Reads a NUL terminated UTF-8 string, with the same rules as read_cstring
+    pub fn read_utf8_cstring(&self, address: u32, max_length: usize) -> Result<String, NarmError>{
+        String::from_utf8(self.read_cstring(address, max_length)?).map_err(|_| NarmError::InvalidGuestString(address))
+    }
+    /// Reads a value such as an integer or a struct declared with impl_guest_pod!
+    pub fn read_pod<T: GuestPod>(&self, address: u32) -> Result<T, NarmError>{
+        Ok(T::read_guest(&self.read_bytes(address, T::SIZE)?))
+    }
+    /// Writes a value such as an integer or a struct declared with impl_guest_pod!
+    /// Padding within the value is written as 0
+    pub fn write_pod<T: GuestPod>(&mut self, address: u32, value: &T) -> Result<(), NarmError>{
+        let mut data = vec![0; T::SIZE];
+        value.write_guest(&mut data);
+        self.write_bytes(address, &data)
+    }
+    /// Reads count consecutive values, such as an array of u16 or u32 given as a pointer and count
+    pub fn read_array<T: GuestPod>(&self, address: u32, count: usize) -> Result<Vec<T>, NarmError>{
+        let size = count.checked_mul(T::SIZE).ok_or(NarmError::EmptyMemoryRead(u32::MAX))?;
+        Ok(self.read_bytes(address, size)?.chunks_exact(T::SIZE).map(T::read_guest).collect())
+    }
+    /// Writes values consecutively, such as an array of u16 or u32
+    pub fn write_array<T: GuestPod>(&mut self, address: u32, values: &[T]) -> Result<(), NarmError>{
+        let mut data = vec![0; values.len() * T::SIZE];
+        for (value, bytes) in values.iter().zip(data.chunks_exact_mut(T::SIZE)){
+            value.write_guest(bytes);
+        }
+        self.write_bytes(address, &data)
+    }
+}
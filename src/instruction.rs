@@ -0,0 +1,1493 @@
+use crate::decode::*;
+use crate::bitmanip::*;
+
+/// The instruction set which is decoded and executed by NarmVM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IsaProfile{
+    /// Plain ARMv6-M, as implemented by the Cortex-M0
+    #[default]
+    ARMv6M,
+    /// ARMv6-M with the ARMv8-M baseline additions: CBZ, CBNZ, MOVW, MOVT, exclusive access, SDIV and UDIV
+    ARMv8MBaseline,
+    /// ARMv6-M with the ARMv7-M instructions which are supported: the ARMv8-M baseline additions, IT, the long multiplies and the common Thumb-2 instructions
+    ARMv7M,
+}
+
+impl IsaProfile{
+    /// CBZ, CBNZ, MOVW, MOVT, B.W, LDREX, STREX and CLREX
+    pub fn has_baseline_extensions(&self) -> bool{
+        matches!(self, IsaProfile::ARMv8MBaseline | IsaProfile::ARMv7M)
+    }
+    /// IT blocks
+    pub fn has_it(&self) -> bool{
+        *self == IsaProfile::ARMv7M
+    }
+    /// The Thumb-2 data processing, load/store, conditional branch and table branch instructions of ARMv7-M
+    pub fn has_thumb2(&self) -> bool{
+        *self == IsaProfile::ARMv7M
+    }
+    /// SDIV and UDIV, which are part of both ARMv8-M baseline and ARMv7-M
+    pub fn has_hardware_divide(&self) -> bool{
+        matches!(self, IsaProfile::ARMv8MBaseline | IsaProfile::ARMv7M)
+    }
+    /// MLA, MLS, UMULL, SMULL, UMLAL and SMLAL
+    pub fn has_long_multiply(&self) -> bool{
+        *self == IsaProfile::ARMv7M
+    }
+}
+
+/// Processor timing used to count the cycles taken by executed instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimingModel{
+    /// Cortex-M0 with the single cycle multiplier and zero wait state memory, as documented in the Cortex-M0 Technical Reference Manual
+    CortexM0,
+}
+
+/// Extra cycles taken on the Cortex-M0 by an instruction which writes PC, to refill the pipeline
+const CORTEX_M0_BRANCH_CYCLES: u32 = 2;
+
+/// The shift applied to a register operand, SRType in the Architecture Reference Manual
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftType{
+    Lsl,
+    Lsr,
+    Asr,
+    Ror,
+    /// Rotate right by one bit through the carry flag
+    Rrx,
+}
+
+/// A single decoded instruction
+/// Decoding is done once per instruction (and cached by NarmVM), so that executing it only involves the actual operation
+/// Register arguments are indexes into the register file, and immediates are already extended and shifted as the encoding requires
+/// Variant names follow the ARM mnemonic and encoding, so that the name of an instruction can be used for reporting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, IntoStaticStr)]
+pub enum Instruction{
+    //32 bit opcodes
+    /// BL T1, imm32 is relative to PC
+    BlT1{imm32: i32},
+    /// DMB T1, which is a NOP as memory accesses are never reordered
+    DmbT1{option: u8},
+    /// DSB T1, a NOP
+    DsbT1{option: u8},
+    /// ISB T1, a NOP
+    IsbT1{option: u8},
+    /// SDIV T1, only decoded for the ARMv8-M baseline and ARMv7-M profiles
+    SdivT1{rd: u8, rn: u8, rm: u8},
+    /// UDIV T1, only decoded for the ARMv8-M baseline and ARMv7-M profiles
+    UdivT1{rd: u8, rn: u8, rm: u8},
+    /// MLA T1, only decoded for the ARMv7-M profile
+    MlaT1{rd: u8, rn: u8, rm: u8, ra: u8},
+    /// MLS T1, only decoded for the ARMv7-M profile
+    MlsT1{rd: u8, rn: u8, rm: u8, ra: u8},
+    /// SMULL T1, only decoded for the ARMv7-M profile
+    SmullT1{rdlo: u8, rdhi: u8, rn: u8, rm: u8},
+    /// UMULL T1, only decoded for the ARMv7-M profile
+    UmullT1{rdlo: u8, rdhi: u8, rn: u8, rm: u8},
+    /// SMLAL T1, only decoded for the ARMv7-M profile
+    SmlalT1{rdlo: u8, rdhi: u8, rn: u8, rm: u8},
+    /// UMLAL T1, only decoded for the ARMv7-M profile
+    UmlalT1{rdlo: u8, rdhi: u8, rn: u8, rm: u8},
+
+    /// MOVW T3, only decoded with ARMv8-M baseline extensions
+    MovwT3{rd: u8, imm16: u32},
+    /// MOVT T1, only decoded with ARMv8-M baseline extensions
+    MovtT1{rd: u8, imm16: u32},
+    /// LDREX T1, imm32 is relative to Rn
+    LdrexT1{rt: u8, rn: u8, imm32: u32},
+    /// LDREXB T1
+    LdrexbT1{rt: u8, rn: u8},
+    /// LDREXH T1
+    LdrexhT1{rt: u8, rn: u8},
+    /// STREX T1, Rd receives 0 if the store was done or 1 if it failed
+    StrexT1{rd: u8, rt: u8, rn: u8, imm32: u32},
+    /// STREXB T1
+    StrexbT1{rd: u8, rt: u8, rn: u8},
+    /// STREXH T1
+    StrexhT1{rd: u8, rt: u8, rn: u8},
+    /// CLREX T1
+    ClrexT1,
+    /// B T4, only decoded with ARMv8-M baseline extensions. imm32 is relative to PC
+    BT4{imm32: i32},
+
+    //ARMv7-M profile, Thumb-2 data processing
+    //Logical operations take the carry out of the immediate expansion or shift, or leave the carry flag unchanged if it is None
+    /// AND (immediate) T1
+    AndImmT1{rd: u8, rn: u8, imm32: u32, carry: Option<bool>, setflags: bool},
+    /// TST (immediate) T1
+    TstImmT1{rn: u8, imm32: u32, carry: Option<bool>},
+    /// BIC (immediate) T1
+    BicImmT1{rd: u8, rn: u8, imm32: u32, carry: Option<bool>, setflags: bool},
+    /// ORR (immediate) T1
+    OrrImmT1{rd: u8, rn: u8, imm32: u32, carry: Option<bool>, setflags: bool},
+    /// MOV (immediate) T2
+    MovImmT2{rd: u8, imm32: u32, carry: Option<bool>, setflags: bool},
+    /// ORN (immediate) T1
+    OrnImmT1{rd: u8, rn: u8, imm32: u32, carry: Option<bool>, setflags: bool},
+    /// MVN (immediate) T1
+    MvnImmT1{rd: u8, imm32: u32, carry: Option<bool>, setflags: bool},
+    /// EOR (immediate) T1
+    EorImmT1{rd: u8, rn: u8, imm32: u32, carry: Option<bool>, setflags: bool},
+    /// TEQ (immediate) T1
+    TeqImmT1{rn: u8, imm32: u32, carry: Option<bool>},
+    /// ADD (immediate) T3, including ADD (SP plus immediate) T3
+    AddImmT3{rd: u8, rn: u8, imm32: u32, setflags: bool},
+    /// CMN (immediate) T1
+    CmnImmT1{rn: u8, imm32: u32},
+    /// ADC (immediate) T1
+    AdcImmT1{rd: u8, rn: u8, imm32: u32, setflags: bool},
+    /// SBC (immediate) T1
+    SbcImmT1{rd: u8, rn: u8, imm32: u32, setflags: bool},
+    /// SUB (immediate) T3, including SUB (SP minus immediate) T2
+    SubImmT3{rd: u8, rn: u8, imm32: u32, setflags: bool},
+    /// CMP (immediate) T2
+    CmpImmT2{rn: u8, imm32: u32},
+    /// RSB (immediate) T2
+    RsbImmT2{rd: u8, rn: u8, imm32: u32, setflags: bool},
+    /// ADDW, ADD (immediate) T4, including ADD (SP plus immediate) T4
+    AddImmT4{rd: u8, rn: u8, imm32: u32},
+    /// SUBW, SUB (immediate) T4, including SUB (SP minus immediate) T3
+    SubImmT4{rd: u8, rn: u8, imm32: u32},
+    /// ADR T2 and T3, imm32 is relative to Align(PC, 4) and is negative for T2
+    AdrT3{rd: u8, imm32: i32},
+    /// AND (register) T2
+    AndRegT2{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// TST (register) T2
+    TstRegT2{rn: u8, rm: u8, shift_type: ShiftType, shift: u32},
+    /// BIC (register) T2
+    BicRegT2{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// ORR (register) T2
+    OrrRegT2{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// MOV (register) T3, including LSL, LSR, ASR, ROR (immediate) T2 and RRX T1 which are MOV with a shifted register
+    MovRegT3{rd: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// ORN (register) T1
+    OrnRegT1{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// MVN (register) T2
+    MvnRegT2{rd: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// EOR (register) T2
+    EorRegT2{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// TEQ (register) T1
+    TeqRegT1{rn: u8, rm: u8, shift_type: ShiftType, shift: u32},
+    /// ADD (register) T3, including ADD (SP plus register) T3
+    AddRegT3{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// CMN (register) T2
+    CmnRegT2{rn: u8, rm: u8, shift_type: ShiftType, shift: u32},
+    /// ADC (register) T2
+    AdcRegT2{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// SBC (register) T2
+    SbcRegT2{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// SUB (register) T2, including SUB (SP minus register) T1
+    SubRegT2{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// CMP (register) T3
+    CmpRegT3{rn: u8, rm: u8, shift_type: ShiftType, shift: u32},
+    /// RSB (register) T1
+    RsbRegT1{rd: u8, rn: u8, rm: u8, shift_type: ShiftType, shift: u32, setflags: bool},
+    /// LSL (register) T2, shifts Rn by the bottom byte of Rm
+    LslRegT2{rd: u8, rn: u8, rm: u8, setflags: bool},
+    /// LSR (register) T2
+    LsrRegT2{rd: u8, rn: u8, rm: u8, setflags: bool},
+    /// ASR (register) T2
+    AsrRegT2{rd: u8, rn: u8, rm: u8, setflags: bool},
+    /// ROR (register) T2
+    RorRegT2{rd: u8, rn: u8, rm: u8, setflags: bool},
+    /// MUL T2
+    MulT2{rd: u8, rn: u8, rm: u8},
+    /// CLZ T1
+    ClzT1{rd: u8, rm: u8},
+    /// RBIT T1
+    RbitT1{rd: u8, rm: u8},
+    /// SXTB T2, Rm is rotated right by rotation before extending
+    SxtbT2{rd: u8, rm: u8, rotation: u32},
+    /// SXTH T2
+    SxthT2{rd: u8, rm: u8, rotation: u32},
+    /// UXTB T2
+    UxtbT2{rd: u8, rm: u8, rotation: u32},
+    /// UXTH T2
+    UxthT2{rd: u8, rm: u8, rotation: u32},
+    /// SBFX T1
+    SbfxT1{rd: u8, rn: u8, lsb: u32, width: u32},
+    /// UBFX T1
+    UbfxT1{rd: u8, rn: u8, lsb: u32, width: u32},
+    /// BFI T1
+    BfiT1{rd: u8, rn: u8, lsb: u32, msb: u32},
+    /// BFC T1
+    BfcT1{rd: u8, lsb: u32, msb: u32},
+
+    //ARMv7-M profile, Thumb-2 load/store
+    //The immediate forms include both the 12 bit positive offset and the 8 bit offset with pre/post indexing and writeback
+    //add selects adding or subtracting the offset, index selects whether the offset applies to the accessed address, and wback writes the offset address back to Rn
+    //Rn of 15 is the literal form, relative to Align(PC, 4)
+    /// LDR (immediate) T3 and T4, and LDR (literal) T2. Rt of 15 is a branch
+    LdrImmT4{rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// LDRB (immediate) T2 and T3, and LDRB (literal) T1
+    LdrbImmT3{rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// LDRH (immediate) T2 and T3, and LDRH (literal) T1
+    LdrhImmT3{rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// LDRSB (immediate) T1 and T2, and LDRSB (literal) T1
+    LdrsbImmT2{rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// LDRSH (immediate) T1 and T2, and LDRSH (literal) T1
+    LdrshImmT2{rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// STR (immediate) T3 and T4
+    StrImmT4{rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// STRB (immediate) T2 and T3
+    StrbImmT3{rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// STRH (immediate) T2 and T3
+    StrhImmT3{rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// LDR (register) T2, Rm is shifted left by shift. Rt of 15 is a branch
+    LdrRegT2{rt: u8, rn: u8, rm: u8, shift: u32},
+    /// LDRB (register) T2
+    LdrbRegT2{rt: u8, rn: u8, rm: u8, shift: u32},
+    /// LDRH (register) T2
+    LdrhRegT2{rt: u8, rn: u8, rm: u8, shift: u32},
+    /// LDRSB (register) T2
+    LdrsbRegT2{rt: u8, rn: u8, rm: u8, shift: u32},
+    /// LDRSH (register) T2
+    LdrshRegT2{rt: u8, rn: u8, rm: u8, shift: u32},
+    /// STR (register) T2
+    StrRegT2{rt: u8, rn: u8, rm: u8, shift: u32},
+    /// STRB (register) T2
+    StrbRegT2{rt: u8, rn: u8, rm: u8, shift: u32},
+    /// STRH (register) T2
+    StrhRegT2{rt: u8, rn: u8, rm: u8, shift: u32},
+    /// LDRD (immediate) T1, and LDRD (literal) T1
+    LdrdImmT1{rt: u8, rt2: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// STRD (immediate) T1
+    StrdImmT1{rt: u8, rt2: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool},
+    /// LDM T2, including POP T2. registers is a bit list of r0-r15, and PC is a branch
+    LdmT2{rn: u8, registers: u16, wback: bool},
+    /// LDMDB T1
+    LdmdbT1{rn: u8, registers: u16, wback: bool},
+    /// STM T2
+    StmT2{rn: u8, registers: u16, wback: bool},
+    /// STMDB T1, including PUSH T2
+    StmdbT1{rn: u8, registers: u16, wback: bool},
+
+    //ARMv7-M profile, Thumb-2 branches
+    /// B T3, imm32 is relative to PC
+    BT3{cond: u8, imm32: i32},
+    /// TBB T1, branches forward by twice the byte at Rn + Rm
+    TbbT1{rn: u8, rm: u8},
+    /// TBH T1, branches forward by twice the halfword at Rn + Rm * 2
+    TbhT1{rn: u8, rm: u8},
+
+    //ARMv8-M baseline and ARMv7-M profiles, 16 bit opcodes
+    /// CBZ T1, imm32 is relative to PC
+    CbzT1{rn: u8, imm32: u32},
+    /// CBNZ T1, imm32 is relative to PC
+    CbnzT1{rn: u8, imm32: u32},
+    /// IT T1, firstcond and mask are stored into ITSTATE as is
+    ItT1{firstcond: u8, mask: u8},
+
+    //NOP pattern
+    /// BKPT imm8
+    Bkpt{imm: u8},
+    /// NOP HINT catch all, including NOP, YIELD, WFE, WFI and SEV
+    Hint{hint: u8},
+    /// UDF T1, causes an error either way. opcode is the masked opcode reported in the error
+    UdfT1{opcode: u16},
+
+    //r3_imm8, r3_reglist and imm11
+    /// LDR lit T1, imm32 is relative to Align(PC, 4)
+    LdrLitT1{rt: u8, imm32: u32},
+    /// LDR imm T2, imm32 is relative to SP
+    LdrSpImmT2{rt: u8, imm32: u32},
+    /// MOV imm T1 flags
+    MovImmT1{rd: u8, imm: u32},
+    /// ADDS imm T2 flags
+    AddImmT2{rdn: u8, imm: u32},
+    /// ADD sp+imm T1 noflags
+    AddSpImmT1{rd: u8, imm32: u32},
+    /// ADR T1, imm32 is relative to Align(PC, 4)
+    AdrT1{rd: u8, imm32: u32},
+    /// CMP imm T1
+    CmpImmT1{rn: u8, imm: u32},
+    /// STR imm T2, imm32 is relative to SP
+    StrSpImmT2{rt: u8, imm32: u32},
+    /// SUBS imm T2 flags
+    SubImmT2{rdn: u8, imm: u32},
+    /// LDM T1
+    LdmT1{rn: u8, reglist: u8},
+    /// STM T1
+    StmT1{rn: u8, reglist: u8},
+    /// B T2, imm32 is relative to PC
+    BT2{imm32: i32},
+
+    //r3_r3, rdn is also the first operand unless noted
+    /// ADC reg T1 flags
+    AdcRegT1{rdn: u8, rm: u8},
+    /// AND reg T1 flags
+    AndRegT1{rdn: u8, rm: u8},
+    /// ASRS reg T1 flags
+    AsrRegT1{rdn: u8, rm: u8},
+    /// BICS T1 flags
+    BicRegT1{rdn: u8, rm: u8},
+    /// CMN T1 flags
+    CmnRegT1{rn: u8, rm: u8},
+    /// CMP reg T1
+    CmpRegT1{rn: u8, rm: u8},
+    /// EORS reg T1 flags
+    EorRegT1{rdn: u8, rm: u8},
+    /// LSL reg T1 flags
+    LslRegT1{rdn: u8, rm: u8},
+    /// LSR reg T1 flags
+    LsrRegT1{rdn: u8, rm: u8},
+    /// MOVS reg T2 flags
+    MovRegT2{rd: u8, rm: u8},
+    /// MUL T1 flags
+    MulT1{rdm: u8, rn: u8},
+    /// MVNS T1 flags
+    MvnRegT1{rd: u8, rm: u8},
+    /// ORRS reg T1 flags
+    OrrRegT1{rdn: u8, rm: u8},
+    /// REV T1
+    RevT1{rd: u8, rm: u8},
+    /// REV16 T1
+    Rev16T1{rd: u8, rm: u8},
+    /// REVSH T1
+    RevshT1{rd: u8, rm: u8},
+    /// ROR reg T1 flags
+    RorRegT1{rdn: u8, rm: u8},
+    /// RSB imm T1 flags (imm is forced to 0 for ARMv6-M)
+    RsbImmT1{rd: u8, rn: u8},
+    /// SBCS T1 flags
+    SbcRegT1{rdn: u8, rm: u8},
+    /// SXTB T1
+    SxtbT1{rd: u8, rm: u8},
+    /// SXTH T1
+    SxthT1{rd: u8, rm: u8},
+    /// TST reg T1 flags
+    TstRegT1{rn: u8, rm: u8},
+    /// UXTB T1
+    UxtbT1{rd: u8, rm: u8},
+    /// UXTH T1
+    UxthT1{rd: u8, rm: u8},
+
+    //r3_r3_r3 and imm3_r3_r3
+    /// ADDS reg T1 flags
+    AddRegT1{rd: u8, rn: u8, rm: u8},
+    /// LDR reg T1
+    LdrRegT1{rt: u8, rn: u8, rm: u8},
+    /// LDRB reg T1
+    LdrbRegT1{rt: u8, rn: u8, rm: u8},
+    /// LDRH reg T1
+    LdrhRegT1{rt: u8, rn: u8, rm: u8},
+    /// LDRSB reg T1
+    LdrsbRegT1{rt: u8, rn: u8, rm: u8},
+    /// LDRSH reg T1
+    LdrshRegT1{rt: u8, rn: u8, rm: u8},
+    /// STR reg T1
+    StrRegT1{rt: u8, rn: u8, rm: u8},
+    /// STRB reg T1
+    StrbRegT1{rt: u8, rn: u8, rm: u8},
+    /// STRH reg T1
+    StrhRegT1{rt: u8, rn: u8, rm: u8},
+    /// SUBS reg T1 flags
+    SubRegT1{rd: u8, rn: u8, rm: u8},
+    /// SUBS imm T1 flags
+    SubImmT1{rd: u8, rn: u8, imm: u32},
+    /// ADD imm T1 flags
+    AddImmT1{rd: u8, rn: u8, imm: u32},
+
+    //n1_r4_rn3
+    /// CMP reg T2
+    CmpRegT2{rn: u8, rm: u8},
+    /// ADD reg T2 noflags, including the ADD sp+reg T1 and T2 pseudo encodings
+    AddRegT2{rdn: u8, rm: u8},
+    /// MOV reg T1 noflags
+    MovRegT1{rd: u8, rm: u8},
+
+    //imm5_r3_r3
+    /// ASR imm T1 flags, shift is already decoded (0 means 32)
+    AsrImmT1{rd: u8, rm: u8, shift: u32},
+    /// LDR imm T1
+    LdrImmT1{rt: u8, rn: u8, imm32: u32},
+    /// LDRB imm T1
+    LdrbImmT1{rt: u8, rn: u8, imm32: u32},
+    /// LDRH imm T1
+    LdrhImmT1{rt: u8, rn: u8, imm32: u32},
+    /// LSL imm T1 flags
+    LslImmT1{rd: u8, rm: u8, shift: u32},
+    /// LSR imm T1 flags, shift is already decoded (0 means 32)
+    LsrImmT1{rd: u8, rm: u8, shift: u32},
+    /// STR imm T1
+    StrImmT1{rt: u8, rn: u8, imm32: u32},
+    /// STRB imm T1
+    StrbImmT1{rt: u8, rn: u8, imm32: u32},
+    /// STRH imm T1
+    StrhImmT1{rt: u8, rn: u8, imm32: u32},
+
+    //B<C> and SVC
+    /// B<c> T1, imm32 is relative to PC
+    BCondT1{cond: u8, imm32: i32},
+    /// SVC T1
+    Svc{imm: u8},
+
+    //x1_rl8
+    /// POP T1, pc is if PC should be popped
+    PopT1{reglist: u8, pc: bool},
+    /// PUSH T1, lr is if LR should be pushed
+    PushT1{reglist: u8, lr: bool},
+
+    //r4_q3
+    /// BX T1
+    BxT1{rm: u8},
+    /// BLX T1
+    BlxT1{rm: u8},
+
+    //imm7
+    /// ADD sp+imm T2 noflags
+    AddSpImmT2{imm32: u32},
+    /// SUB sp-imm T1 noflags
+    SubSpImmT1{imm32: u32},
+
+    /// Any 16 bit opcode which is not supported
+    Invalid(u16),
+    /// Any 32 bit opcode which is not supported
+    Invalid32(u32),
+}
+
+impl Instruction{
+    /// The name of the instruction variant, including encoding, for example "AddImmT2"
+    pub fn name(&self) -> &'static str{
+        self.into()
+    }
+    /// The gas charged for executing the instruction
+    pub fn gas_cost(&self) -> u64{
+        match self{
+            Instruction::SdivT1{..} | Instruction::UdivT1{..} => DIVISION_GAS,
+            Instruction::MlaT1{..} | Instruction::MlsT1{..} => MULTIPLY_ACCUMULATE_GAS,
+            Instruction::SmullT1{..} | Instruction::UmullT1{..} => LONG_MULTIPLY_GAS,
+            Instruction::SmlalT1{..} | Instruction::UmlalT1{..} => LONG_MULTIPLY_ACCUMULATE_GAS,
+            _ => 1
+        }
+    }
+    /// The number of cycles the instruction takes with the given timing model
+    /// taken is whether the instruction wrote PC, such as a conditional branch whose condition passed
+    /// Instructions which the Cortex-M0 lacks are timed as the closest instruction it has, and the multi-cycle multiplies and divisions take their gas cost
+    pub fn cycles(&self, model: TimingModel, taken: bool) -> u32{
+        use Instruction::*;
+        match model{
+            TimingModel::CortexM0 => {
+                let refill = if taken {CORTEX_M0_BRANCH_CYCLES} else {0};
+                match self{
+                    BlT1{..} => 4,
+                    //1 + N, with 3 more for popping PC
+                    PopT1{reglist, pc} => 1 + reglist.count_ones() + if *pc {3} else {0},
+                    PushT1{reglist, lr} => 1 + reglist.count_ones() + *lr as u32,
+                    LdmT1{reglist, ..} | StmT1{reglist, ..} => 1 + reglist.count_ones(),
+                    LdmT2{registers, ..} | LdmdbT1{registers, ..} => {
+                        1 + (registers & 0x7FFF).count_ones() + if registers & 0x8000 != 0 {3} else {0}
+                    },
+                    StmT2{registers, ..} | StmdbT1{registers, ..} => 1 + registers.count_ones(),
+                    LdrdImmT1{..} | StrdImmT1{..} => 3,
+                    DmbT1{..} | DsbT1{..} | IsbT1{..} => 4,
+                    SdivT1{..} | UdivT1{..} | MlaT1{..} | MlsT1{..} | SmullT1{..} | UmullT1{..} | SmlalT1{..} | UmlalT1{..} => {
+                        self.gas_cost() as u32
+                    },
+                    LdrLitT1{..} | LdrSpImmT2{..} | StrSpImmT2{..} |
+                    LdrImmT1{..} | LdrbImmT1{..} | LdrhImmT1{..} | StrImmT1{..} | StrbImmT1{..} | StrhImmT1{..} |
+                    LdrRegT1{..} | LdrbRegT1{..} | LdrhRegT1{..} | LdrsbRegT1{..} | LdrshRegT1{..} |
+                    StrRegT1{..} | StrbRegT1{..} | StrhRegT1{..} |
+                    LdrImmT4{..} | LdrbImmT3{..} | LdrhImmT3{..} | LdrsbImmT2{..} | LdrshImmT2{..} |
+                    StrImmT4{..} | StrbImmT3{..} | StrhImmT3{..} |
+                    LdrRegT2{..} | LdrbRegT2{..} | LdrhRegT2{..} | LdrsbRegT2{..} | LdrshRegT2{..} |
+                    StrRegT2{..} | StrbRegT2{..} | StrhRegT2{..} |
+                    LdrexT1{..} | LdrexbT1{..} | LdrexhT1{..} | StrexT1{..} | StrexbT1{..} | StrexhT1{..} |
+                    TbbT1{..} | TbhT1{..} => 2 + refill,
+                    //data processing, and branches which take 3 cycles when taken
+                    _ => 1 + refill
+                }
+            }
+        }
+    }
+    /// Checks if the instruction is a YIELD, WFE, WFI or SEV hint, which can yield to the host with NarmVM::set_yield_on_hints
+    pub fn is_wait_hint(&self) -> bool{
+        matches!(self, Instruction::Hint{hint: 0x10 | 0x20 | 0x30 | 0x40})
+    }
+    /// Checks if the instruction writes PC. Within an IT block, these are only allowed as the last instruction
+    pub fn is_branch(&self) -> bool{
+        use Instruction::*;
+        match self{
+            BlT1{..} | BT2{..} | BCondT1{..} | BxT1{..} | BlxT1{..} | CbzT1{..} | CbnzT1{..} => true,
+            BT3{..} | BT4{..} | TbbT1{..} | TbhT1{..} => true,
+            PopT1{pc, ..} => *pc,
+            LdmT2{registers, ..} | LdmdbT1{registers, ..} => registers & 0x8000 != 0,
+            LdrImmT4{rt, ..} | LdrRegT2{rt, ..} => *rt == 15,
+            MovRegT1{rd, ..} => *rd == 15,
+            AddRegT2{rdn, ..} => *rdn == 15,
+            _ => false
+        }
+    }
+    /// Checks if the instruction is a 16 bit data processing instruction which sets flags only outside of IT blocks
+    pub fn sets_flags_outside_it_block(&self) -> bool{
+        use Instruction::*;
+        matches!(self,
+            MovImmT1{..} | AddImmT1{..} | AddImmT2{..} | SubImmT1{..} | SubImmT2{..} | AddRegT1{..} | SubRegT1{..} |
+            AdcRegT1{..} | AndRegT1{..} | AsrRegT1{..} | BicRegT1{..} | EorRegT1{..} | LslRegT1{..} | LsrRegT1{..} |
+            MulT1{..} | MvnRegT1{..} | OrrRegT1{..} | RorRegT1{..} | RsbImmT1{..} | SbcRegT1{..} | MovRegT2{..} |
+            AsrImmT1{..} | LslImmT1{..} | LsrImmT1{..})
+    }
+}
+
+/// Gas charged for SDIV and UDIV, reflecting their multi-cycle cost on hardware
+pub const DIVISION_GAS: u64 = 4;
+/// Gas charged for MLA and MLS
+pub const MULTIPLY_ACCUMULATE_GAS: u64 = 2;
+/// Gas charged for UMULL and SMULL
+pub const LONG_MULTIPLY_GAS: u64 = 3;
+/// Gas charged for UMLAL and SMLAL
+pub const LONG_MULTIPLY_ACCUMULATE_GAS: u64 = 4;
+
+/// Decodes a 32 bit opcode, where the first halfword is in the top 16 bits
+pub fn decode_instruction32(opcode32: u32, profile: IsaProfile) -> Instruction{
+    let op32 = opcode32 & !MASK32_X1_IMM10_X1_X1_IMM11;
+    let (s, imm1, j1, j2, imm2) = decode32_x1_imm10_x1_x1_imm11(opcode32);
+    //BL T1, 32bit instruction. J is split into J1 and J2. x, y, and z is combined into one argument using all of the arguments together which control sign extension etc. Allows -16777216 to +16777214
+                   //1111_0xyy_yyyy_yyyy_11J1_Jzzz_zzzz_zzzz
+    //B T4 is encoded the same way, with 10J1_J instead
+    if op32 == 0b1111_0000_0000_0000_1101_0000_0000_0000 || op32 == 0b1111_0000_0000_0000_1001_0000_0000_0000{
+        //I1 = NOT(J1 EOR S);  I2 = NOT(J2 EOR S);  imm32 = SignExtend(S:I1:I2:imm10:imm11:'0', 32);
+        let s1 = s as u32;
+        let i1 = (!(j1 ^ s)) as u32;
+        let i2 = (!(j2 ^ s)) as u32;
+        let value =
+            s1      << 24 |  //1 bit (1+1+10+11+1)
+            i1      << 23 |  //1 bit (1+10+11+1)
+            i2      << 22 |  //1 bit (10+11+1)
+            imm1    << 12 | //10 bits (11+1)
+            imm2    << 1;   //11 bits, bottom bit is 0
+        //25 bits total length
+        let imm32 = sign_extend32(value, 25);
+        if op32 & 0b0000_0000_0000_0000_0100_0000_0000_0000 != 0{
+            return Instruction::BlT1{imm32};
+        }
+        if profile.has_baseline_extensions(){
+            return Instruction::BT4{imm32};
+        }
+        return Instruction::Invalid32(opcode32);
+    }
+    //1111_0011_1011_1111_1000_1111_0ooo_xxxx barriers, where ooo is 100 for DSB, 101 for DMB and 110 for ISB
+    let option = (opcode32 & 0b1111) as u8;
+    match opcode32 & !0b1111{
+        0b1111_0011_1011_1111_1000_1111_0100_0000 => return Instruction::DsbT1{option},
+        0b1111_0011_1011_1111_1000_1111_0101_0000 => return Instruction::DmbT1{option},
+        0b1111_0011_1011_1111_1000_1111_0110_0000 => return Instruction::IsbT1{option},
+        _ => {}
+    }
+    if profile.has_baseline_extensions(){
+        if let Some(instruction) = decode_baseline32(opcode32){
+            return instruction;
+        }
+    }
+    if profile.has_hardware_divide(){
+        let op32 = opcode32 & !MASK32_RN4_RD4_RM4;
+        let (rn, rd, rm) = decode32_rn4_rd4_rm4(opcode32);
+        //SP and PC are unpredictable for all registers
+        let valid = [rn, rd, rm].iter().all(|r| *r != 13 && *r != 15);
+        //1111_1011_1001_nnnn_1111_dddd_1111_mmmm SDIV T1
+        if op32 == 0b1111_1011_1001_0000_1111_0000_1111_0000 && valid{
+            return Instruction::SdivT1{rd: rd as u8, rn: rn as u8, rm: rm as u8};
+        }
+        //1111_1011_1011_nnnn_1111_dddd_1111_mmmm UDIV T1
+        if op32 == 0b1111_1011_1011_0000_1111_0000_1111_0000 && valid{
+            return Instruction::UdivT1{rd: rd as u8, rn: rn as u8, rm: rm as u8};
+        }
+    }
+    if profile.has_long_multiply(){
+        let op32 = opcode32 & !MASK32_RN4_RA4_RD4_RM4;
+        let (rn, ra, rd, rm) = decode32_rn4_ra4_rd4_rm4(opcode32);
+        //SP and PC are unpredictable for all registers
+        let valid = [rn, ra, rd, rm].iter().all(|r| *r != 13 && *r != 15);
+        let (rn, ra, rd, rm) = (rn as u8, ra as u8, rd as u8, rm as u8);
+        match op32{
+            //1111_1011_0000_nnnn_aaaa_dddd_0000_mmmm MLA T1 (Ra of 1111 is MUL T2, which is not supported)
+            0b1111_1011_0000_0000_0000_0000_0000_0000 if valid => return Instruction::MlaT1{rd, rn, rm, ra},
+            //1111_1011_0000_nnnn_aaaa_dddd_0001_mmmm MLS T1
+            0b1111_1011_0000_0000_0000_0000_0001_0000 if valid => return Instruction::MlsT1{rd, rn, rm, ra},
+            _ => {}
+        }
+        //for the long multiplies, Ra is RdLo and Rd is RdHi, which must be different registers
+        let valid = valid && ra != rd;
+        match op32{
+            //1111_1011_1000_nnnn_llll_hhhh_0000_mmmm SMULL T1
+            0b1111_1011_1000_0000_0000_0000_0000_0000 if valid => return Instruction::SmullT1{rdlo: ra, rdhi: rd, rn, rm},
+            //1111_1011_1010_nnnn_llll_hhhh_0000_mmmm UMULL T1
+            0b1111_1011_1010_0000_0000_0000_0000_0000 if valid => return Instruction::UmullT1{rdlo: ra, rdhi: rd, rn, rm},
+            //1111_1011_1100_nnnn_llll_hhhh_0000_mmmm SMLAL T1
+            0b1111_1011_1100_0000_0000_0000_0000_0000 if valid => return Instruction::SmlalT1{rdlo: ra, rdhi: rd, rn, rm},
+            //1111_1011_1110_nnnn_llll_hhhh_0000_mmmm UMLAL T1
+            0b1111_1011_1110_0000_0000_0000_0000_0000 if valid => return Instruction::UmlalT1{rdlo: ra, rdhi: rd, rn, rm},
+            _ => {}
+        }
+    }
+    if profile.has_thumb2(){
+        if let Some(instruction) = decode_thumb2(opcode32){
+            return instruction;
+        }
+    }
+    //later support MSR/MRS?
+    Instruction::Invalid32(opcode32)
+}
+
+/// Decodes the 32 bit Thumb-2 opcodes of the ARMv7-M profile. Unpredictable register combinations are not decoded
+fn decode_thumb2(opcode32: u32) -> Option<Instruction>{
+    //1111_0x0o_ooos_nnnn_0iii_dddd_iiii_iiii data processing (modified immediate)
+    if opcode32 & 0b1111_1010_0000_0000_1000_0000_0000_0000 == 0b1111_0000_0000_0000_0000_0000_0000_0000{
+        return decode_data_immediate32(opcode32);
+    }
+    //1111_0x1o_oooo_nnnn_0iii_dddd_iiii_iiii data processing (plain binary immediate)
+    if opcode32 & 0b1111_1010_0000_0000_1000_0000_0000_0000 == 0b1111_0010_0000_0000_0000_0000_0000_0000{
+        return decode_binary_immediate32(opcode32);
+    }
+    //1110_101o_ooos_nnnn_0iii_dddd_iitt_mmmm data processing (shifted register)
+    if opcode32 & 0b1111_1110_0000_0000_1000_0000_0000_0000 == 0b1110_1010_0000_0000_0000_0000_0000_0000{
+        return decode_data_register32(opcode32);
+    }
+    //1111_1010_xxxx_xxxx_1111_xxxx_xxxx_xxxx data processing (register)
+    if opcode32 & 0b1111_1111_0000_0000_1111_0000_0000_0000 == 0b1111_1010_0000_0000_1111_0000_0000_0000{
+        return decode_misc_register32(opcode32);
+    }
+    //1111_100x_xxxx_xxxx_xxxx_xxxx_xxxx_xxxx load/store single
+    if opcode32 & 0b1111_1110_0000_0000_0000_0000_0000_0000 == 0b1111_1000_0000_0000_0000_0000_0000_0000{
+        return decode_load_store32(opcode32);
+    }
+    //1110_100x_x1xx_xxxx_xxxx_xxxx_xxxx_xxxx load/store dual and table branch
+    if opcode32 & 0b1111_1110_0100_0000_0000_0000_0000_0000 == 0b1110_1000_0100_0000_0000_0000_0000_0000{
+        return decode_load_store_dual32(opcode32);
+    }
+    //1110_100x_x0xx_xxxx_xxxx_xxxx_xxxx_xxxx load/store multiple
+    if opcode32 & 0b1111_1110_0100_0000_0000_0000_0000_0000 == 0b1110_1000_0000_0000_0000_0000_0000_0000{
+        return decode_load_store_multiple32(opcode32);
+    }
+    //1111_1011_0000_nnnn_1111_dddd_0000_mmmm MUL T2
+    if opcode32 & !MASK32_RN4_RD4_RM4 == 0b1111_1011_0000_0000_1111_0000_0000_0000{
+        let (rn, rd, rm) = decode32_rn4_rd4_rm4(opcode32);
+        if [rn, rd, rm].iter().any(|r| *r == 13 || *r == 15){
+            return None;
+        }
+        return Some(Instruction::MulT2{rd: rd as u8, rn: rn as u8, rm: rm as u8});
+    }
+    //1111_0scc_ccii_iiii_10j0_jiii_iiii_iiii B T3, where a condition of 111x is a system instruction
+    if opcode32 & 0b1111_1000_0000_0000_1101_0000_0000_0000 == 0b1111_0000_0000_0000_1000_0000_0000_0000{
+        let cond = ((opcode32 >> 22) & 0b1111) as u8;
+        if cond >= 0b1110{
+            return None;
+        }
+        let (s, _, j1, j2, imm11) = decode32_x1_imm10_x1_x1_imm11(opcode32);
+        let imm6 = (opcode32 >> 16) & 0b11_1111;
+        //imm32 = SignExtend(S:J2:J1:imm6:imm11:'0', 32)
+        let value = (s as u32) << 20 | (j2 as u32) << 19 | (j1 as u32) << 18 | imm6 << 12 | imm11 << 1;
+        return Some(Instruction::BT3{cond, imm32: sign_extend32(value, 21)});
+    }
+    None
+}
+
+/// Decodes the opcode field shared by the modified immediate and shifted register data processing instructions
+/// Returns the operation, and whether it is a compare or test which has no destination register
+fn decode_data_op32(opcode32: u32, rd: usize, rn: usize) -> Option<(u32, bool)>{
+    let op = (opcode32 >> 21) & 0b1111;
+    let setflags = opcode32 & (1 << 20) != 0;
+    let compare = rd == 15 && setflags && matches!(op, 0b0000 | 0b0100 | 0b1000 | 0b1101);
+    let is_move = rn == 15 && matches!(op, 0b0010 | 0b0011);
+    //PC is unpredictable, and SP is only allowed as the destination of ADD and SUB with SP
+    if compare{
+        if rn == 15{
+            return None;
+        }
+    }else if rd == 15 || (rn == 15 && !is_move) || (rd == 13 && !(rn == 13 && matches!(op, 0b1000 | 0b1101))){
+        return None;
+    }
+    Some((op, compare))
+}
+
+/// Decodes the Thumb-2 data processing instructions with a modified immediate
+fn decode_data_immediate32(opcode32: u32) -> Option<Instruction>{
+    use Instruction::*;
+    let (rn, _, rd, _) = decode32_rn4_rt4_rd4_imm8(opcode32);
+    let (op, compare) = decode_data_op32(opcode32, rd, rn)?;
+    let setflags = opcode32 & (1 << 20) != 0;
+    let (imm32, carry) = thumb_expand_imm(decode32_x1_imm3_imm8(opcode32));
+    let (rd, rn) = (rd as u8, rn as u8);
+    match (op, compare, rn == 15){
+        (0b0000, true, _) => Some(TstImmT1{rn, imm32, carry}),
+        (0b0000, false, _) => Some(AndImmT1{rd, rn, imm32, carry, setflags}),
+        (0b0001, _, _) => Some(BicImmT1{rd, rn, imm32, carry, setflags}),
+        (0b0010, _, true) => Some(MovImmT2{rd, imm32, carry, setflags}),
+        (0b0010, _, false) => Some(OrrImmT1{rd, rn, imm32, carry, setflags}),
+        (0b0011, _, true) => Some(MvnImmT1{rd, imm32, carry, setflags}),
+        (0b0011, _, false) => Some(OrnImmT1{rd, rn, imm32, carry, setflags}),
+        (0b0100, true, _) => Some(TeqImmT1{rn, imm32, carry}),
+        (0b0100, false, _) => Some(EorImmT1{rd, rn, imm32, carry, setflags}),
+        (0b1000, true, _) => Some(CmnImmT1{rn, imm32}),
+        (0b1000, false, _) => Some(AddImmT3{rd, rn, imm32, setflags}),
+        (0b1010, _, _) => Some(AdcImmT1{rd, rn, imm32, setflags}),
+        (0b1011, _, _) => Some(SbcImmT1{rd, rn, imm32, setflags}),
+        (0b1101, true, _) => Some(CmpImmT2{rn, imm32}),
+        (0b1101, false, _) => Some(SubImmT3{rd, rn, imm32, setflags}),
+        (0b1110, _, _) => Some(RsbImmT2{rd, rn, imm32, setflags}),
+        _ => None
+    }
+}
+
+/// Decodes the Thumb-2 data processing instructions with a shifted register
+fn decode_data_register32(opcode32: u32) -> Option<Instruction>{
+    use Instruction::*;
+    let (rn, rd, rm, shift_type, shift) = decode32_rn4_imm3_rd4_imm2_type2_rm4(opcode32);
+    let (op, compare) = decode_data_op32(opcode32, rd, rn)?;
+    if rm == 15{
+        return None;
+    }
+    let setflags = opcode32 & (1 << 20) != 0;
+    let (rd, rn, rm) = (rd as u8, rn as u8, rm as u8);
+    match (op, compare, rn == 15){
+        (0b0000, true, _) => Some(TstRegT2{rn, rm, shift_type, shift}),
+        (0b0000, false, _) => Some(AndRegT2{rd, rn, rm, shift_type, shift, setflags}),
+        (0b0001, _, _) => Some(BicRegT2{rd, rn, rm, shift_type, shift, setflags}),
+        (0b0010, _, true) => Some(MovRegT3{rd, rm, shift_type, shift, setflags}),
+        (0b0010, _, false) => Some(OrrRegT2{rd, rn, rm, shift_type, shift, setflags}),
+        (0b0011, _, true) => Some(MvnRegT2{rd, rm, shift_type, shift, setflags}),
+        (0b0011, _, false) => Some(OrnRegT1{rd, rn, rm, shift_type, shift, setflags}),
+        (0b0100, true, _) => Some(TeqRegT1{rn, rm, shift_type, shift}),
+        (0b0100, false, _) => Some(EorRegT2{rd, rn, rm, shift_type, shift, setflags}),
+        (0b1000, true, _) => Some(CmnRegT2{rn, rm, shift_type, shift}),
+        (0b1000, false, _) => Some(AddRegT3{rd, rn, rm, shift_type, shift, setflags}),
+        (0b1010, _, _) => Some(AdcRegT2{rd, rn, rm, shift_type, shift, setflags}),
+        (0b1011, _, _) => Some(SbcRegT2{rd, rn, rm, shift_type, shift, setflags}),
+        (0b1101, true, _) => Some(CmpRegT3{rn, rm, shift_type, shift}),
+        (0b1101, false, _) => Some(SubRegT2{rd, rn, rm, shift_type, shift, setflags}),
+        (0b1110, _, _) => Some(RsbRegT1{rd, rn, rm, shift_type, shift, setflags}),
+        _ => None
+    }
+}
+
+/// Decodes the Thumb-2 data processing instructions with a plain 12 or 16 bit immediate, other than MOVW and MOVT
+fn decode_binary_immediate32(opcode32: u32) -> Option<Instruction>{
+    use Instruction::*;
+    let (rn, _, rd, _) = decode32_rn4_rt4_rd4_imm8(opcode32);
+    let op = (opcode32 >> 20) & 0b1_1111;
+    let imm12 = decode32_x1_imm3_imm8(opcode32);
+    //lsb and msb of the bitfield instructions are imm3:imm2 and the bottom 5 bits
+    let lsb = ((opcode32 >> 10) & 0b1_1100) | ((opcode32 >> 6) & 0b11);
+    let low = opcode32 & 0b1_1111;
+    let invalid = |r: usize| r == 13 || r == 15;
+    let (rd8, rn8) = (rd as u8, rn as u8);
+    match op{
+        //ADDW and SUBW, which are ADR with Rn of 15
+        0b0_0000 | 0b0_1010 => {
+            let subtract = op == 0b0_1010;
+            if rn == 15{
+                if invalid(rd){
+                    return None;
+                }
+                let imm32 = if subtract {-(imm12 as i32)} else {imm12 as i32};
+                return Some(AdrT3{rd: rd8, imm32});
+            }
+            if rd == 15 || (rd == 13 && rn != 13){
+                return None;
+            }
+            if subtract{
+                Some(SubImmT4{rd: rd8, rn: rn8, imm32: imm12})
+            }else{
+                Some(AddImmT4{rd: rd8, rn: rn8, imm32: imm12})
+            }
+        },
+        //SBFX and UBFX, where the bottom 5 bits are the width minus 1
+        0b1_0100 | 0b1_1100 => {
+            let width = low + 1;
+            if invalid(rd) || invalid(rn) || lsb + width > 32{
+                return None;
+            }
+            if op == 0b1_0100{
+                Some(SbfxT1{rd: rd8, rn: rn8, lsb, width})
+            }else{
+                Some(UbfxT1{rd: rd8, rn: rn8, lsb, width})
+            }
+        },
+        //BFI, which is BFC with Rn of 15
+        0b1_0110 => {
+            if invalid(rd) || rn == 13 || low < lsb{
+                return None;
+            }
+            if rn == 15{
+                Some(BfcT1{rd: rd8, lsb, msb: low})
+            }else{
+                Some(BfiT1{rd: rd8, rn: rn8, lsb, msb: low})
+            }
+        },
+        _ => None
+    }
+}
+
+/// Decodes the Thumb-2 single register loads and stores
+fn decode_load_store32(opcode32: u32) -> Option<Instruction>{
+    use Instruction::*;
+    //1111_100s_uzzl_nnnn_tttt_xxxx_xxxx_xxxx where s is sign extension, u selects the 12 bit offset, zz is the size and l is load
+    let (rn, rt, op2, imm8) = decode32_rn4_rt4_rd4_imm8(opcode32);
+    let signed = opcode32 & (1 << 24) != 0;
+    let imm12_form = opcode32 & (1 << 23) != 0;
+    let size = (opcode32 >> 21) & 0b11;
+    let load = opcode32 & (1 << 20) != 0;
+    if size == 0b11 || (signed && (!load || size == 0b10)){
+        return None;
+    }
+    let imm12 = opcode32 & 0b1111_1111_1111;
+    //(imm32, add, index, wback) for the immediate forms, or None for the register form
+    let immediate = if rn == 15{
+        //literal, where u selects adding the offset
+        if !load{
+            return None;
+        }
+        Some((imm12, imm12_form, true, false))
+    }else if imm12_form{
+        //xxxx_iiii_iiii_iiii
+        Some((imm12, true, true, false))
+    }else if op2 & 0b1000 != 0{
+        //1puw_iiii_iiii, where p is index, u is add and w is writeback
+        let (index, add, wback) = (op2 & 0b100 != 0, op2 & 0b10 != 0, op2 & 0b1 != 0);
+        if !index && !wback{
+            return None;
+        }
+        Some((imm8, add, index, wback))
+    }else if (opcode32 >> 6) & 0b11_1111 == 0{
+        //0000_00ss_mmmm
+        None
+    }else{
+        return None;
+    };
+    let (rn, rt) = (rn as u8, rt as u8);
+    //PC is a branch for word loads only, and SP is only allowed for word accesses
+    //PC as Rt of byte and halfword loads are the memory hints PLD and PLI, which are not supported
+    if rt == 15 && !(load && size == 0b10){
+        return None;
+    }
+    if rt == 13 && size != 0b10{
+        return None;
+    }
+    match immediate{
+        Some((imm32, add, index, wback)) => {
+            if wback && rn == rt{
+                return None;
+            }
+            Some(match (load, signed, size){
+                (true, false, 0b00) => LdrbImmT3{rt, rn, imm32, add, index, wback},
+                (true, false, 0b01) => LdrhImmT3{rt, rn, imm32, add, index, wback},
+                (true, false, _) => LdrImmT4{rt, rn, imm32, add, index, wback},
+                (true, true, 0b00) => LdrsbImmT2{rt, rn, imm32, add, index, wback},
+                (true, true, _) => LdrshImmT2{rt, rn, imm32, add, index, wback},
+                (false, _, 0b10) => StrImmT4{rt, rn, imm32, add, index, wback},
+                (false, _, 0b00) => StrbImmT3{rt, rn, imm32, add, index, wback},
+                (false, _, _) => StrhImmT3{rt, rn, imm32, add, index, wback},
+            })
+        },
+        None => {
+            let rm = (opcode32 & 0b1111) as u8;
+            let shift = (opcode32 >> 4) & 0b11;
+            if rm == 13 || rm == 15{
+                return None;
+            }
+            Some(match (load, signed, size){
+                (true, false, 0b00) => LdrbRegT2{rt, rn, rm, shift},
+                (true, false, 0b01) => LdrhRegT2{rt, rn, rm, shift},
+                (true, false, _) => LdrRegT2{rt, rn, rm, shift},
+                (true, true, 0b00) => LdrsbRegT2{rt, rn, rm, shift},
+                (true, true, _) => LdrshRegT2{rt, rn, rm, shift},
+                (false, _, 0b10) => StrRegT2{rt, rn, rm, shift},
+                (false, _, 0b00) => StrbRegT2{rt, rn, rm, shift},
+                (false, _, _) => StrhRegT2{rt, rn, rm, shift},
+            })
+        }
+    }
+}
+
+/// Decodes LDRD, STRD, TBB and TBH. The exclusive loads and stores in the same group are decoded with the ARMv8-M baseline instructions
+fn decode_load_store_dual32(opcode32: u32) -> Option<Instruction>{
+    use Instruction::*;
+    //1110_100p_u1wl_nnnn_tttt_TTTT_iiii_iiii where T is Rt2
+    let (rn, rt, rt2, imm8) = decode32_rn4_rt4_rd4_imm8(opcode32);
+    let index = opcode32 & (1 << 24) != 0;
+    let add = opcode32 & (1 << 23) != 0;
+    let wback = opcode32 & (1 << 21) != 0;
+    let load = opcode32 & (1 << 20) != 0;
+    if !index && !wback{
+        //1110_1000_1101_nnnn_1111_0000_000h_mmmm TBB/TBH T1
+        if opcode32 & !0b0000_0000_0000_1111_0000_0000_0001_1111 == 0b1110_1000_1101_0000_1111_0000_0000_0000{
+            let rm = opcode32 & 0b1111;
+            if rn == 13 || rm == 13 || rm == 15{
+                return None;
+            }
+            let (rn, rm) = (rn as u8, rm as u8);
+            if opcode32 & 0b1_0000 != 0{
+                return Some(TbhT1{rn, rm});
+            }
+            return Some(TbbT1{rn, rm});
+        }
+        return None;
+    }
+    if [rt, rt2].iter().any(|r| *r == 13 || *r == 15) || (wback && (rn == rt || rn == rt2 || rn == 15)){
+        return None;
+    }
+    let (rt, rt2, rn, imm32) = (rt as u8, rt2 as u8, rn as u8, imm8 << 2);
+    if load{
+        if rt == rt2{
+            return None;
+        }
+        Some(LdrdImmT1{rt, rt2, rn, imm32, add, index, wback})
+    }else{
+        if rn == 15{
+            return None;
+        }
+        Some(StrdImmT1{rt, rt2, rn, imm32, add, index, wback})
+    }
+}
+
+/// Decodes the Thumb-2 load and store multiple instructions
+fn decode_load_store_multiple32(opcode32: u32) -> Option<Instruction>{
+    use Instruction::*;
+    //1110_100o_o0wl_nnnn_pm0r_rrrr_rrrr_rrrr where oo is 01 for increment after and 10 for decrement before
+    let rn = ((opcode32 >> 16) & 0b1111) as u8;
+    let wback = opcode32 & (1 << 21) != 0;
+    let load = opcode32 & (1 << 20) != 0;
+    let registers = (opcode32 & 0xFFFF) as u16;
+    //SP is never allowed in the list, PC is only allowed for loads, and only one of PC and LR may be loaded
+    if rn == 15 || registers & (1 << 13) != 0 || registers == 0 || (wback && registers & (1 << rn) != 0){
+        return None;
+    }
+    if load && registers & 0xC000 == 0xC000{
+        return None;
+    }
+    if !load && registers & 0x8000 != 0{
+        return None;
+    }
+    match ((opcode32 >> 23) & 0b11, load){
+        (0b01, true) => Some(LdmT2{rn, registers, wback}),
+        (0b01, false) => Some(StmT2{rn, registers, wback}),
+        (0b10, true) => Some(LdmdbT1{rn, registers, wback}),
+        (0b10, false) => Some(StmdbT1{rn, registers, wback}),
+        _ => None
+    }
+}
+
+/// Decodes the Thumb-2 register shifts, extends and bit operations
+fn decode_misc_register32(opcode32: u32) -> Option<Instruction>{
+    use Instruction::*;
+    let (rn, rd, rm) = decode32_rn4_rd4_rm4(opcode32);
+    if [rd, rm].iter().any(|r| *r == 13 || *r == 15){
+        return None;
+    }
+    let (rn8, rd, rm8) = (rn as u8, rd as u8, rm as u8);
+    //1111_1010_oooo_nnnn_1111_dddd_pppp_mmmm where o is op1 and p is op2
+    let op1 = (opcode32 >> 20) & 0b1111;
+    let op2 = (opcode32 >> 4) & 0b1111;
+    //the rotation of the extends is the bottom 2 bits of op2
+    let rotation = (op2 & 0b11) * 8;
+    match (op1, op2){
+        //0tts, 0000 LSL, LSR, ASR, ROR (register) T2
+        (0b0000..=0b0111, 0b0000) => {
+            if rn == 13 || rn == 15{
+                return None;
+            }
+            let setflags = op1 & 1 != 0;
+            match op1 >> 1{
+                0b00 => Some(LslRegT2{rd, rn: rn8, rm: rm8, setflags}),
+                0b01 => Some(LsrRegT2{rd, rn: rn8, rm: rm8, setflags}),
+                0b10 => Some(AsrRegT2{rd, rn: rn8, rm: rm8, setflags}),
+                _ => Some(RorRegT2{rd, rn: rn8, rm: rm8, setflags})
+            }
+        },
+        //0000, 0001, 0100, 0101 with 10rr and Rn of 15 SXTH, UXTH, SXTB, UXTB T2
+        (0b0000, 0b1000..=0b1011) if rn == 15 => Some(SxthT2{rd, rm: rm8, rotation}),
+        (0b0001, 0b1000..=0b1011) if rn == 15 => Some(UxthT2{rd, rm: rm8, rotation}),
+        (0b0100, 0b1000..=0b1011) if rn == 15 => Some(SxtbT2{rd, rm: rm8, rotation}),
+        (0b0101, 0b1000..=0b1011) if rn == 15 => Some(UxtbT2{rd, rm: rm8, rotation}),
+        //1001, 1010 RBIT T1, where Rm is repeated in the Rn field
+        (0b1001, 0b1010) if rn == rm => Some(RbitT1{rd, rm: rm8}),
+        //1011, 1000 CLZ T1
+        (0b1011, 0b1000) if rn == rm => Some(ClzT1{rd, rm: rm8}),
+        _ => None
+    }
+}
+
+/// Decodes the 32 bit opcodes added by the ARMv8-M baseline profile
+fn decode_baseline32(opcode32: u32) -> Option<Instruction>{
+    let (rn, rt, rd, imm8) = decode32_rn4_rt4_rd4_imm8(opcode32);
+    let low_valid = |r: usize| r != 13 && r != 15;
+    //1111_0x10_0100_yyyy_0zzz_dddd_wwww_wwww MOVW T3
+    //1111_0x10_1100_yyyy_0zzz_dddd_wwww_wwww MOVT T1
+    let op32 = opcode32 & !MASK32_X1_IMM4_IMM3_RD4_IMM8;
+    if op32 == 0b1111_0010_0100_0000_0000_0000_0000_0000 || op32 == 0b1111_0010_1100_0000_0000_0000_0000_0000{
+        if !low_valid(rd){
+            return None;
+        }
+        let imm16 = decode32_x1_imm4_imm3_imm8(opcode32);
+        let rd = rd as u8;
+        if op32 & 0b0000_0000_1000_0000_0000_0000_0000_0000 == 0{
+            return Some(Instruction::MovwT3{rd, imm16});
+        }
+        return Some(Instruction::MovtT1{rd, imm16});
+    }
+    //1111_0011_1011_1111_1000_1111_0010_1111 CLREX T1
+    if opcode32 == 0b1111_0011_1011_1111_1000_1111_0010_1111{
+        return Some(Instruction::ClrexT1);
+    }
+    let (rn, rt, rd) = (rn as u8, rt as u8, rd as u8);
+    let rt_valid = low_valid(rt as usize) && rn != 15;
+    match opcode32 & !MASK32_RN4_RT4_RD4_IMM8{
+        //1110_1000_0101_nnnn_tttt_1111_iiii_iiii LDREX T1
+        0b1110_1000_0101_0000_0000_0000_0000_0000 if rd == 0b1111 && rt_valid => {
+            return Some(Instruction::LdrexT1{rt, rn, imm32: imm8 << 2});
+        },
+        //1110_1000_0100_nnnn_tttt_dddd_iiii_iiii STREX T1
+        0b1110_1000_0100_0000_0000_0000_0000_0000 if rt_valid && low_valid(rd as usize) && rd != rn && rd != rt => {
+            return Some(Instruction::StrexT1{rd, rt, rn, imm32: imm8 << 2});
+        },
+        _ => {}
+    }
+    //for the byte and halfword variants, Rd is in the bottom 4 bits
+    let rd = (imm8 & 0b1111) as u8;
+    match opcode32 & !MASK32_RN4_RT4_RD4{
+        //1110_1000_1101_nnnn_tttt_1111_0100_1111 LDREXB T1
+        0b1110_1000_1101_0000_0000_1111_0100_0000 if rd == 0b1111 && rt_valid => Some(Instruction::LdrexbT1{rt, rn}),
+        //1110_1000_1101_nnnn_tttt_1111_0101_1111 LDREXH T1
+        0b1110_1000_1101_0000_0000_1111_0101_0000 if rd == 0b1111 && rt_valid => Some(Instruction::LdrexhT1{rt, rn}),
+        //1110_1000_1100_nnnn_tttt_1111_0100_dddd STREXB T1
+        0b1110_1000_1100_0000_0000_1111_0100_0000 if rt_valid && low_valid(rd as usize) && rd != rn && rd != rt => {
+            Some(Instruction::StrexbT1{rd, rt, rn})
+        },
+        //1110_1000_1100_nnnn_tttt_1111_0101_dddd STREXH T1
+        0b1110_1000_1100_0000_0000_1111_0101_0000 if rt_valid && low_valid(rd as usize) && rd != rn && rd != rt => {
+            Some(Instruction::StrexhT1{rd, rt, rn})
+        },
+        _ => None
+    }
+}
+
+/// Decodes a 16 bit opcode. Note that the order of the opcode groups here is significant, as some encodings overlap
+pub fn decode_instruction(opcode: u16, profile: IsaProfile) -> Instruction{
+    //ARMv8-M baseline and ARMv7-M profiles
+    if profile.has_baseline_extensions(){
+        //1011_x0y1_yyyy_yzzz CBZ/CBNZ T1, x is set for CBNZ
+        if opcode & 0b1111_0101_0000_0000 == 0b1011_0001_0000_0000{
+            let rn = (opcode & 0b111) as u8;
+            let imm32 = (((opcode & 0b0000_0010_0000_0000) >> 3) | ((opcode & 0b0000_0000_1111_1000) >> 2)) as u32;
+            if opcode & 0b0000_1000_0000_0000 == 0{
+                return Instruction::CbzT1{rn, imm32};
+            }
+            return Instruction::CbnzT1{rn, imm32};
+        }
+    }
+    //ARMv7-M profile
+    if profile.has_it(){
+        //1011_1111_cccc_mmmm IT T1, with a mask of 0 being a hint instead
+        if opcode & 0b1111_1111_0000_0000 == 0b1011_1111_0000_0000 && opcode & 0b1111 != 0{
+            let firstcond = ((opcode & 0b1111_0000) >> 4) as u8;
+            let mask = (opcode & 0b1111) as u8;
+            //firstcond of 1111, or an AL block with any else conditions, is unpredictable
+            if firstcond == 0b1111 || (firstcond == 0b1110 && mask.count_ones() != 1){
+                return Instruction::Invalid(opcode);
+            }
+            return Instruction::ItT1{firstcond, mask};
+        }
+    }
+    //NOP pattern
+    {
+        let op = opcode & !MASK_NOP;
+
+        //1011_1110_xxxx_xxxx BKPT imm8
+        if op == 0b1011_1110_0000_0000{
+            return Instruction::Bkpt{imm: (opcode & 0xFF) as u8};
+        }
+
+        //1011_1111_1QQQ_QQQQ NOP HINT catch all (can be safely treated as imm8
+        //1011_1111_0000_0000 NOP T1
+        //1011_1111_0100_0000 SEV nop
+        //1011_1111_0010_0000 WFE T1 nop
+        //1011_1111_0011_0000 WFI T1 nop
+        //1011_1111_0001_0000 YIELD T1 nop
+        if op == 0b1011_1111_0000_0000{
+            return Instruction::Hint{hint: (opcode & 0xFF) as u8};
+        }
+        //1101_1110_QQQQ_QQQQ UDF error T1, causes error either way
+        if op == 0b1101_1110_0000_0000{
+            return Instruction::UdfT1{opcode: op};
+        }
+    }
+
+    //opcodes for r3_imm8, r3_reglist, and imm11
+    {
+        let op = opcode & !MASK_R3_IMM8;
+        let (reg, imm) = decode_r3_imm8(opcode);
+        let reg = reg as u8;
+        match op{
+            //0100_1xxx_yyyy_yyyy LDR lit T1
+            0b0100_1000_0000_0000 => return Instruction::LdrLitT1{rt: reg, imm32: (imm as u32) << 2},
+            //1001_1xxx_yyyy_yyyy LDR imm T2
+            0b1001_1000_0000_0000 => return Instruction::LdrSpImmT2{rt: reg, imm32: (imm as u32) << 2},
+            //0010_0xxx_yyyy_yyyy MOV imm T1 flags
+            0b0010_0000_0000_0000 => return Instruction::MovImmT1{rd: reg, imm: imm as u32},
+            //0011_0xxx_yyyy_yyyy ADDS imm T2 flags
+            0b0011_0000_0000_0000 => return Instruction::AddImmT2{rdn: reg, imm: imm as u32},
+            //1010_1xxx_yyyy_yyyy ADD sp+imm T1 noflags
+            0b1010_1000_0000_0000 => return Instruction::AddSpImmT1{rd: reg, imm32: (imm as u32) << 2},
+            //1010_0xxx_yyyy_yyyy ADR T1
+            0b1010_0000_0000_0000 => return Instruction::AdrT1{rd: reg, imm32: (imm as u32) << 2},
+            //0010_1xxx_yyyy_yyyy CMP imm T1
+            0b0010_1000_0000_0000 => return Instruction::CmpImmT1{rn: reg, imm: imm as u32},
+            //1001_0xxx_yyyy_yyyy STR imm T2
+            0b1001_0000_0000_0000 => return Instruction::StrSpImmT2{rt: reg, imm32: (imm as u32) << 2},
+            //0011_1xxx_yyyy_yyyy SUBS imm T2 flags
+            0b0011_1000_0000_0000 => return Instruction::SubImmT2{rdn: reg, imm: imm as u32},
+            //1100_1xxx_yyyy_yyyy LDM T1
+            0b1100_1000_0000_0000 => return Instruction::LdmT1{rn: reg, reglist: imm},
+            //1100_0xxx_yyyy_yyyy STM T1
+            0b1100_0000_0000_0000 => return Instruction::StmT1{rn: reg, reglist: imm},
+            //1110_0xxx_xxxx_xxxx B T2
+            0b1110_0000_0000_0000 => {
+                let label = sign_extend32((((reg as u32) << 8) | (imm as u32)) << 1, 12);
+                return Instruction::BT2{imm32: label};
+            },
+            _ => {}
+        }
+    }
+
+    //opcodes for r3_r3
+    {
+        let op = opcode & !MASK_R3_R3;
+        let (reg1, reg2) = decode_r3_r3(opcode);
+        let rm = reg1 as u8;
+        let rdn = reg2 as u8;
+        match op{
+            //0100_0001_01xx_xyyy ADC reg T1 flags
+            0b0100_0001_0100_0000 => return Instruction::AdcRegT1{rdn, rm},
+            //0100_0000_00xx_xyyy AND reg T1 flags
+            0b0100_0000_0000_0000 => return Instruction::AndRegT1{rdn, rm},
+            //0100_0001_00xx_xyyy ASRS reg T1 flags
+            0b0100_0001_0000_0000 => return Instruction::AsrRegT1{rdn, rm},
+            //0100_0011_10xx_xyyy BICS T1 flags
+            0b0100_0011_1000_0000 => return Instruction::BicRegT1{rdn, rm},
+            //0100_0010_11xx_xyyy CMN T1 flags
+            0b0100_0010_1100_0000 => return Instruction::CmnRegT1{rn: rdn, rm},
+            //0100_0010_10xx_xyyy CMP reg T1
+            0b0100_0010_1000_0000 => return Instruction::CmpRegT1{rn: rdn, rm},
+            //0100_0000_01xx_xyyy EORS reg T1 flags
+            0b0100_0000_0100_0000 => return Instruction::EorRegT1{rdn, rm},
+            //0100_0000_10xx_xyyy LSL reg T1 flags
+            0b0100_0000_1000_0000 => return Instruction::LslRegT1{rdn, rm},
+            //0100_0000_11xx_xyyy LSR reg T1 flags
+            0b0100_0000_1100_0000 => return Instruction::LsrRegT1{rdn, rm},
+            //0100_0110_ZZxx_xyyy MOV reg T1 noflags (Z is to only access r0-r7 for ARMv6-M)
+            0b0100_0110_0000_0000 => return Instruction::MovRegT1{rd: rdn, rm},
+            //0000_0000_00xx_xyyy MOVS reg T2 flags
+            //NOTE: this shares the same identifying "mask" as LSL imm T1.
+            0b0000_0000_0000_0000 => return Instruction::MovRegT2{rd: rdn, rm},
+            //0100_0011_01xx_xyyy MUL T1 flags
+            0b0100_0011_0100_0000 => return Instruction::MulT1{rdm: rdn, rn: rm},
+            //0100_0011_11xx_xyyy MVNS T1 flags
+            0b0100_0011_1100_0000 => return Instruction::MvnRegT1{rd: rdn, rm},
+            //0100_0011_00xx_xyyy ORRS reg T1 flags
+            0b0100_0011_0000_0000 => return Instruction::OrrRegT1{rdn, rm},
+            //1011_1010_00xx_xyyy REV T1
+            0b1011_1010_0000_0000 => return Instruction::RevT1{rd: rdn, rm},
+            //1011_1010_01xx_xyyy REV16 T1
+            0b1011_1010_0100_0000 => return Instruction::Rev16T1{rd: rdn, rm},
+            //1011_1010_11xx_xyyy REVSH T1
+            0b1011_1010_1100_0000 => return Instruction::RevshT1{rd: rdn, rm},
+            //0100_0001_11xx_xyyy ROR reg T1 flags
+            0b0100_0001_1100_0000 => return Instruction::RorRegT1{rdn, rm},
+            //0100_0010_01xx_xyyy RSB imm T1 flags (ntoe: imm is forced to 0 for ARMv6-M)
+            0b0100_0010_0100_0000 => return Instruction::RsbImmT1{rd: rdn, rn: rm},
+            //0100_0001_10xx_xyyy SBCS T1 flags
+            0b0100_0001_1000_0000 => return Instruction::SbcRegT1{rdn, rm},
+            //1011_0010_01xx_xyyy SXTB T1
+            0b1011_0010_0100_0000 => return Instruction::SxtbT1{rd: rdn, rm},
+            //1011_0010_00xx_xyyy SXTH T1
+            0b1011_0010_0000_0000 => return Instruction::SxthT1{rd: rdn, rm},
+            //0100_0010_00xx_xyyy TST reg T1 flags
+            0b0100_0010_0000_0000 => return Instruction::TstRegT1{rn: rdn, rm},
+            //1011_0010_11xx_xyyy UXTB T1
+            0b1011_0010_1100_0000 => return Instruction::UxtbT1{rd: rdn, rm},
+            //1011_0010_10xx_xyyy UXTH T1
+            0b1011_0010_1000_0000 => return Instruction::UxthT1{rd: rdn, rm},
+            _ => {}
+        }
+    }
+
+    //opcodes for r3_r3_r3 and imm3_r3_r3
+    {
+        let op = opcode & !MASK_R3_R3_R3;
+        let (reg1, reg2, reg3) = decode_r3_r3_r3(opcode);
+        let rm = reg1 as u8;
+        let rn = reg2 as u8;
+        //reg3 is almost always destination register
+        let rd = reg3 as u8;
+        match op{
+            //0001_100x_xxyy_yzzz ADDS reg T1 flags
+            0b0001_1000_0000_0000 => return Instruction::AddRegT1{rd, rn, rm},
+            //0101_100x_xxyy_yzzz LDR reg T1
+            0b0101_1000_0000_0000 => return Instruction::LdrRegT1{rt: rd, rn, rm},
+            //0101_110x_xxyy_yzzz LDRB reg T1
+            0b0101_1100_0000_0000 => return Instruction::LdrbRegT1{rt: rd, rn, rm},
+            //0101_101x_xxyy_yzzz LDRH reg T1
+            0b0101_1010_0000_0000 => return Instruction::LdrhRegT1{rt: rd, rn, rm},
+            //0101_011x_xxyy_yzzz LDRSB reg T1
+            0b0101_0110_0000_0000 => return Instruction::LdrsbRegT1{rt: rd, rn, rm},
+            //0101_111x_xxyy_yzzz LDRSH reg T1
+            0b0101_1110_0000_0000 => return Instruction::LdrshRegT1{rt: rd, rn, rm},
+            //0101_000x_xxyy_yzzz STR reg T1
+            0b0101_0000_0000_0000 => return Instruction::StrRegT1{rt: rd, rn, rm},
+            //0101_010x_xxyy_yzzz STRB reg T1
+            0b0101_0100_0000_0000 => return Instruction::StrbRegT1{rt: rd, rn, rm},
+            //0101_001x_xxyy_yzzz STRH reg T1
+            0b0101_0010_0000_0000 => return Instruction::StrhRegT1{rt: rd, rn, rm},
+            //0001_101x_xxyy_yzzz SUBS reg T1 flags
+            0b0001_1010_0000_0000 => return Instruction::SubRegT1{rd, rn, rm},
+            //0001_111x_xxyy_yzzz SUBS imm T1 flags
+            0b0001_1110_0000_0000 => return Instruction::SubImmT1{rd, rn, imm: reg1 as u32},
+            //0001_110x_xxyy_yzzz ADD imm T1 flags
+            0b0001_1100_0000_0000 => return Instruction::AddImmT1{rd, rn, imm: reg1 as u32},
+            _ => {}
+        }
+    }
+
+    //n1_r4_rn3
+    {
+        let op = opcode & !MASK_N1_R4_RN3;
+        let (reg1, reg2) = decode_n1_r4_rn3(opcode);
+        let rm = reg1.register as u8;
+        let rdn = reg2.register as u8;
+        match op{
+            //0100_0101_xyyy_yzzz CMP reg T2
+            0b0100_0101_0000_0000 => return Instruction::CmpRegT2{rn: rdn, rm},
+            //0100_0100_xyyy_yzzz ADD reg T2 noflags
+            //0100_0100_x110_1yyy ADD sp+reg T1 noflags (2nd arg must be 1101) -PSUEDO
+            //0100_0100_1xxx_x101 ADD sp+reg T2 noflags (1st and 3rd args form 1101) -PSUEDO
+            0b0100_0100_0000_0000 => return Instruction::AddRegT2{rdn, rm},
+            //0100_0110_xyyy_yzzz MOV reg T1 noflags
+            0b0100_0110_0000_0000 => return Instruction::MovRegT1{rd: rdn, rm},
+            _ => {}
+        }
+    }
+
+    //imm5_r3_r3
+    {
+        let op = opcode & !MASK_IMM5_R3_R3;
+        let (imm, reg1, reg2) = decode_imm5_r3_r3(opcode);
+        let rn = reg1 as u8;
+        let rd = reg2 as u8;
+        match op{
+            //0001_0xxx_xxyy_yzzz ASR imm T1 flags
+            //shift_t = SRType_ASR; shift_n = if imm5 == '00000' then 32 else UInt(imm5);
+            0b0001_0000_0000_0000 => return Instruction::AsrImmT1{rd, rm: rn, shift: if imm == 0 { 32 } else { imm }},
+            //0110_1xxx_xxyy_yzzz LDR imm T1
+            0b0110_1000_0000_0000 => return Instruction::LdrImmT1{rt: rd, rn, imm32: imm << 2},
+            //0111_1xxx_xxyy_yzzz LDRB imm T1
+            0b0111_1000_0000_0000 => return Instruction::LdrbImmT1{rt: rd, rn, imm32: imm},
+            //1000_1xxx_xxyy_yzzz LDRH imm T1
+            0b1000_1000_0000_0000 => return Instruction::LdrhImmT1{rt: rd, rn, imm32: imm << 1},
+            //0000_0xxx_xxyy_yzzz LSL imm T1 flags
+            //note this shares the same identifying mask as MOV reg T2
+            //they only conflict if the imm5 argument here is 0. Thus, if imm5 is 0, then defer decoding, as the mov instruction should execute instead
+            //this shouldn't ever happen right now, but if the opcode decoding logic were reorganized it could happen in the future, so guard for it now
+            0b0000_0000_0000_0000 if imm != 0 => return Instruction::LslImmT1{rd, rm: rn, shift: imm},
+            //0000_1xxx_xxyy_yzzz LSR imm T1 flags
+            0b0000_1000_0000_0000 => return Instruction::LsrImmT1{rd, rm: rn, shift: if imm == 0 { 32 } else { imm }},
+            //0110_0xxx_xxyy_yzzz STR imm T1
+            0b0110_0000_0000_0000 => return Instruction::StrImmT1{rt: rd, rn, imm32: imm << 2},
+            //0111_0xxx_xxyy_yzzz STRB imm T1
+            0b0111_0000_0000_0000 => return Instruction::StrbImmT1{rt: rd, rn, imm32: imm},
+            //1000_0xxx_xxyy_yzzz STRH imm T
+            0b1000_0000_0000_0000 => return Instruction::StrhImmT1{rt: rd, rn, imm32: imm << 1},
+            _ => {}
+        }
+    }
+    //B<C> and SVC
+    {
+        let op = opcode & !MASK_C4_IMM8;
+        //1101_cccc_xxxx_xxxx B<c> T1 (note: if cond == '1110' then UNDEFINED????)
+        //1101_1111_xxxx_xxxx SVC T1 (B with condition code 1111)
+        if op == 0b1101_0000_0000_0000{
+            let (cond, imm) = decode_c4_imm8(opcode);
+            if cond == 0b1111{
+                return Instruction::Svc{imm: imm as u8};
+            }
+            let label = sign_extend32(imm << 1, 9);
+            return Instruction::BCondT1{cond: cond as u8, imm32: label};
+        }
+    }
+    //x1_rl8
+    {
+        let op = opcode & !MASK_X1_RL8;
+        let (option, reglist) = decode_x1_rl8(opcode);
+        match op{
+            //1011_110x_yyyy_yyyy POP T1 (x is if PC should be popped)
+            0b1011_1100_0000_0000 => return Instruction::PopT1{reglist, pc: option},
+            //1011_010x_yyyy_yyyy PUSH T1 (x is if LR should be pushed)
+            0b1011_0100_0000_0000 => return Instruction::PushT1{reglist, lr: option},
+            _ => {}
+        }
+    }
+    //r4_q3
+    {
+        let op = opcode & !MASK_R4_Q3;
+        let reg = decode_r4_q3(opcode).register as u8;
+        match op{
+            //0100_0111_0xxx_xLLL BX T1
+            0b0100_0111_0000_0000 => return Instruction::BxT1{rm: reg},
+            //0100_0111_1xxx_xLLL BLX T1
+            0b0100_0111_1000_0000 => return Instruction::BlxT1{rm: reg},
+            _ => {}
+        }
+    }
+    //imm7
+    {
+        let op = opcode & !MASK_IMM7;
+        let imm = decode_imm7(opcode);
+        match op{
+            //1011_0000_0xxx_xxxx ADD sp+imm T2 noflags
+            0b1011_0000_0000_0000 => return Instruction::AddSpImmT2{imm32: imm << 2},
+            //1011_0000_1xxx_xxxx SUB sp-imm T1 noflags
+            0b1011_0000_1000_0000 => return Instruction::SubSpImmT1{imm32: imm << 2},
+            _ => {}
+        }
+    }
+
+    Instruction::Invalid(opcode)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn decode_instruction(opcode: u16) -> Instruction{
+        super::decode_instruction(opcode, IsaProfile::ARMv6M)
+    }
+    fn decode_instruction32(opcode32: u32) -> Instruction{
+        super::decode_instruction32(opcode32, IsaProfile::ARMv7M)
+    }
+    #[test]
+    fn test_decode_overlapping() {
+        //MOVS reg T2 shares its mask with LSL imm T1 with an imm5 of 0
+        assert_eq!(decode_instruction(0b0000_0000_0000_1010), Instruction::MovRegT2{rd: 2, rm: 1});
+        assert_eq!(decode_instruction(0b0000_0000_0100_1010), Instruction::LslImmT1{rd: 2, rm: 1, shift: 1});
+        //SVC is B<c> with condition code 1111
+        assert_eq!(decode_instruction(0b1101_1111_1111_1111), Instruction::Svc{imm: 0xFF});
+        assert_eq!(decode_instruction(0b1101_0000_1111_1111), Instruction::BCondT1{cond: 0, imm32: -2});
+        assert_eq!(decode_instruction(0b1101_1110_0000_0001), Instruction::UdfT1{opcode: 0b1101_1110_0000_0000});
+    }
+    #[test]
+    fn test_decode_long_multiply() {
+        //umull r0, r1, r2, r3
+        assert_eq!(decode_instruction32(0xFBA2_0103), Instruction::UmullT1{rdlo: 0, rdhi: 1, rn: 2, rm: 3});
+        //RdHi and RdLo being the same register is unpredictable
+        assert_eq!(decode_instruction32(0xFBA2_1103), Instruction::Invalid32(0xFBA2_1103));
+        //MLA with Ra of PC is MUL T2
+        assert_eq!(decode_instruction32(0xFB02_F103), Instruction::MulT2{rd: 1, rn: 2, rm: 3});
+    }
+    #[test]
+    fn test_decode_divide_multiply_profile() {
+        //sdiv r0, r1, r2 and udiv r0, r1, r2 are part of ARMv8-M baseline, but not ARMv6-M
+        let sdiv = Instruction::SdivT1{rd: 0, rn: 1, rm: 2};
+        let udiv = Instruction::UdivT1{rd: 0, rn: 1, rm: 2};
+        assert_eq!(super::decode_instruction32(0xFB91_F0F2, IsaProfile::ARMv6M), Instruction::Invalid32(0xFB91_F0F2));
+        assert_eq!(super::decode_instruction32(0xFBB1_F0F2, IsaProfile::ARMv6M), Instruction::Invalid32(0xFBB1_F0F2));
+        assert_eq!(super::decode_instruction32(0xFB91_F0F2, IsaProfile::ARMv8MBaseline), sdiv);
+        assert_eq!(super::decode_instruction32(0xFBB1_F0F2, IsaProfile::ARMv8MBaseline), udiv);
+        assert_eq!(decode_instruction32(0xFB91_F0F2), sdiv);
+        assert_eq!(decode_instruction32(0xFBB1_F0F2), udiv);
+        //umull r0, r1, r2, r3 and mla r0, r1, r2, r3 are only part of ARMv7-M
+        for profile in [IsaProfile::ARMv6M, IsaProfile::ARMv8MBaseline]{
+            assert_eq!(super::decode_instruction32(0xFBA2_0103, profile), Instruction::Invalid32(0xFBA2_0103));
+            assert_eq!(super::decode_instruction32(0xFB01_3002, profile), Instruction::Invalid32(0xFB01_3002));
+        }
+        assert_eq!(decode_instruction32(0xFB01_3002), Instruction::MlaT1{rd: 0, rn: 1, rm: 2, ra: 3});
+    }
+    #[test]
+    fn test_decode_profile() {
+        //IT is a hint and CBZ is invalid in ARMv6-M
+        assert_eq!(decode_instruction(0b1011_1111_0000_1000), Instruction::Hint{hint: 0b0000_1000});
+        assert_eq!(decode_instruction(0b1011_0001_0000_0000), Instruction::Invalid(0b1011_0001_0000_0000));
+        let v7 = |opcode| super::decode_instruction(opcode, IsaProfile::ARMv7M);
+        assert_eq!(v7(0b1011_1111_0000_1000), Instruction::ItT1{firstcond: 0, mask: 0b1000});
+        assert_eq!(v7(0b1011_1111_0000_0000), Instruction::Hint{hint: 0});
+        assert_eq!(v7(0b1011_1111_1110_1100), Instruction::Invalid(0b1011_1111_1110_1100));
+        assert_eq!(v7(0b1011_0011_1111_1010), Instruction::CbzT1{rn: 2, imm32: 126});
+        assert_eq!(v7(0b1011_1001_0000_1001), Instruction::CbnzT1{rn: 1, imm32: 2});
+        //IT is still a hint in ARMv8-M baseline
+        assert_eq!(super::decode_instruction(0b1011_1111_0000_1000, IsaProfile::ARMv8MBaseline), Instruction::Hint{hint: 0b0000_1000});
+    }
+    #[test]
+    fn test_decode_baseline32() {
+        let baseline = |opcode32| super::decode_instruction32(opcode32, IsaProfile::ARMv8MBaseline);
+        //movw r0, #0xBEEF
+        assert_eq!(baseline(0xF64B_60EF), Instruction::MovwT3{rd: 0, imm16: 0xBEEF});
+        //movt r0, #0xDEAD
+        assert_eq!(baseline(0xF6CD_60AD), Instruction::MovtT1{rd: 0, imm16: 0xDEAD});
+        //ldrex r1, [r2, #4]
+        assert_eq!(baseline(0xE852_1F01), Instruction::LdrexT1{rt: 1, rn: 2, imm32: 4});
+        //strex r3, r1, [r2, #4]
+        assert_eq!(baseline(0xE842_1301), Instruction::StrexT1{rd: 3, rt: 1, rn: 2, imm32: 4});
+        //ldrexb r1, [r2]
+        assert_eq!(baseline(0xE8D2_1F4F), Instruction::LdrexbT1{rt: 1, rn: 2});
+        //strexh r3, r1, [r2]
+        assert_eq!(baseline(0xE8C2_1F53), Instruction::StrexhT1{rd: 3, rt: 1, rn: 2});
+        assert_eq!(baseline(0xF3BF_8F2F), Instruction::ClrexT1);
+        //strex r1, r1, [r2] is unpredictable
+        assert_eq!(baseline(0xE842_1101), Instruction::Invalid32(0xE842_1101));
+        //not available in ARMv6-M
+        assert_eq!(super::decode_instruction32(0xF64B_60EF, IsaProfile::ARMv6M), Instruction::Invalid32(0xF64B_60EF));
+    }
+    #[test]
+    fn test_decode_thumb2() {
+        use Instruction::*;
+        let v7 = |opcode32| super::decode_instruction32(opcode32, IsaProfile::ARMv7M);
+        //add.w r0, r1, #0x00FF00FF
+        assert_eq!(v7(0xF101_10FF), AddImmT3{rd: 0, rn: 1, imm32: 0x00FF_00FF, setflags: false});
+        //sub.w sp, sp, #256
+        assert_eq!(v7(0xF5AD_7D80), SubImmT3{rd: 13, rn: 13, imm32: 256, setflags: false});
+        //tst.w r2, #0x80000000
+        assert_eq!(v7(0xF012_4F00), TstImmT1{rn: 2, imm32: 0x8000_0000, carry: Some(true)});
+        //mov.w r4, #0x55555555
+        assert_eq!(v7(0xF04F_3455), MovImmT2{rd: 4, imm32: 0x5555_5555, carry: None, setflags: false});
+        //orr.w r1, r2, r3, lsl #4
+        assert_eq!(v7(0xEA42_1103), OrrRegT2{rd: 1, rn: 2, rm: 3, shift_type: ShiftType::Lsl, shift: 4, setflags: false});
+        //rrx r0, r1
+        assert_eq!(v7(0xEA4F_0031), MovRegT3{rd: 0, rm: 1, shift_type: ShiftType::Rrx, shift: 1, setflags: false});
+        //lsls.w r5, r6, r7
+        assert_eq!(v7(0xFA16_F507), LslRegT2{rd: 5, rn: 6, rm: 7, setflags: true});
+        //subw r0, sp, #4
+        assert_eq!(v7(0xF2AD_0004), SubImmT4{rd: 0, rn: 13, imm32: 4});
+        //ubfx r0, r1, #4, #8
+        assert_eq!(v7(0xF3C1_1007), UbfxT1{rd: 0, rn: 1, lsb: 4, width: 8});
+        //bfc r0, #0, #1
+        assert_eq!(v7(0xF36F_0000), BfcT1{rd: 0, lsb: 0, msb: 0});
+        //clz r0, r1
+        assert_eq!(v7(0xFAB1_F081), ClzT1{rd: 0, rm: 1});
+        //uxtb.w r0, r1, ror #8
+        assert_eq!(v7(0xFA5F_F091), UxtbT2{rd: 0, rm: 1, rotation: 8});
+        //ldr.w r0, [r1, #0xFFF]
+        assert_eq!(v7(0xF8D1_0FFF), LdrImmT4{rt: 0, rn: 1, imm32: 0xFFF, add: true, index: true, wback: false});
+        //ldr r0, [r1], #4
+        assert_eq!(v7(0xF851_0B04), LdrImmT4{rt: 0, rn: 1, imm32: 4, add: true, index: false, wback: true});
+        //ldrh.w r0, [r1, r2, lsl #1]
+        assert_eq!(v7(0xF831_0012), LdrhRegT2{rt: 0, rn: 1, rm: 2, shift: 1});
+        //ldr.w r0, [pc, #-8]
+        assert_eq!(v7(0xF85F_0008), LdrImmT4{rt: 0, rn: 15, imm32: 8, add: false, index: true, wback: false});
+        //strd r0, r1, [r2, #-8]!
+        assert_eq!(v7(0xE962_0102), StrdImmT1{rt: 0, rt2: 1, rn: 2, imm32: 8, add: false, index: true, wback: true});
+        //push.w {r4-r11, lr}
+        assert_eq!(v7(0xE92D_4FF0), StmdbT1{rn: 13, registers: 0x4FF0, wback: true});
+        //pop.w {r4-r11, pc}
+        assert_eq!(v7(0xE8BD_8FF0), LdmT2{rn: 13, registers: 0x8FF0, wback: true});
+        //tbb [pc, r0]
+        assert_eq!(v7(0xE8DF_F000), TbbT1{rn: 15, rm: 0});
+        //ldr r0, [r0], #4 is unpredictable
+        assert_eq!(v7(0xF850_0B04), Invalid32(0xF850_0B04));
+        //ldm r0!, {r0, r1}
+        assert_eq!(v7(0xE8B0_0003), Invalid32(0xE8B0_0003));
+        //not available in ARMv6-M or ARMv8-M baseline, except for the barriers
+        assert_eq!(super::decode_instruction32(0xF101_10FF, IsaProfile::ARMv6M), Invalid32(0xF101_10FF));
+        assert_eq!(super::decode_instruction32(0xF101_10FF, IsaProfile::ARMv8MBaseline), Invalid32(0xF101_10FF));
+        assert_eq!(super::decode_instruction32(0xF3BF_8F5F, IsaProfile::ARMv6M), DmbT1{option: 0xF});
+    }
+}
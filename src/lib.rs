@@ -1,4 +1,10 @@
-extern crate strum;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[macro_use]
+extern crate alloc;
+// The derives from strum_macros refer to ::std, and only use the parts of it which are also in core
+#[cfg(not(feature = "std"))]
+extern crate core as std;
 #[macro_use]
 extern crate strum_macros;
 
@@ -8,8 +14,27 @@ mod bitmanip;
 pub mod memory;
 pub mod narmvm;
 mod decode;
+/// Decoded instruction representation used for executing and caching opcodes
+pub mod instruction;
+/// Basic block translation used by the block execution engine
+pub mod basicblock;
+/// Neutron ABI (costack and system calls) implemented on top of SVC
+pub mod neutron;
+/// Canonical hashing of the VM state, for verifying that executions agree
+pub mod statehash;
+/// Recording execution so that it can be rewound for debugging
+pub mod replay;
+/// Counting the mix of executed instructions, for tuning gas prices
+pub mod stats;
+/// Nested VMs for contract to contract calls using the Neutron ABI
+pub mod callframe;
+/// Typed access to guest memory for host code, such as strings, arrays and structs
+pub mod guestmem;
+/// ARM semihosting for debug output from the guest
+pub mod semihosting;
 
 #[derive(PartialEq, Debug, Display, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum  NarmError{
     None,
     //unloaded memory means that an unloaded memory area was access
@@ -25,9 +50,53 @@ pub enum  NarmError{
     InvalidOpcode(u16),
     InvalidOpcode32(u32),
     OutOfGas,
-    InvalidArchitectureMode //used when trying to jump to non-thumb code
+    InvalidArchitectureMode, //used when trying to jump to non-thumb code
+    //triggered by the Neutron layer when an SVC number is not part of the Neutron ABI
+    UnknownServiceCall(u32),
+    //triggered when a system call feature is not provided by the host
+    UnknownSystemCall(u32),
+    //triggered when popping or peeking an item which is not on the costack
+    EmptyCostack,
+    //triggered when adding memory would exceed the configured memory limit
+    MemoryLimitExceeded(u32),
+    //triggered when there is not enough gas remaining to pay for memory which was mapped or touched
+    OutOfMemoryGas,
+    //triggered by an instruction which is unpredictable within an IT block, such as a branch which is not the last instruction of the block
+    InvalidITBlockInstruction(u32),
+    //triggered when removing, resizing or naming memory at an address where no memory was added, or resizing it to an invalid size
+    InvalidMemoryRegion(u32),
+    //triggered when SP would be set below the stack limit, with the value SP would have been set to
+    StackOverflow(u32),
+    //triggered when a contract calls another contract without a valid contract id and gas on top of the costack
+    InvalidContractCall,
+    //triggered when a string read from or written to guest memory has no NUL terminator within its maximum length, an unexpected NUL, or invalid UTF-8
+    InvalidGuestString(u32),
+    //triggered when semihosting is enabled and the guest requests a semihosting operation which is not supported
+    UnknownSemihostingCall(u32)
+}
+
+impl NarmError{
+    /// The address of the memory access which caused the error, for errors caused by accessing memory
+    pub fn fault_address(&self) -> Option<u32>{
+        match self{
+            NarmError::UnloadedMemoryRead(a) | NarmError::UnloadedMemoryWrite(a) | NarmError::EmptyMemoryRead(a) | NarmError::EmptyMemoryWrite(a) |
+            NarmError::ReadOnlyMemoryWrite(a) | NarmError::StackOverflow(a) => Some(*a),
+            _ => Option::None
+        }
+    }
 }
 
+/// Map used within the VM. HashMap needs std, so no_std builds use BTreeMap instead
+#[cfg(feature = "std")]
+pub(crate) type Map<K, V> = std::collections::HashMap<K, V>;
+#[cfg(not(feature = "std"))]
+pub(crate) type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+/// Set used within the VM, see Map
+#[cfg(feature = "std")]
+pub(crate) type Set<T> = std::collections::HashSet<T>;
+#[cfg(not(feature = "std"))]
+pub(crate) type Set<T> = alloc::collections::BTreeSet<T>;
+
 /// This specifies a register beyond r0-r7
 /// It is not strictly necessary to be organized like this, but used to prevent programmer errors
 pub struct LongRegister{
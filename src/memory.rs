@@ -1,37 +1,170 @@
-use std::collections::HashMap;
-use std::string::String;
-use std::fmt;
+use alloc::vec::Vec;
+use alloc::string::String;
+use core::fmt;
 
-use crate::NarmError;
+use crate::{NarmError, Map, Set};
 
 
 /// Any virtual address equal to or greater than this value will be considered writeable
 /// Any virtual address less than this will be considered read only
 pub const WRITEABLE_MEMORY:u32 = 0x80000000;
 
+/// The granularity used for charging gas for memory usage
+pub const MEMORY_PAGE_SIZE:u32 = 0x1000;
+
+/// Limits and gas prices applied to memory usage within a MemorySystem
+/// The default has no limit and charges no gas
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryLimits{
+    /// The maximum number of bytes which may be mapped in total across all memory blocks, or None for no limit
+    pub memory_limit: Option<u32>,
+    /// Gas charged for every page which is mapped using NarmVM::map_memory, or added to a block by resize_memory
+    pub gas_per_mapped_page: u64,
+    /// Gas charged the first time a page is written to
+    /// This is charged after the write, so a write which runs out of gas paying for its pages has still changed memory
+    pub gas_per_touched_page: u64,
+}
+
 /// A simple buffer of memory for MemorySystem
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct BufferMemory{
     pub memory: Vec<u8>,
+    /// Set when the VM has cached decoded instructions from this memory, so that writes to it must be reported
+    contains_code: bool,
+    /// Cached hash of each MEMORY_PAGE_SIZE page, used by the incremental state hash. Pages which were written since being hashed are None
+    /// This is empty until the first incremental state hash, so that writes don't need to invalidate anything before then
+    pub(crate) page_hashes: Vec<Option<[u8; 32]>>,
+    /// Label shown in the memory map, such as "code" or "stack"
+    name: Option<String>,
 }
 
 
 
+/// Formats the memory as a hex dump, with addresses given as offsets into the buffer
 impl fmt::Display for BufferMemory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let formatted_vec = String::from_utf8(self.memory.clone()).unwrap();
-        write!(f, "{}", formatted_vec)
+        write_hex_dump(f, 0, self.memory.len() as u64, HexDumpFormat::default(), |offset| self.memory.get(offset as usize).copied())
+    }
+}
+
+/// Layout of a hex dump made by MemorySystem::hex_dump
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HexDumpFormat{
+    /// The number of bytes shown on each line
+    pub width: u32,
+    /// Whether to show the bytes of each line as ASCII after the hex, with unprintable bytes shown as '.'
+    pub ascii: bool,
+}
+
+impl Default for HexDumpFormat{
+    fn default() -> HexDumpFormat{
+        HexDumpFormat{
+            width: 16,
+            ascii: true,
+        }
+    }
+}
+
+/// Writes size bytes starting at address as lines of an address column, hex bytes and an optional ASCII gutter
+/// Bytes which can not be read are shown as "??" in the hex and as a space in the ASCII gutter
+fn write_hex_dump(out: &mut dyn fmt::Write, address: u32, size: u64, format: HexDumpFormat, byte: impl Fn(u32) -> Option<u8>) -> fmt::Result{
+    let width = format.width.max(1) as u64;
+    let mut offset = 0;
+    while offset < size{
+        let line_address = address.wrapping_add(offset as u32);
+        let count = width.min(size - offset) as u32;
+        write!(out, "{:#010x}: ", line_address)?;
+        let mut ascii = String::new();
+        for i in 0..count{
+            match byte(line_address.wrapping_add(i)){
+                Some(b) => {
+                    write!(out, " {:02x}", b)?;
+                    ascii.push(if b.is_ascii_graphic() || b == b' ' {b as char} else {'.'});
+                },
+                None => {
+                    write!(out, " ??")?;
+                    ascii.push(' ');
+                }
+            }
+        }
+        if format.ascii{
+            write!(out, "{:width$}  |{}|", "", ascii, width = (width as usize - count as usize) * 3)?;
+        }
+        writeln!(out)?;
+        offset += width;
+    }
+    Ok(())
+}
+/// Memory compares equal by contents, ignoring whether it holds cached code
+impl PartialEq for BufferMemory{
+    fn eq(&self, other: &Self) -> bool{
+        self.memory == other.memory
+    }
+}
+
+/// A mapped block of memory, as listed by MemorySystem::regions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryRegion<'a>{
+    pub address: u32,
+    pub size: u32,
+    /// Whether the region is in the writeable part of the address space, see WRITEABLE_MEMORY
+    pub writeable: bool,
+    pub name: Option<&'a str>,
+}
+
+/// Formats the region as a line of the memory map
+impl fmt::Display for MemoryRegion<'_>{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = if self.writeable {"read-write"} else {"read-only"};
+        write!(f, "{:#010x}-{:#010x} {:<10} {:#07x} bytes", self.address, self.address + (self.size - 1), access, self.size)?;
+        if let Some(name) = self.name{
+            write!(f, " {}", name)?;
+        }
+        Ok(())
     }
 }
+
 /// The system for tracking all memory within the VM
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct MemorySystem{
-    map: HashMap<u32, BufferMemory>
+    map: Map<u32, BufferMemory>,
+    pub limits: MemoryLimits,
+    /// Pages which have been written to, only tracked when gas_per_touched_page is set
+    touched_pages: Set<u32>,
+    /// Gas which has been accrued by memory usage, but not yet charged to the VM
+    pending_gas: u64,
+    /// Address ranges written to within memory blocks which contain cached code
+    code_writes: Vec<(u32, u32)>,
+    /// Previous contents of written memory, only kept while the VM is recording execution
+    journal: Option<MemoryJournal>,
+}
+
+/// Changes made to memory since the journal was last taken, used by NarmVM to rewind recorded execution
+#[derive(Default, Debug, Clone)]
+pub(crate) struct MemoryJournal{
+    /// The address and previous contents of each write, in the order they were made
+    pub writes: Vec<(u32, Vec<u8>)>,
+    /// Pages which were touched for the first time
+    pub touched: Vec<u32>,
+    /// Set when memory was mapped, which can not be rewound through the journal
+    pub mapped: bool,
+}
+
+/// Memory systems compare equal by their mapped memory and memory usage, ignoring bookkeeping for cached code
+impl PartialEq for MemorySystem{
+    fn eq(&self, other: &Self) -> bool{
+        self.map == other.map &&
+        self.limits == other.limits &&
+        self.touched_pages == other.touched_pages &&
+        self.pending_gas == other.pending_gas
+    }
 }
 
 impl MemorySystem{
     /// This adds a new block of memory to the current memory system
     /// Note that the maximum size allowed is 0x10000 and the address must be aligned on an 0x10000 byte scale (ie, 64Kb)
+    /// This is subject to the memory limit, but does not charge gas, as it is intended for setting up the VM. See NarmVM::map_memory
     pub fn add_memory(&mut self, address: u32, size: u32) -> Result<&mut [u8], NarmError> {
         if address & 0xFFFF != 0{
             return Err(NarmError::UnalignedMemoryAddition);
@@ -41,8 +174,19 @@ impl MemorySystem{
         if self.map.contains_key(&aligned) {
             return Err(NarmError::ConflictingMemoryAddition);
         }
+        if let Some(limit) = self.limits.memory_limit{
+            if (self.mapped_size() as u64) + (size as u64) > limit as u64{
+                return Err(NarmError::MemoryLimitExceeded(address));
+            }
+        }
+        if let Some(journal) = &mut self.journal{
+            journal.mapped = true;
+        }
         let mut b = BufferMemory{
             memory: Vec::default(),
+            contains_code: false,
+            page_hashes: Vec::default(),
+            name: None,
         };
         b.memory.resize(size as usize, 0);
         self.map.insert(aligned, b);
@@ -50,9 +194,76 @@ impl MemorySystem{
         Ok(&mut self.map.get_mut(&aligned).unwrap().memory[0..])
     }
 
+    /// Removes the block of memory which was added at the given address
+    /// Gas already charged for the block is not refunded, and its pages are charged again if they are mapped and written to again
+    pub fn remove_memory(&mut self, address: u32) -> Result<(), NarmError>{
+        let block = self.map.remove(&address).ok_or(NarmError::InvalidMemoryRegion(address))?;
+        if block.contains_code{
+            self.code_writes.push((address, block.memory.len() as u32));
+        }
+        self.untouch_range(address, address + (block.memory.len() as u32 - 1));
+        if let Some(journal) = &mut self.journal{
+            journal.mapped = true;
+        }
+        Ok(())
+    }
+    /// Changes the size of the block of memory which was added at the given address, keeping its contents up to the new size
+    /// The size must be from 1 to 0x10000 bytes. Memory added to the block is zeroed
+    /// Growing a block is subject to the memory limits, and accrues gas for the newly mapped pages in the same way as NarmVM::map_memory
+    /// Shrinking a block forgets which of its unmapped pages were touched, so that they are charged again if it grows back
+    pub fn resize_memory(&mut self, address: u32, size: u32) -> Result<(), NarmError>{
+        let old_size = match self.map.get(&address){
+            Some(block) if size != 0 && size <= 0x10000 => block.memory.len() as u32,
+            _ => return Err(NarmError::InvalidMemoryRegion(address))
+        };
+        if size > old_size{
+            if let Some(limit) = self.limits.memory_limit{
+                if (self.mapped_size() - old_size) as u64 + size as u64 > limit as u64{
+                    return Err(NarmError::MemoryLimitExceeded(address));
+                }
+            }
+            let gas = self.mapping_gas(size) - self.mapping_gas(old_size);
+            self.pending_gas = self.pending_gas.saturating_add(gas);
+        }
+        let block = self.map.get_mut(&address).unwrap();
+        if size < old_size && block.contains_code{
+            self.code_writes.push((address + size, old_size - size));
+        }
+        block.memory.resize(size as usize, 0);
+        //the last page may have changed size, so all pages are rehashed
+        block.page_hashes.clear();
+        let first_unmapped = (address + (size - 1)) / MEMORY_PAGE_SIZE + 1;
+        if first_unmapped <= (address + (old_size - 1)) / MEMORY_PAGE_SIZE{
+            self.untouch_range(first_unmapped * MEMORY_PAGE_SIZE, address + (old_size - 1));
+        }
+        if let Some(journal) = &mut self.journal{
+            journal.mapped = true;
+        }
+        Ok(())
+    }
+    /// Sets the name shown for the block of memory which was added at the given address, such as "code" or "stack"
+    pub fn set_region_name(&mut self, address: u32, name: &str) -> Result<(), NarmError>{
+        let block = self.map.get_mut(&address).ok_or(NarmError::InvalidMemoryRegion(address))?;
+        block.name = Some(String::from(name));
+        Ok(())
+    }
+    /// All mapped memory blocks ordered by address
+    pub fn regions(&self) -> Vec<MemoryRegion<'_>>{
+        self.blocks().into_iter().map(|(address, block)| MemoryRegion{
+            address,
+            size: block.memory.len() as u32,
+            writeable: address >= WRITEABLE_MEMORY,
+            name: block.name.as_deref(),
+        }).collect()
+    }
+
     /// Note that this will not respect the "readonly" flag, nor readonly memory space
     /// This is designed for internal use and with the VM exposed methods checking for these errors
     pub fn get_mut_memory(&mut self, address: u32) -> Result<&mut [u8], NarmError> {
+        //the entire rest of the block is writeable through the returned slice
+        self.get_mut_memory_range(address, None)
+    }
+    fn get_mut_memory_range(&mut self, address: u32, size: Option<u32>) -> Result<&mut [u8], NarmError> {
         match self.map.get_mut(&(address & 0xFFFF0000)){
             Option::None => return Err(NarmError::UnloadedMemoryRead(address)), //should never happen?
             Option::Some(m) =>  {
@@ -60,6 +271,22 @@ impl MemorySystem{
                 if m.memory.len() - 1 < local{
                     return Err(NarmError::EmptyMemoryWrite(address));
                 }
+                if m.contains_code{
+                    let size = size.unwrap_or((m.memory.len() - local) as u32);
+                    self.code_writes.push((address, size));
+                }
+                if !m.page_hashes.is_empty(){
+                    let end = size.map_or(m.memory.len(), |s| (local + s as usize).min(m.memory.len()));
+                    let first = local / MEMORY_PAGE_SIZE as usize;
+                    let last = (end.max(local + 1) - 1) / MEMORY_PAGE_SIZE as usize;
+                    for hash in &mut m.page_hashes[first..=last]{
+                        *hash = None;
+                    }
+                }
+                if let Some(journal) = &mut self.journal{
+                    let end = size.map_or(m.memory.len(), |s| (local + s as usize).min(m.memory.len()));
+                    journal.writes.push((address, m.memory[local..end].to_vec()));
+                }
                 return Ok(&mut (&mut m.memory)[local..])
             }
         }
@@ -81,7 +308,7 @@ impl MemorySystem{
     pub fn get_sized_memory(&self, address: u32, size: u32) -> Result<&[u8], NarmError>{
         let m = self.get_memory(address)?;
         if m.len() < size as usize {
-            return Err(NarmError::EmptyMemoryRead(address + size - 1));
+            return Err(NarmError::EmptyMemoryRead(address.saturating_add(size - 1)));
         }
         Ok(&m[0..size as usize])
     }
@@ -89,12 +316,38 @@ impl MemorySystem{
     /// Note that this will not respect the "readonly" flag, nor readonly memory space
     /// This is designed for internal use and with the VM exposed methods checking for these errors
     pub fn get_mut_sized_memory(&mut self, address: u32, size: u32) -> Result<&mut [u8], NarmError>{
-        let m = self.get_mut_memory(address)?;
+        if self.limits.gas_per_touched_page != 0 && size != 0{
+            //validate before touching so that failed writes are not charged
+            self.get_sized_memory(address, size)?;
+            self.touch(address, size);
+        }
+        let m = self.get_mut_memory_range(address, Some(size))?;
         if m.len() < size as usize {
-            return Err(NarmError::EmptyMemoryWrite(address + size - 1));
+            return Err(NarmError::EmptyMemoryWrite(address.saturating_add(size - 1)));
         }
         Ok(&mut m[0..size as usize])
     }
+    /// Records pages in the given range as written, accruing gas for the ones which are written for the first time
+    fn touch(&mut self, address: u32, size: u32){
+        let first = address / MEMORY_PAGE_SIZE;
+        let last = address.saturating_add(size - 1) / MEMORY_PAGE_SIZE;
+        for page in first..=last{
+            if self.touched_pages.insert(page){
+                self.pending_gas = self.pending_gas.saturating_add(self.limits.gas_per_touched_page);
+                if let Some(journal) = &mut self.journal{
+                    journal.touched.push(page);
+                }
+            }
+        }
+    }
+    /// Forgets that the pages holding the addresses from first to last were touched
+    fn untouch_range(&mut self, first: u32, last: u32){
+        if !self.touched_pages.is_empty(){
+            for page in (first / MEMORY_PAGE_SIZE)..=(last / MEMORY_PAGE_SIZE){
+                self.touched_pages.remove(&page);
+            }
+        }
+    }
     /// Retreives a single u8 from memory
     pub fn get_u8(&self, address: u32) -> Result<u8, NarmError>{
         let m = self.get_sized_memory(address, 1)?;
@@ -102,14 +355,14 @@ impl MemorySystem{
     }
     /// Retreives a single u16 from memory, including endianness correction if needed
     pub fn get_u16(&self, address: u32) -> Result<u16, NarmError>{
-        use std::convert::TryInto;
+        use core::convert::TryInto;
         let m = self.get_sized_memory(address, 2)?;
         let v: [u8; 2] = *(&m[0..2].try_into().unwrap());
         Ok(u16::from_le_bytes(v))
     }
     /// Retreives a single u32 from memory, including endianness correction if needed
     pub fn get_u32(&self, address: u32) -> Result<u32, NarmError>{
-        use std::convert::TryInto;
+        use core::convert::TryInto;
         let m = self.get_sized_memory(address, 4)?;
         let v: [u8; 4] = *(&m[0..4].try_into().unwrap());
         Ok(u32::from_le_bytes(v))
@@ -117,7 +370,7 @@ impl MemorySystem{
 
     /// Retreives a single u64 from memory, including endianness correction if needed
     pub fn get_u64(&self, address: u32) -> Result<u64, NarmError>{
-        use std::convert::TryInto;
+        use core::convert::TryInto;
         let m = self.get_sized_memory(address, 8)?;
         let v: [u8; 8] = *(&m[0..8].try_into().unwrap());
         Ok(u64::from_le_bytes(v))
@@ -149,8 +402,249 @@ impl MemorySystem{
         (&mut m[0..8]).copy_from_slice(&d);
         Ok(v)
     }
+    /// Formats size bytes of memory starting at address as a hex dump, with one line per format.width bytes
+    /// Any address range can be dumped, with unmapped bytes shown as "??". The range stops at the end of the address space
+    pub fn hex_dump(&self, address: u32, size: u32, format: HexDumpFormat) -> String{
+        let mut text = String::new();
+        let size = (size as u64).min((1 << 32) - address as u64);
+        let _ = write_hex_dump(&mut text, address, size, format, |a| self.get_u8(a).ok());
+        text
+    }
     /// Determines if a block of memory exists
     pub fn section_exists(&self, address: u32) -> bool{
         self.map.contains_key(&(address & 0xFFFF0000))
     }
-}
\ No newline at end of file
+    /// Marks memory as containing cached code, so that later writes to it are recorded for invalidating the cache
+    pub(crate) fn mark_code(&mut self, address: u32, size: u32){
+        for a in [address, address.wrapping_add(size - 1)].iter(){
+            if let Some(m) = self.map.get_mut(&(a & 0xFFFF0000)){
+                m.contains_code = true;
+            }
+        }
+    }
+    /// Removes all code marks, for when the decode cache is emptied
+    pub(crate) fn clear_code_marks(&mut self){
+        for m in self.map.values_mut(){
+            m.contains_code = false;
+        }
+        self.code_writes.clear();
+    }
+    pub(crate) fn has_code_writes(&self) -> bool{
+        !self.code_writes.is_empty()
+    }
+    pub(crate) fn take_code_writes(&mut self) -> Vec<(u32, u32)>{
+        core::mem::take(&mut self.code_writes)
+    }
+    /// All memory blocks ordered by address
+    pub(crate) fn blocks(&self) -> Vec<(u32, &BufferMemory)>{
+        let mut blocks: Vec<(u32, &BufferMemory)> = self.map.iter().map(|(a, m)| (*a, m)).collect();
+        blocks.sort_unstable_by_key(|(a, _)| *a);
+        blocks
+    }
+    /// All memory blocks ordered by address, for updating cached page hashes
+    pub(crate) fn blocks_mut(&mut self) -> Vec<(u32, &mut BufferMemory)>{
+        let mut blocks: Vec<(u32, &mut BufferMemory)> = self.map.iter_mut().map(|(a, m)| (*a, m)).collect();
+        blocks.sort_unstable_by_key(|(a, _)| *a);
+        blocks
+    }
+    /// The total number of bytes mapped across all memory blocks
+    pub fn mapped_size(&self) -> u32{
+        self.map.values().map(|m| m.memory.len() as u32).sum()
+    }
+    /// The gas which will be charged for mapping a block of memory of the given size
+    pub fn mapping_gas(&self, size: u32) -> u64{
+        (size as u64).div_ceil(MEMORY_PAGE_SIZE as u64).saturating_mul(self.limits.gas_per_mapped_page)
+    }
+    /// The pages which have been written to and charged for, in ascending order
+    pub(crate) fn touched_pages(&self) -> Vec<u32>{
+        let mut pages: Vec<u32> = self.touched_pages.iter().copied().collect();
+        pages.sort_unstable();
+        pages
+    }
+    /// Gas which has been accrued by memory usage but not yet charged
+    pub fn pending_gas(&self) -> u64{
+        self.pending_gas
+    }
+    pub(crate) fn set_pending_gas(&mut self, gas: u64){
+        self.pending_gas = gas;
+    }
+    /// Starts or stops keeping a journal of changes to memory
+    /// Note that writes through get_mut_memory are journaled as a write of the entire rest of the memory block
+    pub(crate) fn set_journaling(&mut self, enabled: bool){
+        self.journal = if enabled {Some(MemoryJournal::default())} else {None};
+    }
+    /// Returns the changes made since the last call, leaving the journal empty. Returns an empty journal when not journaling
+    pub(crate) fn take_journal(&mut self) -> MemoryJournal{
+        self.journal.as_mut().map(core::mem::take).unwrap_or_default()
+    }
+    /// Writes data without charging gas for touched pages, for restoring the contents of memory
+    pub(crate) fn write_raw(&mut self, address: u32, data: &[u8]) -> Result<(), NarmError>{
+        if data.is_empty(){
+            return Ok(());
+        }
+        let m = self.get_mut_memory_range(address, Some(data.len() as u32))?;
+        if m.len() < data.len(){
+            return Err(NarmError::EmptyMemoryWrite(address.saturating_add(data.len() as u32 - 1)));
+        }
+        m[..data.len()].copy_from_slice(data);
+        Ok(())
+    }
+    /// Marks a page as touched without charging gas for it
+    pub(crate) fn touch_page(&mut self, page: u32){
+        if self.touched_pages.insert(page){
+            if let Some(journal) = &mut self.journal{
+                journal.touched.push(page);
+            }
+        }
+    }
+    pub(crate) fn untouch_page(&mut self, page: u32){
+        self.touched_pages.remove(&page);
+    }
+    /// Returns the gas accrued by memory usage and resets it to 0
+    /// This is used by NarmVM to charge for memory, but can also be used by a host to discard charges for setup done before execution
+    pub fn take_pending_gas(&mut self) -> u64{
+        let gas = self.pending_gas;
+        self.pending_gas = 0;
+        gas
+    }
+}
+
+/// Serialization of memory, enabled by the serde feature
+/// BufferMemory is written as its contents, which is a hex string in human readable formats such as JSON, and plain bytes otherwise
+/// MemorySystem is written as its memory regions ordered by address, together with its limits and memory usage
+/// Bookkeeping for cached code is not serialized
+#[cfg(feature = "serde")]
+mod serialization{
+    use super::*;
+    use core::fmt;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use serde::de::{self, Visitor, SeqAccess};
+
+    const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    fn to_hex(data: &[u8]) -> String{
+        let mut s = String::with_capacity(data.len() * 2);
+        for b in data{
+            s.push(HEX_DIGITS[(b >> 4) as usize] as char);
+            s.push(HEX_DIGITS[(b & 0x0F) as usize] as char);
+        }
+        s
+    }
+
+    fn from_hex(s: &str) -> Option<Vec<u8>>{
+        fn digit(c: u8) -> Option<u8>{
+            (c as char).to_digit(16).map(|d| d as u8)
+        }
+        let s = s.as_bytes();
+        if s.len() & 1 != 0{
+            return None;
+        }
+        s.chunks(2).map(|pair| Some(digit(pair[0])? << 4 | digit(pair[1])?)).collect()
+    }
+
+    impl Serialize for BufferMemory{
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>{
+            if serializer.is_human_readable(){
+                serializer.serialize_str(&to_hex(&self.memory))
+            }else{
+                serializer.serialize_bytes(&self.memory)
+            }
+        }
+    }
+
+    struct BufferMemoryVisitor;
+
+    impl<'de> Visitor<'de> for BufferMemoryVisitor{
+        type Value = BufferMemory;
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result{
+            f.write_str("memory contents as a hex string or bytes")
+        }
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<BufferMemory, E>{
+            let memory = from_hex(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))?;
+            Ok(BufferMemory{memory, ..Default::default()})
+        }
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<BufferMemory, E>{
+            Ok(BufferMemory{memory: v.to_vec(), ..Default::default()})
+        }
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<BufferMemory, E>{
+            Ok(BufferMemory{memory: v, ..Default::default()})
+        }
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BufferMemory, A::Error>{
+            let mut memory = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(b) = seq.next_element()?{
+                memory.push(b);
+            }
+            Ok(BufferMemory{memory, ..Default::default()})
+        }
+    }
+
+    impl<'de> Deserialize<'de> for BufferMemory{
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BufferMemory, D::Error>{
+            if deserializer.is_human_readable(){
+                deserializer.deserialize_str(BufferMemoryVisitor)
+            }else{
+                deserializer.deserialize_byte_buf(BufferMemoryVisitor)
+            }
+        }
+    }
+
+    /// A single memory block within a serialized MemorySystem
+    #[derive(Serialize, Deserialize)]
+    struct SerializedRegion<M>{
+        address: u32,
+        data: M,
+        #[serde(default)]
+        name: Option<String>,
+    }
+
+    /// The stable schema of a serialized MemorySystem
+    #[derive(Serialize, Deserialize)]
+    struct MemorySystemState<M>{
+        regions: Vec<SerializedRegion<M>>,
+        limits: MemoryLimits,
+        touched_pages: Vec<u32>,
+        pending_gas: u64,
+    }
+
+    impl Serialize for MemorySystem{
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>{
+            let mut regions: Vec<SerializedRegion<&BufferMemory>> = self.map.iter().map(|(address, data)| SerializedRegion{
+                address: *address,
+                data,
+                name: data.name.clone()
+            }).collect();
+            regions.sort_unstable_by_key(|r| r.address);
+            let touched_pages = self.touched_pages();
+            MemorySystemState{
+                regions,
+                limits: self.limits,
+                touched_pages,
+                pending_gas: self.pending_gas
+            }.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for MemorySystem{
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<MemorySystem, D::Error>{
+            let state = MemorySystemState::<BufferMemory>::deserialize(deserializer)?;
+            let mut memory = MemorySystem{
+                limits: state.limits,
+                touched_pages: state.touched_pages.into_iter().collect(),
+                pending_gas: state.pending_gas,
+                ..Default::default()
+            };
+            for region in state.regions{
+                //the same restrictions as add_memory apply, so that a deserialized memory system is always valid
+                if region.address & 0xFFFF != 0 || region.data.memory.is_empty() || region.data.memory.len() > 0x10000{
+                    return Err(de::Error::custom(format!("invalid memory region at {:#010x}", region.address)));
+                }
+                let mut data = region.data;
+                data.name = region.name;
+                if memory.map.insert(region.address, data).is_some(){
+                    return Err(de::Error::custom(format!("duplicate memory region at {:#010x}", region.address)));
+                }
+            }
+            Ok(memory)
+        }
+    }
+}
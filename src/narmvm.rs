@@ -2,10 +2,67 @@ use crate::memory::*;
 use crate::NarmError;
 use crate::decode::*;
 use crate::bitmanip::*;
+use crate::instruction::*;
+use crate::basicblock::*;
+use crate::replay::Recording;
+use crate::stats::InstructionStats;
+use crate::semihosting::*;
 use crate::*;
+use alloc::vec::Vec;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::boxed::Box;
 
+/// Returned by execute() and cycle() in place of an SVC number when a wait hint yields to the host, see NarmVM::set_yield_on_hints
+/// The bottom byte is the hint, so that it can never be confused with an SVC number
+pub const HINT_EXIT: u32 = 0x100;
+pub const EXIT_YIELD: u32 = HINT_EXIT | 0x10;
+pub const EXIT_WFE: u32 = HINT_EXIT | 0x20;
+pub const EXIT_WFI: u32 = HINT_EXIT | 0x30;
+pub const EXIT_SEV: u32 = HINT_EXIT | 0x40;
 
-#[derive(Default)]
+/// Number of entries in the decoded instruction cache. Must be a power of 2
+const DECODE_CACHE_SIZE: usize = 4096;
+
+/// An entry in the direct mapped decoded instruction cache
+#[derive(Clone, Copy)]
+struct CachedInstruction{
+    /// Address of the cached instruction. An odd address is never a valid thumb instruction address, and marks an empty entry
+    address: u32,
+    /// The first halfword of the opcode, for debug tracing
+    opcode: u16,
+    size: u8,
+    instruction: Instruction
+}
+
+impl Default for CachedInstruction{
+    fn default() -> CachedInstruction{
+        CachedInstruction{
+            address: 1,
+            opcode: 0,
+            size: 0,
+            instruction: Instruction::Invalid(0)
+        }
+    }
+}
+
+/// A mask of the bottom width bits, for width from 1 to 32
+fn bitfield_mask(width: u32) -> u32{
+    ((1u64 << width) - 1) as u32
+}
+
+fn decode_cache_index(address: u32) -> usize{
+    ((address >> 1) as usize) & (DECODE_CACHE_SIZE - 1)
+}
+
+
+/// A destination for the diagnostics text written by the VM, such as by print_diagnostics or when a breakpoint is hit
+/// This allows hosts without stdout, such as no_std hosts, to capture diagnostics
+pub trait DiagnosticsOutput{
+    fn write_diagnostics(&self, text: &str);
+}
+
+#[derive(Default, Clone)]
 pub struct NarmVM{
     /// "short registers". General registers. r0-r7 
     sreg: [u32; 8],
@@ -24,13 +81,63 @@ pub struct NarmVM{
     pub gas_remaining: u64,
     //pub charger: GasCharger
     pub memory: MemorySystem,
+    /// Cache of decoded instructions, allocated on first use
+    decode_cache: Vec<CachedInstruction>,
+    decode_cache_disabled: bool,
+    /// The instruction set being executed
+    profile: IsaProfile,
+    /// ITSTATE, the condition and mask of the current IT block, or 0 outside of an IT block
+    itstate: u8,
+    /// Translated basic blocks used by the block execution engine, keyed by start address
+    blocks: Map<u32, Arc<BasicBlock>>,
+    /// Local exclusive monitor, holding the address and size of the last LDREX while in the exclusive access state
+    exclusive_monitor: Option<(u32, u32)>,
+    /// SP may not be set below this address, similar to MSPLIM in ARMv8-M. 0 disables the limit
+    stack_limit: u32,
+    /// The lowest value SP was set to since the last reset_lowest_sp, if it was set at all
+    lowest_sp: Option<u32>,
+    /// The value an instruction tried to set SP to below the stack limit. SP is left unchanged and the instruction faults when it completes
+    stack_overflow: Option<u32>,
+    /// The timing model used to count cycles, or None to not count cycles
+    timing_model: Option<TimingModel>,
+    /// Cycles taken by the instructions executed since the last reset_cycle_count, according to the timing model
+    cycle_count: u64,
+    /// If YIELD, WFE, WFI and SEV return to the host instead of being treated as NOP
+    yield_on_hints: bool,
+    /// The error which last stopped execution, shown in the diagnostics message
+    last_error: Option<NarmError>,
+    /// Instruction mix statistics, while they are enabled
+    pub(crate) statistics: Option<Box<InstructionStats>>,
+    /// Where diagnostics text is written. If not set, it is printed to stdout, or discarded without the std feature
+    diagnostics_output: Option<Arc<dyn DiagnosticsOutput + Send + Sync>>,
+    /// Where the output of semihosting calls is written, while semihosting is enabled
+    pub(crate) semihosting: Option<Arc<dyn SemihostingOutput + Send + Sync>>,
+    /// Undo log and snapshots of execution, while recording
+    pub(crate) recording: Option<Box<Recording>>,
     #[cfg(debug_assertions)]
     executed_opcodes: Vec<(u32, u16)>,
     #[cfg(debug_assertions)]
     breakpoint_flipflop: bool
 }
 
-#[derive(Default)]
+/// All execution state outside of memory, used for recording and rewinding execution
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct CpuState{
+    sreg: [u32; 8],
+    long_registers: [u32; 7],
+    pc: u32,
+    last_pc: u32,
+    cpsr: CPSR,
+    gas_remaining: u64,
+    itstate: u8,
+    exclusive_monitor: Option<(u32, u32)>,
+    lowest_sp: Option<u32>,
+    cycle_count: u64,
+    pending_gas: u64
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CPSR{
     pub n: bool,
     pub z: bool,
@@ -69,7 +176,57 @@ impl NarmVM{
             }
         }
     }
+    /// Executes a single instruction, returning the SVC number if one was executed or 0 otherwise
+    /// Gas accrued by memory usage during the instruction is charged afterwards
+    /// Any error clears the local exclusive monitor
     pub fn cycle(&mut self) -> Result<u32, NarmError>{
+        if let Some(mut recording) = self.recording.take(){
+            let result = self.recorded_cycle(&mut recording, false);
+            self.recording = Some(recording);
+            return result;
+        }
+        self.unrecorded_cycle()
+    }
+    pub(crate) fn unrecorded_cycle(&mut self) -> Result<u32, NarmError>{
+        let result = self.execute_instruction().and_then(|r| {
+            if self.memory.pending_gas() != 0{
+                self.charge_memory_gas()?;
+            }
+            Ok(r)
+        });
+        if let Err(e) = result{
+            self.exclusive_monitor = None;
+            self.last_error = Some(e);
+        }
+        result
+    }
+    /// Charges all gas accrued by memory usage. If there is not enough gas remaining, then gas_remaining is set to 0 and an error is returned
+    /// Memory has already been written by then, so a store which fails with OutOfMemoryGas still leaves its data in memory
+    pub fn charge_memory_gas(&mut self) -> Result<(), NarmError>{
+        let gas = self.memory.take_pending_gas();
+        if gas > self.gas_remaining{
+            self.gas_remaining = 0;
+            return Err(NarmError::OutOfMemoryGas);
+        }
+        self.gas_remaining -= gas;
+        Ok(())
+    }
+    /// Adds a new block of memory and immediately charges gas for it, subject to the memory limits of the memory system
+    /// This is intended for hosts which allow the guest to request memory, such as with an sbrk-like system call
+    /// Nothing is mapped if there is not enough gas remaining to pay for it
+    /// MemorySystem::add_memory does not charge gas, so that hosts can set up the VM without charging the guest
+    pub fn map_memory(&mut self, address: u32, size: u32) -> Result<(), NarmError>{
+        let gas = self.memory.mapping_gas(size);
+        if gas.saturating_add(self.memory.pending_gas()) > self.gas_remaining{
+            self.memory.take_pending_gas();
+            self.gas_remaining = 0;
+            return Err(NarmError::OutOfMemoryGas);
+        }
+        self.memory.add_memory(address, size)?;
+        self.gas_remaining -= gas;
+        self.charge_memory_gas()
+    }
+    fn execute_instruction(&mut self) -> Result<u32, NarmError>{
         if self.pc & 1 == 0{
             return Err(NarmError::InvalidArchitectureMode);
         }
@@ -77,765 +234,1274 @@ impl NarmVM{
             return Err(NarmError::OutOfGas);
         }
         self.gas_remaining -= 1;
-        self.virtual_pc = self.pc + 4; // Used as base in most (all?) PC-relative ops. Some docs suggests this shuld be aligned by 4, but compiler disagrees. 
+        self.virtual_pc = self.pc + 4; // Used as base in most (all?) PC-relative ops. Some docs suggests this shuld be aligned by 4, but compiler disagrees.
         self.last_pc = self.pc;
-        let opcode = self.memory.get_u16(self.get_pc_address())?;
-        self.log_opcode(opcode);
-        self.pc += 2;
-
-        if is_32bit_opcode(opcode){
-            
-            let opcode32 = ((opcode as u32) << 16) | (self.memory.get_u16(self.get_pc_address())? as u32);
-            self.pc += 2;
-            let op32 = opcode32 & !MASK32_X1_IMM10_X1_X1_IMM11;
-            let (s, imm1, j1, j2, imm2) = decode32_x1_imm10_x1_x1_imm11(opcode32);
-            //BL T1, 32bit instruction. J is split into J1 and J2. x, y, and z is combined into one argument using all of the arguments together which control sign extension etc. Allows -16777216 to +16777214
-                           //1111_0xyy_yyyy_yyyy_11J1_Jzzz_zzzz_zzzz 
-            if op32 == 0b1111_0000_0000_0000_1101_0000_0000_0000{
-                //I1 = NOT(J1 EOR S);  I2 = NOT(J2 EOR S);  imm32 = SignExtend(S:I1:I2:imm10:imm11:'0', 32);
-                let s1 = s as u32;
-                let i1 = (!(j1 ^ s)) as u32;
-                let i2 = (!(j2 ^ s)) as u32;
-                let value = 
-                    s1      << 24 |  //1 bit (1+1+10+11+1)
-                    i1      << 23 |  //1 bit (1+10+11+1)
-                    i2      << 22 |  //1 bit (10+11+1)
-                    imm1    << 12 | //10 bits (11+1)
-                    imm2    << 1;   //11 bits, bottom bit is 0
-                //25 bits total length
-                let imm32 = sign_extend32(value, 25);
-                let lr = LongRegister{register: 14};
-                
-                self.set_reg(&lr, self.virtual_pc | (self.pc & 1)); //or with bottom bit of current pc to copy interworking mode
-                self.set_thumb_pc_address((self.virtual_pc as i32).wrapping_add(imm32) as u32);
-                
-                return Ok(0);
-            }else{
-                //later support MSR/MRS?
-                return Err(NarmError::InvalidOpcode32(opcode32));
-            }
+        let (instruction, size) = self.fetch_instruction()?;
+        self.pc += size;
+        let extra_gas = instruction.gas_cost() - 1;
+        if extra_gas > self.gas_remaining{
+            self.gas_remaining = 0;
+            return Err(NarmError::OutOfGas);
         }
-
-        //NOP pattern
-        {
-            let op = opcode & !MASK_NOP;
-
-            //1011_1110_xxxx_xxxx BKPT imm8
-            if op == 0b1011_1110_0000_0000{
-                self.breakpoint();
-                return Ok(0);
+        self.gas_remaining -= extra_gas;
+        if self.itstate != 0{
+            return self.execute_it_block_instruction(instruction);
+        }
+        self.execute_decoded(instruction)
+    }
+    /// Fetches and decodes the instruction at the current pc, returning it with its size in bytes
+    /// Decoded instructions are cached by address, and the cache is invalidated by any writes to memory holding cached code
+    fn fetch_instruction(&mut self) -> Result<(Instruction, u32), NarmError>{
+        let address = self.get_pc_address();
+        if self.memory.has_code_writes(){
+            self.invalidate_code();
+        }
+        if !self.decode_cache_disabled{
+            if let Some(entry) = self.decode_cache.get(decode_cache_index(address)){
+                if entry.address == address{
+                    let (instruction, size, opcode) = (entry.instruction, entry.size as u32, entry.opcode);
+                    self.log_opcode(address, opcode);
+                    return Ok((instruction, size));
+                }
             }
-
-            //1011_1111_1QQQ_QQQQ NOP HINT catch all (can be safely treated as imm8
-            //1011_1111_0000_0000 NOP T1
-            //1011_1111_0100_0000 SEV nop
-            //1011_1111_0010_0000 WFE T1 nop
-            //1011_1111_0011_0000 WFI T1 nop
-            //1011_1111_0001_0000 YIELD T1 nop
-            if op == 0b1011_1111_0000_0000{
-                return Ok(0);
+        }
+        let opcode = self.memory.get_u16(address)?;
+        self.log_opcode(address, opcode);
+        let (instruction, size) = if is_32bit_opcode(opcode){
+            let second = match self.memory.get_u16(address.wrapping_add(2)){
+                Ok(v) => v,
+                Err(e) => {
+                    //the first half of the opcode was consumed already
+                    self.pc += 2;
+                    return Err(e);
+                }
+            };
+            (decode_instruction32(((opcode as u32) << 16) | (second as u32), self.profile), 4)
+        }else{
+            (decode_instruction(opcode, self.profile), 2)
+        };
+        if !self.decode_cache_disabled{
+            if self.decode_cache.is_empty(){
+                self.decode_cache.resize(DECODE_CACHE_SIZE, CachedInstruction::default());
             }
-            //1101_1110_QQQQ_QQQQ UDF error T1, causes error either way
-            if op == 0b1101_1110_0000_0000{
-                return Err(NarmError::InvalidOpcode(op));
+            self.decode_cache[decode_cache_index(address)] = CachedInstruction{
+                address,
+                opcode,
+                size: size as u8,
+                instruction
+            };
+            self.memory.mark_code(address, size);
+        }
+        Ok((instruction, size))
+    }
+    /// Decodes the instruction at the given address with the current ISA profile without executing it, returning it with its size in bytes
+    /// This is intended for debuggers and tracing, and does not use the decoded instruction cache
+    pub fn decode_at(&self, address: u32) -> Result<(Instruction, u32), NarmError>{
+        let opcode = self.memory.get_u16(address)?;
+        if is_32bit_opcode(opcode){
+            let second = self.memory.get_u16(address.wrapping_add(2))?;
+            Ok((decode_instruction32(((opcode as u32) << 16) | (second as u32), self.profile), 4))
+        }else{
+            Ok((decode_instruction(opcode, self.profile), 2))
+        }
+    }
+    /// Enables or disables the decoded instruction cache. It is enabled by default
+    /// While disabled, translated blocks are not cached either, and execute_block executes a single instruction with cycle()
+    pub fn set_decode_cache(&mut self, enabled: bool){
+        self.decode_cache_disabled = !enabled;
+        self.clear_code_caches();
+    }
+    /// Selects the instruction set to execute. The default is ARMv6-M
+    pub fn set_isa_profile(&mut self, profile: IsaProfile){
+        self.profile = profile;
+        self.clear_code_caches();
+    }
+    /// Drops all cached instructions and translated blocks
+    pub(crate) fn clear_code_caches(&mut self){
+        self.decode_cache.clear();
+        self.blocks.clear();
+        self.memory.clear_code_marks();
+    }
+    pub(crate) fn cpu_state(&self) -> CpuState{
+        CpuState{
+            sreg: self.sreg,
+            long_registers: self.long_registers,
+            pc: self.pc,
+            last_pc: self.last_pc,
+            cpsr: self.cpsr,
+            gas_remaining: self.gas_remaining,
+            itstate: self.itstate,
+            exclusive_monitor: self.exclusive_monitor,
+            lowest_sp: self.lowest_sp,
+            cycle_count: self.cycle_count,
+            pending_gas: self.memory.pending_gas()
+        }
+    }
+    pub(crate) fn set_cpu_state(&mut self, state: &CpuState){
+        self.sreg = state.sreg;
+        self.long_registers = state.long_registers;
+        self.pc = state.pc;
+        self.last_pc = state.last_pc;
+        self.cpsr = state.cpsr;
+        self.gas_remaining = state.gas_remaining;
+        self.itstate = state.itstate;
+        self.exclusive_monitor = state.exclusive_monitor;
+        self.lowest_sp = state.lowest_sp;
+        self.cycle_count = state.cycle_count;
+        self.memory.set_pending_gas(state.pending_gas);
+    }
+    pub fn get_isa_profile(&self) -> IsaProfile{
+        self.profile
+    }
+    /// Returns the address and size of the last exclusive load, if the local exclusive monitor is in the exclusive access state
+    pub fn get_exclusive_monitor(&self) -> Option<(u32, u32)>{
+        self.exclusive_monitor
+    }
+    /// Clears the local exclusive monitor, so that the next STREX fails
+    /// Hosts should call this when handling an exception-like event, such as an interrupt, outside of an SVC
+    pub fn clear_exclusive_monitor(&mut self){
+        self.exclusive_monitor = None;
+    }
+    /// Checks an exclusive store against the local exclusive monitor. The monitor is cleared whether the store may proceed or not
+    fn take_exclusive_monitor(&mut self, address: u32, size: u32) -> bool{
+        self.exclusive_monitor.take() == Some((address, size))
+    }
+    /// Sets the lowest address SP may be set to, or 0 to remove the limit
+    /// Any instruction which would set SP below the limit faults with StackOverflow, without changing SP or storing anything below the limit
+    /// The limit is not checked against the current value of SP, nor when host code sets SP with set_reg or external_set_reg rather than set_sp
+    pub fn set_stack_limit(&mut self, limit: u32){
+        self.stack_limit = limit;
+    }
+    pub fn get_stack_limit(&self) -> u32{
+        self.stack_limit
+    }
+    /// Returns the lowest value of SP since the last call to reset_lowest_sp, including the current value
+    /// Stack usage at its peak is the initial value of SP minus this
+    pub fn get_lowest_sp(&self) -> u32{
+        self.lowest_sp.map_or(self.get_sp(), |sp| sp.min(self.get_sp()))
+    }
+    /// Restarts tracking of the lowest SP from the current value of SP
+    pub fn reset_lowest_sp(&mut self){
+        self.lowest_sp = None;
+    }
+    /// Returns a StackOverflow error if SP may not be set to the given value
+    fn check_stack_limit(&self, sp: u32) -> Result<(), NarmError>{
+        if sp.align4() < self.stack_limit{
+            return Err(NarmError::StackOverflow(sp.align4()));
+        }
+        Ok(())
+    }
+    /// Makes YIELD, WFE, WFI and SEV stop execution and return HINT_EXIT combined with the hint, such as EXIT_WFI, in place of an SVC number
+    /// Execution resumes after the hint, so the host can deliver an event or run something else first. Otherwise these are treated as NOP, which is the default
+    pub fn set_yield_on_hints(&mut self, enabled: bool){
+        self.yield_on_hints = enabled;
+    }
+    pub fn get_yield_on_hints(&self) -> bool{
+        self.yield_on_hints
+    }
+    /// Sets the timing model used to count the cycles taken by executed instructions, or None to stop counting
+    /// Cycles are counted separately from gas, so that hosts can use them for gas pricing or performance estimates
+    pub fn set_timing_model(&mut self, model: Option<TimingModel>){
+        self.timing_model = model;
+    }
+    pub fn get_timing_model(&self) -> Option<TimingModel>{
+        self.timing_model
+    }
+    /// Returns the cycles taken by the instructions executed since the last call to reset_cycle_count, while a timing model was set
+    /// Instructions which fault are not counted
+    pub fn get_cycle_count(&self) -> u64{
+        self.cycle_count
+    }
+    pub fn reset_cycle_count(&mut self){
+        self.cycle_count = 0;
+    }
+    /// ITSTATE holds the base condition in the top 4 bits, and the mask for the remaining instructions of the IT block in the bottom 4 bits
+    pub fn get_itstate(&self) -> u8{
+        self.itstate
+    }
+    /// Executes an instruction within an IT block, skipping it if its condition does not pass
+    /// Flag setting by 16 bit data processing instructions is suppressed within an IT block
+    fn execute_it_block_instruction(&mut self, instruction: Instruction) -> Result<u32, NarmError>{
+        use Instruction::*;
+        let condition = (self.itstate >> 4) as u32;
+        //ITAdvance
+        if self.itstate & 0b0000_0111 == 0{
+            self.itstate = 0;
+        }else{
+            self.itstate = (self.itstate & 0b1110_0000) | ((self.itstate << 1) & 0b0001_1111);
+        }
+        let unpredictable = match instruction{
+            ItT1{..} | CbzT1{..} | CbnzT1{..} | BCondT1{..} | BT3{..} => true,
+            _ => instruction.is_branch() && self.itstate != 0
+        };
+        if unpredictable{
+            return Err(NarmError::InvalidITBlockInstruction(self.last_pc & !1));
+        }
+        //BKPT executes unconditionally
+        if !self.condition_passes(condition) && !matches!(instruction, Bkpt{..}){
+            //skipped instructions take a single cycle
+            if self.timing_model.is_some(){
+                self.cycle_count += 1;
             }
+            return Ok(0);
         }
-
-        //opcodes for r3_imm8, r3_reglist, and imm11
-        {
-            let op = opcode & !MASK_R3_IMM8;
-            let (reg, imm) = decode_r3_imm8(opcode);
-            match op{
-                //0100_1xxx_yyyy_yyyy LDR lit T1
-                0b0100_1000_0000_0000 => {
-                    /* t = UInt(Rt);  imm32 = ZeroExtend(imm8:'00', 32);  add = TRUE;
-                        base = Align(PC,4);
-                        address = if add then (base + imm32) else (base - imm32);
-                        R[t] = MemU[address,4];
-                    */
-                    let address = self.virtual_pc.align4() + ((imm as u32) << 2);
-                    self.sreg[reg] = self.memory.get_u32(address)?;
-                    return Ok(0);
-                },
-                //1001_1xxx_yyyy_yyyy LDR imm T2
-                0b1001_1000_0000_0000 => {
-                    let address = self.get_sp() + ((imm as u32) << 2);
-                    self.sreg[reg] = self.memory.get_u32(address)?;
-                    return Ok(0);
-                },
-                //0010_0xxx_yyyy_yyyy MOV imm T1 flags
-                //0010_0000_1111_0001
-                0b0010_0000_0000_0000 => {
-                    let imm = imm as u32;
-                    self.sreg[reg] = imm;
-                    //update flags
-                    self.cpsr.z = imm == 0;
-                    self.cpsr.n = imm.get_bit(31);
-                    //C and V flags unchanged
-                    return Ok(0);
-                },
-                //0011_0xxx_yyyy_yyyy ADDS imm T2 flags
-                0b0011_0000_0000_0000 => {
-                    self.sreg[reg] = self.op_add(self.sreg[reg], imm as u32, false, true);
-                    return Ok(0);
-                },
-                //1010_1xxx_yyyy_yyyy ADD sp+imm T1 noflags
-                0b1010_1000_0000_0000 => {
-                    self.sreg[reg] = self.op_add(self.get_sp(), (imm as u32) << 2, false, false);
-                    return Ok(0);
-                },
-                //1010_0xxx_yyyy_yyyy ADR T1
-                0b1010_0000_0000_0000 => {
-                    self.sreg[reg] = self.virtual_pc.align4() + ((imm as u32) << 2);
-                    return Ok(0);
-                },
-                //0010_1xxx_yyyy_yyyy CMP imm T1
-                0b0010_1000_0000_0000 => {
-                    self.op_add(self.sreg[reg], !(imm as u32), true, true); //result is unused
-                    return Ok(0);
-                },
-                //1001_0xxx_yyyy_yyyy STR imm T2
-                0b1001_0000_0000_0000 => {
-                    let address = self.get_sp() + ((imm as u32) << 2);
-                    self.memory.set_u32(address, self.sreg[reg])?;
-                    return Ok(0);
-                },
-                //0011_1xxx_yyyy_yyyy SUBS imm T2 flags
-                0b0011_1000_0000_0000 => {
-                    self.sreg[reg] = self.op_add(self.sreg[reg], !(imm as u32), true, true);
-                    return Ok(0);
-                },
-                //1100_1xxx_yyyy_yyyy LDM T1
-                0b1100_1000_0000_0000 => {
-                    let reglist = imm; //imm is actually a reg list here
-                    let mut address = self.sreg[reg];
-                    let wback = !reglist.get_bit(reg as u8);
-                    let mut count = 0;
-                    for i in 0..=7{
-                        if reglist.get_bit(i){
-                            self.sreg[i as usize] = self.memory.get_u32(address)?;
-                            address += 4;
-                            count += 1;
-                        }
-                    }
-                    if wback && !reglist.get_bit(reg as u8) {
-                        self.sreg[reg] += 4 * count;
-                    }
-                    return Ok(0);
-                },
-                //1100_0xxx_yyyy_yyyy STM T1
-                0b1100_0000_0000_0000 => {
-                    let reglist = imm; //imm is actually a reg list here
-                    let mut address = self.sreg[reg];
-                    let mut count = 0;
-                    for i in 0..=7{
-                        if reglist.get_bit(i){
-                            //NOTE this does not include the "unknown" unpredictable case:
-                            //If the base register is included and not the lowest-numbered register in the list, such an instruction stores an unknown value for the base register. 
-                            //Use of <Rn> in the register list is deprecated.
-                            self.memory.set_u32(address, self.sreg[i as usize])?;
-                            address += 4;
-                            count += 1;
-                        }
-                    }
-                    self.sreg[reg] += 4 * count;
-                    return Ok(0);
-                },
-                //1110_0xxx_xxxx_xxxx B T2
-                0b1110_0000_0000_0000 => {
-                    let label = sign_extend32((((reg as u32) << 8) | (imm as u32)) << 1, 12);
-                    self.set_thumb_pc_address((self.virtual_pc as i32 + label) as u32);
-                    return Ok(0);
+        let flags = self.cpsr;
+        let result = self.execute_decoded(instruction);
+        if instruction.sets_flags_outside_it_block(){
+            self.cpsr = flags;
+        }
+        result
+    }
+    /// Drops any cached instructions and translated blocks which overlap with memory written since the last call
+    fn invalidate_code(&mut self){
+        for (address, size) in self.memory.take_code_writes(){
+            if !self.blocks.is_empty(){
+                self.blocks.retain(|_, block| !block.overlaps(address, size));
+            }
+            if self.decode_cache.is_empty(){
+                continue;
+            }
+            if size as usize >= DECODE_CACHE_SIZE * 2{
+                for entry in self.decode_cache.iter_mut(){
+                    *entry = CachedInstruction::default();
                 }
-                _ => {}
+                continue;
             }
-        }
-
-        //opcodes for r3_r3
-        {
-            let op = opcode & !MASK_R3_R3;
-            let (reg1, reg2) = decode_r3_r3(opcode);
-            let valuen = self.sreg[reg2];
-            let valuem = self.sreg[reg1];
-
-            match op{
-                //0100_0001_01xx_xyyy ADC reg T1 flags
-                0b0100_0001_0100_0000 => {
-                    self.sreg[reg2] = self.op_add(self.sreg[reg2], self.sreg[reg1], self.cpsr.c, true);
-                    return Ok(0);
-                },
-                //0100_0000_00xx_xyyy AND reg T1 flags
-                0b0100_0000_0000_0000 => {
-                    self.sreg[reg2] = self.sreg[reg2] & self.sreg[reg1];
-                    self.cpsr.n = self.sreg[reg2].get_bit(31);
-                    self.cpsr.z = self.sreg[reg2] == 0;
-                    return Ok(0);
-                },
-                //0100_0001_00xx_xyyy ASRS reg T1 flags
-                0b0100_0001_0000_0000 => {
-                    let shift = self.sreg[reg1] & 0xFF;
-                    let (result, _) = (self.sreg[reg2] as i32).overflowing_shr(shift);
-                    if shift != 0 { // Ignore carry flag if shift by 0
-                        let (shift_one_less, _) = (self.sreg[reg2] as i32).overflowing_shr(shift-1);
-                        let carry = (shift_one_less & 0x01) > 0; // Get last bit shifted out
-                        self.cpsr.c = carry;
-                    }
-                    self.sreg[reg2] = result as u32;
-                    self.set_result_flags(result as u32);
-                    return Ok(0);
-                },
-                //0100_0011_10xx_xyyy BICS T1 flags
-                0b0100_0011_1000_0000 => {
-                    let result = self.sreg[reg2] & !self.sreg[reg1];
-                    self.sreg[reg2] = result;
-                    self.set_result_flags(result);
-                    return Ok(0);
-                },
-                //0100_0010_11xx_xyyy CMN T1 flags
-                0b0100_0010_1100_0000 => {
-                    let _result = self.op_add(valuen, valuem, false, true);
-                    return Ok(0);
-                },
-                //0100_0010_10xx_xyyy CMP reg T1
-                0b0100_0010_1000_0000 => {
-                    let _result = self.op_add(valuen, !valuem, true, true);
-                    return Ok(0);
+            //start 2 bytes early, as a 32 bit opcode may begin in the previous halfword
+            let start = (address & !1).wrapping_sub(2);
+            let mut a = start;
+            for _ in 0..=(size / 2 + 1){
+                let entry = &mut self.decode_cache[decode_cache_index(a)];
+                if entry.address == a{
+                    *entry = CachedInstruction::default();
                 }
-                //0100_0000_01xx_xyyy EORS reg T1 flags
-                0b0100_0000_0100_0000 => {
-                    let result = valuen ^ valuem;
-                    self.sreg[reg2] = result;
-                    self.set_result_flags(result);
-                    return Ok(0);
-                },
-                //0100_0000_10xx_xyyy LSL reg T1 flags
-                0b0100_0000_1000_0000 => {
-                    let shift = valuem & 0xFF;
-                    let (result, _) = valuen.overflowing_shl(shift);
-                    if shift != 0 { // Ignore carry flag if shift by 0
-                        let (shift_one_less, _) = valuen.overflowing_shl(shift - 1);
-                        let carry = (shift_one_less & 0x8000_0000) > 0; // Get last bit shifted out
-                        self.cpsr.c = carry;
-                    }
-                    self.sreg[reg2] = result;
-                    self.set_result_flags(result);
-                    return Ok(0);
-                },
-                //0100_0000_11xx_xyyy LSR reg T1 flags
-                0b0100_0000_1100_0000 => {
-                    let shift = valuem & 0xFF;
-                    let (result, _) = valuen.overflowing_shr(shift);
-                    if shift != 0 { // Ignore carry flag if shift by 0
-                        let (shift_one_less, _) = valuen.overflowing_shr(shift - 1);
-                        let carry = (shift_one_less & 0x01) > 0; // Get last bit shifted out
-                        self.cpsr.c = carry;
-                    }
-                    self.sreg[reg2] = result;
-                    self.set_result_flags(result);
-                    return Ok(0);
-                },
-                //0100_0110_ZZxx_xyyy MOV reg T1 noflags (Z is to only access r0-r7 for ARMv6-M)
-                0b0100_0110_0000_0000 => {
-                    self.sreg[reg2] = valuem;
-                    return Ok(0);
-                },
-                //0000_0000_00xx_xyyy MOVS reg T2 flags
-                0b0000_0000_0000_0000 => {
-                    //NOTE: this shares the same identifying "mask" as LSL imm T1.
-                    self.sreg[reg2] = valuem;
-                    self.set_result_flags(valuem);
-                    return Ok(0);
-                },
-                //0100_0011_01xx_xyyy MUL T1 flags
-                0b0100_0011_0100_0000 => {
-                    let (result, _) = (valuen as u32).overflowing_mul(valuem as u32);
-                    self.sreg[reg2] = result;
-                    self.set_result_flags(result);
-                    return Ok(0);
-                },
-                //0100_0011_11xx_xyyy MVNS T1 flags
-                0b0100_0011_1100_0000 => {
-                    self.sreg[reg2] = !valuem;
-                    self.set_result_flags(!valuem);
-                    return Ok(0);
-                },
-                //0100_0011_00xx_xyyy ORRS reg T1 flags
-                0b0100_0011_0000_0000 => {
-                    let result = valuen | valuem;
-                    self.sreg[reg2] = result;
-                    self.set_result_flags(result);
-                    return Ok(0);
-                },
-                //1011_1010_00xx_xyyy REV T1
-                0b1011_1010_0000_0000 => {
-                    //Operation is to turn 0x11223344 into 0x44332211
-                    let result = 
-                        ((valuem & 0xFF) << 24) | 
-                        ((valuem & 0xFF00) << 8) |
-                        ((valuem & 0xFF0000) >> 8) |
-                        ((valuem & 0xFF000000) >> 24);
-                    self.sreg[reg2] = result;
-                    return Ok(0);
-                },
-                //1011_1010_01xx_xyyy REV16 T1
-                0b1011_1010_0100_0000 => {
-                    //operation is to turn 0x11223344 into 0x22114433
-                    let result = 
-                        ((valuem & 0xFF) << 8) | 
-                        ((valuem & 0xFF00) >> 8) |
-                        ((valuem & 0xFF0000) << 8) |
-                        ((valuem & 0xFF000000) >> 8);
-                    self.sreg[reg2] = result;
-                    return Ok(0);
-                },
-                //1011_1010_11xx_xyyy REVSH T1
-                0b1011_1010_1100_0000 => {
-                    //Byte-Reverse Signed Halfword
-                    //Reverses the byte order in the lower 16-bit halfword and sign extends the result to 32btis
-                    //Operation: 0x11223344 -> 0x00004433
-                    //Operation: 0x1122AAFF -> 0xFFFFFFAA
-                    let result = (((valuem & 0xFF) as i8 as i32 as u32) << 8) | ((valuem & 0xFF00) >> 8);
-                    self.sreg[reg2] = result;
-                    return Ok(0);
-                },
-                //0100_0001_11xx_xyyy ROR reg T1 flags
-                0b0100_0001_1100_0000 => {
-                    let shift = valuem % 32;
-                    let result = valuen.rotate_right(shift);
-                    self.sreg[reg2] = result;
-                    //TODO needs live testing to confirm carry behavior, under documented and conflicting sources
-                    // The QEMU-based VM I'm testing against indeed sets carry to last out-shifted bit, so this *should* be correct /Johannes
-                    if shift != 0 { // Ignore carry flag if shift by 0
-                        self.cpsr.c = result & (1 << 31) > 0;
-                    }
-                    self.set_result_flags(result);
-                    return Ok(0);
-                },
-                //0100_0010_01xx_xyyy RSB imm T1 flags (ntoe: imm is forced to 0 for ARMv6-M)
-                0b0100_0010_0100_0000 => {
-                    self.sreg[reg2] = self.op_add(!valuem, 0, true, true);
-                    return Ok(0);
-                },
-                //0100_0001_10xx_xyyy SBCS T1 flags
-                0b0100_0001_1000_0000 => {
-                    self.sreg[reg2] = self.op_add(valuen, !valuem, self.cpsr.c, true);
-                    return Ok(0);
-                },
-                //1011_0010_01xx_xyyy SXTB T1
-                0b1011_0010_0100_0000 => {
-                    self.sreg[reg2] = ((self.sreg[reg1] & 0xFF) as u8 as i8 as i32) as u32;
-                    return Ok(0);
-                },
-                //1011_0010_00xx_xyyy SXTH T1
-                0b1011_0010_0000_0000 => {
-                    self.sreg[reg2] = ((self.sreg[reg1] & 0xFFFF) as u16 as i16 as i32) as u32;
-                    return Ok(0);
-                },
-                //0100_0010_00xx_xyyy TST reg T1 flags
-                0b0100_0010_0000_0000 => {
-                    let result = valuen & valuem;
-                    //result is not written back
-                    self.set_result_flags(result);
-                    return Ok(0);
-                },
-                //1011_0010_11xx_xyyy UXTB T1
-                0b1011_0010_1100_0000 => {
-                    self.sreg[reg2] = self.sreg[reg1] & 0xFF;
-                    return Ok(0);
-                },
-                //1011_0010_10xx_xyyy UXTH T1
-                0b1011_0010_1000_0000 => {
-                    self.sreg[reg2] = self.sreg[reg1] & 0xFFFF;
-                    return Ok(0);
-                },
-                _ => {}
-
+                a = a.wrapping_add(2);
             }
         }
-
-        //opcodes for r3_r3_r3 and imm3_r3_r3
-        {
-            let op = opcode & !MASK_R3_R3_R3;
-            let (reg1, reg2, reg3) = decode_r3_r3_r3(opcode);
-            let valuem = self.sreg[reg1];
-            let valuen= self.sreg[reg2];
-            //reg3 is almost always destination register
-            match op{
-                //0001_100x_xxyy_yzzz ADDS reg T1 flags
-                0b0001_1000_0000_0000 => {
-                    self.sreg[reg3] = self.op_add(valuen, valuem, false, true);
-                    return Ok(0);
-                },
-                //0101_100x_xxyy_yzzz LDR reg T1
-                0b0101_1000_0000_0000 => {
-                    let address = valuen.wrapping_add(valuem);
-                    self.sreg[reg3] = self.memory.get_u32(address)?;
-                    return Ok(0);
-                },
-                //0101_110x_xxyy_yzzz LDRB reg T1
-                0b0101_1100_0000_0000 => {
-                    let address = valuen.wrapping_add(valuem);
-                    self.sreg[reg3] = self.memory.get_u8(address)? as u32;
-                    return Ok(0);
-                },
-                //0101_101x_xxyy_yzzz LDRH reg T1
-                0b0101_1010_0000_0000 => {
-                    let address = valuen.wrapping_add(valuem);
-                    self.sreg[reg3] = self.memory.get_u16(address)? as u32;
-                    return Ok(0);
-                },
-                //0101_011x_xxyy_yzzz LDRSB reg T1
-                0b0101_0110_0000_0000 => {
-                    let address = valuen.wrapping_add(valuem);
-                    self.sreg[reg3] = self.memory.get_u8(address)? as i8 as i32 as u32;
-                    return Ok(0);
-                },
-                //0101_111x_xxyy_yzzz LDRSH reg T1
-                0b0101_1110_0000_0000 => {
-                    let address = valuen.wrapping_add(valuem);
-                    self.sreg[reg3] = self.memory.get_u16(address)? as i16 as i32 as u32;
-                    return Ok(0);
-                },
-                //0101_000x_xxyy_yzzz STR reg T1
-                0b0101_0000_0000_0000 => {
-                    let address = valuen.wrapping_add(valuem);
-                    self.memory.set_u32(address, self.sreg[reg3])?;
-                    return Ok(0);
-                },
-                //0101_010x_xxyy_yzzz STRB reg T1
-                0b0101_0100_0000_0000 => {
-                    let address = valuen.wrapping_add(valuem);
-                    self.memory.set_u8(address, (self.sreg[reg3] & 0xFF) as u8)?;
-                    return Ok(0);
-                },
-                //0101_001x_xxyy_yzzz STRH reg T1
-                0b0101_0010_0000_0000 => {
-                    let address = valuen.wrapping_add(valuem);
-                    self.memory.set_u16(address, (self.sreg[reg3] & 0xFFFF) as u16)?;
-                    return Ok(0);
-                },
-                //0001_101x_xxyy_yzzz SUBS reg T1 flags
-                0b0001_1010_0000_0000 => {
-                    self.sreg[reg3] = self.op_add(valuen, !valuem, true, true);
-                    return Ok(0);
-                },
-                //0001_111x_xxyy_yzzz SUBS imm T1 flags
-                0b0001_1110_0000_0000 => {
-                    let imm3 = reg1 as u32;
-                    self.sreg[reg3] = self.op_add(self.sreg[reg2], !imm3, true, true);
-                    return Ok(0);
-                },
-                //0001_110x_xxyy_yzzz ADD imm T1 flags
-                0b0001_1100_0000_0000 => {
-                    let imm3 = reg1 as u32;
-                    self.sreg[reg3] = self.op_add(self.sreg[reg2], imm3, false, true);
-                    return Ok(0);
-                },
-                _ => {}
+    }
+    /// Alternative to execute() which runs whole basic blocks at a time using execute_block()
+    /// The results, including gas usage and errors, are identical to execute()
+    pub fn execute_blocks(&mut self) -> Result<u32, NarmError>{
+        loop{
+            let result = self.execute_block()?;
+            if result != 0{
+                return Ok(result);
             }
         }
-
-        //n1_r4_rn3
-        {
-            let op = opcode & !MASK_N1_R4_RN3;
-            let (reg1, reg2) = decode_n1_r4_rn3(opcode);
-            match op{
-                //0100_0101_xyyy_yzzz CMP reg T2 
-                0b0100_0101_0000_0000 => {
-                    //Either register being from PC (r15) is considered unpredictable by ARM architecture. To prevent weirdness with later upgrades, this will be forced to 0
-                    let rm = if reg1.register == 15{
-                        0
-                    }else{
-                        self.get_reg(&reg1)
-                    };
-                    let rn = if reg2.register == 15{
-                        0
-                    }else{
-                        self.get_reg(&reg2)
-                    };
-                    self.op_add(rn, !rm, true, true);
-                    return Ok(0);
-                },
-                //0100_0100_xyyy_yzzz ADD reg T2 noflags
-                //0100_0100_x110_1yyy ADD sp+reg T1 noflags (2nd arg must be 1101) -PSUEDO
-                //0100_0100_1xxx_x101 ADD sp+reg T2 noflags (1st and 3rd args form 1101) -PSUEDO
-                0b0100_0100_0000_0000 => {
-                    //note! order of deciding to deal with reg2 vs reg1 being equal to 13 is critical!
-                    //if reg2 is 13, then the T1 encoding logic must be used
-                    if reg1.register == 13{
-                        //sp+reg T1
-                        //ADD <Rdm>, SP, <Rdm>
-                        let rm = self.get_reg(&reg2);
-                        let sp = self.get_reg(&reg1);
-                        let result = self.op_add(sp, rm, false, false);
-                        self.set_reg(&reg2, result);
-                    }else if reg2.register == 13{
-                        //sp+reg T2
-                        //ADD SP,<Rm>
-                        let rm = self.get_reg(&reg1);
-                        let sp = self.get_reg(&reg2);
-                        let result = self.op_add(sp, rm, false, false);
-                        self.set_reg(&reg2, result);
-                    }else{
-                        if reg1.register == 15 && reg2.register == 15{
-                            //listed as UNPREDICTABLE, so just exit here
-                            return Ok(0);
-                        }
-                        let rm = self.get_reg(&reg1);
-                        let rn = self.get_reg(&reg2);
-                        let result = self.op_add(rn, rm, false, false);
-                        self.set_reg(&reg2, result);
-                    }
-                    return Ok(0);
-                },
-                //0100_0110_xyyy_yzzz MOV reg T1 noflags
-                0b0100_0110_0000_0000 => {
-                    
-                    if 
-                        (reg1.register == 15 || reg1.register == 13) && 
-                        (reg2.register == 15 || reg2.register == 13) 
-                    {
-                        // "ARM deprecates the use of the following MOV (register) instructions in which <Rd> is the SP or PC and <Rm> is also the SP or PC"
-                        // Meaning, if both registers are either the SP or the PC it's no good
-                        // Probably shouldn't be an error since it still compiles?
-                    }
-                    else if reg1.register == 15 {
-                        self.set_reg(&reg2, self.get_last_pc());
-                    }
-                    else if reg2.register == 15 {
-                        // Note this is a simple branch in ARMv6, but in ARMv7 will be interworking
-                        self.set_thumb_pc_address(self.get_reg(&reg1));
-                    }
-                    else {
-                        self.set_reg(&reg2, self.get_reg(&reg1));
-                    }
-  
-                    return Ok(0);
-                },
-                _ => {}
+    }
+    /// Executes the basic block at the current pc, returning the SVC number if one was executed or 0 otherwise
+    /// Blocks are translated once and cached by address. The gas for the entire block is charged on entry and the unused part refunded if execution leaves the block early
+    /// Execution may stop within a block, such as after a memory write which accrues memory gas or modifies code. Execution then continues at the following instruction on the next call
+    /// Falls back to a single cycle() when a block can not be used, such as when there is not enough gas remaining for the entire block, while recording,
+    /// or while the decode cache is disabled
+    pub fn execute_block(&mut self) -> Result<u32, NarmError>{
+        let result = self.run_block();
+        if let Err(e) = result{
+            self.exclusive_monitor = None;
+            self.last_error = Some(e);
+        }
+        result
+    }
+    fn run_block(&mut self) -> Result<u32, NarmError>{
+        if self.memory.has_code_writes(){
+            self.invalidate_code();
+        }
+        //recording needs the side effects of each instruction separately, and disabling the decode cache means nothing is cached
+        if self.pc & 1 == 0 || self.itstate != 0 || self.recording.is_some() || self.decode_cache_disabled{
+            return self.cycle();
+        }
+        let address = self.get_pc_address();
+        let block = match self.blocks.get(&address){
+            Some(b) => b.clone(),
+            None => {
+                match BasicBlock::translate(&self.memory, address, self.profile){
+                    Some(b) => {
+                        self.memory.mark_code(b.start, b.end.wrapping_sub(b.start));
+                        let b = Arc::new(b);
+                        self.blocks.insert(address, b.clone());
+                        b
+                    },
+                    None => return self.cycle()
+                }
             }
+        };
+        if self.gas_remaining < block.gas{
+            return self.cycle();
         }
-
-        //imm5_r3_r3
-        {
-            let op = opcode & !MASK_IMM5_R3_R3;
-            let (imm, reg1, reg2) = decode_imm5_r3_r3(opcode);
-            match op{
-                //0001_0xxx_xxyy_yzzz ASR imm T1 flags
-                0b0001_0000_0000_0000 => {
-                    //shift_t = SRType_ASR; shift_n = if imm5 == '00000' then 32 else UInt(imm5);
-                    let shift = if imm == 0{
-                        32
-                    }else{
-                        imm
-                    };
-                    let (result, _) = (self.sreg[reg1] as i32).overflowing_shr(shift);
-                    if shift != 32 { // Ignore carry flag if shift by 0. Doesn't make sense that imm is ever 0, but it compiles...
-                        let (shift_one_less, _) = (self.sreg[reg1] as i32).overflowing_shr(shift - 1);
-                        let carry = (shift_one_less & 0x01) > 0; // Get last bit shifted out
-                        self.cpsr.c = carry;
+        self.gas_remaining -= block.gas;
+        let mut used = 0;
+        let last = block.ops.len() - 1;
+        for (i, op) in block.ops.iter().enumerate(){
+            self.log_opcode(op.address, op.opcode);
+            used += op.instruction.gas_cost();
+            //pc state is only needed by instructions which read or write it, and is otherwise updated when leaving the block
+            let synced = op.reads_pc || i == last;
+            if synced{
+                self.sync_pc(op);
+            }
+            let result = match self.execute_decoded(op.instruction){
+                Ok(r) => r,
+                Err(e) => {
+                    self.gas_remaining += block.gas - used;
+                    if !synced{
+                        self.sync_pc(op);
                     }
-                    self.sreg[reg2] = result as u32;
-                    self.set_result_flags(result as u32);
-                    return Ok(0); 
-                },
-                //0110_1xxx_xxyy_yzzz LDR imm T1
-                0b0110_1000_0000_0000 => {
-                    let imm = imm << 2;
-                    let address = self.sreg[reg1].wrapping_add(imm);
-                    self.sreg[reg2] = self.memory.get_u32(address)?;
-                    return Ok(0);
-                },
-                //0111_1xxx_xxyy_yzzz LDRB imm T1
-                0b0111_1000_0000_0000 => {
-                    let address = self.sreg[reg1].wrapping_add(imm);
-                    self.sreg[reg2] = self.memory.get_u8(address)? as u32;
-                    return Ok(0); 
+                    return Err(e);
                 }
-                //1000_1xxx_xxyy_yzzz LDRH imm T1
-                0b1000_1000_0000_0000 => {
-                    let imm = imm << 1;
-                    let address = self.sreg[reg1].wrapping_add(imm);
-                    self.sreg[reg2] = self.memory.get_u16(address)? as u32;
-                    return Ok(0);   
+            };
+            if i == last{
+                if self.memory.pending_gas() != 0{
+                    self.charge_memory_gas()?;
                 }
-                //0000_0xxx_xxyy_yzzz LSL imm T1 flags
-                0b0000_0000_0000_0000 => {
-                    //note this shares the same identifying mask as MOV reg T2
-                    //they only conflict if the imm5 argument here is 0. Thus, if imm5 is 0, then defer execution, as the mov instruction should execute instead
-                    //this shouldn't ever happen right now, but if the opcode decoding logic were reorganized it could happen in the future, so guard for it now by doing nothing if imm5 is 0
-                    if imm != 0{
-                        let (result, _) = self.sreg[reg1].overflowing_shl(imm);
-                        let (shift_one_less, _) = self.sreg[reg1].overflowing_shl(imm-1);
-                        let carry = (shift_one_less & 0x8000_0000) > 0; // Get last bit shifted out
-                        self.sreg[reg2] = result;
-                        self.set_result_flags(result);
-                        self.cpsr.c = carry;
-                        return Ok(0);
-                    }
-                },
-                //0000_1xxx_xxyy_yzzz LSR imm T1 flags
-                0b0000_1000_0000_0000 => {
-                    let imm = if imm == 0{
-                        32
-                    }else{
-                        imm
-                    };
-                    let (result, _) = self.sreg[reg1].overflowing_shr(imm);
-                    if imm != 32 { // Ignore carry flag if shift by 0. Doesn't make sense that imm is ever 0, but it compiles...
-                        let (shift_one_less, _) = self.sreg[reg1].overflowing_shr(imm-1);
-                        let carry = (shift_one_less & 0x01) > 0; // Get last bit shifted out
-                        self.cpsr.c = carry;
-                    }
-                    self.sreg[reg2] = result;
-                    self.set_result_flags(result);
-                    return Ok(0);
-                },
-                //0110_0xxx_xxyy_yzzz STR imm T1
-                0b0110_0000_0000_0000 => {
-                    let imm = imm << 2;
-                    let address = self.sreg[reg1].wrapping_add(imm);
-                    self.memory.set_u32(address, self.sreg[reg2])?;
-                    return Ok(0); 
-                },
-                //0111_0xxx_xxyy_yzzz STRB imm T1
-                0b0111_0000_0000_0000 => {
-                    let address = self.sreg[reg1].wrapping_add(imm);
-                    self.memory.set_u8(address, (self.sreg[reg2] & 0xFF) as u8)?;
-                    return Ok(0); 
-                },
-                //1000_0xxx_xxyy_yzzz STRH imm T
-                0b1000_0000_0000_0000 => {
-                    let imm = imm << 1;
-                    let address = self.sreg[reg1].wrapping_add(imm);
-                    self.memory.set_u16(address, (self.sreg[reg2] & 0xFFFF) as u16)?;
-                    return Ok(0); 
-                },
-                _ => {}
+                return Ok(result);
             }
-        }
-        //B<C> and SVC
-        {
-            let op = opcode & !MASK_C4_IMM8;
-            //1101_cccc_xxxx_xxxx B<c> T1 (note: if cond == '1110' then UNDEFINED????)
-            //1101_1111_xxxx_xxxx SVC T1 (B with condition code 1111)
-            if op == 0b1101_0000_0000_0000{
-                let (cond, imm) = decode_c4_imm8(opcode);
-                if cond == 0b1111{
-                    //SVC opcode, so just exit with specified code
-                    return Ok(imm);
-                }
-                if self.condition_passes(cond){
-                    let label = sign_extend32((imm as u32) << 1, 9);
-                    self.set_thumb_pc_address((self.virtual_pc as i32 + label) as u32);
+            if op.writes_memory && (self.memory.pending_gas() != 0 || self.memory.has_code_writes()){
+                //leave the block so that memory gas is charged and modified code is retranslated
+                self.gas_remaining += block.gas - used;
+                self.sync_pc(op);
+                if self.memory.pending_gas() != 0{
+                    self.charge_memory_gas()?;
                 }
-                return Ok(0);
+                return Ok(result);
             }
         }
-        //imm5_r3_r3
-        {
-            let op = opcode & !MASK_X1_RL8;
-            let (option, reglist) = decode_x1_rl8(opcode);
-            match op{
-                //1011_110x_yyyy_yyyy POP T1 (x is if PC should be popped)
-                0b1011_1100_0000_0000 => {
-                    let mut address = self.get_sp();
-                    let mut count = 0;
-                    for i in 0..=7{
-                        if reglist.get_bit(i){
-                            self.sreg[i as usize] = self.memory.get_u32(address)?;
-                            address += 4;
-                            count += 1;
-                        }
+        Ok(0)
+    }
+    /// Sets pc, virtual_pc and last_pc to the state cycle() would have while executing the given instruction
+    fn sync_pc(&mut self, op: &BlockOp){
+        self.last_pc = op.address | 1;
+        self.virtual_pc = self.last_pc + 4;
+        self.pc = self.last_pc + op.size as u32;
+    }
+    /// Executes an already decoded instruction. pc must already point at the following instruction
+    /// Instructions which do not write PC leave it unchanged, so an instruction wrote PC if it differs afterwards, even in the block engine where pc is not always kept up to date
+    fn execute_decoded(&mut self, instruction: Instruction) -> Result<u32, NarmError>{
+        let next_pc = self.pc;
+        let result = self.execute_operation(instruction);
+        if let Some(sp) = self.stack_overflow.take(){
+            return Err(NarmError::StackOverflow(sp));
+        }
+        if let (Some(model), Ok(_)) = (self.timing_model, &result){
+            self.cycle_count += instruction.cycles(model, self.pc != next_pc) as u64;
+        }
+        if let (Some(statistics), Ok(_)) = (&mut self.statistics, &result){
+            statistics.record(&instruction, self.pc != next_pc);
+        }
+        result
+    }
+    fn execute_operation(&mut self, instruction: Instruction) -> Result<u32, NarmError>{
+        use Instruction::*;
+        match instruction{
+            BlT1{imm32} => {
+                let lr = LongRegister{register: 14};
+                self.write_long_reg(&lr, self.virtual_pc | (self.pc & 1)); //or with bottom bit of current pc to copy interworking mode
+                self.set_thumb_pc_address((self.virtual_pc as i32).wrapping_add(imm32) as u32);
+            },
+            SdivT1{rd, rn, rm} => {
+                let n = self.get_reg(&LongRegister{register: rn as usize}) as i32;
+                let m = self.get_reg(&LongRegister{register: rm as usize}) as i32;
+                //division by zero gives 0 as the divide by zero trap is not supported. INT_MIN / -1 overflows back to INT_MIN
+                let result = if m == 0 { 0 } else { n.wrapping_div(m) };
+                self.write_long_reg(&LongRegister{register: rd as usize}, result as u32);
+            },
+            UdivT1{rd, rn, rm} => {
+                let n = self.get_reg(&LongRegister{register: rn as usize});
+                let m = self.get_reg(&LongRegister{register: rm as usize});
+                let result = n.checked_div(m).unwrap_or(0);
+                self.write_long_reg(&LongRegister{register: rd as usize}, result);
+            },
+            CbzT1{rn, imm32} => {
+                if self.sreg[rn as usize] == 0{
+                    self.set_thumb_pc_address(self.virtual_pc + imm32);
+                }
+            },
+            CbnzT1{rn, imm32} => {
+                if self.sreg[rn as usize] != 0{
+                    self.set_thumb_pc_address(self.virtual_pc + imm32);
+                }
+            },
+            ItT1{firstcond, mask} => {
+                self.itstate = (firstcond << 4) | mask;
+            },
+            MovwT3{rd, imm16} => {
+                self.write_long_reg(&LongRegister{register: rd as usize}, imm16);
+            },
+            MovtT1{rd, imm16} => {
+                let rd = LongRegister{register: rd as usize};
+                let value = (self.get_reg(&rd) & 0xFFFF) | (imm16 << 16);
+                self.write_long_reg(&rd, value);
+            },
+            LdrexT1{rt, rn, imm32} => {
+                let address = self.get_reg(&LongRegister{register: rn as usize}).wrapping_add(imm32);
+                let value = self.memory.get_u32(address)?;
+                self.exclusive_monitor = Some((address, 4));
+                self.write_long_reg(&LongRegister{register: rt as usize}, value);
+            },
+            LdrexbT1{rt, rn} => {
+                let address = self.get_reg(&LongRegister{register: rn as usize});
+                let value = self.memory.get_u8(address)? as u32;
+                self.exclusive_monitor = Some((address, 1));
+                self.write_long_reg(&LongRegister{register: rt as usize}, value);
+            },
+            LdrexhT1{rt, rn} => {
+                let address = self.get_reg(&LongRegister{register: rn as usize});
+                let value = self.memory.get_u16(address)? as u32;
+                self.exclusive_monitor = Some((address, 2));
+                self.write_long_reg(&LongRegister{register: rt as usize}, value);
+            },
+            StrexT1{rd, rt, rn, imm32} => {
+                let address = self.get_reg(&LongRegister{register: rn as usize}).wrapping_add(imm32);
+                if self.take_exclusive_monitor(address, 4){
+                    self.memory.set_u32(address, self.get_reg(&LongRegister{register: rt as usize}))?;
+                    self.write_long_reg(&LongRegister{register: rd as usize}, 0);
+                }else{
+                    self.write_long_reg(&LongRegister{register: rd as usize}, 1);
+                }
+            },
+            StrexbT1{rd, rt, rn} => {
+                let address = self.get_reg(&LongRegister{register: rn as usize});
+                if self.take_exclusive_monitor(address, 1){
+                    self.memory.set_u8(address, (self.get_reg(&LongRegister{register: rt as usize}) & 0xFF) as u8)?;
+                    self.write_long_reg(&LongRegister{register: rd as usize}, 0);
+                }else{
+                    self.write_long_reg(&LongRegister{register: rd as usize}, 1);
+                }
+            },
+            StrexhT1{rd, rt, rn} => {
+                let address = self.get_reg(&LongRegister{register: rn as usize});
+                if self.take_exclusive_monitor(address, 2){
+                    self.memory.set_u16(address, (self.get_reg(&LongRegister{register: rt as usize}) & 0xFFFF) as u16)?;
+                    self.write_long_reg(&LongRegister{register: rd as usize}, 0);
+                }else{
+                    self.write_long_reg(&LongRegister{register: rd as usize}, 1);
+                }
+            },
+            ClrexT1 => {
+                self.exclusive_monitor = None;
+            },
+            BT4{imm32} => {
+                self.set_thumb_pc_address((self.virtual_pc as i32 + imm32) as u32);
+            },
+            DmbT1{..} | DsbT1{..} | IsbT1{..} => {},
+            AndImmT1{rd, rn, imm32, carry, setflags} => {
+                self.set_logical_result(rd, self.read_reg(rn) & imm32, carry.unwrap_or(self.cpsr.c), setflags);
+            },
+            TstImmT1{rn, imm32, carry} => {
+                self.set_logical_flags(self.read_reg(rn) & imm32, carry.unwrap_or(self.cpsr.c));
+            },
+            BicImmT1{rd, rn, imm32, carry, setflags} => {
+                self.set_logical_result(rd, self.read_reg(rn) & !imm32, carry.unwrap_or(self.cpsr.c), setflags);
+            },
+            OrrImmT1{rd, rn, imm32, carry, setflags} => {
+                self.set_logical_result(rd, self.read_reg(rn) | imm32, carry.unwrap_or(self.cpsr.c), setflags);
+            },
+            MovImmT2{rd, imm32, carry, setflags} => {
+                self.set_logical_result(rd, imm32, carry.unwrap_or(self.cpsr.c), setflags);
+            },
+            OrnImmT1{rd, rn, imm32, carry, setflags} => {
+                self.set_logical_result(rd, self.read_reg(rn) | !imm32, carry.unwrap_or(self.cpsr.c), setflags);
+            },
+            MvnImmT1{rd, imm32, carry, setflags} => {
+                self.set_logical_result(rd, !imm32, carry.unwrap_or(self.cpsr.c), setflags);
+            },
+            EorImmT1{rd, rn, imm32, carry, setflags} => {
+                self.set_logical_result(rd, self.read_reg(rn) ^ imm32, carry.unwrap_or(self.cpsr.c), setflags);
+            },
+            TeqImmT1{rn, imm32, carry} => {
+                self.set_logical_flags(self.read_reg(rn) ^ imm32, carry.unwrap_or(self.cpsr.c));
+            },
+            AddImmT3{rd, rn, imm32, setflags} => {
+                let result = self.op_add(self.read_reg(rn), imm32, false, setflags);
+                self.write_reg(rd, result);
+            },
+            CmnImmT1{rn, imm32} => {
+                let _result = self.op_add(self.read_reg(rn), imm32, false, true);
+            },
+            AdcImmT1{rd, rn, imm32, setflags} => {
+                let result = self.op_add(self.read_reg(rn), imm32, self.cpsr.c, setflags);
+                self.write_reg(rd, result);
+            },
+            SbcImmT1{rd, rn, imm32, setflags} => {
+                let result = self.op_add(self.read_reg(rn), !imm32, self.cpsr.c, setflags);
+                self.write_reg(rd, result);
+            },
+            SubImmT3{rd, rn, imm32, setflags} => {
+                let result = self.op_add(self.read_reg(rn), !imm32, true, setflags);
+                self.write_reg(rd, result);
+            },
+            CmpImmT2{rn, imm32} => {
+                let _result = self.op_add(self.read_reg(rn), !imm32, true, true);
+            },
+            RsbImmT2{rd, rn, imm32, setflags} => {
+                let result = self.op_add(!self.read_reg(rn), imm32, true, setflags);
+                self.write_reg(rd, result);
+            },
+            AddImmT4{rd, rn, imm32} => {
+                self.write_reg(rd, self.read_reg(rn).wrapping_add(imm32));
+            },
+            SubImmT4{rd, rn, imm32} => {
+                self.write_reg(rd, self.read_reg(rn).wrapping_sub(imm32));
+            },
+            AdrT3{rd, imm32} => {
+                self.write_reg(rd, (self.virtual_pc.align4() as i32 + imm32) as u32);
+            },
+            AndRegT2{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, carry) = self.shifted_operand(rm, shift_type, shift);
+                self.set_logical_result(rd, self.read_reg(rn) & operand, carry, setflags);
+            },
+            TstRegT2{rn, rm, shift_type, shift} => {
+                let (operand, carry) = self.shifted_operand(rm, shift_type, shift);
+                self.set_logical_flags(self.read_reg(rn) & operand, carry);
+            },
+            BicRegT2{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, carry) = self.shifted_operand(rm, shift_type, shift);
+                self.set_logical_result(rd, self.read_reg(rn) & !operand, carry, setflags);
+            },
+            OrrRegT2{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, carry) = self.shifted_operand(rm, shift_type, shift);
+                self.set_logical_result(rd, self.read_reg(rn) | operand, carry, setflags);
+            },
+            MovRegT3{rd, rm, shift_type, shift, setflags} => {
+                let (operand, carry) = self.shifted_operand(rm, shift_type, shift);
+                self.set_logical_result(rd, operand, carry, setflags);
+            },
+            OrnRegT1{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, carry) = self.shifted_operand(rm, shift_type, shift);
+                self.set_logical_result(rd, self.read_reg(rn) | !operand, carry, setflags);
+            },
+            MvnRegT2{rd, rm, shift_type, shift, setflags} => {
+                let (operand, carry) = self.shifted_operand(rm, shift_type, shift);
+                self.set_logical_result(rd, !operand, carry, setflags);
+            },
+            EorRegT2{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, carry) = self.shifted_operand(rm, shift_type, shift);
+                self.set_logical_result(rd, self.read_reg(rn) ^ operand, carry, setflags);
+            },
+            TeqRegT1{rn, rm, shift_type, shift} => {
+                let (operand, carry) = self.shifted_operand(rm, shift_type, shift);
+                self.set_logical_flags(self.read_reg(rn) ^ operand, carry);
+            },
+            AddRegT3{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, _) = self.shifted_operand(rm, shift_type, shift);
+                let result = self.op_add(self.read_reg(rn), operand, false, setflags);
+                self.write_reg(rd, result);
+            },
+            CmnRegT2{rn, rm, shift_type, shift} => {
+                let (operand, _) = self.shifted_operand(rm, shift_type, shift);
+                let _result = self.op_add(self.read_reg(rn), operand, false, true);
+            },
+            AdcRegT2{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, _) = self.shifted_operand(rm, shift_type, shift);
+                let result = self.op_add(self.read_reg(rn), operand, self.cpsr.c, setflags);
+                self.write_reg(rd, result);
+            },
+            SbcRegT2{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, _) = self.shifted_operand(rm, shift_type, shift);
+                let result = self.op_add(self.read_reg(rn), !operand, self.cpsr.c, setflags);
+                self.write_reg(rd, result);
+            },
+            SubRegT2{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, _) = self.shifted_operand(rm, shift_type, shift);
+                let result = self.op_add(self.read_reg(rn), !operand, true, setflags);
+                self.write_reg(rd, result);
+            },
+            CmpRegT3{rn, rm, shift_type, shift} => {
+                let (operand, _) = self.shifted_operand(rm, shift_type, shift);
+                let _result = self.op_add(self.read_reg(rn), !operand, true, true);
+            },
+            RsbRegT1{rd, rn, rm, shift_type, shift, setflags} => {
+                let (operand, _) = self.shifted_operand(rm, shift_type, shift);
+                let result = self.op_add(!self.read_reg(rn), operand, true, setflags);
+                self.write_reg(rd, result);
+            },
+            LslRegT2{rd, rn, rm, setflags} => {
+                let (result, carry) = shift_c(self.read_reg(rn), ShiftType::Lsl, self.read_reg(rm) & 0xFF, self.cpsr.c);
+                self.set_logical_result(rd, result, carry, setflags);
+            },
+            LsrRegT2{rd, rn, rm, setflags} => {
+                let (result, carry) = shift_c(self.read_reg(rn), ShiftType::Lsr, self.read_reg(rm) & 0xFF, self.cpsr.c);
+                self.set_logical_result(rd, result, carry, setflags);
+            },
+            AsrRegT2{rd, rn, rm, setflags} => {
+                let (result, carry) = shift_c(self.read_reg(rn), ShiftType::Asr, self.read_reg(rm) & 0xFF, self.cpsr.c);
+                self.set_logical_result(rd, result, carry, setflags);
+            },
+            RorRegT2{rd, rn, rm, setflags} => {
+                let (result, carry) = shift_c(self.read_reg(rn), ShiftType::Ror, self.read_reg(rm) & 0xFF, self.cpsr.c);
+                self.set_logical_result(rd, result, carry, setflags);
+            },
+            MulT2{rd, rn, rm} => {
+                self.write_reg(rd, self.read_reg(rn).wrapping_mul(self.read_reg(rm)));
+            },
+            ClzT1{rd, rm} => {
+                self.write_reg(rd, self.read_reg(rm).leading_zeros());
+            },
+            RbitT1{rd, rm} => {
+                self.write_reg(rd, self.read_reg(rm).reverse_bits());
+            },
+            SxtbT2{rd, rm, rotation} => {
+                self.write_reg(rd, self.read_reg(rm).rotate_right(rotation) as u8 as i8 as i32 as u32);
+            },
+            SxthT2{rd, rm, rotation} => {
+                self.write_reg(rd, self.read_reg(rm).rotate_right(rotation) as u16 as i16 as i32 as u32);
+            },
+            UxtbT2{rd, rm, rotation} => {
+                self.write_reg(rd, self.read_reg(rm).rotate_right(rotation) as u8 as u32);
+            },
+            UxthT2{rd, rm, rotation} => {
+                self.write_reg(rd, self.read_reg(rm).rotate_right(rotation) as u16 as u32);
+            },
+            SbfxT1{rd, rn, lsb, width} => {
+                //move the top bit of the field to bit 31, then shift back with sign extension
+                let value = self.read_reg(rn) << (32 - lsb - width);
+                self.write_reg(rd, ((value as i32) >> (32 - width)) as u32);
+            },
+            UbfxT1{rd, rn, lsb, width} => {
+                self.write_reg(rd, (self.read_reg(rn) >> lsb) & bitfield_mask(width));
+            },
+            BfiT1{rd, rn, lsb, msb} => {
+                let mask = bitfield_mask(msb - lsb + 1) << lsb;
+                self.write_reg(rd, (self.read_reg(rd) & !mask) | ((self.read_reg(rn) << lsb) & mask));
+            },
+            BfcT1{rd, lsb, msb} => {
+                let mask = bitfield_mask(msb - lsb + 1) << lsb;
+                self.write_reg(rd, self.read_reg(rd) & !mask);
+            },
+            LdrImmT4{rt, rn, imm32, add, index, wback} => {
+                self.load_immediate(rt, rn, imm32, add, index, wback, 4, false)?;
+            },
+            LdrbImmT3{rt, rn, imm32, add, index, wback} => {
+                self.load_immediate(rt, rn, imm32, add, index, wback, 1, false)?;
+            },
+            LdrhImmT3{rt, rn, imm32, add, index, wback} => {
+                self.load_immediate(rt, rn, imm32, add, index, wback, 2, false)?;
+            },
+            LdrsbImmT2{rt, rn, imm32, add, index, wback} => {
+                self.load_immediate(rt, rn, imm32, add, index, wback, 1, true)?;
+            },
+            LdrshImmT2{rt, rn, imm32, add, index, wback} => {
+                self.load_immediate(rt, rn, imm32, add, index, wback, 2, true)?;
+            },
+            StrImmT4{rt, rn, imm32, add, index, wback} => {
+                self.store_immediate(rt, rn, imm32, add, index, wback, 4)?;
+            },
+            StrbImmT3{rt, rn, imm32, add, index, wback} => {
+                self.store_immediate(rt, rn, imm32, add, index, wback, 1)?;
+            },
+            StrhImmT3{rt, rn, imm32, add, index, wback} => {
+                self.store_immediate(rt, rn, imm32, add, index, wback, 2)?;
+            },
+            LdrRegT2{rt, rn, rm, shift} => {
+                let value = self.load_sized(self.read_reg(rn).wrapping_add(self.read_reg(rm) << shift), 4, false)?;
+                self.set_load_result(rt, value)?;
+            },
+            LdrbRegT2{rt, rn, rm, shift} => {
+                let value = self.load_sized(self.read_reg(rn).wrapping_add(self.read_reg(rm) << shift), 1, false)?;
+                self.write_reg(rt, value);
+            },
+            LdrhRegT2{rt, rn, rm, shift} => {
+                let value = self.load_sized(self.read_reg(rn).wrapping_add(self.read_reg(rm) << shift), 2, false)?;
+                self.write_reg(rt, value);
+            },
+            LdrsbRegT2{rt, rn, rm, shift} => {
+                let value = self.load_sized(self.read_reg(rn).wrapping_add(self.read_reg(rm) << shift), 1, true)?;
+                self.write_reg(rt, value);
+            },
+            LdrshRegT2{rt, rn, rm, shift} => {
+                let value = self.load_sized(self.read_reg(rn).wrapping_add(self.read_reg(rm) << shift), 2, true)?;
+                self.write_reg(rt, value);
+            },
+            StrRegT2{rt, rn, rm, shift} => {
+                self.store_sized(self.read_reg(rn).wrapping_add(self.read_reg(rm) << shift), 4, self.read_reg(rt))?;
+            },
+            StrbRegT2{rt, rn, rm, shift} => {
+                self.store_sized(self.read_reg(rn).wrapping_add(self.read_reg(rm) << shift), 1, self.read_reg(rt))?;
+            },
+            StrhRegT2{rt, rn, rm, shift} => {
+                self.store_sized(self.read_reg(rn).wrapping_add(self.read_reg(rm) << shift), 2, self.read_reg(rt))?;
+            },
+            LdrdImmT1{rt, rt2, rn, imm32, add, index, wback} => {
+                let (address, offset_address) = self.offset_address(rn, imm32, add, index);
+                let value = self.memory.get_u32(address)?;
+                let value2 = self.memory.get_u32(address.wrapping_add(4))?;
+                if wback{
+                    self.write_reg(rn, offset_address);
+                }
+                self.write_reg(rt, value);
+                self.write_reg(rt2, value2);
+            },
+            StrdImmT1{rt, rt2, rn, imm32, add, index, wback} => {
+                let (address, offset_address) = self.offset_address(rn, imm32, add, index);
+                if wback && rn == 13{
+                    self.check_stack_limit(offset_address)?;
+                }
+                self.memory.set_u32(address, self.read_reg(rt))?;
+                self.memory.set_u32(address.wrapping_add(4), self.read_reg(rt2))?;
+                if wback{
+                    self.write_reg(rn, offset_address);
+                }
+            },
+            LdmT2{rn, registers, wback} => {
+                self.load_multiple(rn, registers, wback, false)?;
+            },
+            LdmdbT1{rn, registers, wback} => {
+                self.load_multiple(rn, registers, wback, true)?;
+            },
+            StmT2{rn, registers, wback} => {
+                self.store_multiple(rn, registers, wback, false)?;
+            },
+            StmdbT1{rn, registers, wback} => {
+                self.store_multiple(rn, registers, wback, true)?;
+            },
+            BT3{cond, imm32} => {
+                if self.condition_passes(cond as u32){
+                    self.set_thumb_pc_address((self.virtual_pc as i32 + imm32) as u32);
+                }
+            },
+            TbbT1{rn, rm} => {
+                //PC as Rn is the address of the table which directly follows the instruction
+                let pc = self.virtual_pc & !1;
+                let base = if rn == 15 {pc} else {self.read_reg(rn)};
+                let offset = self.memory.get_u8(base.wrapping_add(self.read_reg(rm)))? as u32;
+                self.set_thumb_pc_address(pc.wrapping_add(offset * 2));
+            },
+            TbhT1{rn, rm} => {
+                let pc = self.virtual_pc & !1;
+                let base = if rn == 15 {pc} else {self.read_reg(rn)};
+                let offset = self.memory.get_u16(base.wrapping_add(self.read_reg(rm) << 1))? as u32;
+                self.set_thumb_pc_address(pc.wrapping_add(offset * 2));
+            },
+            MlaT1{rd, rn, rm, ra} => {
+                let n = self.get_reg(&LongRegister{register: rn as usize});
+                let m = self.get_reg(&LongRegister{register: rm as usize});
+                let a = self.get_reg(&LongRegister{register: ra as usize});
+                self.write_long_reg(&LongRegister{register: rd as usize}, a.wrapping_add(n.wrapping_mul(m)));
+            },
+            MlsT1{rd, rn, rm, ra} => {
+                let n = self.get_reg(&LongRegister{register: rn as usize});
+                let m = self.get_reg(&LongRegister{register: rm as usize});
+                let a = self.get_reg(&LongRegister{register: ra as usize});
+                self.write_long_reg(&LongRegister{register: rd as usize}, a.wrapping_sub(n.wrapping_mul(m)));
+            },
+            SmullT1{rdlo, rdhi, rn, rm} => {
+                let n = self.get_reg(&LongRegister{register: rn as usize}) as i32 as i64;
+                let m = self.get_reg(&LongRegister{register: rm as usize}) as i32 as i64;
+                self.set_long_result(rdlo, rdhi, n.wrapping_mul(m) as u64);
+            },
+            UmullT1{rdlo, rdhi, rn, rm} => {
+                let n = self.get_reg(&LongRegister{register: rn as usize}) as u64;
+                let m = self.get_reg(&LongRegister{register: rm as usize}) as u64;
+                self.set_long_result(rdlo, rdhi, n * m);
+            },
+            SmlalT1{rdlo, rdhi, rn, rm} => {
+                let n = self.get_reg(&LongRegister{register: rn as usize}) as i32 as i64;
+                let m = self.get_reg(&LongRegister{register: rm as usize}) as i32 as i64;
+                let a = self.get_long_operand(rdlo, rdhi);
+                self.set_long_result(rdlo, rdhi, (n.wrapping_mul(m) as u64).wrapping_add(a));
+            },
+            UmlalT1{rdlo, rdhi, rn, rm} => {
+                let n = self.get_reg(&LongRegister{register: rn as usize}) as u64;
+                let m = self.get_reg(&LongRegister{register: rm as usize}) as u64;
+                let a = self.get_long_operand(rdlo, rdhi);
+                self.set_long_result(rdlo, rdhi, (n * m).wrapping_add(a));
+            },
+            Bkpt{imm} => {
+                self.exclusive_monitor = None;
+                if let (SEMIHOSTING_BKPT, Some(output)) = (imm, self.semihosting.clone()){
+                    return self.semihosting_call(&*output);
+                }
+                self.breakpoint();
+            },
+            Hint{hint} => {
+                if self.yield_on_hints && instruction.is_wait_hint(){
+                    return Ok(HINT_EXIT | hint as u32);
+                }
+            },
+            UdfT1{opcode} => {
+                return Err(NarmError::InvalidOpcode(opcode));
+            },
+            LdrLitT1{rt, imm32} => {
+                /* t = UInt(Rt);  imm32 = ZeroExtend(imm8:'00', 32);  add = TRUE;
+                    base = Align(PC,4);
+                    address = if add then (base + imm32) else (base - imm32);
+                    R[t] = MemU[address,4];
+                */
+                let address = self.virtual_pc.align4() + imm32;
+                self.sreg[rt as usize] = self.memory.get_u32(address)?;
+            },
+            LdrSpImmT2{rt, imm32} => {
+                let address = self.get_sp() + imm32;
+                self.sreg[rt as usize] = self.memory.get_u32(address)?;
+            },
+            MovImmT1{rd, imm} => {
+                self.sreg[rd as usize] = imm;
+                //update flags
+                self.cpsr.z = imm == 0;
+                self.cpsr.n = imm.get_bit(31);
+                //C and V flags unchanged
+            },
+            AddImmT2{rdn, imm} => {
+                let rdn = rdn as usize;
+                self.sreg[rdn] = self.op_add(self.sreg[rdn], imm, false, true);
+            },
+            AddSpImmT1{rd, imm32} => {
+                self.sreg[rd as usize] = self.op_add(self.get_sp(), imm32, false, false);
+            },
+            AdrT1{rd, imm32} => {
+                self.sreg[rd as usize] = self.virtual_pc.align4() + imm32;
+            },
+            CmpImmT1{rn, imm} => {
+                self.op_add(self.sreg[rn as usize], !imm, true, true); //result is unused
+            },
+            StrSpImmT2{rt, imm32} => {
+                let address = self.get_sp() + imm32;
+                self.memory.set_u32(address, self.sreg[rt as usize])?;
+            },
+            SubImmT2{rdn, imm} => {
+                let rdn = rdn as usize;
+                self.sreg[rdn] = self.op_add(self.sreg[rdn], !imm, true, true);
+            },
+            LdmT1{rn, reglist} => {
+                let reg = rn as usize;
+                let mut address = self.sreg[reg];
+                let wback = !reglist.get_bit(reg as u8);
+                let mut count = 0;
+                for i in 0..=7{
+                    if reglist.get_bit(i){
+                        self.sreg[i as usize] = self.memory.get_u32(address)?;
+                        address += 4;
+                        count += 1;
                     }
-                    if option{
-                        //pop PC
-                        self.set_interworking_pc(self.memory.get_u32(address)?)?;
+                }
+                if wback && !reglist.get_bit(reg as u8) {
+                    self.sreg[reg] += 4 * count;
+                }
+            },
+            StmT1{rn, reglist} => {
+                let reg = rn as usize;
+                let mut address = self.sreg[reg];
+                let mut count = 0;
+                for i in 0..=7{
+                    if reglist.get_bit(i){
+                        //NOTE this does not include the "unknown" unpredictable case:
+                        //If the base register is included and not the lowest-numbered register in the list, such an instruction stores an unknown value for the base register.
+                        //Use of <Rn> in the register list is deprecated.
+                        self.memory.set_u32(address, self.sreg[i as usize])?;
+                        address += 4;
                         count += 1;
                     }
-                    self.set_sp(self.get_sp() + 4 * count);
-                    return Ok(0);
-                },
-                //1011_010x_yyyy_yyyy PUSH T1 (x is if LR should be pushed)
-                0b1011_0100_0000_0000 => {
-                    let mut address = self.get_sp() - 4 * reglist.count_ones();
-                    if option{
-                        address -= 4;
+                }
+                self.sreg[reg] += 4 * count;
+            },
+            BT2{imm32} => {
+                self.set_thumb_pc_address((self.virtual_pc as i32 + imm32) as u32);
+            },
+            AdcRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                self.sreg[rdn] = self.op_add(self.sreg[rdn], self.sreg[rm], self.cpsr.c, true);
+            },
+            AndRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                self.sreg[rdn] = self.sreg[rdn] & self.sreg[rm];
+                self.cpsr.n = self.sreg[rdn].get_bit(31);
+                self.cpsr.z = self.sreg[rdn] == 0;
+            },
+            AsrRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                let shift = self.sreg[rm] & 0xFF;
+                let (result, _) = (self.sreg[rdn] as i32).overflowing_shr(shift);
+                if shift != 0 { // Ignore carry flag if shift by 0
+                    let (shift_one_less, _) = (self.sreg[rdn] as i32).overflowing_shr(shift-1);
+                    let carry = (shift_one_less & 0x01) > 0; // Get last bit shifted out
+                    self.cpsr.c = carry;
+                }
+                self.sreg[rdn] = result as u32;
+                self.set_result_flags(result as u32);
+            },
+            BicRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                let result = self.sreg[rdn] & !self.sreg[rm];
+                self.sreg[rdn] = result;
+                self.set_result_flags(result);
+            },
+            CmnRegT1{rn, rm} => {
+                let _result = self.op_add(self.sreg[rn as usize], self.sreg[rm as usize], false, true);
+            },
+            CmpRegT1{rn, rm} => {
+                let _result = self.op_add(self.sreg[rn as usize], !self.sreg[rm as usize], true, true);
+            },
+            EorRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                let result = self.sreg[rdn] ^ self.sreg[rm];
+                self.sreg[rdn] = result;
+                self.set_result_flags(result);
+            },
+            LslRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                let valuen = self.sreg[rdn];
+                let shift = self.sreg[rm] & 0xFF;
+                let (result, _) = valuen.overflowing_shl(shift);
+                if shift != 0 { // Ignore carry flag if shift by 0
+                    let (shift_one_less, _) = valuen.overflowing_shl(shift - 1);
+                    let carry = (shift_one_less & 0x8000_0000) > 0; // Get last bit shifted out
+                    self.cpsr.c = carry;
+                }
+                self.sreg[rdn] = result;
+                self.set_result_flags(result);
+            },
+            LsrRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                let valuen = self.sreg[rdn];
+                let shift = self.sreg[rm] & 0xFF;
+                let (result, _) = valuen.overflowing_shr(shift);
+                if shift != 0 { // Ignore carry flag if shift by 0
+                    let (shift_one_less, _) = valuen.overflowing_shr(shift - 1);
+                    let carry = (shift_one_less & 0x01) > 0; // Get last bit shifted out
+                    self.cpsr.c = carry;
+                }
+                self.sreg[rdn] = result;
+                self.set_result_flags(result);
+            },
+            MovRegT2{rd, rm} => {
+                let valuem = self.sreg[rm as usize];
+                self.sreg[rd as usize] = valuem;
+                self.set_result_flags(valuem);
+            },
+            MulT1{rdm, rn} => {
+                let (rdm, rn) = (rdm as usize, rn as usize);
+                let (result, _) = self.sreg[rdm].overflowing_mul(self.sreg[rn]);
+                self.sreg[rdm] = result;
+                self.set_result_flags(result);
+            },
+            MvnRegT1{rd, rm} => {
+                let valuem = self.sreg[rm as usize];
+                self.sreg[rd as usize] = !valuem;
+                self.set_result_flags(!valuem);
+            },
+            OrrRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                let result = self.sreg[rdn] | self.sreg[rm];
+                self.sreg[rdn] = result;
+                self.set_result_flags(result);
+            },
+            RevT1{rd, rm} => {
+                //Operation is to turn 0x11223344 into 0x44332211
+                let valuem = self.sreg[rm as usize];
+                let result =
+                    ((valuem & 0xFF) << 24) |
+                    ((valuem & 0xFF00) << 8) |
+                    ((valuem & 0xFF0000) >> 8) |
+                    ((valuem & 0xFF000000) >> 24);
+                self.sreg[rd as usize] = result;
+            },
+            Rev16T1{rd, rm} => {
+                //operation is to turn 0x11223344 into 0x22114433
+                let valuem = self.sreg[rm as usize];
+                let result =
+                    ((valuem & 0xFF) << 8) |
+                    ((valuem & 0xFF00) >> 8) |
+                    ((valuem & 0xFF0000) << 8) |
+                    ((valuem & 0xFF000000) >> 8);
+                self.sreg[rd as usize] = result;
+            },
+            RevshT1{rd, rm} => {
+                //Byte-Reverse Signed Halfword
+                //Reverses the byte order in the lower 16-bit halfword and sign extends the result to 32btis
+                //Operation: 0x11223344 -> 0x00004433
+                //Operation: 0x1122AAFF -> 0xFFFFFFAA
+                let valuem = self.sreg[rm as usize];
+                let result = (((valuem & 0xFF) as i8 as i32 as u32) << 8) | ((valuem & 0xFF00) >> 8);
+                self.sreg[rd as usize] = result;
+            },
+            RorRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                let shift = self.sreg[rm] % 32;
+                let result = self.sreg[rdn].rotate_right(shift);
+                self.sreg[rdn] = result;
+                //TODO needs live testing to confirm carry behavior, under documented and conflicting sources
+                // The QEMU-based VM I'm testing against indeed sets carry to last out-shifted bit, so this *should* be correct /Johannes
+                if shift != 0 { // Ignore carry flag if shift by 0
+                    self.cpsr.c = result & (1 << 31) > 0;
+                }
+                self.set_result_flags(result);
+            },
+            RsbImmT1{rd, rn} => {
+                self.sreg[rd as usize] = self.op_add(!self.sreg[rn as usize], 0, true, true);
+            },
+            SbcRegT1{rdn, rm} => {
+                let (rdn, rm) = (rdn as usize, rm as usize);
+                self.sreg[rdn] = self.op_add(self.sreg[rdn], !self.sreg[rm], self.cpsr.c, true);
+            },
+            SxtbT1{rd, rm} => {
+                self.sreg[rd as usize] = ((self.sreg[rm as usize] & 0xFF) as u8 as i8 as i32) as u32;
+            },
+            SxthT1{rd, rm} => {
+                self.sreg[rd as usize] = ((self.sreg[rm as usize] & 0xFFFF) as u16 as i16 as i32) as u32;
+            },
+            TstRegT1{rn, rm} => {
+                let result = self.sreg[rn as usize] & self.sreg[rm as usize];
+                //result is not written back
+                self.set_result_flags(result);
+            },
+            UxtbT1{rd, rm} => {
+                self.sreg[rd as usize] = self.sreg[rm as usize] & 0xFF;
+            },
+            UxthT1{rd, rm} => {
+                self.sreg[rd as usize] = self.sreg[rm as usize] & 0xFFFF;
+            },
+            AddRegT1{rd, rn, rm} => {
+                self.sreg[rd as usize] = self.op_add(self.sreg[rn as usize], self.sreg[rm as usize], false, true);
+            },
+            LdrRegT1{rt, rn, rm} => {
+                let address = self.sreg[rn as usize].wrapping_add(self.sreg[rm as usize]);
+                self.sreg[rt as usize] = self.memory.get_u32(address)?;
+            },
+            LdrbRegT1{rt, rn, rm} => {
+                let address = self.sreg[rn as usize].wrapping_add(self.sreg[rm as usize]);
+                self.sreg[rt as usize] = self.memory.get_u8(address)? as u32;
+            },
+            LdrhRegT1{rt, rn, rm} => {
+                let address = self.sreg[rn as usize].wrapping_add(self.sreg[rm as usize]);
+                self.sreg[rt as usize] = self.memory.get_u16(address)? as u32;
+            },
+            LdrsbRegT1{rt, rn, rm} => {
+                let address = self.sreg[rn as usize].wrapping_add(self.sreg[rm as usize]);
+                self.sreg[rt as usize] = self.memory.get_u8(address)? as i8 as i32 as u32;
+            },
+            LdrshRegT1{rt, rn, rm} => {
+                let address = self.sreg[rn as usize].wrapping_add(self.sreg[rm as usize]);
+                self.sreg[rt as usize] = self.memory.get_u16(address)? as i16 as i32 as u32;
+            },
+            StrRegT1{rt, rn, rm} => {
+                let address = self.sreg[rn as usize].wrapping_add(self.sreg[rm as usize]);
+                self.memory.set_u32(address, self.sreg[rt as usize])?;
+            },
+            StrbRegT1{rt, rn, rm} => {
+                let address = self.sreg[rn as usize].wrapping_add(self.sreg[rm as usize]);
+                self.memory.set_u8(address, (self.sreg[rt as usize] & 0xFF) as u8)?;
+            },
+            StrhRegT1{rt, rn, rm} => {
+                let address = self.sreg[rn as usize].wrapping_add(self.sreg[rm as usize]);
+                self.memory.set_u16(address, (self.sreg[rt as usize] & 0xFFFF) as u16)?;
+            },
+            SubRegT1{rd, rn, rm} => {
+                self.sreg[rd as usize] = self.op_add(self.sreg[rn as usize], !self.sreg[rm as usize], true, true);
+            },
+            SubImmT1{rd, rn, imm} => {
+                self.sreg[rd as usize] = self.op_add(self.sreg[rn as usize], !imm, true, true);
+            },
+            AddImmT1{rd, rn, imm} => {
+                self.sreg[rd as usize] = self.op_add(self.sreg[rn as usize], imm, false, true);
+            },
+            CmpRegT2{rn, rm} => {
+                //Either register being from PC (r15) is considered unpredictable by ARM architecture. To prevent weirdness with later upgrades, this will be forced to 0
+                let reg1 = LongRegister{register: rm as usize};
+                let reg2 = LongRegister{register: rn as usize};
+                let rm = if reg1.register == 15{
+                    0
+                }else{
+                    self.get_reg(&reg1)
+                };
+                let rn = if reg2.register == 15{
+                    0
+                }else{
+                    self.get_reg(&reg2)
+                };
+                self.op_add(rn, !rm, true, true);
+            },
+            AddRegT2{rdn, rm} => {
+                let reg1 = LongRegister{register: rm as usize};
+                let reg2 = LongRegister{register: rdn as usize};
+                //note! order of deciding to deal with reg2 vs reg1 being equal to 13 is critical!
+                //if reg2 is 13, then the T1 encoding logic must be used
+                if reg1.register == 13{
+                    //sp+reg T1
+                    //ADD <Rdm>, SP, <Rdm>
+                    let rm = self.get_reg(&reg2);
+                    let sp = self.get_reg(&reg1);
+                    let result = self.op_add(sp, rm, false, false);
+                    self.write_long_reg(&reg2, result);
+                }else if reg2.register == 13{
+                    //sp+reg T2
+                    //ADD SP,<Rm>
+                    let rm = self.get_reg(&reg1);
+                    let sp = self.get_reg(&reg2);
+                    let result = self.op_add(sp, rm, false, false);
+                    self.write_long_reg(&reg2, result);
+                }else{
+                    if reg1.register == 15 && reg2.register == 15{
+                        //listed as UNPREDICTABLE, so just exit here
+                        return Ok(0);
                     }
-                    let mut count = 0;
-                    for i in 0..=7{
-                        if reglist.get_bit(i){
-                            self.memory.set_u32(address, self.sreg[i as usize])?;
-                            address += 4;
-                            count += 1;
-                        }
+                    let rm = self.get_reg(&reg1);
+                    let rn = self.get_reg(&reg2);
+                    let result = self.op_add(rn, rm, false, false);
+                    self.write_long_reg(&reg2, result);
+                }
+            },
+            MovRegT1{rd, rm} => {
+                let reg1 = LongRegister{register: rm as usize};
+                let reg2 = LongRegister{register: rd as usize};
+                if
+                    (reg1.register == 15 || reg1.register == 13) &&
+                    (reg2.register == 15 || reg2.register == 13)
+                {
+                    // "ARM deprecates the use of the following MOV (register) instructions in which <Rd> is the SP or PC and <Rm> is also the SP or PC"
+                    // Meaning, if both registers are either the SP or the PC it's no good
+                    // Probably shouldn't be an error since it still compiles?
+                }
+                else if reg1.register == 15 {
+                    self.write_long_reg(&reg2, self.get_last_pc());
+                }
+                else if reg2.register == 15 {
+                    // Note this is a simple branch in ARMv6, but in ARMv7 will be interworking
+                    self.set_thumb_pc_address(self.get_reg(&reg1));
+                }
+                else {
+                    self.write_long_reg(&reg2, self.get_reg(&reg1));
+                }
+            },
+            AsrImmT1{rd, rm, shift} => {
+                let rm = rm as usize;
+                let (result, _) = (self.sreg[rm] as i32).overflowing_shr(shift);
+                if shift != 32 { // Ignore carry flag if shift by 0. Doesn't make sense that imm is ever 0, but it compiles...
+                    let (shift_one_less, _) = (self.sreg[rm] as i32).overflowing_shr(shift - 1);
+                    let carry = (shift_one_less & 0x01) > 0; // Get last bit shifted out
+                    self.cpsr.c = carry;
+                }
+                self.sreg[rd as usize] = result as u32;
+                self.set_result_flags(result as u32);
+            },
+            LdrImmT1{rt, rn, imm32} => {
+                let address = self.sreg[rn as usize].wrapping_add(imm32);
+                self.sreg[rt as usize] = self.memory.get_u32(address)?;
+            },
+            LdrbImmT1{rt, rn, imm32} => {
+                let address = self.sreg[rn as usize].wrapping_add(imm32);
+                self.sreg[rt as usize] = self.memory.get_u8(address)? as u32;
+            },
+            LdrhImmT1{rt, rn, imm32} => {
+                let address = self.sreg[rn as usize].wrapping_add(imm32);
+                self.sreg[rt as usize] = self.memory.get_u16(address)? as u32;
+            },
+            LslImmT1{rd, rm, shift} => {
+                let rm = rm as usize;
+                let (result, _) = self.sreg[rm].overflowing_shl(shift);
+                let (shift_one_less, _) = self.sreg[rm].overflowing_shl(shift - 1);
+                let carry = (shift_one_less & 0x8000_0000) > 0; // Get last bit shifted out
+                self.sreg[rd as usize] = result;
+                self.set_result_flags(result);
+                self.cpsr.c = carry;
+            },
+            LsrImmT1{rd, rm, shift} => {
+                let rm = rm as usize;
+                let (result, _) = self.sreg[rm].overflowing_shr(shift);
+                if shift != 32 { // Ignore carry flag if shift by 0. Doesn't make sense that imm is ever 0, but it compiles...
+                    let (shift_one_less, _) = self.sreg[rm].overflowing_shr(shift - 1);
+                    let carry = (shift_one_less & 0x01) > 0; // Get last bit shifted out
+                    self.cpsr.c = carry;
+                }
+                self.sreg[rd as usize] = result;
+                self.set_result_flags(result);
+            },
+            StrImmT1{rt, rn, imm32} => {
+                let address = self.sreg[rn as usize].wrapping_add(imm32);
+                self.memory.set_u32(address, self.sreg[rt as usize])?;
+            },
+            StrbImmT1{rt, rn, imm32} => {
+                let address = self.sreg[rn as usize].wrapping_add(imm32);
+                self.memory.set_u8(address, (self.sreg[rt as usize] & 0xFF) as u8)?;
+            },
+            StrhImmT1{rt, rn, imm32} => {
+                let address = self.sreg[rn as usize].wrapping_add(imm32);
+                self.memory.set_u16(address, (self.sreg[rt as usize] & 0xFFFF) as u16)?;
+            },
+            BCondT1{cond, imm32} => {
+                if self.condition_passes(cond as u32){
+                    self.set_thumb_pc_address((self.virtual_pc as i32 + imm32) as u32);
+                }
+            },
+            Svc{imm} => {
+                //SVC opcode, so just exit with specified code. Like any exception, this clears the local exclusive monitor
+                self.exclusive_monitor = None;
+                return Ok(imm as u32);
+            },
+            PopT1{reglist, pc} => {
+                let mut address = self.get_sp();
+                let mut count = 0;
+                for i in 0..=7{
+                    if reglist.get_bit(i){
+                        self.sreg[i as usize] = self.memory.get_u32(address)?;
+                        address += 4;
+                        count += 1;
                     }
-                    if option{
-                        //push LR
-                        let lr = LongRegister{register: 14};
-                        self.memory.set_u32(address, self.get_reg(&lr))?;
+                }
+                if pc{
+                    //pop PC
+                    self.set_interworking_pc(self.memory.get_u32(address)?)?;
+                    count += 1;
+                }
+                self.set_sp(self.get_sp() + 4 * count)?;
+            },
+            PushT1{reglist, lr} => {
+                let mut address = self.get_sp() - 4 * reglist.count_ones();
+                if lr{
+                    address -= 4;
+                }
+                self.check_stack_limit(address)?;
+                let mut count = 0;
+                for i in 0..=7{
+                    if reglist.get_bit(i){
+                        self.memory.set_u32(address, self.sreg[i as usize])?;
+                        address += 4;
                         count += 1;
                     }
-                    self.set_sp(self.get_sp() - 4 * count);
-                    return Ok(0); 
-                },
-                _ => {}
-            }
-        }
-        //r4_q3
-        {
-            let op = opcode & !MASK_R4_Q3;
-            let reg = decode_r4_q3(opcode);
-            let value = self.get_reg(&reg); 
-            match op{
-                //0100_0111_0xxx_xLLL BX T1
-                0b0100_0111_0000_0000 => {
-                    self.set_interworking_pc(value)?;
-                    return Ok(0);
-                },
-                //0100_0111_1xxx_xLLL BLX T1
-                0b0100_0111_1000_0000 => {
-                    let lr = LongRegister{register: 14};
-                    self.set_reg(&lr, (self.virtual_pc - 2) | 1);
-                    self.set_interworking_pc(value)?;
-                    return Ok(0);
                 }
-                _ => {}
-            }
-        }
-        //imm7
-        {
-            let op = opcode & !MASK_IMM7;
-            let imm = decode_imm7(opcode);
-            match op{
-                //0100_0111_0xxx_xxxx ADD sp+imm T2 noflags
-                0b1011_0000_0000_0000 => {
-                    let sp = LongRegister{ register: 13 };
-                    let result = self.op_add(self.get_sp(), (imm as u32) << 2, false, false);
-                    self.set_reg(&sp, result);
-                    return Ok(0);
-                },
-                //0100_0111_1xxx_xxxx SUB sp-imm T1 noflags
-                0b1011_0000_1000_0000 => {
-                    let sp = LongRegister{ register: 13 };
-                    let result = self.op_add(self.get_sp(), !((imm as u32) << 2), true, false);
-                    self.set_reg(&sp, result);
-                    return Ok(0);
+                if lr{
+                    //push LR
+                    let lr = LongRegister{register: 14};
+                    self.memory.set_u32(address, self.get_reg(&lr))?;
+                    count += 1;
                 }
-                _ => {}
+                self.set_sp(self.get_sp() - 4 * count)?;
+            },
+            BxT1{rm} => {
+                let value = self.get_reg(&LongRegister{register: rm as usize});
+                self.set_interworking_pc(value)?;
+            },
+            BlxT1{rm} => {
+                let value = self.get_reg(&LongRegister{register: rm as usize});
+                let lr = LongRegister{register: 14};
+                self.write_long_reg(&lr, (self.virtual_pc - 2) | 1);
+                self.set_interworking_pc(value)?;
+            },
+            AddSpImmT2{imm32} => {
+                let sp = LongRegister{ register: 13 };
+                let result = self.op_add(self.get_sp(), imm32, false, false);
+                self.write_long_reg(&sp, result);
+            },
+            SubSpImmT1{imm32} => {
+                let sp = LongRegister{ register: 13 };
+                let result = self.op_add(self.get_sp(), !imm32, true, false);
+                self.write_long_reg(&sp, result);
+            },
+            Invalid(opcode) => {
+                return Err(NarmError::InvalidOpcode(opcode));
+            },
+            Invalid32(opcode32) => {
+                //later support MSR/MRS?
+                return Err(NarmError::InvalidOpcode32(opcode32));
             }
         }
-
-
-        Err(NarmError::InvalidOpcode(opcode))
+        Ok(0)
     }
     #[cfg(not(debug_assertions))]
     fn breakpoint(&self){}
     #[cfg(not(debug_assertions))]
-    fn log_opcode(&self, _opcode: u16){}
+    fn log_opcode(&self, _address: u32, _opcode: u16){}
     #[cfg(debug_assertions)]
     fn breakpoint(&mut self){
-        println!("breakpoint triggered!");
+        self.write_diagnostics("breakpoint triggered!");
         self.print_diagnostics();
         if self.breakpoint_flipflop{
             self.breakpoint_flipflop = false;
@@ -845,9 +1511,9 @@ impl NarmVM{
         }
     }
     #[cfg(debug_assertions)]
-    fn log_opcode(&mut self, opcode: u16){
+    fn log_opcode(&mut self, address: u32, opcode: u16){
         if self.breakpoint_flipflop{
-            self.executed_opcodes.push((self.get_pc_address(), opcode));
+            self.executed_opcodes.push((address, opcode));
         }
     }
 
@@ -877,6 +1543,141 @@ impl NarmVM{
         }
     }
 
+    /// Reads a 64 bit value held in a pair of registers, as used by the long multiply instructions
+    fn get_long_operand(&self, rdlo: u8, rdhi: u8) -> u64{
+        let lo = self.get_reg(&LongRegister{register: rdlo as usize}) as u64;
+        let hi = self.get_reg(&LongRegister{register: rdhi as usize}) as u64;
+        (hi << 32) | lo
+    }
+    /// Writes a 64 bit result into a pair of registers
+    fn set_long_result(&mut self, rdlo: u8, rdhi: u8, value: u64){
+        self.write_long_reg(&LongRegister{register: rdlo as usize}, value as u32);
+        self.write_long_reg(&LongRegister{register: rdhi as usize}, (value >> 32) as u32);
+    }
+    /// Reads a register for the Thumb-2 instructions, which index the full register file
+    fn read_reg(&self, register: u8) -> u32{
+        self.get_reg(&LongRegister{register: register as usize})
+    }
+    fn write_reg(&mut self, register: u8, value: u32){
+        self.write_long_reg(&LongRegister{register: register as usize}, value);
+    }
+    /// Returns a shifted register operand of a Thumb-2 data processing instruction, along with the carry out of the shift
+    fn shifted_operand(&self, rm: u8, shift_type: ShiftType, shift: u32) -> (u32, bool){
+        shift_c(self.read_reg(rm), shift_type, shift, self.cpsr.c)
+    }
+    /// Sets N, Z and C for a logical operation
+    fn set_logical_flags(&mut self, result: u32, carry: bool){
+        self.set_result_flags(result);
+        self.cpsr.c = carry;
+    }
+    fn set_logical_result(&mut self, rd: u8, result: u32, carry: bool, setflags: bool){
+        self.write_reg(rd, result);
+        if setflags{
+            self.set_logical_flags(result, carry);
+        }
+    }
+    /// Returns the address accessed by a Thumb-2 load or store with an immediate offset, and the offset address which is written back to Rn
+    fn offset_address(&self, rn: u8, imm32: u32, add: bool, index: bool) -> (u32, u32){
+        let base = if rn == 15 {self.virtual_pc.align4()} else {self.read_reg(rn)};
+        let offset_address = if add {base.wrapping_add(imm32)} else {base.wrapping_sub(imm32)};
+        (if index {offset_address} else {base}, offset_address)
+    }
+    /// Loads a byte, halfword or word, zero or sign extended to 32 bits
+    fn load_sized(&mut self, address: u32, size: u32, signed: bool) -> Result<u32, NarmError>{
+        Ok(match (size, signed){
+            (1, false) => self.memory.get_u8(address)? as u32,
+            (1, true) => self.memory.get_u8(address)? as i8 as i32 as u32,
+            (2, false) => self.memory.get_u16(address)? as u32,
+            (2, true) => self.memory.get_u16(address)? as i16 as i32 as u32,
+            _ => self.memory.get_u32(address)?
+        })
+    }
+    /// Stores the bottom byte, halfword or the full word of a value
+    fn store_sized(&mut self, address: u32, size: u32, value: u32) -> Result<(), NarmError>{
+        match size{
+            1 => {self.memory.set_u8(address, value as u8)?;},
+            2 => {self.memory.set_u16(address, value as u16)?;},
+            _ => {self.memory.set_u32(address, value)?;}
+        }
+        Ok(())
+    }
+    /// Writes the result of a load, where loading PC is an interworking branch
+    fn set_load_result(&mut self, rt: u8, value: u32) -> Result<(), NarmError>{
+        if rt == 15{
+            return self.set_interworking_pc(value);
+        }
+        self.write_reg(rt, value);
+        Ok(())
+    }
+    /// Executes a Thumb-2 load with an immediate offset. Rn is only written back if the load succeeds
+    #[allow(clippy::too_many_arguments)]
+    fn load_immediate(&mut self, rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool, size: u32, signed: bool) -> Result<(), NarmError>{
+        let (address, offset_address) = self.offset_address(rn, imm32, add, index);
+        let value = self.load_sized(address, size, signed)?;
+        if wback{
+            self.write_reg(rn, offset_address);
+        }
+        self.set_load_result(rt, value)
+    }
+    /// Executes a Thumb-2 store with an immediate offset. Rn is only written back if the store succeeds
+    #[allow(clippy::too_many_arguments)]
+    fn store_immediate(&mut self, rt: u8, rn: u8, imm32: u32, add: bool, index: bool, wback: bool, size: u32) -> Result<(), NarmError>{
+        let (address, offset_address) = self.offset_address(rn, imm32, add, index);
+        if wback && rn == 13{
+            self.check_stack_limit(offset_address)?;
+        }
+        self.store_sized(address, size, self.read_reg(rt))?;
+        if wback{
+            self.write_reg(rn, offset_address);
+        }
+        Ok(())
+    }
+    /// Executes LDM or LDMDB. registers is a bit list of r0-r15, which are loaded from ascending addresses
+    fn load_multiple(&mut self, rn: u8, registers: u16, wback: bool, decrement: bool) -> Result<(), NarmError>{
+        let base = self.read_reg(rn);
+        let size = 4 * registers.count_ones();
+        let start = if decrement {base.wrapping_sub(size)} else {base};
+        let mut values = [0; 16];
+        let mut address = start;
+        for (i, value) in values.iter_mut().enumerate(){
+            if registers & (1 << i) != 0{
+                *value = self.memory.get_u32(address)?;
+                address = address.wrapping_add(4);
+            }
+        }
+        for (i, value) in values.iter().enumerate().take(15){
+            if registers & (1 << i) != 0{
+                self.write_reg(i as u8, *value);
+            }
+        }
+        if wback{
+            self.write_reg(rn, if decrement {start} else {base.wrapping_add(size)});
+        }
+        if registers & 0x8000 != 0{
+            self.set_interworking_pc(values[15])?;
+        }
+        Ok(())
+    }
+    /// Executes STM or STMDB. registers is a bit list of r0-r14, which are stored to ascending addresses
+    fn store_multiple(&mut self, rn: u8, registers: u16, wback: bool, decrement: bool) -> Result<(), NarmError>{
+        let base = self.read_reg(rn);
+        let size = 4 * registers.count_ones();
+        let start = if decrement {base.wrapping_sub(size)} else {base};
+        if wback && decrement && rn == 13{
+            self.check_stack_limit(start)?;
+        }
+        let mut address = start;
+        for i in 0..15{
+            if registers & (1 << i) != 0{
+                self.memory.set_u32(address, self.read_reg(i as u8))?;
+                address = address.wrapping_add(4);
+            }
+        }
+        if wback{
+            self.write_reg(rn, if decrement {start} else {base.wrapping_add(size)});
+        }
+        Ok(())
+    }
     fn set_result_flags(&mut self, result: u32){
         self.cpsr.n = result.get_bit(31);
         self.cpsr.z = result == 0;
@@ -892,11 +1693,9 @@ impl NarmVM{
         carry = carry | precarry;
 
         if set_flags{
-            let (_, preoverflow) = (operand1 as i32).overflowing_add(carry_in as i32);
-            let (_, mut overflow) = (prelim_sum as i32).overflowing_add(operand2 as i32);
-            
-            // Both these flags can't ever both be set for analogous reasons as for carry
-            overflow = overflow | preoverflow;
+            // Unlike carry, both additions can overflow, such as for 0x7FFFFFFF + 0x80000000 + 1, in which case they cancel out
+            let signed_sum = operand1 as i32 as i64 + operand2 as i32 as i64 + carry_in as i64;
+            let overflow = result as i32 as i64 != signed_sum;
             self.cpsr.v = overflow;
             self.cpsr.c = carry;
             self.cpsr.n = result.get_bit(31);
@@ -924,15 +1723,20 @@ impl NarmVM{
     pub fn get_sp(&self) -> u32{
         self.long_registers[13 - 8]
     }
-    pub fn set_sp(&mut self, value: u32){
+    /// Sets SP, which fails with StackOverflow if the value is below the stack limit
+    pub fn set_sp(&mut self, value: u32) -> Result<(), NarmError>{
+        self.check_stack_limit(value)?;
         let r = LongRegister{register: 13};
         self.set_reg(&r, value);
+        Ok(())
     }
+    /// Sets a register from host code. SP is aligned and counts towards get_lowest_sp, but is not checked against the stack limit, see set_sp
     pub fn set_reg(&mut self, reg: &LongRegister, value: u32){
         let mut final_value = value;
         let reg = reg.register;
         if reg == 13{
             final_value = value.align4(); //special handling of r13/LR
+            self.track_lowest_sp(final_value);
         } else if reg == 15{
             // Direct PC writes (no interworking) should set lowest bit to current mode (1/thumbs for this VM)
             self.pc = value | 1;
@@ -944,6 +1748,18 @@ impl NarmVM{
             self.long_registers[reg as usize - 8] = final_value;
         }
     }
+    /// Sets a register while executing an instruction
+    /// Setting SP below the stack limit leaves it unchanged, and the instruction then faults with StackOverflow once it has finished
+    fn write_long_reg(&mut self, reg: &LongRegister, value: u32){
+        if reg.register == 13 && value.align4() < self.stack_limit{
+            self.stack_overflow = Some(value.align4());
+            return;
+        }
+        self.set_reg(reg, value);
+    }
+    fn track_lowest_sp(&mut self, sp: u32){
+        self.lowest_sp = Some(self.lowest_sp.map_or(sp, |lowest| lowest.min(sp)));
+    }
     pub fn get_reg(& self, reg: &LongRegister) -> u32{
         let reg = reg.register;
         if reg == 15{
@@ -976,6 +1792,9 @@ impl NarmVM{
             self.sreg[reg] = value;
         }
         else if reg < 15 {
+            if reg == 13 {
+                self.track_lowest_sp(value);
+            }
             self.long_registers[reg - 8] = value;
         }
         else if reg == 15 {
@@ -991,13 +1810,37 @@ impl NarmVM{
         msg.push_str(&format!("n: {}\n", self.cpsr.n));
         msg.push_str(&format!("c: {}\n", self.cpsr.c));
         msg.push_str(&format!("v: {}\n", self.cpsr.v));
+        if self.itstate != 0{
+            msg.push_str(&format!("itstate: {:#010b}\n", self.itstate));
+        }
         msg.push_str(&format!("gas remaining: {}\n", self.gas_remaining));
+        if self.stack_limit != 0{
+            msg.push_str(&format!("stack limit: {:#010x}, lowest sp: {:#010x}\n", self.stack_limit, self.get_lowest_sp()));
+        }
+        if let Some(model) = self.timing_model{
+            msg.push_str(&format!("cycles: {} ({:?})\n", self.cycle_count, model));
+        }
+        msg.push_str("memory map:\n");
+        for region in self.memory.regions(){
+            msg.push_str(&format!("  {}\n", region));
+        }
+        if self.memory.section_exists(self.get_sp()){
+            msg.push_str("memory around sp:\n");
+            msg.push_str(&self.memory.hex_dump((self.get_sp() & !15).saturating_sub(16), 64, HexDumpFormat::default()));
+        }
+        if let Some(e) = self.last_error{
+            msg.push_str(&format!("last error: {:?}\n", e));
+            if let Some(address) = e.fault_address(){
+                msg.push_str(&format!("memory around {:#010x}:\n", address));
+                msg.push_str(&self.memory.hex_dump((address & !15).saturating_sub(16), 48, HexDumpFormat::default()));
+            }
+        }
         msg.push_str(&format!("pc opcode -2 : {:#06x}\n", self.memory.get_u16(self.get_pc_address() - 2).unwrap_or_default()));
         msg.push_str(&format!("pc opcode -2 : {}\n", self.format_binary_opcode(self.memory.get_u16(self.get_pc_address() - 2).unwrap_or_default())));
         msg.push_str(&format!("pc opcode +0 : {:#06x}\n", self.memory.get_u16(self.get_pc_address()).unwrap_or_default()));
         msg.push_str(&format!("pc opcode +0 : {}\n", self.format_binary_opcode(self.memory.get_u16(self.get_pc_address()).unwrap_or_default())));
-        msg.push_str(&format!("pc opcode +2 : {:#06x}\n", self.memory.get_u16(self.get_pc_address() + 2).unwrap_or_default()));
-        msg.push_str(&format!("pc opcode +2 : {}\n", self.format_binary_opcode(self.memory.get_u16(self.get_pc_address() + 2).unwrap_or_default())));
+        msg.push_str(&format!("pc opcode +2 : {:#06x}\n", self.memory.get_u16(self.get_pc_address().wrapping_add(2)).unwrap_or_default()));
+        msg.push_str(&format!("pc opcode +2 : {}\n", self.format_binary_opcode(self.memory.get_u16(self.get_pc_address().wrapping_add(2)).unwrap_or_default())));
         msg.push_str(&format!("{}\n", self.get_execution_flow_text()));
         msg
     }
@@ -1034,7 +1877,20 @@ impl NarmVM{
         msg
     }
     pub fn print_diagnostics(&self){
-        println!("{}", self.get_diagnostics_message())
+        self.write_diagnostics(&self.get_diagnostics_message());
+    }
+    /// Sets where diagnostics text is written, or None to print it to stdout
+    pub fn set_diagnostics_output(&mut self, output: Option<Arc<dyn DiagnosticsOutput + Send + Sync>>){
+        self.diagnostics_output = output;
+    }
+    fn write_diagnostics(&self, text: &str){
+        match &self.diagnostics_output{
+            Some(output) => output.write_diagnostics(text),
+            #[cfg(feature = "std")]
+            None => println!("{}", text),
+            #[cfg(not(feature = "std"))]
+            None => {}
+        }
     }
     /// Helper function to simplify copying a set of data into VM memory
     pub fn copy_into_memory(&mut self, address: u32, data: &[u8]) -> Result<(), NarmError>{
@@ -1063,5 +1919,84 @@ mod tests {
     }
 }
 
+/// Serialization of the VM state, enabled by the serde feature
+/// Registers, flags, gas and memory are written with a stable schema, where registers holds r0-r15 and r15 includes the thumb bit
+/// Caches, the diagnostics output and debugging state are not serialized, and start empty when deserializing
+#[cfg(feature = "serde")]
+mod serialization{
+    use super::*;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
 
+    /// The stable schema of a serialized NarmVM
+    #[derive(Serialize, Deserialize)]
+    struct VMState<M>{
+        registers: [u32; 16],
+        last_pc: u32,
+        cpsr: CPSR,
+        gas_remaining: u64,
+        profile: IsaProfile,
+        itstate: u8,
+        exclusive_monitor: Option<(u32, u32)>,
+        #[serde(default)]
+        stack_limit: u32,
+        #[serde(default)]
+        lowest_sp: Option<u32>,
+        #[serde(default)]
+        timing_model: Option<TimingModel>,
+        #[serde(default)]
+        cycle_count: u64,
+        #[serde(default)]
+        yield_on_hints: bool,
+        memory: M,
+    }
+
+    impl Serialize for NarmVM{
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>{
+            let mut registers = [0; 16];
+            for (i, r) in registers.iter_mut().enumerate(){
+                *r = self.external_get_reg(i);
+            }
+            VMState{
+                registers,
+                last_pc: self.last_pc,
+                cpsr: self.cpsr,
+                gas_remaining: self.gas_remaining,
+                profile: self.profile,
+                itstate: self.itstate,
+                exclusive_monitor: self.exclusive_monitor,
+                stack_limit: self.stack_limit,
+                lowest_sp: self.lowest_sp,
+                timing_model: self.timing_model,
+                cycle_count: self.cycle_count,
+                yield_on_hints: self.yield_on_hints,
+                memory: &self.memory,
+            }.serialize(serializer)
+        }
+    }
 
+    impl<'de> Deserialize<'de> for NarmVM{
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<NarmVM, D::Error>{
+            let state = VMState::<MemorySystem>::deserialize(deserializer)?;
+            let mut vm = NarmVM{
+                last_pc: state.last_pc,
+                cpsr: state.cpsr,
+                gas_remaining: state.gas_remaining,
+                memory: state.memory,
+                profile: state.profile,
+                itstate: state.itstate,
+                exclusive_monitor: state.exclusive_monitor,
+                stack_limit: state.stack_limit,
+                timing_model: state.timing_model,
+                cycle_count: state.cycle_count,
+                yield_on_hints: state.yield_on_hints,
+                ..Default::default()
+            };
+            for (i, r) in state.registers.iter().enumerate(){
+                vm.external_set_reg(i, *r);
+            }
+            //restoring SP is not a new lowest SP
+            vm.lowest_sp = state.lowest_sp;
+            Ok(vm)
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::narmvm::NarmVM;
+use alloc::vec::Vec;
+use crate::NarmError;
+
+/// SVC number used by `__push_costack(pointer, size)`
+/// Copies `size` bytes of guest memory starting at `pointer` (r0, r1) onto the costack, charging COSTACK_GAS_PER_BYTE for each byte
+pub const SVC_PUSH_COSTACK: u32 = 0x10;
+/// SVC number used by `__pop_costack(pointer, max_size) -> size`
+/// Pops the top costack item and copies at most `max_size` bytes of it to `pointer` (r0, r1)
+/// The full size of the popped item is returned in r0, so that truncation can be detected
+pub const SVC_POP_COSTACK: u32 = 0x11;
+/// SVC number used by `__peek_costack(pointer, max_size, index) -> size`
+/// Same as pop, but does not remove the item. The item is selected by r2, where 0 is the top of the costack
+pub const SVC_PEEK_COSTACK: u32 = 0x12;
+/// SVC number used by `__drop_costack()`, which discards the top costack item, if any
+pub const SVC_DROP_COSTACK: u32 = 0x13;
+/// SVC number used by `__costack_count() -> count`
+pub const SVC_COSTACK_COUNT: u32 = 0x14;
+/// SVC number used by `__system_call(feature, function) -> result`
+/// Arguments and results beyond r0 are expected to be passed across the costack
+pub const SVC_SYSTEM_CALL: u32 = 0x20;
+/// SVC number used by `__exit(code)`
+pub const SVC_EXIT: u32 = 0xFF;
+/// Gas charged for each byte pushed to the costack by the guest, as the costack is held in host memory
+pub const COSTACK_GAS_PER_BYTE: u64 = 1;
+
+/// The costack is a push/pop data stack which is shared between the host and the guest
+/// It is used for passing arguments and results across contract and system call boundaries
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct CoStack{
+    items: Vec<Vec<u8>>
+}
+
+impl CoStack{
+    /// Pushes a new item to the top of the costack
+    pub fn push(&mut self, data: &[u8]){
+        self.items.push(data.to_vec());
+    }
+    /// Removes and returns the top item of the costack
+    pub fn pop(&mut self) -> Option<Vec<u8>>{
+        self.items.pop()
+    }
+    /// Returns a reference to an item of the costack without removing it. Index 0 is the top of the costack
+    pub fn peek(&self, index: usize) -> Option<&[u8]>{
+        if index >= self.items.len(){
+            return None;
+        }
+        Some(&self.items[self.items.len() - 1 - index])
+    }
+    /// The number of items currently on the costack
+    pub fn len(&self) -> usize{
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool{
+        self.items.is_empty()
+    }
+    /// Removes all items from the costack
+    pub fn clear(&mut self){
+        self.items.clear();
+    }
+}
+
+/// Implemented by the host to service `__system_call` requests from the guest
+pub trait SystemCallHandler{
+    /// Handles a single system call. The returned value is placed in r0 of the guest
+    /// The VM and costack are provided so that the handler can read arguments and push results
+    fn system_call(&mut self, vm: &mut NarmVM, costack: &mut CoStack, feature: u32, function: u32) -> Result<u32, NarmError>;
+    /// Called by CallManager before a contract starts executing, so that host state can be checkpointed
+    fn begin_call(&mut self, _contract: u32){}
+    /// Called by CallManager when a contract call ends. Reverted is true unless the contract exited with 0, in which case
+    /// host state changed since the matching begin_call should be discarded
+    fn end_call(&mut self, _reverted: bool){}
+}
+
+/// A system call handler which has no features available. Every system call results in an error
+#[derive(Default, Debug, Clone, Copy)]
+pub struct NullSystemCalls;
+
+impl SystemCallHandler for NullSystemCalls{
+    fn system_call(&mut self, _vm: &mut NarmVM, _costack: &mut CoStack, feature: u32, _function: u32) -> Result<u32, NarmError>{
+        Err(NarmError::UnknownSystemCall(feature))
+    }
+}
+
+/// Implements the Neutron ABI on top of the service calls (SVC) exposed by NarmVM
+#[derive(Default, Debug, Clone)]
+pub struct Neutron{
+    pub costack: CoStack
+}
+
+impl Neutron{
+    /// Executes the VM until the guest calls `__exit`, returning the exit code (r0) given to it
+    /// All other Neutron service calls are handled transparently, with system calls being dispatched to the handler
+    pub fn execute(&mut self, vm: &mut NarmVM, handler: &mut dyn SystemCallHandler) -> Result<u32, NarmError>{
+        loop{
+            let svc = vm.execute()?;
+            if let Some(code) = self.handle_service_call(vm, handler, svc)?{
+                return Ok(code);
+            }
+        }
+    }
+
+    /// Handles a single service call that was returned from NarmVM::execute or NarmVM::cycle
+    /// Returns the exit code if the service call was `__exit`, otherwise None and execution can be resumed
+    pub fn handle_service_call(&mut self, vm: &mut NarmVM, handler: &mut dyn SystemCallHandler, svc: u32) -> Result<Option<u32>, NarmError>{
+        match svc{
+            SVC_PUSH_COSTACK => {
+                let address = vm.external_get_reg(0);
+                let size = vm.external_get_reg(1);
+                let gas = size as u64 * COSTACK_GAS_PER_BYTE;
+                if gas > vm.gas_remaining{
+                    vm.gas_remaining = 0;
+                    return Err(NarmError::OutOfGas);
+                }
+                vm.gas_remaining -= gas;
+                let data = if size == 0{
+                    &[]
+                }else{
+                    vm.memory.get_sized_memory(address, size)?
+                };
+                self.costack.push(data);
+            },
+            SVC_POP_COSTACK => {
+                let item = match self.costack.pop(){
+                    Some(i) => i,
+                    None => return Err(NarmError::EmptyCostack)
+                };
+                self.copy_item_into_vm(vm, &item)?;
+            },
+            SVC_PEEK_COSTACK => {
+                let index = vm.external_get_reg(2) as usize;
+                let item = match self.costack.peek(index){
+                    Some(i) => i.to_vec(),
+                    None => return Err(NarmError::EmptyCostack)
+                };
+                self.copy_item_into_vm(vm, &item)?;
+            },
+            SVC_DROP_COSTACK => {
+                self.costack.pop();
+            },
+            SVC_COSTACK_COUNT => {
+                vm.external_set_reg(0, self.costack.len() as u32);
+            },
+            SVC_SYSTEM_CALL => {
+                let feature = vm.external_get_reg(0);
+                let function = vm.external_get_reg(1);
+                let result = handler.system_call(vm, &mut self.costack, feature, function)?;
+                vm.external_set_reg(0, result);
+            },
+            SVC_EXIT => {
+                return Ok(Some(vm.external_get_reg(0)));
+            },
+            _ => {
+                return Err(NarmError::UnknownServiceCall(svc));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Copies an item to the guest using the pop/peek calling convention: r0 = pointer, r1 = max size, result r0 = full size
+    fn copy_item_into_vm(&self, vm: &mut NarmVM, item: &[u8]) -> Result<(), NarmError>{
+        let address = vm.external_get_reg(0);
+        let max_size = vm.external_get_reg(1) as usize;
+        let size = if item.len() < max_size{
+            item.len()
+        }else{
+            max_size
+        };
+        if size > 0{
+            vm.copy_into_memory(address, &item[0..size])?;
+        }
+        vm.external_set_reg(0, item.len() as u32);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_costack_order() {
+        let mut costack = CoStack::default();
+        costack.push(&[1]);
+        costack.push(&[2, 2]);
+        assert_eq!(costack.len(), 2);
+        assert_eq!(costack.peek(0).unwrap(), &[2, 2]);
+        assert_eq!(costack.peek(1).unwrap(), &[1]);
+        assert!(costack.peek(2).is_none());
+        assert_eq!(costack.pop().unwrap(), vec![2, 2]);
+        assert_eq!(costack.pop().unwrap(), vec![1]);
+        assert!(costack.pop().is_none());
+    }
+}
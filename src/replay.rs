@@ -0,0 +1,280 @@
+use crate::memory::MemorySystem;
+use crate::narmvm::{CpuState, NarmVM};
+use crate::NarmError;
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/*
+
+Recording keeps an undo log entry for every cycle() since the latest snapshot, holding the state before the instruction and the
+previous contents of any memory it wrote. Stepping back within the undo log only applies these entries.
+
+After every snapshot_interval instructions the full state is stored as a snapshot and the undo log is emptied, which bounds memory use.
+Earlier instructions are reached by restoring the latest snapshot before them and executing forward, which rebuilds the undo log.
+
+Execution is deterministic apart from the host, which may change registers and memory between instructions, such as when handling
+an SVC. These changes are detected at the start of the next recorded instruction and stored, so that they are reapplied when
+executing forward from a snapshot. Mapping memory can not be replayed like this, so a new snapshot is taken instead.
+
+Positions count the cycle() calls made since recording started, including ones which returned an error.
+
+*/
+
+/// Settings for recording execution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordingConfig{
+    /// A snapshot of all registers and memory is stored after this many instructions since the previous snapshot
+    /// Smaller intervals make rewinding faster, at the cost of more memory
+    pub snapshot_interval: u64,
+    /// The maximum number of snapshots kept. Once exceeded the oldest snapshot is dropped, and execution before the new oldest snapshot can no longer be reached
+    pub max_snapshots: usize,
+}
+
+impl Default for RecordingConfig{
+    fn default() -> RecordingConfig{
+        RecordingConfig{
+            snapshot_interval: 10_000,
+            max_snapshots: 64,
+        }
+    }
+}
+
+/// The state before a recorded instruction, and the previous contents of the memory it wrote
+#[derive(Clone)]
+struct UndoEntry{
+    state: CpuState,
+    writes: Vec<(u32, Vec<u8>)>,
+    touched: Vec<u32>,
+}
+
+#[derive(Clone)]
+struct Snapshot{
+    position: u64,
+    state: CpuState,
+    memory: MemorySystem,
+}
+
+/// A change made by the host before the instruction at position, with the new contents of the memory it wrote
+#[derive(Clone)]
+struct HostChange{
+    position: u64,
+    state: CpuState,
+    writes: Vec<(u32, Vec<u8>)>,
+    touched: Vec<u32>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Recording{
+    config: RecordingConfig,
+    position: u64,
+    /// Undo entries for the instructions directly before position
+    undo: Vec<UndoEntry>,
+    /// Snapshots ordered by position
+    snapshots: VecDeque<Snapshot>,
+    /// Host changes ordered by position
+    host_changes: Vec<HostChange>,
+    /// The state after the last recorded instruction, for detecting changes made by the host
+    last_state: CpuState,
+}
+
+impl Recording{
+    fn earliest(&self) -> u64{
+        self.snapshots.front().map_or(self.position, |s| s.position)
+    }
+    /// The earliest position which can be reached through the undo log
+    fn undo_start(&self) -> u64{
+        self.position - self.undo.len() as u64
+    }
+    fn add_snapshot(&mut self, vm: &NarmVM){
+        let position = self.position;
+        //a snapshot taken after host changes replaces one taken before them
+        self.snapshots.retain(|s| s.position < position);
+        self.snapshots.push_back(Snapshot{
+            position,
+            state: vm.cpu_state(),
+            memory: vm.memory.clone(),
+        });
+        if self.snapshots.len() > self.config.max_snapshots{
+            self.snapshots.pop_front();
+            let earliest = self.earliest();
+            self.host_changes.retain(|c| c.position >= earliest);
+        }
+        self.undo.clear();
+    }
+    /// Drops everything recorded after the current position, as execution may take a different path from here
+    fn truncate(&mut self){
+        let position = self.position;
+        self.snapshots.retain(|s| s.position <= position);
+        self.host_changes.retain(|c| c.position <= position);
+    }
+}
+
+impl NarmVM{
+    /// Starts recording execution, so that it can be rewound with step_back, reverse_continue or rewind_to
+    /// Any previous recording is discarded, and the current state becomes position 0
+    /// Recording requires the side effects of each instruction, so execute_blocks() executes one instruction at a time while recording
+    pub fn start_recording(&mut self, config: RecordingConfig){
+        self.memory.set_journaling(true);
+        let mut recording = Recording{
+            config: RecordingConfig{
+                snapshot_interval: config.snapshot_interval.max(1),
+                max_snapshots: config.max_snapshots.max(1),
+            },
+            position: 0,
+            undo: Vec::new(),
+            snapshots: VecDeque::new(),
+            host_changes: Vec::new(),
+            last_state: self.cpu_state(),
+        };
+        recording.add_snapshot(self);
+        self.recording = Some(Box::new(recording));
+    }
+    /// Stops recording and discards everything recorded
+    pub fn stop_recording(&mut self){
+        self.recording = None;
+        self.memory.set_journaling(false);
+    }
+    pub fn is_recording(&self) -> bool{
+        self.recording.is_some()
+    }
+    /// The number of instructions executed since recording started, or None when not recording
+    pub fn recorded_position(&self) -> Option<u64>{
+        self.recording.as_ref().map(|r| r.position)
+    }
+    /// The earliest position which can still be rewound to, or None when not recording
+    pub fn earliest_recorded_position(&self) -> Option<u64>{
+        self.recording.as_ref().map(|r| r.earliest())
+    }
+    /// Rewinds by one instruction. Returns false if not recording or already at the earliest recorded position
+    pub fn step_back(&mut self) -> bool{
+        match self.recorded_position(){
+            Some(position) if position > 0 => self.rewind_to(position - 1),
+            _ => false
+        }
+    }
+    /// Steps back until stop returns true for the VM state, such as when pc reaches a breakpoint address
+    /// Returns false if the earliest recorded position was reached without stopping
+    pub fn reverse_continue<F: FnMut(&NarmVM) -> bool>(&mut self, mut stop: F) -> bool{
+        while self.step_back(){
+            if stop(self){
+                return true;
+            }
+        }
+        false
+    }
+    /// Rewinds to the state just before the instruction at the given position was executed
+    /// Everything recorded after the position is discarded, so that execution can continue from there with cycle() or execute()
+    /// Returns false, changing nothing, if not recording or the position is outside of the recorded range
+    pub fn rewind_to(&mut self, position: u64) -> bool{
+        let mut recording = match self.recording.take(){
+            Some(r) => r,
+            None => return false
+        };
+        let possible = position <= recording.position && position >= recording.earliest();
+        if possible{
+            if position >= recording.undo_start(){
+                while recording.position > position{
+                    let entry = recording.undo.pop().unwrap();
+                    self.undo(entry);
+                    recording.position -= 1;
+                }
+            }else{
+                self.replay_to(&mut recording, position);
+            }
+            self.memory.take_journal();
+            recording.last_state = self.cpu_state();
+            recording.truncate();
+        }
+        self.recording = Some(recording);
+        possible
+    }
+    /// Executes and records a single instruction. While replaying, host changes are expected to have been reapplied already and are not recorded again
+    pub(crate) fn recorded_cycle(&mut self, recording: &mut Recording, replaying: bool) -> Result<u32, NarmError>{
+        self.record_host_changes(recording, replaying);
+        let state = self.cpu_state();
+        let result = self.unrecorded_cycle();
+        let journal = self.memory.take_journal();
+        recording.undo.push(UndoEntry{
+            state,
+            writes: journal.writes,
+            touched: journal.touched,
+        });
+        recording.position += 1;
+        recording.last_state = self.cpu_state();
+        if !replaying && recording.undo.len() as u64 >= recording.config.snapshot_interval{
+            recording.add_snapshot(self);
+        }
+        result
+    }
+    /// Records changes made since the last recorded instruction, which can only have been made by the host
+    /// The previous contents of memory written by the host are added to the undo entry of the last instruction, so that stepping back over it restores them too
+    fn record_host_changes(&mut self, recording: &mut Recording, replaying: bool){
+        let journal = self.memory.take_journal();
+        if journal.mapped && !replaying{
+            let position = recording.position;
+            recording.host_changes.retain(|c| c.position != position);
+            recording.add_snapshot(self);
+            return;
+        }
+        if journal.writes.is_empty() && journal.touched.is_empty() && self.cpu_state() == recording.last_state{
+            return;
+        }
+        if !replaying{
+            let writes = journal.writes.iter().map(|(address, old)| {
+                let new = self.memory.get_sized_memory(*address, old.len() as u32).map_or_else(|_| old.clone(), |m| m.to_vec());
+                (*address, new)
+            }).collect();
+            recording.host_changes.push(HostChange{
+                position: recording.position,
+                state: self.cpu_state(),
+                writes,
+                touched: journal.touched.clone(),
+            });
+        }
+        if let Some(entry) = recording.undo.last_mut(){
+            entry.writes.extend(journal.writes);
+            entry.touched.extend(journal.touched);
+        }
+    }
+    fn undo(&mut self, entry: UndoEntry){
+        //restored in reverse order, in case writes overlap
+        for (address, old) in entry.writes.iter().rev(){
+            let _ = self.memory.write_raw(*address, old);
+        }
+        for page in entry.touched{
+            self.memory.untouch_page(page);
+        }
+        self.set_cpu_state(&entry.state);
+    }
+    /// Restores the latest snapshot at or before position, then executes forward to it, reapplying host changes along the way
+    fn replay_to(&mut self, recording: &mut Recording, position: u64){
+        let snapshot = recording.snapshots.iter().rev().find(|s| s.position <= position).unwrap().clone();
+        self.memory = snapshot.memory;
+        self.set_cpu_state(&snapshot.state);
+        self.clear_code_caches();
+        self.memory.set_journaling(true);
+        recording.position = snapshot.position;
+        recording.undo.clear();
+        self.apply_host_changes(recording);
+        //host changes at the snapshot are not part of the undo log, as it starts after them
+        self.memory.take_journal();
+        recording.last_state = self.cpu_state();
+        while recording.position < position{
+            let _ = self.recorded_cycle(recording, true);
+            self.apply_host_changes(recording);
+        }
+        self.record_host_changes(recording, true);
+    }
+    fn apply_host_changes(&mut self, recording: &Recording){
+        for change in recording.host_changes.iter().filter(|c| c.position == recording.position){
+            for (address, data) in change.writes.iter(){
+                let _ = self.memory.write_raw(*address, data);
+            }
+            for page in change.touched.iter(){
+                self.memory.touch_page(*page);
+            }
+            self.set_cpu_state(&change.state);
+        }
+    }
+}
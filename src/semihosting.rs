@@ -0,0 +1,103 @@
+use crate::narmvm::NarmVM;
+use crate::NarmError;
+use alloc::sync::Arc;
+
+/*
+
+ARM semihosting lets a guest ask the host for services by executing BKPT 0xAB with the operation in r0 and its argument in
+r1, which is either a value or a pointer to a block of word sized parameters. The result is returned in r0. Only the
+operations needed for debug output are implemented, which is enough for crates such as cortex-m-semihosting:
+
+SYS_OPEN (0x01)     only for the special file ":tt", which opens stdin, stdout or stderr depending on the mode
+SYS_WRITEC (0x03)   r1 points to a single character for the debug console
+SYS_WRITE0 (0x04)   r1 points to a NUL terminated string for the debug console. Only its first SEMIHOSTING_MAX_WRITE bytes are written
+SYS_WRITE (0x05)    r1 points to [handle, pointer, length]. At most SEMIHOSTING_MAX_WRITE bytes are written, and the number
+                    of bytes not written is returned, so that the guest writes the rest with another call
+SYS_CLOCK (0x10)    returns centiseconds since execution started, as given by the host, or -1
+SYS_EXIT (0x18)     stops execution, returning SEMIHOSTING_EXIT from execute(), with the reason left in r1
+
+Semihosting is meant for debugging, and output is not charged any gas beyond the BKPT itself. Instead, the output of a
+single call is limited to SEMIHOSTING_MAX_WRITE bytes, so that a call can't make the host copy a large part of memory.
+
+*/
+
+/// The BKPT immediate used for semihosting calls
+pub const SEMIHOSTING_BKPT: u8 = 0xAB;
+pub const SYS_OPEN: u32 = 0x01;
+pub const SYS_WRITEC: u32 = 0x03;
+pub const SYS_WRITE0: u32 = 0x04;
+pub const SYS_WRITE: u32 = 0x05;
+pub const SYS_CLOCK: u32 = 0x10;
+pub const SYS_EXIT: u32 = 0x18;
+/// The SYS_EXIT reason given for a successful exit by the application
+pub const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+/// The handle which SYS_WRITEC and SYS_WRITE0 write to, as well as the handle of ":tt" opened for writing
+pub const STDOUT_HANDLE: u32 = 1;
+/// Returned by execute() and cycle() in place of an SVC number when the guest calls SYS_EXIT, with the exit reason in r1
+pub const SEMIHOSTING_EXIT: u32 = 0x200;
+/// The most bytes passed to SemihostingOutput by a single SYS_WRITE or SYS_WRITE0 call
+pub const SEMIHOSTING_MAX_WRITE: usize = 0x1000;
+
+/// The host side of semihosting, which receives the output of the guest
+pub trait SemihostingOutput{
+    /// Writes data to a handle. The debug console and stdout are STDOUT_HANDLE, and stderr is 2
+    fn write(&self, handle: u32, data: &[u8]);
+    /// The centiseconds since execution started for SYS_CLOCK, or None if no clock is available
+    /// There is no clock by default, as it would make execution depend on the host
+    fn clock(&self) -> Option<u32>{
+        None
+    }
+}
+
+impl NarmVM{
+    /// Enables semihosting calls made with BKPT 0xAB, passing their output to the given handler, or None to disable them
+    /// While disabled, which is the default, BKPT 0xAB is a breakpoint like any other BKPT
+    pub fn set_semihosting(&mut self, output: Option<Arc<dyn SemihostingOutput + Send + Sync>>){
+        self.semihosting = output;
+    }
+    pub fn get_semihosting(&self) -> Option<&Arc<dyn SemihostingOutput + Send + Sync>>{
+        self.semihosting.as_ref()
+    }
+    /// Handles a BKPT 0xAB while semihosting is enabled, returning SEMIHOSTING_EXIT for SYS_EXIT or 0 otherwise
+    pub(crate) fn semihosting_call(&mut self, output: &dyn SemihostingOutput) -> Result<u32, NarmError>{
+        let operation = self.external_get_reg(0);
+        let argument = self.external_get_reg(1);
+        let result = match operation{
+            SYS_OPEN => {
+                let parameters = self.read_array::<u32>(argument, 3)?;
+                let name = self.read_cstring(parameters[0], parameters[2] as usize)?;
+                match (name.as_slice(), parameters[1]){
+                    (b":tt", 0..=3) => 0,
+                    (b":tt", 4..=7) => STDOUT_HANDLE,
+                    (b":tt", 8..=11) => 2,
+                    _ => u32::MAX,
+                }
+            },
+            SYS_WRITEC => {
+                output.write(STDOUT_HANDLE, &[self.memory.get_u8(argument)?]);
+                //r0 is corrupted by SYS_WRITEC, so it is left unchanged
+                operation
+            },
+            SYS_WRITE0 => {
+                let string = match self.read_cstring(argument, SEMIHOSTING_MAX_WRITE){
+                    //longer strings are cut off, as there is no way to tell the guest how much was written
+                    Err(NarmError::InvalidGuestString(_)) => self.read_bytes(argument, SEMIHOSTING_MAX_WRITE)?,
+                    string => string?,
+                };
+                output.write(STDOUT_HANDLE, &string);
+                operation
+            },
+            SYS_WRITE => {
+                let parameters = self.read_array::<u32>(argument, 3)?;
+                let length = (parameters[2] as usize).min(SEMIHOSTING_MAX_WRITE);
+                output.write(parameters[0], &self.read_bytes(parameters[1], length)?);
+                parameters[2] - length as u32
+            },
+            SYS_CLOCK => output.clock().unwrap_or(u32::MAX),
+            SYS_EXIT => return Ok(SEMIHOSTING_EXIT),
+            _ => return Err(NarmError::UnknownSemihostingCall(operation)),
+        };
+        self.external_set_reg(0, result);
+        Ok(0)
+    }
+}
@@ -0,0 +1,103 @@
+use crate::memory::*;
+use crate::narmvm::NarmVM;
+use sha2::{Digest, Sha256};
+
+/// A SHA-256 digest of the VM state
+pub type StateHash = [u8; 32];
+
+/// Domain separation tag at the start of every state hash, which is changed if the encoding ever changes
+pub const STATE_HASH_TAG: &[u8] = b"narm-state-v2";
+
+/*
+
+The canonical state hash is SHA-256 over the following encoding, where all integers are little endian:
+
+- STATE_HASH_TAG
+- r0 to r15 as u32, where r15 is PC including the thumb bit
+- The CPSR flags as a u32, with N, Z, C and V in bits 31 to 28
+- ITSTATE as a u8
+- gas_remaining as a u64
+- The stack limit as a u32
+- The local exclusive monitor as a u8 which is 1 if it is in the exclusive access state, followed by the address and size
+  of the last exclusive load as u32s, which are 0 when it is not
+- Options as a u8, with bit 0 set if wait hints yield to the host and bit 1 set if semihosting is enabled
+- The gas accrued by memory usage but not yet charged as a u64
+- The number of touched pages as a u32, followed by the number of each touched page as a u32 in ascending order
+- The number of mapped memory blocks as a u32
+- For each memory block in address order: its address as a u32, its size as a u32, and then the SHA-256 hash of each
+  MEMORY_PAGE_SIZE page of the block in order, where the last page may be shorter
+
+Memory is hashed per page so that the incremental variant can reuse the hashes of pages which were not written to.
+Both variants always give the same result.
+
+*/
+
+fn hash_page(page: &[u8]) -> [u8; 32]{
+    Sha256::digest(page).into()
+}
+
+/// Hashes everything except for memory
+fn hash_header(vm: &NarmVM, block_count: usize) -> Sha256{
+    let mut hasher = Sha256::new();
+    hasher.update(STATE_HASH_TAG);
+    for i in 0..=15{
+        hasher.update(vm.external_get_reg(i).to_le_bytes());
+    }
+    hasher.update(vm.cpsr.get_cpsr().to_le_bytes());
+    hasher.update([vm.get_itstate()]);
+    hasher.update(vm.gas_remaining.to_le_bytes());
+    hasher.update(vm.get_stack_limit().to_le_bytes());
+    let (monitor_set, (address, size)) = match vm.get_exclusive_monitor(){
+        Some(monitor) => (1u8, monitor),
+        None => (0u8, (0, 0))
+    };
+    hasher.update([monitor_set]);
+    hasher.update(address.to_le_bytes());
+    hasher.update(size.to_le_bytes());
+    hasher.update([vm.get_yield_on_hints() as u8 | (vm.get_semihosting().is_some() as u8) << 1]);
+    hasher.update(vm.memory.pending_gas().to_le_bytes());
+    let touched_pages = vm.memory.touched_pages();
+    hasher.update((touched_pages.len() as u32).to_le_bytes());
+    for page in touched_pages{
+        hasher.update(page.to_le_bytes());
+    }
+    hasher.update((block_count as u32).to_le_bytes());
+    hasher
+}
+
+fn hash_block_header(hasher: &mut Sha256, address: u32, block: &BufferMemory){
+    hasher.update(address.to_le_bytes());
+    hasher.update((block.memory.len() as u32).to_le_bytes());
+}
+
+impl NarmVM{
+    /// Computes the canonical hash of the registers, flags, gas, execution options, memory usage and all mapped memory
+    /// Two VMs with the same hash will execute identically, given the same ISA profile, memory limits and host
+    pub fn state_hash(&self) -> StateHash{
+        let blocks = self.memory.blocks();
+        let mut hasher = hash_header(self, blocks.len());
+        for (address, block) in blocks{
+            hash_block_header(&mut hasher, address, block);
+            for page in block.memory.chunks(MEMORY_PAGE_SIZE as usize){
+                hasher.update(hash_page(page));
+            }
+        }
+        hasher.finalize().into()
+    }
+
+    /// Computes the same hash as state_hash, but caches the hash of each memory page and only rehashes pages written to since the last call
+    /// The first call hashes all memory, so this is only faster when hashing the same VM repeatedly
+    pub fn incremental_state_hash(&mut self) -> StateHash{
+        let block_count = self.memory.blocks().len();
+        let mut hasher = hash_header(self, block_count);
+        for (address, block) in self.memory.blocks_mut(){
+            hash_block_header(&mut hasher, address, block);
+            let page_count = block.memory.len().div_ceil(MEMORY_PAGE_SIZE as usize);
+            block.page_hashes.resize(page_count, None);
+            for (page, hash) in block.memory.chunks(MEMORY_PAGE_SIZE as usize).zip(block.page_hashes.iter_mut()){
+                hasher.update(hash.get_or_insert_with(|| hash_page(page)));
+            }
+        }
+        hasher.finalize().into()
+    }
+}
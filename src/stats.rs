@@ -0,0 +1,261 @@
+use crate::instruction::Instruction;
+use crate::narmvm::NarmVM;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/*
+
+Statistics count the instructions which complete successfully, in both execution engines. Instructions which fault, and
+instructions skipped by an IT block, are not counted. Rewinding recorded execution does not remove counts.
+
+The export format is plain text with one count per line, so that exports from many runs can be concatenated or merged:
+
+    instruction <name> <count>
+    branch <condition> <taken> <not taken>
+    load <width in bytes> <count>
+    store <width in bytes> <count>
+
+Memory accesses count each register transferred, so LDM of 3 registers counts as 3 word loads, and LDRD as 2.
+
+*/
+
+/// Condition code mnemonics, indexed by the condition field of a conditional branch
+const CONDITIONS: [&str; 14] = ["eq", "ne", "cs", "cc", "mi", "pl", "vs", "vc", "hi", "ls", "ge", "lt", "gt", "le"];
+
+/// How often a conditional branch was taken
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BranchCounts{
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Counts of memory accesses by width
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WidthCounts{
+    pub byte: u64,
+    pub halfword: u64,
+    pub word: u64,
+}
+
+impl WidthCounts{
+    fn add(&mut self, width: u32, count: u64){
+        match width{
+            1 => self.byte += count,
+            2 => self.halfword += count,
+            _ => self.word += count,
+        }
+    }
+    fn get(&self, width: u32) -> u64{
+        match width{
+            1 => self.byte,
+            2 => self.halfword,
+            _ => self.word,
+        }
+    }
+    pub fn total(&self) -> u64{
+        self.byte + self.halfword + self.word
+    }
+}
+
+/// Counts of executed instructions, collected by NarmVM while statistics are enabled
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InstructionStats{
+    /// Executed instructions by mnemonic and encoding, as given by Instruction::name, for example "AddImmT2"
+    pub instructions: BTreeMap<String, u64>,
+    /// Conditional branches by condition code, such as "eq". CBZ and CBNZ are counted as "cbz" and "cbnz"
+    pub branches: BTreeMap<String, BranchCounts>,
+    pub loads: WidthCounts,
+    pub stores: WidthCounts,
+}
+
+impl InstructionStats{
+    /// Counts an instruction which completed, where taken is whether it wrote PC
+    pub(crate) fn record(&mut self, instruction: &Instruction, taken: bool){
+        let name = instruction.name();
+        match self.instructions.get_mut(name){
+            Some(count) => *count += 1,
+            None => {
+                self.instructions.insert(name.to_string(), 1);
+            }
+        }
+        if let Some(condition) = branch_condition(instruction){
+            let counts = match self.branches.get_mut(condition){
+                Some(c) => c,
+                None => self.branches.entry(condition.to_string()).or_default(),
+            };
+            if taken{
+                counts.taken += 1;
+            }else{
+                counts.not_taken += 1;
+            }
+        }
+        if let Some((store, width, count)) = memory_accesses(instruction){
+            if store{
+                self.stores.add(width, count as u64);
+            }else{
+                self.loads.add(width, count as u64);
+            }
+        }
+    }
+    /// The total number of instructions counted
+    pub fn total(&self) -> u64{
+        self.instructions.values().sum()
+    }
+    /// Instruction counts by mnemonic, combining the encodings of each, for example "AddImm" for AddImmT1 to AddImmT4
+    pub fn by_mnemonic(&self) -> BTreeMap<String, u64>{
+        let mut mnemonics = BTreeMap::new();
+        for (name, count) in self.instructions.iter(){
+            *mnemonics.entry(mnemonic(name).to_string()).or_insert(0) += count;
+        }
+        mnemonics
+    }
+    /// Adds all counts from other, such as statistics from another run
+    pub fn merge(&mut self, other: &InstructionStats){
+        for (name, count) in other.instructions.iter(){
+            *self.instructions.entry(name.clone()).or_insert(0) += count;
+        }
+        for (condition, counts) in other.branches.iter(){
+            let entry = self.branches.entry(condition.clone()).or_default();
+            entry.taken += counts.taken;
+            entry.not_taken += counts.not_taken;
+        }
+        for width in [1, 2, 4]{
+            self.loads.add(width, other.loads.get(width));
+            self.stores.add(width, other.stores.get(width));
+        }
+    }
+    /// A human readable report of the instruction mix, with the most executed instructions first
+    pub fn report(&self) -> String{
+        let total = self.total();
+        let mut text = String::new();
+        let _ = writeln!(text, "instructions: {}", total);
+        let mut instructions: Vec<(&String, &u64)> = self.instructions.iter().collect();
+        instructions.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        for (name, count) in instructions{
+            let _ = writeln!(text, "  {:<12} {:>10} {:>6.2}%", name, count, *count as f64 * 100.0 / total as f64);
+        }
+        if !self.branches.is_empty(){
+            let _ = writeln!(text, "conditional branches: (taken / not taken)");
+            for (condition, counts) in self.branches.iter(){
+                let _ = writeln!(text, "  {:<12} {:>10} {:>10}", condition, counts.taken, counts.not_taken);
+            }
+        }
+        let _ = writeln!(text, "loads:  {} (byte {}, halfword {}, word {})", self.loads.total(), self.loads.byte, self.loads.halfword, self.loads.word);
+        let _ = writeln!(text, "stores: {} (byte {}, halfword {}, word {})", self.stores.total(), self.stores.byte, self.stores.halfword, self.stores.word);
+        text
+    }
+    /// Exports the statistics in the line based text format described at the top of this module
+    pub fn export(&self) -> String{
+        let mut text = String::new();
+        for (name, count) in self.instructions.iter(){
+            let _ = writeln!(text, "instruction {} {}", name, count);
+        }
+        for (condition, counts) in self.branches.iter(){
+            let _ = writeln!(text, "branch {} {} {}", condition, counts.taken, counts.not_taken);
+        }
+        for width in [1, 2, 4]{
+            let _ = writeln!(text, "load {} {}", width, self.loads.get(width));
+            let _ = writeln!(text, "store {} {}", width, self.stores.get(width));
+        }
+        text
+    }
+    /// Reads statistics in the export format. Repeated entries are added together, so concatenated exports are merged
+    /// Returns the 1 based line number of the first invalid line on failure
+    pub fn import(text: &str) -> Result<InstructionStats, usize>{
+        let mut stats = InstructionStats::default();
+        for (i, line) in text.lines().enumerate(){
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let number = |index: usize| fields.get(index).and_then(|f| f.parse::<u64>().ok()).ok_or(i + 1);
+            match fields.first(){
+                None => continue,
+                Some(&"instruction") if fields.len() == 3 => {
+                    *stats.instructions.entry(fields[1].to_string()).or_insert(0) += number(2)?;
+                },
+                Some(&"branch") if fields.len() == 4 => {
+                    let counts = stats.branches.entry(fields[1].to_string()).or_default();
+                    counts.taken += number(2)?;
+                    counts.not_taken += number(3)?;
+                },
+                Some(&"load") | Some(&"store") if fields.len() == 3 => {
+                    let width = number(1)?;
+                    if !matches!(width, 1 | 2 | 4){
+                        return Err(i + 1);
+                    }
+                    let counts = if fields[0] == "load" {&mut stats.loads} else {&mut stats.stores};
+                    counts.add(width as u32, number(2)?);
+                },
+                _ => return Err(i + 1),
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// Removes the encoding from an instruction name, such as the T2 of AddImmT2
+fn mnemonic(name: &str) -> &str{
+    let trimmed = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    match trimmed.strip_suffix('T'){
+        Some(m) if trimmed.len() < name.len() && !m.is_empty() => m,
+        _ => name,
+    }
+}
+
+/// The condition of a conditional branch
+fn branch_condition(instruction: &Instruction) -> Option<&'static str>{
+    use Instruction::*;
+    match instruction{
+        BCondT1{cond, ..} | BT3{cond, ..} => CONDITIONS.get(*cond as usize).copied(),
+        CbzT1{..} => Some("cbz"),
+        CbnzT1{..} => Some("cbnz"),
+        _ => None,
+    }
+}
+
+/// Returns whether an instruction stores, the width of each access in bytes, and the number of accesses, for instructions which access memory
+fn memory_accesses(instruction: &Instruction) -> Option<(bool, u32, u32)>{
+    use Instruction::*;
+    Some(match instruction{
+        LdrbImmT1{..} | LdrbRegT1{..} | LdrsbRegT1{..} | LdrbImmT3{..} | LdrsbImmT2{..} | LdrbRegT2{..} | LdrsbRegT2{..} |
+        LdrexbT1{..} | TbbT1{..} => (false, 1, 1),
+        LdrhImmT1{..} | LdrhRegT1{..} | LdrshRegT1{..} | LdrhImmT3{..} | LdrshImmT2{..} | LdrhRegT2{..} | LdrshRegT2{..} |
+        LdrexhT1{..} | TbhT1{..} => (false, 2, 1),
+        LdrImmT1{..} | LdrRegT1{..} | LdrLitT1{..} | LdrSpImmT2{..} | LdrImmT4{..} | LdrRegT2{..} | LdrexT1{..} => (false, 4, 1),
+        LdrdImmT1{..} => (false, 4, 2),
+        LdmT1{reglist, ..} => (false, 4, reglist.count_ones()),
+        LdmT2{registers, ..} | LdmdbT1{registers, ..} => (false, 4, registers.count_ones()),
+        PopT1{reglist, pc} => (false, 4, reglist.count_ones() + *pc as u32),
+        StrbImmT1{..} | StrbRegT1{..} | StrbImmT3{..} | StrbRegT2{..} | StrexbT1{..} => (true, 1, 1),
+        StrhImmT1{..} | StrhRegT1{..} | StrhImmT3{..} | StrhRegT2{..} | StrexhT1{..} => (true, 2, 1),
+        StrImmT1{..} | StrRegT1{..} | StrSpImmT2{..} | StrImmT4{..} | StrRegT2{..} | StrexT1{..} => (true, 4, 1),
+        StrdImmT1{..} => (true, 4, 2),
+        StmT1{reglist, ..} => (true, 4, reglist.count_ones()),
+        StmT2{registers, ..} | StmdbT1{registers, ..} => (true, 4, registers.count_ones()),
+        PushT1{reglist, lr} => (true, 4, reglist.count_ones() + *lr as u32),
+        _ => return None,
+    })
+}
+
+impl NarmVM{
+    /// Starts or stops collecting instruction statistics. Stopping discards the counts collected so far
+    pub fn set_statistics(&mut self, enabled: bool){
+        if !enabled{
+            self.statistics = None;
+        }else if self.statistics.is_none(){
+            self.statistics = Some(Box::default());
+        }
+    }
+    /// Returns the statistics collected so far, if they are enabled
+    pub fn get_statistics(&self) -> Option<&InstructionStats>{
+        self.statistics.as_deref()
+    }
+    /// Returns the statistics collected so far and starts counting from zero, if they are enabled
+    pub fn take_statistics(&mut self) -> Option<InstructionStats>{
+        self.statistics.as_mut().map(|s| core::mem::take(&mut **s))
+    }
+}
@@ -3,6 +3,7 @@
 extern crate elf;
 
 use narm::narmvm::*;
+use narm::NarmError;
 
 const DEFAULT_GAS: u64 = 10000;
 
@@ -32,6 +33,22 @@ pub fn create_vm_from_asm(assembly_code: &str) -> NarmVM {
     vm
 }
 
+// Creates a VM the same way as create_vm_from_asm, but from opcodes which were encoded by hand
+// This is used for exhaustive tests which would be too slow to assemble, or for opcodes which an assembler refuses
+#[cfg(test)]
+pub fn create_vm_from_opcodes(opcodes: &[u16]) -> NarmVM {
+    let code: Vec<u8> = opcodes.iter().flat_map(|op| op.to_le_bytes()).collect();
+
+    let mut vm = NarmVM::default();
+    vm.memory.add_memory(0x01_0000, 0x01_0000).unwrap();
+    vm.copy_into_memory(0x01_0000, &code).unwrap();
+    //add stack memory
+    vm.memory.add_memory(STACK_MEM_START, 0xFFFF).unwrap();
+    vm.set_thumb_pc_address(ASM_ENTRY);
+    vm.gas_remaining = DEFAULT_GAS;
+    vm
+}
+
 #[cfg(test)]
 pub fn asm(input: &str) -> elf::File {
     use std::io::Write;
@@ -357,6 +374,28 @@ pub fn stack_mem_address(offset: u32) -> u32 {
     return STACK_MEM_START + offset;
 }
 
+// Executes the VM with execute() while executing a copy of it with the block execution engine, asserting that both end in identical states
+// Returns the result of execute(), so it can be used in place of it
+pub fn execute_differential(vm: &mut NarmVM) -> Result<u32, NarmError> {
+    let mut block_vm = vm.clone();
+    let result = vm.execute();
+    let block_result = block_vm.execute_blocks();
+    assert_eq!(result, block_result, "\n\n>>> Block engine: Different execution result\n\n");
+    for i in 0..=15 {
+        assert_eq!(vm.external_get_reg(i), block_vm.external_get_reg(i), "\n\n>>> Block engine: Register r{} differs\n\n", i);
+    }
+    assert_eq!(vm.cpsr, block_vm.cpsr, "\n\n>>> Block engine: Condition flags differ\n\n");
+    assert_eq!(vm.get_itstate(), block_vm.get_itstate(), "\n\n>>> Block engine: ITSTATE differs\n\n");
+    assert_eq!(vm.get_last_pc(), block_vm.get_last_pc(), "\n\n>>> Block engine: Last pc differs\n\n");
+    assert_eq!(vm.get_exclusive_monitor(), block_vm.get_exclusive_monitor(), "\n\n>>> Block engine: Exclusive monitor differs\n\n");
+    assert_eq!(vm.gas_remaining, block_vm.gas_remaining, "\n\n>>> Block engine: Remaining gas differs\n\n");
+    assert_eq!(vm.get_lowest_sp(), block_vm.get_lowest_sp(), "\n\n>>> Block engine: Lowest sp differs\n\n");
+    assert_eq!(vm.get_cycle_count(), block_vm.get_cycle_count(), "\n\n>>> Block engine: Cycle count differs\n\n");
+    assert_eq!(vm.get_statistics(), block_vm.get_statistics(), "\n\n>>> Block engine: Instruction statistics differ\n\n");
+    assert!(vm.memory == block_vm.memory, "\n\n>>> Block engine: Memory differs\n\n");
+    result
+}
+
 // Macro to reduce boilerplate code when executing VM instance and asserting results
 // Note: If you're using stepping without SVC op to execute VM you can't use this macro
 #[macro_export]
@@ -364,11 +403,11 @@ macro_rules! execute_and_assert {
     ( $states:ident, $vms:ident, $index:expr ) => {
         if $states[$index].expect_exec_error {
             assert!(
-                $vms[$index].execute().is_err(),
+                execute_differential(&mut $vms[$index]).is_err(),
                 "\n\n>>> Execution: Expected error, got none \n\n"
             );
         } else {
-            let svc_param = $vms[$index].execute().unwrap();
+            let svc_param = execute_differential(&mut $vms[$index]).unwrap();
             assert_eq!(
                 svc_param,
                 $states[$index].svc_param,
@@ -0,0 +1,108 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::NarmError;
+
+/*
+
+Integration test for the basic block execution engine
+
+Note that every test using execute_and_assert! or execute_differential also compares the block engine against cycle()
+
+General test cases:
+
+- Running out of gas in the middle of a block stops at the same instruction as cycle()
+- Errors in the middle of a block leave the same state as cycle()
+- Guest code overwriting a later instruction within the block currently being executed
+- Host writes between blocks invalidate translated blocks
+
+*/
+
+const COUNT_LOOP: &str = "
+        movs    r0, #0x00
+        movs    r1, #0x10
+    loop:
+        adds    r0, #0x01
+        adds    r2, #0x02
+        adds    r3, #0x03
+        cmp     r0, r1
+        bne     loop
+        svc     #0xFF
+";
+
+// Running out of gas in the middle of a block stops at the same instruction as cycle(), for every possible gas amount
+#[test]
+pub fn test_block_out_of_gas() {
+    // 2 instructions of setup, 5 per iteration, and the final svc
+    let needed = 2 + 5 * 0x10 + 1;
+    for gas in 0..=needed {
+        let mut vm = create_vm_from_asm(COUNT_LOOP);
+        vm.gas_remaining = gas;
+        let result = execute_differential(&mut vm);
+        if gas < needed {
+            assert_eq!(result, Err(NarmError::OutOfGas));
+        } else {
+            assert_eq!(result, Ok(0xFF));
+            assert_eq!(vm.external_get_reg(3), 0x30);
+        }
+    }
+}
+
+// Errors in the middle of a block leave the same state as cycle()
+#[test]
+pub fn test_block_error() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs    r0, #0x01
+        ldr     r1, [r0]
+        movs    r2, #0x02
+        svc     #0xFF
+    ",
+    );
+    assert!(execute_differential(&mut vm).is_err());
+    assert_eq!(vm.get_last_pc(), code_mem_address(OP_SIZE));
+    assert_eq!(vm.external_get_reg(2), 0x00);
+}
+
+// Guest code overwriting a later instruction within the block currently being executed
+#[test]
+pub fn test_block_self_modifying() {
+    let mut vm = create_vm_from_asm(
+        "
+        ldr     r1, =0x01000A       // address of patched
+        ldr     r2, =0x2002         // movs r0, #0x02
+        strh    r2, [r1]
+        nop
+        nop
+    patched:
+        movs    r0, #0x01
+        svc     #0xFF
+    ",
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    vm.print_diagnostics();
+    assert_eq!(vm.external_get_reg(0), 0x02);
+}
+
+// Host writes between blocks invalidate translated blocks
+#[test]
+pub fn test_block_host_write() {
+    let mut vm = create_vm_from_asm(
+        "
+    start:
+        movs    r0, #0x01
+        movs    r1, #0x01
+        b       start
+    ",
+    );
+    vm.execute_block().unwrap();
+    assert_eq!(vm.external_get_reg(0), 0x01);
+    assert_eq!(vm.get_pc_address(), ASM_ENTRY);
+
+    // movs r0, #0x02
+    vm.copy_into_memory(ASM_ENTRY, &[0x02, 0x20]).unwrap();
+    vm.execute_block().unwrap();
+    assert_eq!(vm.external_get_reg(0), 0x02);
+    assert_eq!(vm.gas_remaining, 10000 - 6);
+}
@@ -366,91 +366,91 @@ pub fn test_branch_conditions() {
     println!("\n>>> [1/14] Testing for condition type: EQ \n");
     let mut vm = create_vm_from_asm(&format!("beq test1 {}", post_ops));
     vm.cpsr.z = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // NE: Z == 0
     println!("\n>>> [2/14] Testing for condition type: NE \n");
     let mut vm = create_vm_from_asm(&format!("bne test1 {}", post_ops));
     vm.cpsr.z = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // CS: C == 1
     println!("\n>>> [3/14] Testing for condition type: CS \n");
     let mut vm = create_vm_from_asm(&format!("bcs test1 {}", post_ops));
     vm.cpsr.c = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // CC: C == 0
     println!("\n>>> [4/14] Testing for condition type: CC \n");
     let mut vm = create_vm_from_asm(&format!("bcc test1 {}", post_ops));
     vm.cpsr.c = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // MI: N == 1
     println!("\n>>> [5/14] Testing for condition type: MI \n");
     let mut vm = create_vm_from_asm(&format!("bmi test1 {}", post_ops));
     vm.cpsr.n = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // PL: N == 0
     println!("\n>>> [6/14] Testing for condition type: PL \n");
     let mut vm = create_vm_from_asm(&format!("bpl test1 {}", post_ops));
     vm.cpsr.n = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // VS: V == 1
     println!("\n>>> [7/14] Testing for condition type: VS \n");
     let mut vm = create_vm_from_asm(&format!("bvs test1 {}", post_ops));
     vm.cpsr.v = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // VC: V == 0
     println!("\n>>> [8/14] Testing for condition type: VC \n");
     let mut vm = create_vm_from_asm(&format!("bvc test1 {}", post_ops));
     vm.cpsr.v = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // HI: C == 1 AND Z == 0
     println!("\n>>> [9/14] Testing for condition type: HI \n");
     let mut vm = create_vm_from_asm(&format!("bhi test1 {}", post_ops));
     vm.cpsr.c = true;
     vm.cpsr.z = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // LS: C == 0 OR  Z == 1
     println!("\n>>> [10/14] Testing for condition type: LS \n");
     let mut vm = create_vm_from_asm(&format!("bls test1 {}", post_ops));
     vm.cpsr.c = false;
     vm.cpsr.z = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     let mut vm = create_vm_from_asm(&format!("bls test1 {}", post_ops));
     vm.cpsr.c = true;
     vm.cpsr.z = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // GE: N == V
     println!("\n>>> [11/14] Testing for condition type: GE \n");
     let mut vm = create_vm_from_asm(&format!("bge test1 {}", post_ops));
     vm.cpsr.n = true;
     vm.cpsr.v = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     let mut vm = create_vm_from_asm(&format!("bge test1 {}", post_ops));
     vm.cpsr.n = false;
     vm.cpsr.v = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // LT: N != V
     println!("\n>>> [12/14] Testing for condition type: LT \n");
     let mut vm = create_vm_from_asm(&format!("blt test1 {}", post_ops));
     vm.cpsr.n = true;
     vm.cpsr.v = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
     let mut vm = create_vm_from_asm(&format!("blt test1 {}", post_ops));
     vm.cpsr.n = false;
     vm.cpsr.v = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // GT: Z == 0 AND N == V
     println!("\n>>> [13/14] Testing for condition type: GT \n");
@@ -458,13 +458,13 @@ pub fn test_branch_conditions() {
     vm.cpsr.z = false;
     vm.cpsr.n = false;
     vm.cpsr.v = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     let mut vm = create_vm_from_asm(&format!("bgt test1 {}", post_ops));
     vm.cpsr.z = false;
     vm.cpsr.n = true;
     vm.cpsr.v = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     // LE: Z == 1 OR  N != V
     println!("\n>>> [14/14] Testing for condition type: LE \n");
@@ -472,19 +472,19 @@ pub fn test_branch_conditions() {
     vm.cpsr.z = true;
     vm.cpsr.n = true;
     vm.cpsr.v = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     let mut vm = create_vm_from_asm(&format!("ble test1 {}", post_ops));
     vm.cpsr.z = false;
     vm.cpsr.n = true;
     vm.cpsr.v = false;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 
     let mut vm = create_vm_from_asm(&format!("ble test1 {}", post_ops));
     vm.cpsr.z = false;
     vm.cpsr.n = false;
     vm.cpsr.v = true;
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
 }
 
 // Test proper function with different op alignment
@@ -0,0 +1,289 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::callframe::*;
+use narm::memory::MemoryLimits;
+use narm::narmvm::*;
+use narm::neutron::*;
+use narm::NarmError;
+
+/*
+
+Integration test for nested contract calls with CallManager
+
+General test cases:
+
+- A contract calls another contract, receives its output and is refunded the gas it did not use
+- A reverted call keeps its output, and host state changed by it is rolled back
+- A faulting callee gives an empty output, and the forwarded gas is limited to the gas the caller has remaining
+- Calling an unknown contract, or calling without a contract id and gas, fails
+- Recursive calls stop at the maximum call depth
+- Memory for the callee is charged to the gas forwarded to it
+- A callee whose memory can not be mapped returns the forwarded gas which was not charged
+
+*/
+
+const CALLER: u32 = 1;
+const ECHO: u32 = 2;
+const REVERT: u32 = 3;
+const SPIN: u32 = 4;
+const RECURSIVE: u32 = 5;
+const GAS: u64 = 100_000;
+
+fn image(assembly_code: &str) -> ContractImage {
+    let file = asm(assembly_code);
+    ContractImage { code: file.get_section(".text").unwrap().data.clone() }
+}
+
+// Pushes 0x11223344 as input, calls contract `id` forwarding `gas`, then pushes the status of the call and exits with 0
+fn caller(id: u32, gas: u64) -> ContractImage {
+    image(&format!(
+        "
+        ldr r4, =0x81000000
+        ldr r0, =0x11223344
+        str r0, [r4]
+        mov r0, r4
+        movs r1, #4
+        svc #0x10
+        ldr r0, ={}
+        str r0, [r4]
+        ldr r0, ={}
+        str r0, [r4, #4]
+        mov r0, r4
+        movs r1, #8
+        svc #0x10
+        ldr r0, ={}
+        str r0, [r4]
+        mov r0, r4
+        movs r1, #4
+        svc #0x10
+        ldr r0, =0x80000000
+        movs r1, #0
+        svc #0x20
+        str r0, [r4]
+        mov r0, r4
+        movs r1, #4
+        svc #0x10
+        movs r0, #0
+        svc #0xff
+        ",
+        gas as u32,
+        (gas >> 32) as u32,
+        id
+    ))
+}
+
+// Pops a u32, makes system call 1 to change host state, then pushes the u32 plus 1 and exits with the given code
+fn echo(exit_code: u8) -> ContractImage {
+    image(&format!(
+        "
+        ldr r4, =0x81000000
+        mov r0, r4
+        movs r1, #4
+        svc #0x11
+        movs r0, #1
+        movs r1, #0
+        svc #0x20
+        ldr r0, [r4]
+        adds r0, #1
+        str r0, [r4]
+        mov r0, r4
+        movs r1, #4
+        svc #0x10
+        movs r0, #{}
+        svc #0xff
+        ",
+        exit_code
+    ))
+}
+
+fn manager() -> CallManager {
+    let mut manager = CallManager::default();
+    manager.add_contract(ECHO, echo(0));
+    manager.add_contract(REVERT, echo(1));
+    manager.add_contract(SPIN, image("b ."));
+    manager
+}
+
+fn items(costack: &CoStack) -> Vec<u32> {
+    (0..costack.len())
+        .rev()
+        .map(|i| {
+            let mut word = [0u8; 4];
+            word.copy_from_slice(costack.peek(i).unwrap());
+            u32::from_le_bytes(word)
+        })
+        .collect()
+}
+
+// Host state which system call 1 increments, checkpointed for each contract call
+#[derive(Default)]
+struct TestHost {
+    value: u32,
+    checkpoints: Vec<u32>,
+    events: Vec<String>,
+}
+
+impl SystemCallHandler for TestHost {
+    fn system_call(
+        &mut self,
+        _vm: &mut NarmVM,
+        _costack: &mut CoStack,
+        feature: u32,
+        _function: u32,
+    ) -> Result<u32, NarmError> {
+        if feature != 1 {
+            return Err(NarmError::UnknownSystemCall(feature));
+        }
+        self.value += 1;
+        Ok(0)
+    }
+    fn begin_call(&mut self, contract: u32) {
+        self.checkpoints.push(self.value);
+        self.events.push(format!("begin {}", contract));
+    }
+    fn end_call(&mut self, reverted: bool) {
+        let checkpoint = self.checkpoints.pop().unwrap();
+        if reverted {
+            self.value = checkpoint;
+        }
+        self.events.push(format!("end {}", reverted));
+    }
+}
+
+// A contract calls another contract, receives its output and is refunded the gas it did not use
+#[test]
+pub fn test_callframe_call() {
+    let mut manager = manager();
+    manager.add_contract(CALLER, caller(ECHO, 10_000));
+    let mut host = TestHost::default();
+    let result = manager.call(&mut host, CALLER, CoStack::default(), GAS);
+    assert_eq!(result.status, CallStatus::Success);
+    assert_eq!(items(&result.output), vec![0x11223345, 0]);
+    assert_eq!(host.value, 1);
+    assert_eq!(host.events, vec!["begin 1", "begin 2", "end false", "end false"]);
+    // only the instructions executed by both contracts are charged
+    assert!(result.gas_remaining >= GAS - 100, "{}", result.gas_remaining);
+
+    // the host can also call a contract directly with its own input
+    let mut input = CoStack::default();
+    input.push(&7u32.to_le_bytes());
+    let result = manager.call(&mut host, ECHO, input, 1000);
+    assert_eq!(result.status, CallStatus::Success);
+    assert_eq!(items(&result.output), vec![8]);
+}
+
+// A reverted call keeps its output, and host state changed by it is rolled back
+#[test]
+pub fn test_callframe_revert() {
+    let mut manager = manager();
+    manager.add_contract(CALLER, caller(REVERT, 10_000));
+    let mut host = TestHost::default();
+    let result = manager.call(&mut host, CALLER, CoStack::default(), GAS);
+    assert_eq!(result.status, CallStatus::Success);
+    assert_eq!(items(&result.output), vec![0x11223345, CallStatus::Revert(1).code()]);
+    assert_eq!(host.value, 0);
+    assert_eq!(host.events, vec!["begin 1", "begin 3", "end true", "end false"]);
+
+    let mut input = CoStack::default();
+    input.push(&7u32.to_le_bytes());
+    let result = manager.call(&mut host, REVERT, input, 1000);
+    assert_eq!(result.status, CallStatus::Revert(1));
+    assert_eq!(items(&result.output), vec![8]);
+    assert_eq!(host.value, 0);
+}
+
+// A faulting callee gives an empty output, and the forwarded gas is limited to the gas the caller has remaining
+#[test]
+pub fn test_callframe_fault_and_gas() {
+    let mut manager = manager();
+    manager.add_contract(CALLER, caller(SPIN, 1000));
+    let mut host = TestHost::default();
+    let result = manager.call(&mut host, CALLER, CoStack::default(), GAS);
+    assert_eq!(result.status, CallStatus::Success);
+    assert_eq!(items(&result.output), vec![CallStatus::Fault(NarmError::OutOfGas).code()]);
+    assert!(result.gas_remaining < GAS - 1000);
+    assert!(result.gas_remaining >= GAS - 1100, "{}", result.gas_remaining);
+
+    // the callee uses all the gas of the caller, so the caller runs out of gas once the call returns
+    manager.add_contract(CALLER, caller(SPIN, u64::MAX));
+    let result = manager.call(&mut host, CALLER, CoStack::default(), GAS);
+    assert_eq!(result.status, CallStatus::Fault(NarmError::OutOfGas));
+    assert!(result.output.is_empty());
+    assert_eq!(result.gas_remaining, 0);
+    assert_eq!(host.events.last().unwrap(), "end true");
+}
+
+// Calling an unknown contract, or calling without a contract id and gas, fails
+#[test]
+pub fn test_callframe_invalid_calls() {
+    let mut manager = manager();
+    manager.add_contract(CALLER, caller(99, 1000));
+    let mut host = TestHost::default();
+    let result = manager.call(&mut host, CALLER, CoStack::default(), GAS);
+    assert_eq!(result.status, CallStatus::Success);
+    assert_eq!(items(&result.output), vec![CallStatus::UnknownContract.code()]);
+    assert_eq!(host.events, vec!["begin 1", "end false"]);
+
+    let result = manager.call(&mut host, 99, CoStack::default(), 1000);
+    assert_eq!(result.status, CallStatus::UnknownContract);
+    assert_eq!(result.gas_remaining, 1000);
+
+    manager.add_contract(
+        CALLER,
+        image(
+            "
+            ldr r0, =0x80000000
+            movs r1, #0
+            svc #0x20
+            ",
+        ),
+    );
+    let result = manager.call(&mut host, CALLER, CoStack::default(), 1000);
+    assert_eq!(result.status, CallStatus::Fault(NarmError::InvalidContractCall));
+}
+
+// Recursive calls stop at the maximum call depth
+#[test]
+pub fn test_callframe_depth() {
+    let mut manager = manager();
+    manager.add_contract(RECURSIVE, caller(RECURSIVE, u64::MAX));
+    manager.max_depth = 3;
+    let mut host = TestHost::default();
+    let result = manager.call(&mut host, RECURSIVE, CoStack::default(), GAS);
+    assert_eq!(result.status, CallStatus::Success);
+    assert_eq!(items(&result.output), vec![CallStatus::DepthExceeded.code(), 0, 0, 0]);
+    assert_eq!(host.events.iter().filter(|e| e.starts_with("begin")).count(), 4);
+    assert!(host.checkpoints.is_empty());
+}
+
+// Memory for the callee is charged to the gas forwarded to it
+#[test]
+pub fn test_callframe_memory_gas() {
+    let mut manager = manager();
+    manager.limits = MemoryLimits { gas_per_mapped_page: 1, ..MemoryLimits::default() };
+    let mut host = TestHost::default();
+    let vm = manager.create_vm(manager.get_contract(ECHO).unwrap(), 1000).unwrap();
+    let pages = 1000 - vm.gas_remaining;
+    assert!(pages > 0);
+    assert_eq!(vm.get_sp(), STACK_ADDRESS + STACK_SIZE);
+
+    let result = manager.call(&mut host, ECHO, CoStack::default(), pages - 1);
+    assert_eq!(result.status, CallStatus::Fault(NarmError::OutOfMemoryGas));
+    assert_eq!(result.gas_remaining, 0);
+}
+
+// A callee whose memory can not be mapped returns the forwarded gas which was not charged
+#[test]
+pub fn test_callframe_image_too_large() {
+    let mut manager = manager();
+    manager.limits = MemoryLimits { memory_limit: Some(0x1_8000), gas_per_mapped_page: 1, ..MemoryLimits::default() };
+    manager.add_contract(ECHO, ContractImage { code: vec![0; 0x2_0000] });
+    let mut host = TestHost::default();
+    let result = manager.call(&mut host, ECHO, CoStack::default(), 1000);
+    assert_eq!(result.status, CallStatus::Fault(NarmError::MemoryLimitExceeded(CODE_ADDRESS + 0x1_0000)));
+    // only the first 64Kb of the image was mapped
+    assert_eq!(result.gas_remaining, 1000 - 0x10);
+    assert!(result.output.is_empty());
+}
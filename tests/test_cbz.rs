@@ -0,0 +1,231 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+
+/*
+
+Integration test for compare and branch on (non-)zero in the ARMv7-M profile
+
+Included varieties:
+
+CBZ <Rn>, <label> T1                Branch forward if Rn is zero
+CBNZ <Rn>, <label> T1               Branch forward if Rn is not zero
+
+General test cases:
+
+- Branch taken
+- Branch not taken
+- Branch to the furthest possible label
+- Invalid opcode in the ARMv6-M profile
+
+*/
+
+// String representation of ops for use in debug output
+const OPCODES: &'static [&'static str] = &["CBZ <Rn>, <label> T1", "CBNZ <Rn>, <label> T1"];
+
+// Simple constant for number of opcodes tested in this file
+const NUM_OPCODES: &'static usize = &2;
+
+// Branch taken
+#[test]
+pub fn test_cbz_taken() {
+    println!("\n>>> Cbz op test case: Branch taken \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    vm_states[0].r[3] = Some(0x00);
+    vm_states[1].r[3] = Some(0x01);
+
+    // VM initialization
+
+    // 0: CBZ <Rn>, <label> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal = "
+        .arch armv7-m
+        cbz     r3, skip
+        movs    r0, #0x01
+    skip:
+        svc     #0xFF
+        "
+    );
+
+    // 1: CBNZ <Rn>, <label> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal = "
+        .arch armv7-m
+        cbnz    r3, skip
+        movs    r0, #0x01
+    skip:
+        svc     #0xFF
+        "
+    );
+
+    for i in ops_to_test.iter() {
+        vms[*i].set_isa_profile(IsaProfile::ARMv7M);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Branch not taken
+#[test]
+pub fn test_cbz_not_taken() {
+    println!("\n>>> Cbz op test case: Branch not taken \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    vm_states[0].r[3] = Some(0x8000_0000);
+    vm_states[1].r[3] = Some(0x00);
+
+    // VM initialization
+
+    // 0: CBZ <Rn>, <label> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal = "
+        .arch armv7-m
+        cbz     r3, skip
+        movs    r0, #0x01
+    skip:
+        svc     #0xFF
+        "
+    );
+
+    // 1: CBNZ <Rn>, <label> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal = "
+        .arch armv7-m
+        cbnz    r3, skip
+        movs    r0, #0x01
+    skip:
+        svc     #0xFF
+        "
+    );
+
+    for i in ops_to_test.iter() {
+        vms[*i].set_isa_profile(IsaProfile::ARMv7M);
+    }
+    set_for_all!(vm_states[ops_to_test].r[0] = Some(0x01));
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Branch to the furthest possible label
+#[test]
+pub fn test_cbz_far() {
+    println!("\n>>> Cbz op test case: Branch to the furthest possible label \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    vm_states[1].r[7] = Some(0x01);
+
+    // VM initialization
+
+    // 0: CBZ <Rn>, <label> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal = "
+        .arch armv7-m
+        cbz     r7, skip
+        .rept 64
+        movs    r0, #0x01
+        .endr
+    skip:
+        svc     #0xFF
+        "
+    );
+
+    // 1: CBNZ <Rn>, <label> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal = "
+        .arch armv7-m
+        cbnz    r7, skip
+        .rept 64
+        movs    r0, #0x01
+        .endr
+    skip:
+        svc     #0xFF
+        "
+    );
+
+    for i in ops_to_test.iter() {
+        vms[*i].set_isa_profile(IsaProfile::ARMv7M);
+    }
+    set_for_all!(vm_states[ops_to_test].pc_address = Some(ASM_ENTRY + 130 + OP_SIZE));
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Invalid opcode in the ARMv6-M profile
+#[test]
+pub fn test_cbz_armv6m() {
+    println!("\n>>> Cbz op test case: Invalid opcode in the ARMv6-M profile \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    vm_states[0].expect_exec_error = true;
+    vm_states[1].expect_exec_error = true;
+
+    // VM initialization
+
+    // 0: CBZ <Rn>, <label> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal = "
+        .arch armv7-m
+        cbz     r3, skip
+        movs    r0, #0x01
+    skip:
+        svc     #0xFF
+        "
+    );
+
+    // 1: CBNZ <Rn>, <label> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal = "
+        .arch armv7-m
+        cbnz    r3, skip
+        movs    r0, #0x01
+    skip:
+        svc     #0xFF
+        "
+    );
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
@@ -26,7 +26,7 @@ pub fn test_execute() {
         svc                 #0xFF
     ",
     );
-    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
     vm.print_diagnostics();
 
     assert_eq!(0xF1, vm.external_get_reg(0));
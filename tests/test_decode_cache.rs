@@ -0,0 +1,101 @@
+extern crate narm;
+mod common;
+
+use common::*;
+
+/*
+
+Integration test for the decoded instruction cache
+
+General test cases:
+
+- Guest code overwriting an instruction which was already executed (and thus cached)
+- Host overwriting cached code between cycles through copy_into_memory and get_mut_memory
+- Identical results with the cache disabled
+- Blocks are not cached while the cache is disabled
+
+*/
+
+// Guest code which rewrites the instruction at "patched" after it has been executed once
+const SELF_MODIFYING: &str = "
+        movs    r4, #0x00
+    patched:
+        movs    r0, #0x01
+        adds    r4, r0
+        cmp     r4, #0x01
+        bne     done
+        ldr     r1, =0x010002      // address of patched
+        ldr     r2, =0x2002         // movs r0, #0x02
+        strh    r2, [r1]
+        b       patched
+    done:
+        svc     #0xFF
+";
+
+// Guest overwriting already executed code is picked up by the next execution of that address
+#[test]
+pub fn test_decode_cache_guest_write() {
+    let mut vm = create_vm_from_asm(SELF_MODIFYING);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    vm.print_diagnostics();
+    assert_eq!(vm.external_get_reg(0), 0x02);
+    assert_eq!(vm.external_get_reg(4), 0x03);
+}
+
+// Same program with the cache disabled
+#[test]
+pub fn test_decode_cache_disabled() {
+    let mut vm = create_vm_from_asm(SELF_MODIFYING);
+    vm.set_decode_cache(false);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    vm.print_diagnostics();
+    assert_eq!(vm.external_get_reg(0), 0x02);
+    assert_eq!(vm.external_get_reg(4), 0x03);
+}
+
+// Host writes between cycles through copy_into_memory and get_mut_memory invalidate cached code
+#[test]
+pub fn test_decode_cache_host_write() {
+    let mut vm = create_vm_from_asm(
+        "
+    start:
+        movs    r0, #0x01
+        b       start
+    ",
+    );
+    vm.cycle().unwrap();
+    vm.cycle().unwrap();
+    assert_eq!(vm.external_get_reg(0), 0x01);
+
+    // movs r0, #0x02
+    vm.copy_into_memory(ASM_ENTRY, &[0x02, 0x20]).unwrap();
+    vm.cycle().unwrap();
+    vm.cycle().unwrap();
+    assert_eq!(vm.external_get_reg(0), 0x02);
+
+    // movs r0, #0x03
+    vm.memory.get_mut_memory(ASM_ENTRY).unwrap()[0] = 0x03;
+    vm.cycle().unwrap();
+    assert_eq!(vm.external_get_reg(0), 0x03);
+}
+
+// Blocks are not cached while the cache is disabled, so execute_block runs a single instruction
+#[test]
+pub fn test_decode_cache_disabled_blocks() {
+    let program = "
+        movs r0, #1
+        movs r1, #2
+        movs r2, #3
+        svc #1
+    ";
+    let mut vm = create_vm_from_asm(program);
+    assert_eq!(vm.execute_block(), Ok(1));
+    assert_eq!(vm.get_pc_address(), ASM_ENTRY + 8);
+
+    let mut vm = create_vm_from_asm(program);
+    vm.set_decode_cache(false);
+    assert_eq!(vm.execute_block(), Ok(0));
+    assert_eq!(vm.get_pc_address(), ASM_ENTRY + 2);
+    assert_eq!(vm.execute_blocks(), Ok(1));
+    assert_eq!(vm.external_get_reg(2), 3);
+}
@@ -0,0 +1,83 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::narmvm::*;
+use std::sync::{Arc, Mutex};
+
+/*
+
+Integration test for routing diagnostics through DiagnosticsOutput
+
+General test cases:
+
+- print_diagnostics writes to the configured output instead of stdout
+- Breakpoints write to the configured output (debug builds only)
+- Clones of the VM share the output
+
+*/
+
+// Collects all diagnostics text written by the VM
+#[derive(Default)]
+struct CapturedDiagnostics {
+    text: Mutex<String>,
+}
+
+impl DiagnosticsOutput for CapturedDiagnostics {
+    fn write_diagnostics(&self, text: &str) {
+        let mut captured = self.text.lock().unwrap();
+        captured.push_str(text);
+        captured.push('\n');
+    }
+}
+
+// print_diagnostics writes to the configured output instead of stdout
+#[test]
+pub fn test_diagnostics_output() {
+    let output = Arc::new(CapturedDiagnostics::default());
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #0x12
+        svc #0xFF
+        ",
+    );
+    vm.set_diagnostics_output(Some(output.clone()));
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    vm.print_diagnostics();
+    let text = output.text.lock().unwrap().clone();
+    assert!(text.contains("r0: 0x00000012"));
+    assert!(text.contains("gas remaining: "));
+
+    // Clones of the VM share the output
+    output.text.lock().unwrap().clear();
+    vm.clone().print_diagnostics();
+    assert!(output.text.lock().unwrap().contains("r0: 0x00000012"));
+
+    // Without an output, diagnostics go back to stdout
+    output.text.lock().unwrap().clear();
+    vm.set_diagnostics_output(None);
+    vm.print_diagnostics();
+    assert!(output.text.lock().unwrap().is_empty());
+}
+
+// Breakpoints write to the configured output, which is only done in debug builds
+#[test]
+pub fn test_diagnostics_breakpoint() {
+    let output = Arc::new(CapturedDiagnostics::default());
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #0x12
+        bkpt
+        svc #0xFF
+        ",
+    );
+    vm.set_diagnostics_output(Some(output.clone()));
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    let text = output.text.lock().unwrap().clone();
+    if cfg!(debug_assertions) {
+        assert!(text.starts_with("breakpoint triggered!\n"));
+        assert!(text.contains("r0: 0x00000012"));
+    } else {
+        assert!(text.is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+
+/*
+
+Integration test for hardware division, which is part of the ARMv8-M baseline and ARMv7-M profiles
+
+Division rounds towards zero, and division by zero gives a result of 0, as the divide by zero trap is not supported.
+
+Included varieties:
+
+SDIV <Rd>, <Rn>, <Rm> T1            Rd <- Rn / Rm (signed)
+UDIV <Rd>, <Rn>, <Rm> T1            Rd <- Rn / Rm (unsigned)
+
+General test cases:
+
+- Divide with a remainder
+- Divide negative values
+- Divide by zero
+- Divide INT_MIN by -1 (signed overflow)
+- Divide using high registers
+- Division is charged its own gas cost
+- Division is an invalid opcode in the ARMv6-M profile
+
+*/
+
+// String representation of ops for use in debug output
+const OPCODES: &'static [&'static str] = &["SDIV <Rd>, <Rn>, <Rm> T1", "UDIV <Rd>, <Rn>, <Rm> T1"];
+
+// Simple constant for number of opcodes tested in this file
+const NUM_OPCODES: &'static usize = &2;
+
+// Divide with a remainder
+#[test]
+pub fn test_div_remainder() {
+    println!("\n>>> Div op test case: Divide with a remainder \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[1] = Some(0x0001_0007));
+    set_for_all!(vm_states[ops_to_test].r[2] = Some(0x0000_0010));
+
+    // VM initialization
+
+    // 0: SDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv7-m
+        sdiv r0, r1, r2"
+    );
+
+    // 1: UDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        udiv r0, r1, r2"
+    );
+
+    set_for_all!(vm_states[ops_to_test].r[0] = Some(0x0000_1000));
+
+    for vm in vms.iter_mut() {
+        vm.set_isa_profile(IsaProfile::ARMv7M);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Divide negative values
+#[test]
+pub fn test_div_negative() {
+    println!("\n>>> Div op test case: Divide negative values \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[1] = Some(-7i32 as u32));
+    set_for_all!(vm_states[ops_to_test].r[2] = Some(0x0000_0002));
+
+    // VM initialization
+
+    // 0: SDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv7-m
+        sdiv r0, r1, r2"
+    );
+    vm_states[0].r[0] = Some(-3i32 as u32); // Rounds towards zero
+
+    // 1: UDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        udiv r0, r1, r2"
+    );
+    vm_states[1].r[0] = Some(0x7FFF_FFFC);
+
+    for vm in vms.iter_mut() {
+        vm.set_isa_profile(IsaProfile::ARMv7M);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Divide by zero
+#[test]
+pub fn test_div_zero() {
+    println!("\n>>> Div op test case: Divide by zero \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[0] = Some(0x1234_5678));
+    set_for_all!(vm_states[ops_to_test].r[1] = Some(0x1234_5678));
+    set_for_all!(vm_states[ops_to_test].r[2] = Some(0x00));
+
+    // VM initialization
+
+    // 0: SDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv7-m
+        sdiv r0, r1, r2"
+    );
+
+    // 1: UDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        udiv r0, r1, r2"
+    );
+
+    set_for_all!(vm_states[ops_to_test].r[0] = Some(0x00));
+
+    for vm in vms.iter_mut() {
+        vm.set_isa_profile(IsaProfile::ARMv7M);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Divide INT_MIN by -1 (signed overflow)
+#[test]
+pub fn test_div_overflow() {
+    println!("\n>>> Div op test case: Divide INT_MIN by -1 (signed overflow) \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[1] = Some(0x8000_0000));
+    set_for_all!(vm_states[ops_to_test].r[2] = Some(0xFFFF_FFFF));
+
+    // VM initialization
+
+    // 0: SDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv7-m
+        sdiv r0, r1, r2"
+    );
+    vm_states[0].r[0] = Some(0x8000_0000); // Wraps back around to INT_MIN
+
+    // 1: UDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        udiv r0, r1, r2"
+    );
+    vm_states[1].r[0] = Some(0x00);
+
+    for vm in vms.iter_mut() {
+        vm.set_isa_profile(IsaProfile::ARMv7M);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Divide using high registers
+#[test]
+pub fn test_div_high_registers() {
+    println!("\n>>> Div op test case: Divide using high registers \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[9] = Some(0x0000_0064));
+    set_for_all!(vm_states[ops_to_test].r[12] = Some(0x0000_0005));
+
+    // VM initialization
+
+    // 0: SDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv7-m
+        sdiv r8, r9, r12"
+    );
+
+    // 1: UDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        udiv r8, r9, r12"
+    );
+
+    set_for_all!(vm_states[ops_to_test].r[8] = Some(0x0000_0014));
+
+    for vm in vms.iter_mut() {
+        vm.set_isa_profile(IsaProfile::ARMv7M);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Division is charged its own gas cost
+#[test]
+pub fn test_div_gas() {
+    use narm::instruction::DIVISION_GAS;
+    use narm::NarmError;
+
+    let code = "
+        .arch armv7-m
+        udiv r0, r1, r2
+        svc #0xFF
+    ";
+    let mut vm = create_vm_from_asm(code);
+    vm.set_isa_profile(IsaProfile::ARMv8MBaseline);
+    vm.gas_remaining = DIVISION_GAS + 1;
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.gas_remaining, 0x00);
+
+    let mut vm = create_vm_from_asm(code);
+    vm.set_isa_profile(IsaProfile::ARMv8MBaseline);
+    vm.gas_remaining = DIVISION_GAS - 1;
+    assert_eq!(execute_differential(&mut vm), Err(NarmError::OutOfGas));
+    assert_eq!(vm.gas_remaining, 0x00);
+}
+
+// Division is an invalid opcode in the ARMv6-M profile
+#[test]
+pub fn test_div_disabled() {
+    println!("\n>>> Div op test case: Division is an invalid opcode in the ARMv6-M profile \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = [0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[1] = Some(0x0000_0064));
+    set_for_all!(vm_states[ops_to_test].r[2] = Some(0x0000_0005));
+    vm_states[0].expect_exec_error = true;
+    vm_states[1].expect_exec_error = true;
+
+    // VM initialization
+
+    // 0: SDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv7-m
+        sdiv r0, r1, r2"
+    );
+
+    // 1: UDIV <Rd>, <Rn>, <Rm> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        udiv r0, r1, r2"
+    );
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
@@ -0,0 +1,188 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::guestmem::*;
+use narm::impl_guest_pod;
+use narm::memory::MemoryLimits;
+use narm::narmvm::*;
+use narm::NarmError;
+
+/*
+
+Integration test for typed guest memory access from host code
+
+General test cases:
+
+- Byte slices can be read and written across contiguous memory blocks
+- Failed reads and writes report the missing memory, and writes leave memory unchanged
+- Writes to read only memory fail, and large reads fail before allocating
+- C strings are read up to their NUL within a maximum length, and written with a NUL
+- UTF-8 strings are validated
+- Arrays of integers are read and written little endian
+- Structs declared with impl_guest_pod! use the C layout of the guest
+- Writes are charged for touched pages and are seen by cached code
+
+*/
+
+const BLOCK_A: u32 = 0x8200_0000;
+const BLOCK_B: u32 = 0x8201_0000;
+
+fn create_vm() -> NarmVM {
+    let mut vm = create_vm_from_asm("svc #1");
+    vm.memory.add_memory(BLOCK_A, 0x1_0000).unwrap();
+    vm.memory.add_memory(BLOCK_B, 0x100).unwrap();
+    vm
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Message {
+    kind: u8,
+    length: u32,
+    flags: u16,
+    id: u64,
+    tag: [u8; 3],
+}
+
+impl_guest_pod!(Message { kind: u8, length: u32, flags: u16, id: u64, tag: [u8; 3] });
+
+// Byte slices can be read and written across contiguous memory blocks
+#[test]
+pub fn test_guestmem_bytes() {
+    let mut vm = create_vm();
+    let data: Vec<u8> = (0..32).collect();
+    vm.write_bytes(BLOCK_B - 16, &data).unwrap();
+    assert_eq!(vm.read_bytes(BLOCK_B - 16, 32).unwrap(), data);
+    assert_eq!(vm.memory.get_u8(BLOCK_B).unwrap(), 16);
+    assert_eq!(vm.read_bytes(BLOCK_B - 2, 4).unwrap(), vec![14, 15, 16, 17]);
+    assert_eq!(vm.read_bytes(0x5000_0000, 0).unwrap(), Vec::<u8>::new());
+}
+
+// Failed reads and writes report the missing memory, and writes leave memory unchanged
+#[test]
+pub fn test_guestmem_bounds() {
+    let mut vm = create_vm();
+    assert_eq!(vm.read_bytes(0x5000_0000, 4), Err(NarmError::UnloadedMemoryRead(0x5000_0000)));
+    assert_eq!(vm.read_bytes(BLOCK_B + 0xF0, 0x20), Err(NarmError::EmptyMemoryRead(BLOCK_B + 0x10F)));
+    assert_eq!(vm.read_bytes(0xFFFF_FFF0, 0x20), Err(NarmError::EmptyMemoryRead(0xFFFF_FFFF)));
+
+    assert_eq!(vm.write_bytes(BLOCK_B + 0xF0, &[1; 0x20]), Err(NarmError::EmptyMemoryWrite(BLOCK_B + 0x10F)));
+    assert_eq!(vm.read_bytes(BLOCK_B + 0xF0, 0x10).unwrap(), vec![0; 0x10]);
+    // the end of the write would be in mapped memory
+    assert_eq!(vm.write_bytes(BLOCK_A - 4, &[1; 8]), Err(NarmError::UnloadedMemoryWrite(BLOCK_A - 4)));
+    assert_eq!(vm.write_bytes(BLOCK_B + 0x1_0000 - 4, &[1; 8]), Err(NarmError::EmptyMemoryWrite(BLOCK_B + 0xFFFF)));
+    assert_eq!(vm.memory.get_u8(BLOCK_A + 1).unwrap(), 0);
+}
+
+// Writes to read only memory fail, and large reads fail before allocating
+#[test]
+pub fn test_guestmem_read_only() {
+    let mut vm = create_vm_from_asm("svc #1");
+    let code = vm.read_bytes(ASM_ENTRY, 2).unwrap();
+    assert_eq!(vm.write_bytes(ASM_ENTRY, &[0; 2]), Err(NarmError::ReadOnlyMemoryWrite(ASM_ENTRY)));
+    assert_eq!(vm.write_pod(ASM_ENTRY, &0u16), Err(NarmError::ReadOnlyMemoryWrite(ASM_ENTRY)));
+    assert_eq!(vm.write_array(ASM_ENTRY, &[0u16]), Err(NarmError::ReadOnlyMemoryWrite(ASM_ENTRY)));
+    assert_eq!(vm.write_cstring(ASM_ENTRY, b"a"), Err(NarmError::ReadOnlyMemoryWrite(ASM_ENTRY)));
+    assert_eq!(vm.read_bytes(ASM_ENTRY, 2).unwrap(), code);
+    // the end of the write would be in writeable memory
+    vm.memory.add_memory(0x7FFF_0000, 0x1_0000).unwrap();
+    assert_eq!(vm.write_bytes(0x7FFF_FFFE, &[1; 4]), Err(NarmError::ReadOnlyMemoryWrite(0x7FFF_FFFE)));
+    assert_eq!(vm.memory.get_u8(0x7FFF_FFFF).unwrap(), 0);
+
+    let vm = create_vm();
+    assert_eq!(vm.read_bytes(BLOCK_A, 0x1000_0000), Err(NarmError::EmptyMemoryRead(BLOCK_B + 0xFFFF)));
+    assert_eq!(vm.read_array::<u64>(BLOCK_A, 0x0200_0000), Err(NarmError::EmptyMemoryRead(BLOCK_B + 0xFFFF)));
+}
+
+// C strings are read up to their NUL within a maximum length, and written with a NUL
+#[test]
+pub fn test_guestmem_cstring() {
+    let mut vm = create_vm();
+    vm.write_cstring(BLOCK_B - 3, b"hello").unwrap();
+    assert_eq!(vm.memory.get_u8(BLOCK_B + 2).unwrap(), 0);
+    assert_eq!(vm.read_cstring(BLOCK_B - 3, 100).unwrap(), b"hello");
+    assert_eq!(vm.read_cstring(BLOCK_B - 3, 5).unwrap(), b"hello");
+    assert_eq!(vm.read_cstring(BLOCK_B - 3, 4), Err(NarmError::InvalidGuestString(BLOCK_B - 3)));
+    assert_eq!(vm.read_cstring(BLOCK_B + 2, 0).unwrap(), b"");
+
+    // a string which runs off the end of memory
+    vm.write_bytes(BLOCK_B + 0xFC, &[1; 4]).unwrap();
+    assert_eq!(vm.read_cstring(BLOCK_B + 0xFC, 100), Err(NarmError::EmptyMemoryRead(BLOCK_B + 0x100)));
+    assert_eq!(vm.write_cstring(BLOCK_B, b"a\0b"), Err(NarmError::InvalidGuestString(BLOCK_B)));
+}
+
+// UTF-8 strings are validated
+#[test]
+pub fn test_guestmem_utf8() {
+    let mut vm = create_vm();
+    vm.write_cstring(BLOCK_A, "héllo".as_bytes()).unwrap();
+    assert_eq!(vm.read_utf8(BLOCK_A, 6).unwrap(), "héllo");
+    assert_eq!(vm.read_utf8_cstring(BLOCK_A, 16).unwrap(), "héllo");
+    // cutting the é in half
+    assert_eq!(vm.read_utf8(BLOCK_A, 2), Err(NarmError::InvalidGuestString(BLOCK_A)));
+    vm.write_cstring(BLOCK_A, &[0x68, 0xFF]).unwrap();
+    assert_eq!(vm.read_utf8_cstring(BLOCK_A, 16), Err(NarmError::InvalidGuestString(BLOCK_A)));
+    assert_eq!(vm.read_cstring(BLOCK_A, 16).unwrap(), vec![0x68, 0xFF]);
+}
+
+// Arrays of integers are read and written little endian
+#[test]
+pub fn test_guestmem_arrays() {
+    let mut vm = create_vm();
+    vm.write_array(BLOCK_B - 4, &[0x1122_3344u32, 0x5566_7788]).unwrap();
+    assert_eq!(vm.memory.get_u32(BLOCK_B).unwrap(), 0x5566_7788);
+    assert_eq!(vm.read_array::<u32>(BLOCK_B - 4, 2).unwrap(), vec![0x1122_3344, 0x5566_7788]);
+    assert_eq!(vm.read_array::<u16>(BLOCK_B - 4, 4).unwrap(), vec![0x3344, 0x1122, 0x7788, 0x5566]);
+    assert_eq!(vm.read_array::<i16>(BLOCK_B, 1).unwrap(), vec![0x7788]);
+    assert_eq!(vm.read_pod::<u64>(BLOCK_B - 4).unwrap(), 0x5566_7788_1122_3344);
+    assert_eq!(vm.read_array::<u32>(BLOCK_B, 0x41), Err(NarmError::EmptyMemoryRead(BLOCK_B + 0x103)));
+    assert_eq!(vm.read_array::<u32>(BLOCK_B, usize::MAX), Err(NarmError::EmptyMemoryRead(0xFFFF_FFFF)));
+}
+
+// Structs declared with impl_guest_pod! use the C layout of the guest
+#[test]
+pub fn test_guestmem_struct() {
+    assert_eq!(<Message as GuestPod>::ALIGN, 8);
+    // kind at 0, length at 4, flags at 8, id at 16, tag at 24, padded to 32
+    assert_eq!(<Message as GuestPod>::SIZE, 32);
+
+    let mut vm = create_vm();
+    let message = Message { kind: 7, length: 0x1234, flags: 0xBEEF, id: 0x0102_0304_0506_0708, tag: *b"abc" };
+    vm.write_pod(BLOCK_A, &message).unwrap();
+    assert_eq!(vm.memory.get_u8(BLOCK_A).unwrap(), 7);
+    assert_eq!(vm.memory.get_u32(BLOCK_A + 4).unwrap(), 0x1234);
+    assert_eq!(vm.memory.get_u16(BLOCK_A + 8).unwrap(), 0xBEEF);
+    assert_eq!(vm.memory.get_u64(BLOCK_A + 16).unwrap(), 0x0102_0304_0506_0708);
+    assert_eq!(vm.read_bytes(BLOCK_A + 24, 3).unwrap(), b"abc");
+    assert_eq!(vm.read_pod::<Message>(BLOCK_A).unwrap(), message);
+
+    let messages = vec![message, Message::default()];
+    vm.write_array(BLOCK_B - 32, &messages).unwrap();
+    assert_eq!(vm.read_array::<Message>(BLOCK_B - 32, 2).unwrap(), messages);
+}
+
+// Writes are charged for touched pages and are seen by cached code
+#[test]
+pub fn test_guestmem_gas_and_code() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #1
+        svc #1
+        ",
+    );
+    // the code is run from writeable memory, as the host can't write to read only memory
+    let code = vm.read_bytes(ASM_ENTRY, 4).unwrap();
+    vm.memory.limits = MemoryLimits { gas_per_touched_page: 5, ..MemoryLimits::default() };
+    vm.write_bytes(STACK_MEM_START, &code).unwrap();
+    vm.set_thumb_pc_address(STACK_MEM_START);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.external_get_reg(0), 1);
+
+    // overwrite movs r0, #1 with movs r0, #2
+    vm.write_array(STACK_MEM_START, &[0x2002u16]).unwrap();
+    vm.write_bytes(STACK_MEM_START + 0x1000, &[1; 4]).unwrap();
+    assert_eq!(vm.memory.pending_gas(), 5);
+    vm.set_thumb_pc_address(STACK_MEM_START);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.external_get_reg(0), 2);
+}
@@ -0,0 +1,92 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::memory::*;
+use narm::NarmError;
+
+/*
+
+Integration test for hex dumps of memory
+
+General test cases:
+
+- Lines have an address column, hex bytes and an ASCII gutter, and a partial last line is padded
+- The width and ASCII gutter are configurable
+- Unmapped bytes are shown as ?? and a dump stops at the end of the address space
+- Displaying a memory block with bytes which are not UTF-8 gives a hex dump instead of panicking
+- The diagnostics message dumps memory around SP and around the address of the last memory fault
+
+*/
+
+// Lines have an address column, hex bytes and an ASCII gutter, and a partial last line is padded
+#[test]
+pub fn test_hexdump_format() {
+    let mut memory = MemorySystem::default();
+    memory.add_memory(0x8000_0000, 0x100).unwrap();
+    memory.get_mut_sized_memory(0x8000_0000, 20).unwrap().copy_from_slice(b"Hello, world!\n\xff\x00abcd");
+    assert_eq!(
+        memory.hex_dump(0x8000_0000, 20, HexDumpFormat::default()),
+        "0x80000000:  48 65 6c 6c 6f 2c 20 77 6f 72 6c 64 21 0a ff 00  |Hello, world!...|\n\
+         0x80000010:  61 62 63 64                                      |abcd|\n"
+    );
+    assert_eq!(memory.hex_dump(0x8000_0001, 2, HexDumpFormat::default()), "0x80000001:  65 6c                                            |el|\n");
+    assert_eq!(memory.hex_dump(0x8000_0000, 0, HexDumpFormat::default()), "");
+}
+
+// The width and ASCII gutter are configurable
+#[test]
+pub fn test_hexdump_options() {
+    let mut memory = MemorySystem::default();
+    memory.add_memory(0x8000_0000, 0x100).unwrap();
+    memory.get_mut_sized_memory(0x8000_0000, 6).unwrap().copy_from_slice(b"narm!!");
+    let format = HexDumpFormat { width: 4, ascii: false };
+    assert_eq!(memory.hex_dump(0x8000_0000, 6, format), "0x80000000:  6e 61 72 6d\n0x80000004:  21 21\n");
+    let format = HexDumpFormat { width: 4, ascii: true };
+    assert_eq!(memory.hex_dump(0x8000_0000, 6, format), "0x80000000:  6e 61 72 6d  |narm|\n0x80000004:  21 21        |!!|\n");
+    // a width of 0 is treated as 1
+    let format = HexDumpFormat { width: 0, ascii: false };
+    assert_eq!(memory.hex_dump(0x8000_0000, 2, format), "0x80000000:  6e\n0x80000001:  61\n");
+}
+
+// Unmapped bytes are shown as ?? and a dump stops at the end of the address space
+#[test]
+pub fn test_hexdump_unmapped() {
+    let mut memory = MemorySystem::default();
+    memory.add_memory(0x8000_0000, 0x10).unwrap();
+    memory.get_mut_sized_memory(0x8000_000E, 2).unwrap().copy_from_slice(b"ok");
+    let format = HexDumpFormat { width: 4, ascii: true };
+    assert_eq!(memory.hex_dump(0x8000_000E, 4, format), "0x8000000e:  6f 6b ?? ??  |ok  |\n");
+    assert_eq!(memory.hex_dump(0xFFFF_FFFE, 16, format), "0xfffffffe:  ?? ??        |  |\n");
+}
+
+// Displaying a memory block with bytes which are not UTF-8 gives a hex dump instead of panicking
+#[test]
+pub fn test_hexdump_display() {
+    let mut block = BufferMemory::default();
+    block.memory = vec![0xff, 0xfe, b'a', 0];
+    assert_eq!(block.to_string(), "0x00000000:  ff fe 61 00                                      |..a.|\n");
+    assert_eq!(BufferMemory::default().to_string(), "");
+}
+
+// The diagnostics message dumps memory around SP and around the address of the last memory fault
+#[test]
+pub fn test_hexdump_diagnostics() {
+    let mut vm = create_vm_from_asm(
+        "
+        ldr r0, =0x81000104
+        mov sp, r0
+        ldr r1, =0x6c6c6548
+        str r1, [r0]
+        ldr r1, =0x8100fffd
+        ldr r2, [r1]
+        ",
+    );
+    let error = NarmError::EmptyMemoryRead(0x8101_0000);
+    assert_eq!(execute_differential(&mut vm), Err(error));
+    let message = vm.get_diagnostics_message();
+    assert!(message.contains("memory around sp:\n0x810000f0:  00"), "{}", message);
+    assert!(message.contains("0x81000100:  00 00 00 00 48 65 6c 6c 00"), "{}", message);
+    assert!(message.contains(&format!("last error: {:?}\nmemory around 0x81010000:\n0x8100fff0:  00", error)), "{}", message);
+    assert!(message.contains("0x81010000:  ?? ??"), "{}", message);
+}
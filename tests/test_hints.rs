@@ -0,0 +1,112 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+
+/*
+
+Integration test for yielding to the host on wait hints
+
+General test cases:
+
+- YIELD, WFE, WFI and SEV are treated as NOP by default
+- With yielding enabled, each wait hint returns its exit reason and execution resumes after it
+- NOP and other hints never yield
+- Wait hints skipped by an IT block do not yield
+- Yielding is available from cycle(), and wait hints are charged gas like NOP
+
+*/
+
+const WAIT_HINTS: &str = "
+        movs r0, #1
+        yield
+        adds r0, #1
+        wfe
+        adds r0, #1
+        wfi
+        adds r0, #1
+        sev
+        adds r0, #1
+        svc #1
+        ";
+
+// YIELD, WFE, WFI and SEV are treated as NOP by default
+#[test]
+pub fn test_hints_disabled() {
+    let mut vm = create_vm_from_asm(WAIT_HINTS);
+    assert!(!vm.get_yield_on_hints());
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.external_get_reg(0), 5);
+}
+
+// With yielding enabled, each wait hint returns its exit reason and execution resumes after it
+#[test]
+pub fn test_hints_yield() {
+    let mut vm = create_vm_from_asm(WAIT_HINTS);
+    vm.set_yield_on_hints(true);
+    for (exit, r0) in [(EXIT_YIELD, 1), (EXIT_WFE, 2), (EXIT_WFI, 3), (EXIT_SEV, 4), (1, 5)] {
+        assert_eq!(execute_differential(&mut vm), Ok(exit));
+        assert_eq!(vm.external_get_reg(0), r0);
+    }
+    assert_eq!(EXIT_WFI, HINT_EXIT | 0x30);
+
+    // the host can stop yielding at any time
+    let mut vm = create_vm_from_asm(WAIT_HINTS);
+    vm.set_yield_on_hints(true);
+    assert_eq!(execute_differential(&mut vm), Ok(EXIT_YIELD));
+    vm.set_yield_on_hints(false);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+}
+
+// NOP and other hints never yield
+#[test]
+pub fn test_hints_nop() {
+    let mut vm = create_vm_from_opcodes(&[
+        0xBF00, //nop
+        0xBF50, //unallocated hint
+        0xBF80, //unallocated hint
+        0xDF01, //svc #1
+    ]);
+    vm.set_yield_on_hints(true);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+}
+
+// Wait hints skipped by an IT block do not yield
+#[test]
+pub fn test_hints_it_block() {
+    let mut vm = create_vm_from_asm(
+        "
+        .arch armv7-m
+        movs r0, #0
+        cmp r0, #1
+        ite eq
+        wfieq
+        wfene
+        svc #1
+        ",
+    );
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    vm.set_yield_on_hints(true);
+    assert_eq!(execute_differential(&mut vm), Ok(EXIT_WFE));
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+}
+
+// Yielding is available from cycle(), and wait hints are charged gas like NOP
+#[test]
+pub fn test_hints_cycle() {
+    let mut vm = create_vm_from_asm(
+        "
+        wfi
+        nop
+        svc #1
+        ",
+    );
+    vm.set_yield_on_hints(true);
+    let gas = vm.gas_remaining;
+    assert_eq!(vm.cycle(), Ok(EXIT_WFI));
+    assert_eq!(vm.get_pc_address(), ASM_ENTRY + 2);
+    assert_eq!(vm.cycle(), Ok(0));
+    assert_eq!(gas - vm.gas_remaining, 2);
+}
@@ -0,0 +1,220 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::NarmError;
+
+/*
+
+Integration test for IT blocks in the ARMv7-M profile
+
+Included varieties:
+
+IT{x{y{z}}} <firstcond> T1          Executes the following 1-4 instructions conditionally
+
+General test cases:
+
+- Every condition and mask combination, with every combination of flags
+- ITSTATE is kept across cycle() calls
+- 16 bit data processing instructions don't set flags within an IT block, but compares still do
+- A branch is allowed as the last instruction of an IT block
+- A branch which is not the last instruction of an IT block is an error
+- CBZ and IT within an IT block are errors
+- IT is a hint (NOP) in the ARMv6-M profile
+
+*/
+
+// Reference implementation of the condition codes, following the ARMv7-M Architecture Reference Manual
+fn reference_condition(cond: u16, n: bool, z: bool, c: bool, v: bool) -> bool {
+    let result = match cond >> 1 {
+        0b000 => z,
+        0b001 => c,
+        0b010 => n,
+        0b011 => v,
+        0b100 => c && !z,
+        0b101 => n == v,
+        0b110 => n == v && !z,
+        _ => true,
+    };
+    if cond & 1 == 1 && cond != 0b1111 {
+        !result
+    } else {
+        result
+    }
+}
+
+// Every condition and mask combination, with every combination of flags
+#[test]
+pub fn test_it_all_conditions() {
+    for firstcond in 0x00..=0x0E {
+        for mask in 0x01..=0x0F {
+            // An AL block can't contain else conditions
+            if firstcond == 0x0E && mask != 0x08 {
+                continue;
+            }
+            let count = 4 - (mask as u32).trailing_zeros() as usize;
+            // it<x<y<z>>> <firstcond>, followed by a "movs rK, #0x00" for every instruction in the block
+            let mut opcodes = vec![0xBF00 | (firstcond << 4) | mask];
+            for k in 0..count {
+                opcodes.push(0x2000 | ((k as u16) << 8));
+            }
+            opcodes.push(0xDFFF); // svc #0xFF
+            for flags in 0x00..=0x0F {
+                let (n, z, c, v) = (flags & 8 != 0, flags & 4 != 0, flags & 2 != 0, flags & 1 != 0);
+                let mut vm = create_vm_from_opcodes(&opcodes);
+                vm.set_isa_profile(IsaProfile::ARMv7M);
+                for k in 0..4 {
+                    vm.external_set_reg(k, 0xFF);
+                }
+                vm.cpsr.n = n;
+                vm.cpsr.z = z;
+                vm.cpsr.c = c;
+                vm.cpsr.v = v;
+
+                assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+
+                for k in 0..4 {
+                    let cond = if k == 0 {
+                        firstcond
+                    } else {
+                        (firstcond & 0x0E) | ((mask >> (4 - k)) & 1)
+                    };
+                    let executed = k < count && reference_condition(cond, n, z, c, v);
+                    let expected = if executed { 0x00 } else { 0xFF };
+                    assert_eq!(
+                        vm.external_get_reg(k),
+                        expected,
+                        "\n\n>>> firstcond {:#06b}, mask {:#06b}, flags {:#06b}: r{} expected 0x{:02X}\n\n",
+                        firstcond,
+                        mask,
+                        flags,
+                        k,
+                        expected
+                    );
+                }
+                // movs would have set the zero flag outside of an IT block
+                assert_eq!((vm.cpsr.n, vm.cpsr.z, vm.cpsr.c, vm.cpsr.v), (n, z, c, v));
+                assert_eq!(vm.get_itstate(), 0x00);
+            }
+        }
+    }
+}
+
+// ITSTATE is kept across cycle() calls
+#[test]
+pub fn test_it_cycle() {
+    let mut vm = create_vm_from_asm(
+        "
+        .arch armv7-m
+        ite     eq
+        moveq   r0, #0x01
+        movne   r0, #0x02
+        svc     #0xFF
+    ",
+    );
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    vm.cpsr.z = false;
+
+    vm.cycle().unwrap();
+    assert_eq!(vm.get_itstate(), 0b0000_1100); // EQ, then one else
+    vm.cycle().unwrap();
+    assert_eq!(vm.get_itstate(), 0b0001_1000); // NE
+    assert_eq!(vm.external_get_reg(0), 0x00);
+    vm.cycle().unwrap();
+    assert_eq!(vm.get_itstate(), 0x00);
+    assert_eq!(vm.external_get_reg(0), 0x02);
+    assert_eq!(vm.cycle().unwrap(), 0xFF);
+}
+
+// 16 bit data processing instructions don't set flags within an IT block, but compares still do
+#[test]
+pub fn test_it_flags() {
+    let mut vm = create_vm_from_asm(
+        "
+        .arch armv7-m
+        movs    r0, #0x00
+        itt     eq
+        subeq   r0, #0x01
+        moveq   r1, #0x01
+        it      eq
+        cmpeq   r1, #0x02
+        svc     #0xFF
+    ",
+    );
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(0), 0xFFFF_FFFF);
+    // The subtraction left the zero flag set by the first movs, so the following instructions executed
+    assert_eq!(vm.external_get_reg(1), 0x01);
+    // Set by the compare
+    assert!(vm.cpsr.n);
+    assert!(!vm.cpsr.z);
+    assert!(!vm.cpsr.c);
+}
+
+// A branch is allowed as the last instruction of an IT block
+#[test]
+pub fn test_it_branch_last() {
+    let mut vm = create_vm_from_asm(
+        "
+        .arch armv7-m
+        movs    r0, #0x00
+        itt     eq
+        moveq   r1, #0x01
+        beq     skip
+        movs    r2, #0x01
+    skip:
+        svc     #0xFF
+    ",
+    );
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(1), 0x01);
+    assert_eq!(vm.external_get_reg(2), 0x00);
+}
+
+// A branch which is not the last instruction of an IT block is an error
+#[test]
+pub fn test_it_branch_not_last() {
+    // itt eq; b.n +0; movs r0, #0x00; svc #0xFF
+    let mut vm = create_vm_from_opcodes(&[0xBF04, 0xE000, 0x2000, 0xDFFF]);
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    vm.cpsr.z = true;
+    assert_eq!(
+        execute_differential(&mut vm),
+        Err(NarmError::InvalidITBlockInstruction(ASM_ENTRY + OP_SIZE))
+    );
+}
+
+// CBZ and IT within an IT block are errors
+#[test]
+pub fn test_it_invalid_instructions() {
+    // it eq; cbz r0, +0; svc #0xFF
+    let mut vm = create_vm_from_opcodes(&[0xBF08, 0xB100, 0xDFFF]);
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    assert_eq!(
+        execute_differential(&mut vm),
+        Err(NarmError::InvalidITBlockInstruction(ASM_ENTRY + OP_SIZE))
+    );
+
+    // it eq; it eq; svc #0xFF
+    let mut vm = create_vm_from_opcodes(&[0xBF08, 0xBF08, 0xDFFF]);
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    assert_eq!(
+        execute_differential(&mut vm),
+        Err(NarmError::InvalidITBlockInstruction(ASM_ENTRY + OP_SIZE))
+    );
+}
+
+// IT is a hint (NOP) in the ARMv6-M profile
+#[test]
+pub fn test_it_armv6m() {
+    // it eq; movs r0, #0x00; svc #0xFF
+    let mut vm = create_vm_from_opcodes(&[0xBF08, 0x2000, 0xDFFF]);
+    vm.external_set_reg(0, 0xFF);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(0), 0x00);
+    assert!(vm.cpsr.z);
+    assert_eq!(vm.get_itstate(), 0x00);
+}
@@ -0,0 +1,250 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+
+/*
+
+Integration test for exclusive access in the ARMv8-M baseline and ARMv7-M profiles
+
+Included varieties:
+
+LDREX <Rt>, [<Rn>{, #<imm>}] T1         Rt <- [Rn + imm], and marks the address for exclusive access
+LDREXB <Rt>, [<Rn>] T1                  Rt <- [Rn] (byte), and marks the address for exclusive access
+LDREXH <Rt>, [<Rn>] T1                  Rt <- [Rn] (halfword), and marks the address for exclusive access
+STREX <Rd>, <Rt>, [<Rn>{, #<imm>}] T1   [Rn + imm] <- Rt and Rd <- 0 if the address is marked, otherwise Rd <- 1
+STREXB <Rd>, <Rt>, [<Rn>] T1            [Rn] <- Rt (byte) and Rd <- 0 if the address is marked, otherwise Rd <- 1
+STREXH <Rd>, <Rt>, [<Rn>] T1            [Rn] <- Rt (halfword) and Rd <- 0 if the address is marked, otherwise Rd <- 1
+CLREX T1                                Clears the local exclusive monitor
+
+General test cases:
+
+- Exclusive store after an exclusive load succeeds
+- Exclusive store without an exclusive load fails
+- Byte and halfword variants
+- Exclusive store to a different address or with a different size fails
+- CLREX, SVC, errors and the host clear the monitor
+- Compare-exchange loop as generated for core::sync::atomic
+
+*/
+
+const DATA_ADDRESS: u32 = STACK_MEM_START + 0x100;
+
+// Assembles the code with r0 pointing at DATA_ADDRESS, which holds the given word
+fn create_exclusive_vm(body: &str, data: u32) -> NarmVM {
+    let mut vm = create_vm_from_asm(&format!(
+        "
+        .arch armv8-m.base
+        movw r0, #0x{:04X}
+        movt r0, #0x{:04X}
+        {}
+        svc #0xFF
+        ",
+        DATA_ADDRESS & 0xFFFF,
+        DATA_ADDRESS >> 16,
+        body
+    ));
+    vm.set_isa_profile(IsaProfile::ARMv8MBaseline);
+    vm.copy_into_memory(DATA_ADDRESS, &data.to_le_bytes()).unwrap();
+    vm
+}
+
+fn get_data(vm: &NarmVM) -> u32 {
+    vm.memory.get_u32(DATA_ADDRESS).unwrap()
+}
+
+// Exclusive store after an exclusive load succeeds
+#[test]
+pub fn test_ldrex_success() {
+    let mut vm = create_exclusive_vm(
+        "
+        ldrex r1, [r0, #4]
+        adds r1, #0x01
+        strex r3, r1, [r0, #4]
+        ",
+        0x00,
+    );
+    vm.copy_into_memory(DATA_ADDRESS + 4, &[0xFF, 0x00, 0x00, 0x00]).unwrap();
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(3), 0x00);
+    assert_eq!(vm.memory.get_u32(DATA_ADDRESS + 4).unwrap(), 0x100);
+    assert_eq!(vm.get_exclusive_monitor(), None);
+}
+
+// Exclusive store without an exclusive load fails, and a second exclusive store after a successful one fails
+#[test]
+pub fn test_ldrex_no_monitor() {
+    let mut vm = create_exclusive_vm(
+        "
+        movs r1, #0x55
+        strex r3, r1, [r0]
+        ldrex r2, [r0]
+        strex r4, r1, [r0]
+        movs r1, #0x66
+        strex r5, r1, [r0]
+        ",
+        0x1234_5678,
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(2), 0x1234_5678);
+    assert_eq!(vm.external_get_reg(3), 0x01);
+    assert_eq!(vm.external_get_reg(4), 0x00);
+    assert_eq!(vm.external_get_reg(5), 0x01);
+    assert_eq!(get_data(&vm), 0x55);
+}
+
+// Byte and halfword variants
+#[test]
+pub fn test_ldrex_byte_halfword() {
+    let mut vm = create_exclusive_vm(
+        "
+        ldrexb r1, [r0]
+        adds r1, #0x01
+        strexb r3, r1, [r0]
+        adds r0, #0x02
+        ldrexh r2, [r0]
+        adds r2, #0x01
+        strexh r4, r2, [r0]
+        ",
+        0xBEEF_12FF,
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(1), 0x100);
+    assert_eq!(vm.external_get_reg(2), 0xBEF0);
+    assert_eq!(vm.external_get_reg(3), 0x00);
+    assert_eq!(vm.external_get_reg(4), 0x00);
+    // Only the low byte and the high halfword were stored
+    assert_eq!(get_data(&vm), 0xBEF0_1200);
+}
+
+// Exclusive store to a different address or with a different size fails
+#[test]
+pub fn test_ldrex_mismatch() {
+    let mut vm = create_exclusive_vm(
+        "
+        movs r1, #0x55
+        ldrex r2, [r0]
+        strex r3, r1, [r0, #4]
+        ldrexb r2, [r0]
+        strex r4, r1, [r0]
+        ldrex r2, [r0]
+        strexh r5, r1, [r0]
+        ",
+        0x00,
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(3), 0x01);
+    assert_eq!(vm.external_get_reg(4), 0x01);
+    assert_eq!(vm.external_get_reg(5), 0x01);
+    assert_eq!(get_data(&vm), 0x00);
+    assert_eq!(vm.memory.get_u32(DATA_ADDRESS + 4).unwrap(), 0x00);
+}
+
+// CLREX clears the monitor
+#[test]
+pub fn test_ldrex_clrex() {
+    let mut vm = create_exclusive_vm(
+        "
+        movs r1, #0x55
+        ldrex r2, [r0]
+        clrex
+        strex r3, r1, [r0]
+        ",
+        0x00,
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(3), 0x01);
+    assert_eq!(get_data(&vm), 0x00);
+}
+
+// SVC clears the monitor
+#[test]
+pub fn test_ldrex_svc() {
+    let mut vm = create_exclusive_vm(
+        "
+        movs r1, #0x55
+        ldrex r2, [r0]
+        svc #0x01
+        strex r3, r1, [r0]
+        ",
+        0x00,
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0x01);
+    assert_eq!(vm.get_exclusive_monitor(), None);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(3), 0x01);
+    assert_eq!(get_data(&vm), 0x00);
+}
+
+// Errors and the host clear the monitor
+#[test]
+pub fn test_ldrex_error_and_host() {
+    let mut vm = create_exclusive_vm(
+        "
+        ldrex r2, [r0]
+        ldr r1, [r1]
+        ",
+        0x00,
+    );
+    // Unmapped address
+    vm.external_set_reg(1, 0x0F00_0000);
+    assert!(execute_differential(&mut vm).is_err());
+    assert_eq!(vm.get_exclusive_monitor(), None);
+
+    let mut vm = create_exclusive_vm("ldrex r2, [r0]", 0x00);
+    vm.cycle().unwrap();
+    vm.cycle().unwrap();
+    vm.cycle().unwrap();
+    assert_eq!(vm.get_exclusive_monitor(), Some((DATA_ADDRESS, 4)));
+    vm.clear_exclusive_monitor();
+    assert_eq!(vm.get_exclusive_monitor(), None);
+}
+
+// Compare-exchange loop as generated for core::sync::atomic, in both extended profiles
+#[test]
+pub fn test_ldrex_compare_exchange() {
+    let compare_exchange = "
+        movs r1, #0x05
+        movs r2, #0x09
+    retry:
+        ldrex r3, [r0]
+        cmp r3, r1
+        bne fail
+        strex r4, r2, [r0]
+        cmp r4, #0x00
+        bne retry
+        movs r5, #0x01
+        b done
+    fail:
+        clrex
+        movs r5, #0x00
+    done:
+    ";
+    for profile in [IsaProfile::ARMv8MBaseline, IsaProfile::ARMv7M].iter() {
+        // Expected value found, the new value is stored
+        let mut vm = create_exclusive_vm(compare_exchange, 0x05);
+        vm.set_isa_profile(*profile);
+        assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+        assert_eq!(vm.external_get_reg(5), 0x01);
+        assert_eq!(get_data(&vm), 0x09);
+
+        // Another value found, nothing is stored
+        let mut vm = create_exclusive_vm(compare_exchange, 0x06);
+        vm.set_isa_profile(*profile);
+        assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+        assert_eq!(vm.external_get_reg(3), 0x06);
+        assert_eq!(vm.external_get_reg(5), 0x00);
+        assert_eq!(get_data(&vm), 0x06);
+        assert_eq!(vm.get_exclusive_monitor(), None);
+    }
+}
+
+// Exclusive access is an invalid opcode in the ARMv6-M profile
+#[test]
+pub fn test_ldrex_armv6m() {
+    let mut vm = create_exclusive_vm("clrex", 0x00);
+    vm.set_isa_profile(IsaProfile::ARMv6M);
+    assert!(execute_differential(&mut vm).is_err());
+}
@@ -0,0 +1,101 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::narmvm::*;
+use narm::NarmError;
+
+/*
+
+Integration test for memory limits and memory gas charging
+
+General test cases:
+
+- Adding memory beyond the memory limit fails
+- Mapping memory charges gas per page
+- Memory added by the host is free, and is not charged to the first instruction
+- Writing to a page for the first time charges gas, later writes to the same page are free
+- Running out of gas while paying for memory gives a distinct error
+
+*/
+
+// Adding memory beyond the memory limit fails
+#[test]
+pub fn test_memory_limit() {
+    let mut vm = NarmVM::default();
+    vm.memory.limits.memory_limit = Some(0x01_8000);
+    vm.memory.add_memory(0x01_0000, 0x01_0000).unwrap();
+    assert_eq!(
+        vm.memory.add_memory(0x8000_0000, 0x01_0000),
+        Err(NarmError::MemoryLimitExceeded(0x8000_0000))
+    );
+    assert!(!vm.memory.section_exists(0x8000_0000));
+    vm.memory.add_memory(0x8000_0000, 0x8000).unwrap();
+    assert_eq!(vm.memory.mapped_size(), 0x01_8000);
+}
+
+// Mapping memory charges gas per page, rounding partial pages up
+#[test]
+pub fn test_memory_mapped_gas() {
+    let mut vm = NarmVM::default();
+    vm.gas_remaining = 1000;
+    vm.memory.limits.gas_per_mapped_page = 100;
+    vm.map_memory(0x8000_0000, 0x2001).unwrap();
+    assert_eq!(vm.gas_remaining, 700);
+
+    assert_eq!(
+        vm.map_memory(0x8100_0000, 0x01_0000),
+        Err(NarmError::OutOfMemoryGas)
+    );
+    assert_eq!(vm.gas_remaining, 0);
+    assert!(!vm.memory.section_exists(0x8100_0000));
+}
+
+// Memory added by the host is free, and is not charged to the first instruction
+#[test]
+pub fn test_memory_setup_free() {
+    let mut vm = create_vm_from_asm("svc #0xFF");
+    vm.memory.limits.gas_per_mapped_page = 100;
+    vm.memory.add_memory(0x8200_0000, 0x01_0000).unwrap();
+    assert_eq!(vm.memory.pending_gas(), 0);
+    vm.gas_remaining = 10;
+    assert_eq!(execute_differential(&mut vm), Ok(0xFF));
+    assert_eq!(vm.gas_remaining, 9);
+}
+
+// Writing to a page for the first time charges gas, later writes to the same page are free
+#[test]
+pub fn test_memory_touched_gas() {
+    let mut vm = create_vm_from_asm(
+        "
+        ldr     r0, =0x81000000
+        ldr     r1, =0x81001000
+        str     r0, [r0]
+        str     r0, [r0, #0x04]
+        strb    r0, [r1]
+        svc     #0xFF
+    ",
+    );
+    vm.gas_remaining = 1000;
+    vm.memory.limits.gas_per_touched_page = 100;
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    vm.print_diagnostics();
+    // 6 instructions and 2 distinct pages written
+    assert_eq!(vm.gas_remaining, 1000 - 6 - 200);
+}
+
+// Running out of gas while paying for a touched page gives OutOfMemoryGas rather than OutOfGas
+#[test]
+pub fn test_memory_touched_out_of_gas() {
+    let mut vm = create_vm_from_asm(
+        "
+        ldr     r0, =0x81000000
+        str     r0, [r0]
+        svc     #0xFF
+    ",
+    );
+    vm.gas_remaining = 50;
+    vm.memory.limits.gas_per_touched_page = 100;
+    assert_eq!(execute_differential(&mut vm), Err(NarmError::OutOfMemoryGas));
+    assert_eq!(vm.gas_remaining, 0);
+}
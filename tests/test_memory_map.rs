@@ -0,0 +1,231 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::memory::*;
+use narm::narmvm::*;
+use narm::replay::*;
+use narm::NarmError;
+
+/*
+
+Integration test for managing the memory map
+
+General test cases:
+
+- Regions are listed in address order with their size, permissions and name
+- Removed memory can no longer be accessed, and can be added again
+- Resizing keeps the contents up to the new size and zeroes added memory
+- Growing memory is subject to the memory limit and charges gas for new pages
+- Pages of removed or shrunk memory are charged for being touched again once remapped
+- Removing or resizing memory holding executed code invalidates cached instructions
+- Removing, resizing or naming memory which was not added fails
+- The memory map is part of the diagnostics message
+- Changes to the memory map can be rewound while recording
+
+*/
+
+// movs r0, #1; svc #1
+const PROGRAM_1: [u16; 2] = [0x2001, 0xDF01];
+// movs r0, #2; svc #1
+const PROGRAM_2: [u16; 2] = [0x2002, 0xDF01];
+
+fn to_bytes(opcodes: &[u16]) -> Vec<u8> {
+    opcodes.iter().flat_map(|op| op.to_le_bytes()).collect()
+}
+
+// Regions are listed in address order with their size, permissions and name
+#[test]
+pub fn test_memory_map_regions() {
+    let mut vm = NarmVM::default();
+    vm.memory.add_memory(0x8100_0000, 0x2000).unwrap();
+    vm.memory.add_memory(0x01_0000, 0x01_0000).unwrap();
+    vm.memory.add_memory(0x8000_0000, 0x10).unwrap();
+    vm.memory.set_region_name(0x01_0000, "code").unwrap();
+    vm.memory.set_region_name(0x8100_0000, "scratch").unwrap();
+    vm.memory.set_region_name(0x8100_0000, "stack").unwrap();
+    assert_eq!(
+        vm.memory.regions(),
+        vec![
+            MemoryRegion { address: 0x01_0000, size: 0x01_0000, writeable: false, name: Some("code") },
+            MemoryRegion { address: 0x8000_0000, size: 0x10, writeable: true, name: None },
+            MemoryRegion { address: 0x8100_0000, size: 0x2000, writeable: true, name: Some("stack") },
+        ]
+    );
+    assert_eq!(vm.memory.regions()[2].to_string(), "0x81000000-0x81001fff read-write 0x02000 bytes stack");
+    assert_eq!(vm.memory.regions()[1].to_string(), "0x80000000-0x8000000f read-write 0x00010 bytes");
+}
+
+// Removed memory can no longer be accessed, and can be added again
+#[test]
+pub fn test_memory_map_remove() {
+    let mut vm = NarmVM::default();
+    vm.memory.add_memory(0x8100_0000, 0x100).unwrap();
+    vm.memory.set_u32(0x8100_0010, 0x1234_5678).unwrap();
+    vm.memory.remove_memory(0x8100_0000).unwrap();
+    assert!(!vm.memory.section_exists(0x8100_0000));
+    assert!(vm.memory.regions().is_empty());
+    assert_eq!(vm.memory.get_u32(0x8100_0010), Err(NarmError::UnloadedMemoryRead(0x8100_0010)));
+
+    // memory added again starts out empty and unnamed
+    vm.memory.add_memory(0x8100_0000, 0x100).unwrap();
+    assert_eq!(vm.memory.get_u32(0x8100_0010), Ok(0));
+    assert_eq!(vm.memory.regions()[0].name, None);
+}
+
+// Resizing keeps the contents up to the new size and zeroes added memory
+#[test]
+pub fn test_memory_map_resize() {
+    let mut vm = NarmVM::default();
+    vm.memory.add_memory(0x8100_0000, 0x100).unwrap();
+    vm.copy_into_memory(0x8100_00F8, &[0xFF; 8]).unwrap();
+    vm.memory.resize_memory(0x8100_0000, 0x1000).unwrap();
+    assert_eq!(vm.memory.regions()[0].size, 0x1000);
+    assert_eq!(vm.memory.get_u64(0x8100_00F8), Ok(u64::MAX));
+    assert_eq!(vm.memory.get_u32(0x8100_0100), Ok(0));
+    vm.memory.set_u32(0x8100_0FFC, 1).unwrap();
+
+    vm.memory.resize_memory(0x8100_0000, 0xFC).unwrap();
+    assert_eq!(vm.memory.get_u32(0x8100_00F8), Ok(u32::MAX));
+    assert_eq!(vm.memory.get_u32(0x8100_00FC), Err(NarmError::EmptyMemoryRead(0x8100_00FC)));
+    // growing again does not bring back the old contents
+    vm.memory.resize_memory(0x8100_0000, 0x01_0000).unwrap();
+    assert_eq!(vm.memory.get_u32(0x8100_00FC), Ok(0));
+    assert_eq!(vm.memory.get_u32(0x8100_0FFC), Ok(0));
+
+    // the incremental state hash notices the changed size of the last page
+    let hash = vm.incremental_state_hash();
+    vm.memory.resize_memory(0x8100_0000, 0x8001).unwrap();
+    assert_ne!(vm.incremental_state_hash(), hash);
+    assert_eq!(vm.incremental_state_hash(), vm.state_hash());
+}
+
+// Growing memory is subject to the memory limit and charges gas for new pages
+#[test]
+pub fn test_memory_map_resize_limits() {
+    let mut vm = NarmVM::default();
+    vm.memory.limits.memory_limit = Some(0x3000);
+    vm.memory.limits.gas_per_mapped_page = 100;
+    vm.memory.add_memory(0x01_0000, 0x1000).unwrap();
+    vm.memory.add_memory(0x8100_0000, 0x800).unwrap();
+    assert_eq!(vm.memory.pending_gas(), 0);
+
+    assert_eq!(vm.memory.resize_memory(0x8100_0000, 0x2001), Err(NarmError::MemoryLimitExceeded(0x8100_0000)));
+    assert_eq!(vm.memory.regions()[1].size, 0x800);
+    // the partially used page was already paid for
+    vm.memory.resize_memory(0x8100_0000, 0x2000).unwrap();
+    assert_eq!(vm.memory.take_pending_gas(), 100);
+    vm.memory.resize_memory(0x8100_0000, 0x10).unwrap();
+    assert_eq!(vm.memory.take_pending_gas(), 0);
+    assert_eq!(vm.memory.mapped_size(), 0x1010);
+}
+
+// Pages of removed or shrunk memory are charged for being touched again once remapped
+#[test]
+pub fn test_memory_map_retouch() {
+    let mut vm = NarmVM::default();
+    vm.gas_remaining = 1000;
+    vm.memory.limits.gas_per_touched_page = 100;
+    vm.map_memory(0x8200_0000, 0x2000).unwrap();
+    vm.memory.set_u8(0x8200_0000, 1).unwrap();
+    vm.memory.set_u8(0x8200_1000, 1).unwrap();
+    assert_eq!(vm.memory.take_pending_gas(), 200);
+
+    vm.memory.remove_memory(0x8200_0000).unwrap();
+    vm.map_memory(0x8200_0000, 0x2000).unwrap();
+    vm.memory.set_u8(0x8200_0000, 1).unwrap();
+    assert_eq!(vm.memory.take_pending_gas(), 100);
+
+    // the page holding the new end of the block stays touched
+    vm.memory.set_u8(0x8200_1000, 1).unwrap();
+    vm.memory.take_pending_gas();
+    vm.memory.resize_memory(0x8200_0000, 0x1001).unwrap();
+    vm.memory.set_u8(0x8200_1000, 1).unwrap();
+    assert_eq!(vm.memory.take_pending_gas(), 0);
+    vm.memory.resize_memory(0x8200_0000, 0x800).unwrap();
+    vm.memory.resize_memory(0x8200_0000, 0x2000).unwrap();
+    vm.memory.set_u8(0x8200_0000, 1).unwrap();
+    vm.memory.set_u8(0x8200_1000, 1).unwrap();
+    assert_eq!(vm.memory.take_pending_gas(), 100);
+}
+
+// Removing or resizing memory holding executed code invalidates cached instructions
+#[test]
+pub fn test_memory_map_code_invalidation() {
+    let mut vm = create_vm_from_opcodes(&PROGRAM_1);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.external_get_reg(0), 1);
+
+    vm.memory.remove_memory(ASM_ENTRY).unwrap();
+    vm.set_thumb_pc_address(ASM_ENTRY);
+    assert_eq!(execute_differential(&mut vm), Err(NarmError::UnloadedMemoryRead(ASM_ENTRY)));
+
+    vm.memory.add_memory(ASM_ENTRY, 0x100).unwrap();
+    vm.copy_into_memory(ASM_ENTRY, &to_bytes(&PROGRAM_2)).unwrap();
+    vm.set_thumb_pc_address(ASM_ENTRY);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.external_get_reg(0), 2);
+
+    // shrinking away the SVC and growing again leaves zeroes, which decode as movs r0, r0
+    vm.memory.resize_memory(ASM_ENTRY, 2).unwrap();
+    vm.memory.resize_memory(ASM_ENTRY, 4).unwrap();
+    vm.set_thumb_pc_address(ASM_ENTRY);
+    assert_eq!(execute_differential(&mut vm), Err(NarmError::EmptyMemoryRead(ASM_ENTRY + 4)));
+}
+
+// Removing, resizing or naming memory which was not added fails
+#[test]
+pub fn test_memory_map_invalid_region() {
+    let mut vm = NarmVM::default();
+    vm.memory.add_memory(0x8100_0000, 0x100).unwrap();
+    assert_eq!(vm.memory.remove_memory(0x8200_0000), Err(NarmError::InvalidMemoryRegion(0x8200_0000)));
+    // regions are identified by the address they were added at
+    assert_eq!(vm.memory.remove_memory(0x8100_0010), Err(NarmError::InvalidMemoryRegion(0x8100_0010)));
+    assert_eq!(vm.memory.resize_memory(0x8200_0000, 0x100), Err(NarmError::InvalidMemoryRegion(0x8200_0000)));
+    assert_eq!(vm.memory.resize_memory(0x8100_0000, 0), Err(NarmError::InvalidMemoryRegion(0x8100_0000)));
+    assert_eq!(vm.memory.resize_memory(0x8100_0000, 0x01_0001), Err(NarmError::InvalidMemoryRegion(0x8100_0000)));
+    assert_eq!(vm.memory.set_region_name(0x8200_0000, "stack"), Err(NarmError::InvalidMemoryRegion(0x8200_0000)));
+    assert_eq!(vm.memory.regions().len(), 1);
+    assert_eq!(vm.memory.regions()[0].size, 0x100);
+}
+
+// The memory map is part of the diagnostics message
+#[test]
+pub fn test_memory_map_diagnostics() {
+    let mut vm = create_vm_from_opcodes(&PROGRAM_1);
+    vm.memory.set_region_name(ASM_ENTRY, "code").unwrap();
+    let message = vm.get_diagnostics_message();
+    assert!(message.contains(
+        "memory map:\n  0x00010000-0x0001ffff read-only  0x10000 bytes code\n  0x81000000-0x8100fffe read-write 0x0ffff bytes\n"
+    ), "{}", message);
+}
+
+// Changes to the memory map can be rewound while recording
+#[test]
+pub fn test_memory_map_replay() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #1
+        movs r0, #2
+        movs r0, #3
+        svc #1
+        ",
+    );
+    vm.memory.add_memory(0x8200_0000, 0x100).unwrap();
+    vm.memory.set_u8(0x8200_0000, 7).unwrap();
+    vm.start_recording(RecordingConfig::default());
+    vm.cycle().unwrap();
+    vm.memory.remove_memory(0x8200_0000).unwrap();
+    vm.cycle().unwrap();
+    vm.memory.resize_memory(STACK_MEM_START, 0x10).unwrap();
+    vm.cycle().unwrap();
+
+    assert!(vm.rewind_to(2));
+    assert_eq!(vm.memory.regions()[1].size, 0x10);
+    assert!(vm.rewind_to(1));
+    assert_eq!(vm.memory.regions()[1].size, 0xFFFF);
+    assert!(!vm.memory.section_exists(0x8200_0000));
+    assert!(vm.rewind_to(0));
+    assert_eq!(vm.memory.get_u8(0x8200_0000), Ok(7));
+    assert_eq!(vm.external_get_reg(0), 0);
+}
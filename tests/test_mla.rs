@@ -0,0 +1,164 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+
+/*
+
+Integration test for the multiply accumulate instructions of the ARMv7-M profile
+
+Note that result bits that don't fit in 32 bits (overflow) are simply discarded.
+
+Included varieties:
+
+MLA <Rd>, <Rn>, <Rm>, <Ra> T1       Rd <- Ra + Rn * Rm
+MLS <Rd>, <Rn>, <Rm>, <Ra> T1       Rd <- Ra - Rn * Rm
+
+General test cases:
+
+- Calculate result that fits in 32 bits
+- Calculate result that overflow 32 bits
+- Using high registers
+- Flags are not affected
+
+*/
+
+// String representation of ops for use in debug output
+const OPCODES: &'static [&'static str] = &["MLA <Rd>, <Rn>, <Rm>, <Ra> T1", "MLS <Rd>, <Rn>, <Rm>, <Ra> T1"];
+
+// Simple constant for number of opcodes tested in this file
+const NUM_OPCODES: &'static usize = &2;
+
+// Calculate result that fits in 32 bits
+#[test]
+pub fn test_mla_inside_32() {
+    println!("\n>>> Mla op test case: Calculate result that fits in 32 bits \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[1] = Some(0x0000_1234));
+    set_for_all!(vm_states[ops_to_test].r[2] = Some(0x0000_0010));
+    set_for_all!(vm_states[ops_to_test].r[3] = Some(0x0010_0000));
+
+    // VM initialization
+
+    // 0: MLA <Rd>, <Rn>, <Rm>, <Ra> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv7-m
+        mla r0, r1, r2, r3"
+    );
+    vm_states[0].r[0] = Some(0x0011_2340);
+
+    // 1: MLS <Rd>, <Rn>, <Rm>, <Ra> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        mls r0, r1, r2, r3"
+    );
+    vm_states[1].r[0] = Some(0x000E_DCC0);
+
+    for vm in vms.iter_mut() {
+        vm.set_isa_profile(IsaProfile::ARMv7M);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Calculate result that overflow 32 bits
+#[test]
+pub fn test_mla_overflow_32() {
+    println!("\n>>> Mla op test case: Calculate result that overflow 32 bits \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[1] = Some(0x1111_1234));
+    set_for_all!(vm_states[ops_to_test].r[2] = Some(0x0003_0002));
+    set_for_all!(vm_states[ops_to_test].r[3] = Some(0xF000_0000));
+
+    // VM initialization
+
+    // 0: MLA <Rd>, <Rn>, <Rm>, <Ra> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv7-m
+        mla r0, r1, r2, r3"
+    );
+    vm_states[0].r[0] = Some(0x48BE_2468);
+
+    // 1: MLS <Rd>, <Rn>, <Rm>, <Ra> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        mls r0, r1, r2, r3"
+    );
+    vm_states[1].r[0] = Some(0x9741_DB98);
+
+    for vm in vms.iter_mut() {
+        vm.set_isa_profile(IsaProfile::ARMv7M);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Using high registers, with flags which should not be affected
+#[test]
+pub fn test_mla_high_registers() {
+    println!("\n>>> Mla op test case: Using high registers \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[9] = Some(0x0000_0003));
+    set_for_all!(vm_states[ops_to_test].r[10] = Some(0x0000_0005));
+    set_for_all!(vm_states[ops_to_test].r[14] = Some(0x0000_0001));
+
+    set_for_all!(vm_states[ops_to_test].z = Some(true)); // Shouldn't be affected at all
+    set_for_all!(vm_states[ops_to_test].c = Some(true)); // Shouldn't be affected at all
+
+    // VM initialization
+
+    // 0: MLA <Rd>, <Rn>, <Rm>, <Ra> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv7-m
+        mla r8, r9, r10, r14"
+    );
+    vm_states[0].r[8] = Some(0x0000_0010);
+
+    // 1: MLS <Rd>, <Rn>, <Rm>, <Ra> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        mls r8, r9, r10, r14"
+    );
+    vm_states[1].r[8] = Some(0xFFFF_FFF2);
+
+    for vm in vms.iter_mut() {
+        vm.set_isa_profile(IsaProfile::ARMv7M);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
@@ -0,0 +1,152 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+
+/*
+
+Integration test for wide moves in the ARMv8-M baseline and ARMv7-M profiles
+
+Included varieties:
+
+MOVW <Rd>, #<imm16> T3              Rd <- imm16
+MOVT <Rd>, #<imm16> T1              Rd[31:16] <- imm16, Rd[15:0] is kept
+
+General test cases:
+
+- Move into low and high registers
+- Combined MOVW and MOVT building a 32 bit constant
+- Flags are not affected
+- Invalid opcode in the ARMv6-M profile
+
+*/
+
+// String representation of ops for use in debug output
+const OPCODES: &'static [&'static str] = &["MOVW <Rd>, #<imm16> T3", "MOVT <Rd>, #<imm16> T1"];
+
+// Simple constant for number of opcodes tested in this file
+const NUM_OPCODES: &'static usize = &2;
+
+// Move into low and high registers, with flags which should not be affected
+#[test]
+pub fn test_movw_registers() {
+    println!("\n>>> Movw op test case: Move into low and high registers \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[0] = Some(0x1234_5678));
+    set_for_all!(vm_states[ops_to_test].r[9] = Some(0x1234_5678));
+    set_for_all!(vm_states[ops_to_test].z = Some(true)); // Shouldn't be affected at all
+    set_for_all!(vm_states[ops_to_test].c = Some(true)); // Shouldn't be affected at all
+
+    // VM initialization
+
+    // 0: MOVW <Rd>, #<imm16> T3
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv8-m.base
+        movw r0, #0xBEEF
+        movw r9, #0x0001"
+    );
+    vm_states[0].r[0] = Some(0x0000_BEEF);
+    vm_states[0].r[9] = Some(0x0000_0001);
+
+    // 1: MOVT <Rd>, #<imm16> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv8-m.base
+        movt r0, #0xDEAD
+        movt r9, #0xFFFF"
+    );
+    vm_states[1].r[0] = Some(0xDEAD_5678);
+    vm_states[1].r[9] = Some(0xFFFF_5678);
+
+    for i in ops_to_test.iter() {
+        vms[*i].set_isa_profile(IsaProfile::ARMv8MBaseline);
+    }
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Combined MOVW and MOVT building a 32 bit constant, in both extended profiles
+#[test]
+pub fn test_movw_constant() {
+    println!("\n>>> Movw op test case: Combined MOVW and MOVT building a 32 bit constant \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].r[7] = Some(0xCAFE_F00D));
+
+    // VM initialization
+
+    // 0: MOVW <Rd>, #<imm16> T3
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv8-m.base
+        movw r7, #0xF00D
+        movt r7, #0xCAFE"
+    );
+    vms[0].set_isa_profile(IsaProfile::ARMv8MBaseline);
+
+    // 1: MOVT <Rd>, #<imm16> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv7-m
+        movw r7, #0xF00D
+        movt r7, #0xCAFE"
+    );
+    vms[1].set_isa_profile(IsaProfile::ARMv7M);
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
+
+// Invalid opcode in the ARMv6-M profile
+#[test]
+pub fn test_movw_armv6m() {
+    println!("\n>>> Movw op test case: Invalid opcode in the ARMv6-M profile \n");
+
+    // Arrays holding instances of VMs and matching state structs
+    let mut vms: [NarmVM; *NUM_OPCODES] = Default::default();
+    let mut vm_states: [VMState; *NUM_OPCODES] = Default::default();
+
+    // Tell macros which op varieties are tested in this function
+    let ops_to_test = vec![0, 1];
+
+    set_for_all!(vm_states[ops_to_test].expect_exec_error = true);
+
+    // VM initialization
+
+    // 0: MOVW <Rd>, #<imm16> T3
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 0,
+        asm_literal_add_svc = ".arch armv8-m.base
+        movw r0, #0xBEEF"
+    );
+
+    // 1: MOVT <Rd>, #<imm16> T1
+    create_vm!(
+        arrays = (vms, vm_states),
+        op_id = 1,
+        asm_literal_add_svc = ".arch armv8-m.base
+        movt r0, #0xDEAD"
+    );
+
+    run_test!(arrays = (vms, vm_states), op_ids = ops_to_test);
+}
@@ -0,0 +1,163 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::{LONG_MULTIPLY_ACCUMULATE_GAS, LONG_MULTIPLY_GAS};
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+use narm::NarmError;
+
+/*
+
+Integration test for the long multiply instructions of the ARMv7-M profile
+
+Results are compared against Rust's native widening multiplication for a set of interesting operands.
+
+Included varieties:
+
+UMULL <RdLo>, <RdHi>, <Rn>, <Rm> T1     RdHi:RdLo <- Rn * Rm (unsigned)
+SMULL <RdLo>, <RdHi>, <Rn>, <Rm> T1     RdHi:RdLo <- Rn * Rm (signed)
+UMLAL <RdLo>, <RdHi>, <Rn>, <Rm> T1     RdHi:RdLo <- RdHi:RdLo + Rn * Rm (unsigned)
+SMLAL <RdLo>, <RdHi>, <Rn>, <Rm> T1     RdHi:RdLo <- RdHi:RdLo + Rn * Rm (signed)
+
+General test cases:
+
+- Every combination of interesting operands, compared against native multiplication
+- Accumulating into a value which overflows 64 bits
+- Using high registers
+- Each instruction is charged its own gas cost
+- Flags are not affected
+- The long multiplies are invalid opcodes outside the ARMv7-M profile
+
+*/
+
+const OPERANDS: [u32; 12] = [
+    0x0000_0000,
+    0x0000_0001,
+    0x0000_0002,
+    0x0000_FFFF,
+    0x0001_0000,
+    0x1234_5678,
+    0x7FFF_FFFF,
+    0x8000_0000,
+    0x8000_0001,
+    0xDEAD_BEEF,
+    0xFFFF_FFFE,
+    0xFFFF_FFFF,
+];
+
+const ACCUMULATORS: [u64; 4] = [
+    0x0000_0000_0000_0000,
+    0x0000_0001_FFFF_FFFF,
+    0x8000_0000_0000_0000,
+    0xFFFF_FFFF_FFFF_FFFF,
+];
+
+// Assembles the op once, with operands in r2 and r3 and the result in r0 (low) and r1 (high)
+fn create_long_vm(op: &str) -> NarmVM {
+    let mut vm = create_vm_from_asm(&format!(
+        "
+        .arch armv7-m
+        {} r0, r1, r2, r3
+        svc #0xFF
+        ",
+        op
+    ));
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    vm.gas_remaining = 0x1000;
+    vm
+}
+
+fn run_long(vm: &NarmVM, n: u32, m: u32, accumulator: u64) -> u64 {
+    let mut vm = vm.clone();
+    vm.external_set_reg(0, accumulator as u32);
+    vm.external_set_reg(1, (accumulator >> 32) as u32);
+    vm.external_set_reg(2, n);
+    vm.external_set_reg(3, m);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    // Operands are left alone
+    assert_eq!(vm.external_get_reg(2), n);
+    assert_eq!(vm.external_get_reg(3), m);
+    ((vm.external_get_reg(1) as u64) << 32) | vm.external_get_reg(0) as u64
+}
+
+// Every combination of interesting operands, compared against native multiplication
+#[test]
+pub fn test_mull_native() {
+    let umull = create_long_vm("umull");
+    let smull = create_long_vm("smull");
+    for n in OPERANDS.iter().copied() {
+        for m in OPERANDS.iter().copied() {
+            let expected = (n as u64) * (m as u64);
+            assert_eq!(run_long(&umull, n, m, 0x00), expected, "\n\n>>> UMULL 0x{} * 0x{}\n\n", format_padded_hex(n), format_padded_hex(m));
+            let expected = ((n as i32 as i64) * (m as i32 as i64)) as u64;
+            assert_eq!(run_long(&smull, n, m, 0x00), expected, "\n\n>>> SMULL 0x{} * 0x{}\n\n", format_padded_hex(n), format_padded_hex(m));
+        }
+    }
+}
+
+// Every combination of interesting operands and accumulators, compared against native multiplication
+#[test]
+pub fn test_mlal_native() {
+    let umlal = create_long_vm("umlal");
+    let smlal = create_long_vm("smlal");
+    for n in OPERANDS.iter().copied() {
+        for m in OPERANDS.iter().copied() {
+            for a in ACCUMULATORS.iter().copied() {
+                let expected = ((n as u64) * (m as u64)).wrapping_add(a);
+                assert_eq!(run_long(&umlal, n, m, a), expected, "\n\n>>> UMLAL 0x{} * 0x{} + {:#018x}\n\n", format_padded_hex(n), format_padded_hex(m), a);
+                let expected = ((n as i32 as i64) * (m as i32 as i64)).wrapping_add(a as i64) as u64;
+                assert_eq!(run_long(&smlal, n, m, a), expected, "\n\n>>> SMLAL 0x{} * 0x{} + {:#018x}\n\n", format_padded_hex(n), format_padded_hex(m), a);
+            }
+        }
+    }
+}
+
+// Using high registers, with flags which should not be affected
+#[test]
+pub fn test_mull_high_registers() {
+    let mut vm = create_vm_from_asm(
+        "
+        .arch armv7-m
+        umull r8, r9, r10, r11
+        smlal r12, r14, r10, r11
+        svc #0xFF
+    ",
+    );
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    vm.external_set_reg(10, 0xFFFF_FFFF);
+    vm.external_set_reg(11, 0x0000_0002);
+    vm.external_set_reg(12, 0x0000_0001);
+    vm.cpsr.z = true;
+    vm.cpsr.c = true;
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(8), 0xFFFF_FFFE);
+    assert_eq!(vm.external_get_reg(9), 0x0000_0001);
+    // -1 * 2 + 1
+    assert_eq!(vm.external_get_reg(12), 0xFFFF_FFFF);
+    assert_eq!(vm.external_get_reg(14), 0xFFFF_FFFF);
+    assert!(vm.cpsr.z);
+    assert!(vm.cpsr.c);
+    assert!(!vm.cpsr.n);
+}
+
+// Each instruction is charged its own gas cost
+#[test]
+pub fn test_mull_gas() {
+    for (op, gas) in [("umull", LONG_MULTIPLY_GAS), ("smull", LONG_MULTIPLY_GAS), ("umlal", LONG_MULTIPLY_ACCUMULATE_GAS), ("smlal", LONG_MULTIPLY_ACCUMULATE_GAS)].iter() {
+        let mut vm = create_long_vm(op);
+        vm.gas_remaining = gas + 1;
+        assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+        assert_eq!(vm.gas_remaining, 0x00);
+    }
+}
+
+// The long multiplies are invalid opcodes outside the ARMv7-M profile
+#[test]
+pub fn test_mull_profiles() {
+    for profile in [IsaProfile::ARMv6M, IsaProfile::ARMv8MBaseline].iter() {
+        let mut vm = create_long_vm("umull");
+        vm.set_isa_profile(*profile);
+        assert_eq!(execute_differential(&mut vm), Err(NarmError::InvalidOpcode32(0xFBA2_0103)), "{:?}", profile);
+    }
+}
@@ -0,0 +1,290 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::narmvm::*;
+use narm::neutron::*;
+use narm::NarmError;
+
+/*
+
+Integration test for the Neutron ABI layer
+
+Included service calls:
+
+SVC #0x10       __push_costack(pointer, size)
+SVC #0x11       __pop_costack(pointer, max_size) -> size
+SVC #0x12       __peek_costack(pointer, max_size, index) -> size
+SVC #0x13       __drop_costack()
+SVC #0x14       __costack_count() -> count
+SVC #0x20       __system_call(feature, function) -> result
+SVC #0xFF       __exit(code)
+
+*/
+
+// Same program as test_rust_helloworld, but with the Neutron service calls in place of the nop stubs
+const HELLO_WORLD: &str = "
+        ldr        r0, =0x81000200
+        mov        sp, r0
+        bl         main
+        nop
+
+    __exit:
+        svc        #0xff
+
+    __push_costack:
+        svc        #0x10
+        mov        pc, lr
+
+
+    __system_call:
+        svc        #0x20
+        mov        pc, lr
+
+    main:
+        push       {r7, lr}
+        add        r7, sp, #0x0
+        sub        sp, #0x10
+        ldr        r0, =test_string     //argument #1 for method _str_as_ptr
+        str        r0, [sp, #0x8]
+        movs       r1, #0xb             //argument #2 for method _str_as_ptr
+        str        r1, [sp, #0xc]
+        bl         _str_as_ptr
+        str        r0, [sp, #0x4]
+        ldr        r0, =test_string     // argument #1 for method _str_len
+        movs       r1, #0xb             // argument #2 for method _str_len
+        bl         _str_len
+        str        r0, [sp]
+        ldr        r0, [sp, #0x4]
+        ldr        r1, [sp]
+        bl         __push_costack
+        movs       r0, #0x5             // argument #1 for method __exit
+        bl         __exit               // __exit
+
+
+    _str_len:
+        push       {r7, lr}
+        add        r7, sp, #0x0
+        sub        sp, #0x28
+        str        r0, [sp, #0x28 + -24]
+        str        r1, [sp, #0x28 + -20]
+        str        r0, [sp, #0x28 + -16]
+        str        r1, [sp, #0x28 + -12]
+        str        r0, [sp, #0x28 + -8]
+        str        r1, [sp, #0x28 + -4]
+        str        r0, [sp, #0x28 + -28]
+        str        r1, [sp, #0x28 + -32]
+        ldr        r0, [sp, #0x28 + -28]   // argument #1 for method _slice_len
+        ldr        r1, [sp, #0x28 + -32]   // argument #2 for method _slice_len
+        bl         _slice_len
+        str        r0, [sp, #0x28 + -36]
+        add        sp, #0x28
+        pop        {r7, pc}
+
+
+    _str_as_ptr:
+        sub        sp, #0x8
+        str        r0, [sp, #0x8 + -8]
+        str        r1, [sp, #0x8 + -4]
+        add        sp, #0x8
+        bx         lr
+
+
+
+    _slice_len:
+        sub        sp, #0x10
+        str        r0, [sp, #0x10 + -8]
+        str        r1, [sp, #0x10 + -4]
+        str        r0, [sp, #0x10 + -16]
+        str        r1, [sp, #0x10 + -12]
+        ldr        r0, [sp, #0x10 + -12]
+        add        sp, #0x10
+        bx         lr
+
+
+    test_string:
+        .string \"foo bar 123!\"
+
+";
+
+// Test system call handler: feature 0x01 adds the two top costack items (u32 LE) and pushes the sum
+struct TestSystemCalls {
+    calls: u32,
+}
+
+impl SystemCallHandler for TestSystemCalls {
+    fn system_call(
+        &mut self,
+        _vm: &mut NarmVM,
+        costack: &mut CoStack,
+        feature: u32,
+        function: u32,
+    ) -> Result<u32, NarmError> {
+        self.calls += 1;
+        if feature != 0x01 {
+            return Err(NarmError::UnknownSystemCall(feature));
+        }
+        let mut a = [0u8; 4];
+        let mut b = [0u8; 4];
+        a.copy_from_slice(&costack.pop().unwrap());
+        b.copy_from_slice(&costack.pop().unwrap());
+        let sum = u32::from_le_bytes(a).wrapping_add(u32::from_le_bytes(b));
+        costack.push(&sum.to_le_bytes());
+        Ok(function)
+    }
+}
+
+// Hello world program pushes its string to the costack and exits with code 5
+#[test]
+pub fn test_neutron_hello_world() {
+    println!("\n>>> Neutron Hello World Rust Program Test\n");
+    let mut vm = create_vm_from_asm(HELLO_WORLD);
+    let mut neutron = Neutron::default();
+
+    let result = neutron.execute(&mut vm, &mut NullSystemCalls);
+    vm.print_diagnostics();
+    assert_eq!(result.unwrap(), 0x05);
+    assert_eq!(neutron.costack.len(), 1);
+    assert_eq!(neutron.costack.peek(0).unwrap(), b"foo bar 123");
+}
+
+// Push two values, sum them with a system call, then pop the result back into memory
+#[test]
+pub fn test_neutron_system_call() {
+    println!("\n>>> Neutron system call test\n");
+    let mut vm = create_vm_from_asm(
+        "
+        ldr        r0, =0x81000000
+        ldr        r1, =0x12345678
+        str        r1, [r0]
+        ldr        r1, =0x01010101
+        str        r1, [r0, #0x04]
+        movs       r1, #0x04
+        svc        #0x10                // push [0x81000000]
+        adds       r0, #0x04
+        movs       r1, #0x04
+        svc        #0x10                // push [0x81000004]
+        movs       r0, #0x01
+        movs       r1, #0x2A
+        svc        #0x20                // system call feature 1, function 0x2A
+        mov        r4, r0
+        ldr        r0, =0x81000010
+        movs       r1, #0x08
+        svc        #0x11                // pop into [0x81000010]
+        mov        r5, r0
+        svc        #0x14                // costack count
+        mov        r6, r0
+        movs       r0, #0x00
+        svc        #0xFF
+    ",
+    );
+    let mut neutron = Neutron::default();
+    let mut handler = TestSystemCalls { calls: 0 };
+
+    let result = neutron.execute(&mut vm, &mut handler);
+    vm.print_diagnostics();
+    assert_eq!(result.unwrap(), 0x00);
+    assert_eq!(handler.calls, 1);
+    assert_eq!(vm.external_get_reg(4), 0x2A);
+    assert_eq!(vm.external_get_reg(5), 0x04);
+    assert_eq!(vm.external_get_reg(6), 0x00);
+    assert_eq!(vm.memory.get_u32(0x8100_0010).unwrap(), 0x1335_5779);
+    assert!(neutron.costack.is_empty());
+}
+
+// Peeking leaves the costack intact and truncates to the given max size, but reports the full size
+#[test]
+pub fn test_neutron_peek_truncate() {
+    println!("\n>>> Neutron costack peek test\n");
+    let mut vm = create_vm_from_asm(
+        "
+        ldr        r0, =0x81000000
+        movs       r1, #0x02
+        movs       r2, #0x01
+        svc        #0x12                // peek second item, at most 2 bytes
+        mov        r4, r0
+        svc        #0x13                // drop top item
+        svc        #0x14
+        mov        r5, r0
+        svc        #0xFF
+    ",
+    );
+    let mut neutron = Neutron::default();
+    neutron.costack.push(&[0xAA, 0xBB, 0xCC]);
+    neutron.costack.push(&[0x11]);
+
+    let result = neutron.execute(&mut vm, &mut NullSystemCalls);
+    vm.print_diagnostics();
+    assert_eq!(result.unwrap(), 0x01);
+    assert_eq!(vm.external_get_reg(4), 0x03);
+    assert_eq!(vm.external_get_reg(5), 0x01);
+    assert_eq!(vm.memory.get_u16(0x8100_0000).unwrap(), 0xBBAA);
+    assert_eq!(vm.memory.get_u8(0x8100_0002).unwrap(), 0x00);
+}
+
+// Errors from the Neutron layer are returned to the host
+#[test]
+pub fn test_neutron_errors() {
+    println!("\n>>> Neutron error test\n");
+    let mut vm = create_vm_from_asm(
+        "
+        svc        #0x11
+    ",
+    );
+    let mut neutron = Neutron::default();
+    assert_eq!(
+        neutron.execute(&mut vm, &mut NullSystemCalls),
+        Err(NarmError::EmptyCostack)
+    );
+
+    let mut vm = create_vm_from_asm(
+        "
+        svc        #0x20
+    ",
+    );
+    assert_eq!(
+        neutron.execute(&mut vm, &mut NullSystemCalls),
+        Err(NarmError::UnknownSystemCall(0x00))
+    );
+
+    let mut vm = create_vm_from_asm(
+        "
+        svc        #0x42
+    ",
+    );
+    assert_eq!(
+        neutron.execute(&mut vm, &mut NullSystemCalls),
+        Err(NarmError::UnknownServiceCall(0x42))
+    );
+}
+
+// Pushes to the costack are charged per byte, and pushes beyond mapped memory fail without allocating
+#[test]
+pub fn test_neutron_push_gas() {
+    let program = "
+        ldr        r0, =0x81000010
+        movs       r1, #0x08
+        svc        #0x10
+        ldr        r1, =0xFFFFFFF8
+        svc        #0x10
+    ";
+    let mut vm = create_vm_from_asm(program);
+    vm.gas_remaining = 1000;
+    let mut neutron = Neutron::default();
+    assert_eq!(vm.execute(), Ok(SVC_PUSH_COSTACK));
+    let gas = vm.gas_remaining;
+    assert_eq!(neutron.handle_service_call(&mut vm, &mut NullSystemCalls, SVC_PUSH_COSTACK), Ok(None));
+    assert_eq!(vm.gas_remaining, gas - 8 * COSTACK_GAS_PER_BYTE);
+    assert_eq!(neutron.costack.peek(0).unwrap(), &[0; 8]);
+    // not enough gas to pay for the push
+    assert_eq!(neutron.execute(&mut vm, &mut NullSystemCalls), Err(NarmError::OutOfGas));
+    assert_eq!(vm.gas_remaining, 0);
+    assert_eq!(neutron.costack.len(), 1);
+
+    let mut vm = create_vm_from_asm(program);
+    vm.gas_remaining = u64::MAX;
+    assert_eq!(Neutron::default().execute(&mut vm, &mut NullSystemCalls), Err(NarmError::EmptyMemoryRead(0xFFFF_FFFF)));
+    assert_eq!(vm.memory.get_mut_sized_memory(0x8100_0010, 0xFFFF_FFF8).err(), Some(NarmError::EmptyMemoryWrite(0xFFFF_FFFF)));
+    vm.memory.limits.gas_per_touched_page = 1;
+    assert_eq!(vm.memory.get_mut_sized_memory(0x8100_0010, 0xFFFF_FFF8).err(), Some(NarmError::EmptyMemoryRead(0xFFFF_FFFF)));
+}
@@ -0,0 +1,220 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::memory::*;
+use narm::narmvm::*;
+use narm::replay::*;
+
+/*
+
+Integration test for recording execution and rewinding it
+
+General test cases:
+
+- Stepping back restores the exact state at every earlier instruction, within and across snapshots
+- Changes made by the host between instructions are restored and replayed, including mapping memory
+- Rewinding to an arbitrary position and executing forward again gives the same execution
+- reverse_continue stops where the predicate matches, or at the earliest position
+- Dropped snapshots limit how far back execution can be rewound
+- Rewinding fails without a recording
+
+*/
+
+// Stores to memory across several pages, calling SVC 1 every 8 iterations, and exits with SVC 0xFF
+const PROGRAM: &'static str = "
+    ldr r1, =0x81000000
+    movs r2, #0
+loop:
+    str r2, [r1]
+    adds r1, #0xFF
+    adds r1, #0x81
+    adds r2, #1
+    movs r3, #7
+    ands r3, r2
+    bne skip
+    svc #1
+skip:
+    cmp r2, #0x30
+    bne loop
+    svc #0xFF
+";
+
+// The registers, flags, gas and memory compared after rewinding
+type State = (Vec<u32>, u32, u64, MemorySystem);
+
+fn state(vm: &NarmVM) -> State {
+    let registers = (0..=15).map(|r| vm.external_get_reg(r)).collect();
+    (registers, vm.cpsr.get_cpsr(), vm.gas_remaining, vm.memory.clone())
+}
+
+// The host handler for SVC 1, which changes registers, memory and gas
+fn host(vm: &mut NarmVM) {
+    let r2 = vm.external_get_reg(2);
+    vm.external_set_reg(4, r2 * 3);
+    vm.copy_into_memory(0x8100_0800 + r2, &[0xAA, 0xBB]).unwrap();
+    vm.gas_remaining -= 10;
+}
+
+fn create_replay_vm() -> NarmVM {
+    let mut vm = create_vm_from_asm(PROGRAM);
+    vm.memory.limits.gas_per_touched_page = 5;
+    vm
+}
+
+// Executes the program until SVC 0xFF, returning the state at each recorded position
+fn run_recorded(vm: &mut NarmVM) -> Vec<State> {
+    let mut states = vec![state(vm)];
+    loop {
+        let svc = vm.cycle().unwrap();
+        if svc == 1 {
+            host(vm);
+        }
+        states.push(state(vm));
+        if svc == 0xFF {
+            return states;
+        }
+    }
+}
+
+fn assert_state(vm: &NarmVM, states: &[State], position: u64) {
+    assert_eq!(vm.recorded_position(), Some(position));
+    let (registers, cpsr, gas, memory) = state(vm);
+    let expected = &states[position as usize];
+    assert_eq!((registers, cpsr, gas), (expected.0.clone(), expected.1, expected.2), "state differs at position {}", position);
+    assert!(memory == expected.3, "memory differs at position {}", position);
+}
+
+// Stepping back restores the exact state at every earlier instruction, within and across snapshots
+// Changes made by the host between instructions are restored and replayed
+#[test]
+pub fn test_replay_step_back() {
+    for interval in [1, 7, 50, 100_000] {
+        let mut vm = create_replay_vm();
+        vm.start_recording(RecordingConfig {
+            snapshot_interval: interval,
+            max_snapshots: 1000,
+        });
+        let states = run_recorded(&mut vm);
+        let last = states.len() as u64 - 1;
+        assert_state(&vm, &states, last);
+        for position in (0..last).rev() {
+            assert!(vm.step_back());
+            assert_state(&vm, &states, position);
+        }
+        assert!(!vm.step_back());
+        assert_state(&vm, &states, 0);
+    }
+}
+
+// Rewinding to an arbitrary position and executing forward again gives the same execution
+#[test]
+pub fn test_replay_rewind_and_continue() {
+    let mut vm = create_replay_vm();
+    vm.start_recording(RecordingConfig {
+        snapshot_interval: 16,
+        max_snapshots: 1000,
+    });
+    let states = run_recorded(&mut vm);
+    let last = states.len() as u64 - 1;
+    for position in [last - 3, 100, 37, 2, 0] {
+        assert!(vm.rewind_to(position));
+        assert_state(&vm, &states, position);
+        // everything after the position was discarded
+        assert!(!vm.rewind_to(position + 1));
+    }
+    // executing forward again reaches the same states, with the host acting as before
+    let replayed = run_recorded(&mut vm);
+    assert_eq!(replayed.len(), states.len());
+    for position in (0..=last).rev().step_by(5) {
+        assert!(vm.rewind_to(position), "rewinding to {}", position);
+        assert_state(&vm, &states, position);
+    }
+    assert!(!vm.rewind_to(last));
+}
+
+// Mapping memory between instructions takes a snapshot, which is then used for rewinding
+#[test]
+pub fn test_replay_mapped_memory() {
+    let mut vm = create_replay_vm();
+    vm.start_recording(RecordingConfig {
+        snapshot_interval: 1000,
+        max_snapshots: 10,
+    });
+    let mut states = vec![state(&vm)];
+    for i in 0..20 {
+        vm.cycle().unwrap();
+        if i == 9 {
+            vm.memory.add_memory(0x8200_0000, 0x100).unwrap();
+            vm.copy_into_memory(0x8200_0010, &[1, 2, 3]).unwrap();
+        }
+        states.push(state(&vm));
+    }
+    for position in (0..20).rev() {
+        assert!(vm.step_back());
+        assert_state(&vm, &states, position);
+    }
+    assert!(!vm.memory.section_exists(0x8200_0000));
+    assert!(!vm.step_back());
+}
+
+// reverse_continue stops where the predicate matches, or at the earliest position
+#[test]
+pub fn test_replay_reverse_continue() {
+    let mut vm = create_replay_vm();
+    vm.start_recording(RecordingConfig {
+        snapshot_interval: 10,
+        max_snapshots: 1000,
+    });
+    let states = run_recorded(&mut vm);
+    // back to the last store of the loop, before it executes
+    let store = ASM_ENTRY + 4;
+    assert!(vm.reverse_continue(|vm| vm.get_pc_address() == store));
+    assert_eq!(vm.get_pc_address(), store);
+    assert_eq!(vm.external_get_reg(2), 0x2F);
+    let position = vm.recorded_position().unwrap();
+    assert_state(&vm, &states, position);
+    // the store before that one
+    assert!(vm.reverse_continue(|vm| vm.get_pc_address() == store));
+    assert_eq!(vm.external_get_reg(2), 0x2E);
+
+    assert!(!vm.reverse_continue(|_| false));
+    assert_state(&vm, &states, 0);
+}
+
+// Dropped snapshots limit how far back execution can be rewound
+#[test]
+pub fn test_replay_snapshot_limit() {
+    let mut vm = create_replay_vm();
+    vm.start_recording(RecordingConfig {
+        snapshot_interval: 10,
+        max_snapshots: 3,
+    });
+    let states = run_recorded(&mut vm);
+    let last = states.len() as u64 - 1;
+    let earliest = vm.earliest_recorded_position().unwrap();
+    assert_eq!(earliest, (last / 10 - 2) * 10);
+    assert!(!vm.rewind_to(earliest - 1));
+    assert_state(&vm, &states, last);
+    assert!(!vm.reverse_continue(|_| false));
+    assert_state(&vm, &states, earliest);
+}
+
+// Rewinding fails without a recording
+#[test]
+pub fn test_replay_not_recording() {
+    let mut vm = create_replay_vm();
+    assert!(!vm.is_recording());
+    assert_eq!(vm.recorded_position(), None);
+    vm.cycle().unwrap();
+    assert!(!vm.step_back());
+    assert!(!vm.rewind_to(0));
+
+    vm.start_recording(RecordingConfig::default());
+    assert!(vm.is_recording());
+    vm.cycle().unwrap();
+    assert_eq!(vm.recorded_position(), Some(1));
+    vm.stop_recording();
+    assert!(!vm.step_back());
+    assert_eq!(vm.get_pc_address(), ASM_ENTRY + 4);
+}
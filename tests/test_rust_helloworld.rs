@@ -103,7 +103,7 @@ pub fn test_rust_hello_world() {
     ",
     );
 
-    let result = vm.execute();
+    let result = execute_differential(&mut vm);
     vm.print_diagnostics();
     assert_eq!(result.unwrap(), 0xFF);
 }
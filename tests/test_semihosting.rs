@@ -0,0 +1,226 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::narmvm::*;
+use narm::semihosting::*;
+use narm::NarmError;
+use std::sync::{Arc, Mutex};
+
+/*
+
+Integration test for ARM semihosting
+
+General test cases:
+
+- BKPT 0xAB is an ordinary breakpoint unless semihosting is enabled
+- SYS_WRITEC and SYS_WRITE0 write to the debug console
+- SYS_WRITE writes to a handle opened as ":tt" with SYS_OPEN, like cortex-m-semihosting does
+- SYS_WRITE and SYS_WRITE0 output at most SEMIHOSTING_MAX_WRITE bytes per call
+- SYS_CLOCK returns the host clock, or -1 without one
+- SYS_EXIT stops execution with the reason in r1, and other BKPT immediates are unaffected
+- Unknown operations and invalid pointers fail
+
+*/
+
+// Collects every write made by the guest, along with the handle it was written to
+#[derive(Default)]
+struct TestOutput {
+    writes: Mutex<Vec<(u32, Vec<u8>)>>,
+    clock: Option<u32>,
+}
+
+impl SemihostingOutput for TestOutput {
+    fn write(&self, handle: u32, data: &[u8]) {
+        self.writes.lock().unwrap().push((handle, data.to_vec()));
+    }
+    fn clock(&self) -> Option<u32> {
+        self.clock
+    }
+}
+
+fn enable(vm: &mut NarmVM, output: TestOutput) -> Arc<TestOutput> {
+    let output = Arc::new(output);
+    vm.set_semihosting(Some(output.clone()));
+    output
+}
+
+// BKPT 0xAB is an ordinary breakpoint unless semihosting is enabled
+#[test]
+pub fn test_semihosting_disabled() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #0x18
+        bkpt #0xab
+        svc #1
+        ",
+    );
+    assert!(vm.get_semihosting().is_none());
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.external_get_reg(0), 0x18);
+}
+
+// SYS_WRITEC and SYS_WRITE0 write to the debug console
+#[test]
+pub fn test_semihosting_write_console() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #0x03
+        adr r1, text
+        bkpt #0xab
+        mov r4, r0
+        movs r0, #0x04
+        adr r1, text
+        bkpt #0xab
+        svc #1
+        .align 2
+    text:
+        .string \"hi there\\n\"
+        ",
+    );
+    let output = enable(&mut vm, TestOutput::default());
+    assert_eq!(vm.execute(), Ok(1));
+    assert_eq!(vm.external_get_reg(4), SYS_WRITEC);
+    assert_eq!(vm.external_get_reg(0), SYS_WRITE0);
+    assert_eq!(
+        *output.writes.lock().unwrap(),
+        vec![(STDOUT_HANDLE, b"h".to_vec()), (STDOUT_HANDLE, b"hi there\n".to_vec())]
+    );
+}
+
+// SYS_WRITE writes to a handle opened as ":tt" with SYS_OPEN, like cortex-m-semihosting does
+#[test]
+pub fn test_semihosting_write_handle() {
+    let mut vm = create_vm_from_asm(
+        "
+        ldr r4, =0x81000000
+        adr r0, tt
+        movs r1, #4
+        movs r2, #3
+        stm r4!, {r0, r1, r2}
+        subs r4, #12
+        movs r0, #0x01
+        mov r1, r4
+        bkpt #0xab
+        adr r1, message
+        movs r2, #5
+        stm r4!, {r0, r1, r2}
+        subs r4, #12
+        movs r0, #0x05
+        mov r1, r4
+        bkpt #0xab
+        mov r5, r0
+        adr r0, other
+        movs r1, #4
+        movs r2, #4
+        stm r4!, {r0, r1, r2}
+        subs r4, #12
+        movs r0, #0x01
+        mov r1, r4
+        bkpt #0xab
+        svc #1
+        .align 2
+    tt:
+        .string \":tt\"
+        .align 2
+    other:
+        .string \"file\"
+        .align 2
+    message:
+        .ascii \"hello world\"
+        ",
+    );
+    let output = enable(&mut vm, TestOutput::default());
+    assert_eq!(vm.execute(), Ok(1));
+    assert_eq!(*output.writes.lock().unwrap(), vec![(STDOUT_HANDLE, b"hello".to_vec())]);
+    assert_eq!(vm.external_get_reg(5), 0);
+    assert_eq!(vm.external_get_reg(0), u32::MAX);
+}
+
+// SYS_WRITE and SYS_WRITE0 output at most SEMIHOSTING_MAX_WRITE bytes per call
+#[test]
+pub fn test_semihosting_write_limit() {
+    let mut vm = create_vm_from_asm(
+        "
+        ldr r1, =0x81000000
+        movs r0, #0x05
+        bkpt #0xab
+        mov r4, r0
+        ldr r1, =0x81000100
+        movs r0, #0x04
+        bkpt #0xab
+        svc #1
+        ",
+    );
+    let mut text = vec![b'a'; 0x1800];
+    text.push(0);
+    vm.write_bytes(STACK_MEM_START + 0x100, &text).unwrap();
+    vm.write_array(STACK_MEM_START, &[STDOUT_HANDLE, STACK_MEM_START + 0x100, 0x1800]).unwrap();
+    let output = enable(&mut vm, TestOutput::default());
+    assert_eq!(vm.execute(), Ok(1));
+    // SYS_WRITE returns the number of bytes not written, and SYS_WRITE0 cuts the string off
+    assert_eq!(vm.external_get_reg(4), 0x1800 - SEMIHOSTING_MAX_WRITE as u32);
+    let max_write = (STDOUT_HANDLE, vec![b'a'; SEMIHOSTING_MAX_WRITE]);
+    assert_eq!(*output.writes.lock().unwrap(), vec![max_write.clone(), max_write]);
+}
+
+// SYS_CLOCK returns the host clock, or -1 without one
+#[test]
+pub fn test_semihosting_clock() {
+    let program = "
+        movs r0, #0x10
+        bkpt #0xab
+        svc #1
+        ";
+    let mut vm = create_vm_from_asm(program);
+    enable(&mut vm, TestOutput { clock: Some(250), ..TestOutput::default() });
+    assert_eq!(vm.execute(), Ok(1));
+    assert_eq!(vm.external_get_reg(0), 250);
+
+    let mut vm = create_vm_from_asm(program);
+    enable(&mut vm, TestOutput::default());
+    assert_eq!(vm.execute(), Ok(1));
+    assert_eq!(vm.external_get_reg(0), u32::MAX);
+}
+
+// SYS_EXIT stops execution with the reason in r1, and other BKPT immediates are unaffected
+#[test]
+pub fn test_semihosting_exit() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #0x18
+        bkpt #0x01
+        ldr r1, =0x20026
+        bkpt #0xab
+        svc #1
+        ",
+    );
+    enable(&mut vm, TestOutput::default());
+    assert_eq!(execute_differential(&mut vm), Ok(SEMIHOSTING_EXIT));
+    assert_eq!(vm.external_get_reg(1), ADP_STOPPED_APPLICATION_EXIT);
+    assert_eq!(vm.get_pc_address(), ASM_ENTRY + 8);
+}
+
+// Unknown operations and invalid pointers fail
+#[test]
+pub fn test_semihosting_errors() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #0x02
+        bkpt #0xab
+        ",
+    );
+    enable(&mut vm, TestOutput::default());
+    assert_eq!(vm.execute(), Err(NarmError::UnknownSemihostingCall(0x02)));
+
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #0x04
+        ldr r1, =0x50000000
+        bkpt #0xab
+        ",
+    );
+    let output = enable(&mut vm, TestOutput::default());
+    assert_eq!(vm.execute(), Err(NarmError::UnloadedMemoryRead(0x5000_0000)));
+    assert!(output.writes.lock().unwrap().is_empty());
+}
@@ -0,0 +1,128 @@
+#![cfg(feature = "serde")]
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::memory::*;
+use narm::narmvm::*;
+use narm::NarmError;
+
+/*
+
+Integration test for the serde feature
+
+General test cases:
+
+- A VM serialized part way through execution resumes identically after deserializing
+- The JSON schema of registers, flags, gas and memory regions, with hex encoded memory
+- Binary formats round trip memory as bytes
+- Errors serialize by name
+- Invalid memory regions are rejected
+
+*/
+
+const PROGRAM: &str = "
+        movs r0, #0
+        movs r1, #10
+    loop:
+        adds r0, r0, r1
+        subs r1, #1
+        bne loop
+        ldr r2, =0x81000010
+        str r0, [r2]
+        svc #0xFF
+";
+
+// A VM serialized part way through execution resumes identically after deserializing
+#[test]
+pub fn test_serde_resume() {
+    let mut vm = create_vm_from_asm(PROGRAM);
+    for _ in 0..7 {
+        vm.cycle().unwrap();
+    }
+    let json = serde_json::to_string(&vm).unwrap();
+    let mut restored: NarmVM = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+
+    assert_eq!(vm.execute().unwrap(), 0xFF);
+    assert_eq!(restored.execute().unwrap(), 0xFF);
+    for i in 0..=15 {
+        assert_eq!(vm.external_get_reg(i), restored.external_get_reg(i), "r{} differs", i);
+    }
+    assert_eq!(vm.cpsr, restored.cpsr);
+    assert_eq!(vm.gas_remaining, restored.gas_remaining);
+    assert!(vm.memory == restored.memory);
+    assert_eq!(restored.memory.get_u32(0x8100_0010).unwrap(), 55);
+}
+
+// The JSON schema of registers, flags, gas and memory regions, with hex encoded memory
+#[test]
+pub fn test_serde_schema() {
+    let mut vm = NarmVM::default();
+    vm.memory.add_memory(0x8000_0000, 4).unwrap();
+    vm.memory.set_u32(0x8000_0000, 0xDEAD_BEEF).unwrap();
+    vm.external_set_reg(1, 0x1234);
+    vm.external_set_reg(15, 0x0001_0001);
+    vm.cpsr.z = true;
+    vm.gas_remaining = 1000;
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+
+    let value = serde_json::to_value(&vm).unwrap();
+    assert_eq!(value["registers"][1], 0x1234);
+    assert_eq!(value["registers"][15], 0x0001_0001);
+    assert_eq!(value["cpsr"], serde_json::json!({"n": false, "z": true, "c": false, "v": false}));
+    assert_eq!(value["gas_remaining"], 1000);
+    assert_eq!(value["profile"], "ARMv7M");
+    assert_eq!(value["exclusive_monitor"], serde_json::Value::Null);
+    assert_eq!(value["memory"]["regions"], serde_json::json!([{"address": 0x8000_0000u32, "data": "efbeadde", "name": null}]));
+    assert_eq!(value["memory"]["pending_gas"], 0);
+
+    let restored: NarmVM = serde_json::from_value(value).unwrap();
+    assert_eq!(restored.external_get_reg(1), 0x1234);
+    assert_eq!(restored.get_isa_profile(), IsaProfile::ARMv7M);
+    assert!(restored.memory == vm.memory);
+}
+
+// Binary formats round trip memory as bytes
+#[test]
+pub fn test_serde_binary() {
+    let mut memory = MemorySystem::default();
+    memory.limits.gas_per_touched_page = 5;
+    memory.add_memory(0x8000_0000, 0x100).unwrap();
+    memory.add_memory(0x0001_0000, 0x10).unwrap();
+    memory.set_region_name(0x0001_0000, ".text").unwrap();
+    memory.set_u32(0x8000_0080, 0x0102_0304).unwrap();
+
+    let bytes = bincode::serialize(&memory).unwrap();
+    let restored: MemorySystem = bincode::deserialize(&bytes).unwrap();
+    assert!(restored == memory);
+    assert_eq!(restored.get_u32(0x8000_0080).unwrap(), 0x0102_0304);
+    assert_eq!(restored.pending_gas(), 5);
+    assert_eq!(restored.regions()[0].name, Some(".text"));
+}
+
+// Errors serialize by name
+#[test]
+pub fn test_serde_error() {
+    let error = NarmError::UnloadedMemoryRead(0x10);
+    let json = serde_json::to_string(&error).unwrap();
+    assert_eq!(json, r#"{"UnloadedMemoryRead":16}"#);
+    assert_eq!(serde_json::from_str::<NarmError>(&json).unwrap(), error);
+    assert_eq!(serde_json::to_string(&NarmError::OutOfGas).unwrap(), r#""OutOfGas""#);
+}
+
+// Invalid memory regions are rejected
+#[test]
+pub fn test_serde_invalid_memory() {
+    let json = |regions: &str| format!(r#"{{"regions":{},"limits":{{"memory_limit":null,"gas_per_mapped_page":0,"gas_per_touched_page":0}},"touched_pages":[],"pending_gas":0}}"#, regions);
+    assert!(serde_json::from_str::<MemorySystem>(&json(r#"[{"address":65536,"data":"00ff"}]"#)).is_ok());
+    // Unaligned
+    assert!(serde_json::from_str::<MemorySystem>(&json(r#"[{"address":65537,"data":"00ff"}]"#)).is_err());
+    // Empty
+    assert!(serde_json::from_str::<MemorySystem>(&json(r#"[{"address":65536,"data":""}]"#)).is_err());
+    // Duplicate
+    assert!(serde_json::from_str::<MemorySystem>(&json(r#"[{"address":65536,"data":"00"},{"address":65536,"data":"00"}]"#)).is_err());
+    // Not hex
+    assert!(serde_json::from_str::<MemorySystem>(&json(r#"[{"address":65536,"data":"0g"}]"#)).is_err());
+}
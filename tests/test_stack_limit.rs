@@ -0,0 +1,206 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+use narm::NarmError;
+
+/*
+
+Integration test for the stack limit and lowest SP tracking
+
+General test cases:
+
+- PUSH below the stack limit faults without storing anything or changing SP
+- SUB SP and other writes to SP below the stack limit fault without changing SP
+- SP may be set exactly to the stack limit
+- set_sp checks the stack limit
+- Host writes to SP with set_reg and external_set_reg are not checked, but count towards the lowest SP
+- Thumb-2 stores which write back SP are checked before storing
+- There is no stack limit by default
+- The lowest SP is tracked through calls and can be reset
+
+*/
+
+const STACK_TOP: u32 = STACK_MEM_START + 0x1000;
+
+// PUSH below the stack limit faults without storing anything or changing SP
+#[test]
+pub fn test_stack_limit_push() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #1
+        movs r1, #2
+        push {r0, r1}
+        push {r0, r1, lr}
+        svc #1
+        ",
+    );
+    vm.set_sp(STACK_TOP).unwrap();
+    vm.set_stack_limit(STACK_TOP - 16);
+    let result = execute_differential(&mut vm);
+    assert_eq!(result, Err(NarmError::StackOverflow(STACK_TOP - 20)));
+    assert_eq!(vm.get_last_pc(), (ASM_ENTRY + 6) | THUMBS_MODE);
+    assert_eq!(vm.get_sp(), STACK_TOP - 8);
+    assert_eq!(vm.memory.get_u32(STACK_TOP - 8), Ok(1));
+    assert_eq!(vm.memory.get_u32(STACK_TOP - 12), Ok(0));
+    assert_eq!(vm.memory.get_u32(STACK_TOP - 20), Ok(0));
+}
+
+// SUB SP and other writes to SP below the stack limit fault without changing SP
+#[test]
+pub fn test_stack_limit_sub_sp() {
+    let mut vm = create_vm_from_asm(
+        "
+        sub sp, #0x100
+        movs r0, #1
+        svc #1
+        ",
+    );
+    vm.set_sp(STACK_TOP).unwrap();
+    vm.set_stack_limit(STACK_TOP - 0xFC);
+    assert_eq!(execute_differential(&mut vm), Err(NarmError::StackOverflow(STACK_TOP - 0x100)));
+    assert_eq!(vm.get_sp(), STACK_TOP);
+    assert_eq!(vm.get_last_pc(), ASM_ENTRY | THUMBS_MODE);
+
+    let mut vm = create_vm_from_asm(
+        "
+        ldr r0, =0x81000800
+        mov sp, r0
+        svc #1
+        ",
+    );
+    vm.set_sp(STACK_TOP).unwrap();
+    vm.set_stack_limit(STACK_MEM_START + 0x804);
+    assert_eq!(execute_differential(&mut vm), Err(NarmError::StackOverflow(STACK_MEM_START + 0x800)));
+    assert_eq!(vm.get_sp(), STACK_TOP);
+}
+
+// SP may be set exactly to the stack limit
+#[test]
+pub fn test_stack_limit_exact() {
+    let mut vm = create_vm_from_asm(
+        "
+        sub sp, #8
+        push {r0, r1}
+        pop {r0, r1}
+        svc #1
+        ",
+    );
+    vm.set_sp(STACK_TOP).unwrap();
+    vm.set_stack_limit(STACK_TOP - 16);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.get_sp(), STACK_TOP - 8);
+    assert_eq!(vm.get_lowest_sp(), STACK_TOP - 16);
+}
+
+// set_sp checks the stack limit
+#[test]
+pub fn test_stack_limit_set_sp() {
+    let mut vm = NarmVM::default();
+    vm.set_stack_limit(0x8100_0000);
+    assert_eq!(vm.get_stack_limit(), 0x8100_0000);
+    assert_eq!(vm.set_sp(0x80FF_FFFC), Err(NarmError::StackOverflow(0x80FF_FFFC)));
+    assert_eq!(vm.get_sp(), 0);
+    // the bottom two bits of SP are always cleared
+    assert_eq!(vm.set_sp(0x8100_0003), Ok(()));
+    assert_eq!(vm.get_sp(), 0x8100_0000);
+}
+
+// Host writes to SP with set_reg and external_set_reg are not checked, but count towards the lowest SP
+#[test]
+pub fn test_stack_limit_host_writes() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #1
+        svc #1
+        ",
+    );
+    vm.set_sp(STACK_TOP).unwrap();
+    vm.set_stack_limit(STACK_TOP - 8);
+    vm.set_reg(&narm::LongRegister { register: 13 }, STACK_TOP - 16);
+    assert_eq!(vm.get_sp(), STACK_TOP - 16);
+    vm.external_set_reg(13, STACK_TOP - 32);
+    assert_eq!(vm.get_sp(), STACK_TOP - 32);
+    vm.external_set_reg(13, STACK_TOP);
+    assert_eq!(vm.get_lowest_sp(), STACK_TOP - 32);
+    // nothing is left to fault on the next instruction
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+}
+
+// Thumb-2 stores which write back SP are checked before storing
+#[test]
+pub fn test_stack_limit_thumb2() {
+    let bodies = [
+        ("push.w {r0, r1, r2}", STACK_TOP - 12),
+        ("stmdb sp!, {r0-r2}", STACK_TOP - 12),
+        ("str r0, [sp, #-12]!", STACK_TOP - 12),
+        ("strd r0, r1, [sp, #-8]!", STACK_TOP - 8),
+    ];
+    for (body, sp) in bodies {
+        let mut vm = create_vm_from_asm(&format!(
+            "
+            .arch armv7-m
+            movs r0, #1
+            {}
+            svc #1
+            ",
+            body
+        ));
+        vm.set_isa_profile(IsaProfile::ARMv7M);
+        vm.set_sp(STACK_TOP).unwrap();
+        vm.set_stack_limit(STACK_TOP - 4);
+        assert_eq!(execute_differential(&mut vm), Err(NarmError::StackOverflow(sp)), "{}", body);
+        assert_eq!(vm.get_sp(), STACK_TOP, "{}", body);
+        assert_eq!(vm.memory.get_u32(sp), Ok(0), "{}", body);
+        assert_eq!(vm.memory.get_u32(STACK_TOP - 4), Ok(0), "{}", body);
+    }
+}
+
+// There is no stack limit by default
+#[test]
+pub fn test_stack_limit_default() {
+    let mut vm = create_vm_from_asm(
+        "
+        sub sp, #0x1FC
+        sub sp, #0x1FC
+        push {r0-r7, lr}
+        svc #1
+        ",
+    );
+    vm.set_sp(STACK_TOP).unwrap();
+    assert_eq!(vm.get_stack_limit(), 0);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.get_sp(), STACK_TOP - 0x3F8 - 36);
+    assert!(!vm.get_diagnostics_message().contains("stack limit"));
+}
+
+// The lowest SP is tracked through calls and can be reset
+#[test]
+pub fn test_stack_limit_lowest_sp() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #3
+        bl recurse
+        svc #1
+    recurse:
+        push {r0, lr}
+        subs r0, #1
+        beq done
+        bl recurse
+    done:
+        pop {r0, pc}
+        ",
+    );
+    vm.set_sp(STACK_TOP).unwrap();
+    assert_eq!(vm.get_lowest_sp(), STACK_TOP);
+    vm.set_stack_limit(STACK_MEM_START);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.get_sp(), STACK_TOP);
+    assert_eq!(vm.get_lowest_sp(), STACK_TOP - 24);
+    assert!(vm.get_diagnostics_message().contains("stack limit: 0x81000000, lowest sp: 0x81000fe8\n"));
+
+    vm.reset_lowest_sp();
+    assert_eq!(vm.get_lowest_sp(), STACK_TOP);
+}
@@ -0,0 +1,170 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+use narm::semihosting::SemihostingOutput;
+use narm::statehash::*;
+use std::sync::Arc;
+
+/*
+
+Integration test for the canonical state hash
+
+General test cases:
+
+- The encoding matches a reference computed independently
+- Identical states give identical hashes, regardless of the order memory was mapped in
+- Any change to registers, flags, gas, execution options, memory usage or memory changes the hash
+- The incremental hash always equals the full hash, across guest writes, host writes and newly mapped memory
+
+*/
+
+fn to_hex(hash: &StateHash) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// A named change to the VM state
+type StateChange = (&'static str, fn(&mut NarmVM));
+
+struct NoOutput;
+
+impl SemihostingOutput for NoOutput {
+    fn write(&self, _handle: u32, _data: &[u8]) {}
+}
+
+fn create_hash_vm() -> NarmVM {
+    let mut vm = NarmVM::default();
+    vm.memory.add_memory(0x8100_0000, 0x1800).unwrap();
+    vm.copy_into_memory(0x8100_1000, &[1, 2, 3, 4]).unwrap();
+    vm.external_set_reg(0, 0x1122_3344);
+    vm.external_set_reg(13, 0x8100_0100);
+    vm.set_thumb_pc_address(0x1_0000);
+    vm.cpsr.z = true;
+    vm.cpsr.c = true;
+    vm.gas_remaining = 12345;
+    vm
+}
+
+// The encoding matches a reference computed independently
+// Changing this hash means that all previously agreed state hashes change, and STATE_HASH_TAG must be updated
+#[test]
+pub fn test_statehash_reference() {
+    let mut vm = create_hash_vm();
+    let expected = "269c9fc0b5d5c79b881a26fabb94ffbb26ab9561488582cdfeeab652be41c5ce";
+    assert_eq!(to_hex(&vm.state_hash()), expected);
+    assert_eq!(to_hex(&vm.incremental_state_hash()), expected);
+}
+
+// Identical states give identical hashes, regardless of the order memory was mapped in
+#[test]
+pub fn test_statehash_deterministic() {
+    let mut a = NarmVM::default();
+    a.memory.add_memory(0x1_0000, 0x100).unwrap();
+    a.memory.add_memory(0x8100_0000, 0x2000).unwrap();
+    a.memory.add_memory(0x2_0000, 0x10).unwrap();
+    let mut b = NarmVM::default();
+    b.memory.add_memory(0x8100_0000, 0x2000).unwrap();
+    b.memory.add_memory(0x2_0000, 0x10).unwrap();
+    b.memory.add_memory(0x1_0000, 0x100).unwrap();
+    assert_eq!(a.state_hash(), b.state_hash());
+    assert_eq!(a.state_hash(), a.clone().state_hash());
+    assert_eq!(a.incremental_state_hash(), b.state_hash());
+}
+
+// Any change to registers, flags, gas, execution options, memory usage or memory changes the hash
+#[test]
+pub fn test_statehash_changes() {
+    let base = create_hash_vm();
+    let hash = base.state_hash();
+    let changes: [StateChange; 15] = [
+        ("r0", |vm| vm.external_set_reg(0, 0)),
+        ("r14", |vm| vm.external_set_reg(14, 1)),
+        ("pc", |vm| vm.set_thumb_pc_address(0x1_0002)),
+        ("n flag", |vm| vm.cpsr.n = true),
+        ("c flag", |vm| vm.cpsr.c = false),
+        ("gas", |vm| vm.gas_remaining -= 1),
+        ("memory in the last page", |vm| vm.memory.set_u8(0x8100_17FF, 1).map(|_| ()).unwrap()),
+        ("memory in the first page", |vm| vm.memory.set_u8(0x8100_0001, 1).map(|_| ()).unwrap()),
+        ("mapped memory", |vm| vm.memory.add_memory(0x2_0000, 0x10).map(|_| ()).unwrap()),
+        ("stack limit", |vm| vm.set_stack_limit(0x8100_0000)),
+        ("yield on hints", |vm| vm.set_yield_on_hints(true)),
+        ("semihosting", |vm| vm.set_semihosting(Some(Arc::new(NoOutput)))),
+        ("pending gas", |vm| {
+            vm.memory.limits.gas_per_mapped_page = 1;
+            vm.memory.resize_memory(0x8100_0000, 0x3000).unwrap();
+            vm.memory.limits.gas_per_mapped_page = 0;
+            vm.memory.resize_memory(0x8100_0000, 0x1800).unwrap();
+        }),
+        ("touched pages", |vm| {
+            vm.memory.limits.gas_per_touched_page = 1;
+            vm.memory.set_u8(0x8100_0001, 0).unwrap();
+            vm.memory.limits.gas_per_touched_page = 0;
+            vm.memory.take_pending_gas();
+        }),
+        ("exclusive monitor", |vm| {
+            vm.memory.add_memory(0x1_0000, 0x10).unwrap();
+            // ldrex r0, [r13]
+            vm.copy_into_memory(0x1_0000, &[0x5D, 0xE8, 0x00, 0x0F]).unwrap();
+            vm.set_isa_profile(IsaProfile::ARMv7M);
+            vm.cycle().unwrap();
+            assert!(vm.get_exclusive_monitor().is_some());
+            vm.external_set_reg(0, 0x1122_3344);
+            vm.set_thumb_pc_address(0x1_0000);
+            vm.gas_remaining = 12345;
+            vm.memory.remove_memory(0x1_0000).unwrap();
+        }),
+    ];
+    let mut hashes = vec![hash];
+    for (name, change) in changes.iter() {
+        let mut vm = base.clone();
+        change(&mut vm);
+        let changed = vm.state_hash();
+        assert!(!hashes.contains(&changed), "changing {} did not give a new hash", name);
+        hashes.push(changed);
+    }
+}
+
+// The incremental hash always equals the full hash, across guest writes, host writes and newly mapped memory
+#[test]
+pub fn test_statehash_incremental() {
+    let mut vm = create_vm_from_asm(
+        "
+        ldr r1, =0x81000000
+        movs r2, #0
+    loop:
+        str r2, [r1]
+        adds r1, #0xFF
+        adds r1, #0xFF
+        adds r2, #1
+        cmp r2, #0x40
+        bne loop
+        svc #0xFF
+        ",
+    );
+    assert_eq!(vm.incremental_state_hash(), vm.state_hash());
+    let mut cycles = 0;
+    while vm.cycle().unwrap() == 0 {
+        cycles += 1;
+        if cycles % 7 == 0 {
+            assert_eq!(vm.incremental_state_hash(), vm.state_hash(), "after {} cycles", cycles);
+        }
+    }
+    assert_eq!(vm.incremental_state_hash(), vm.state_hash());
+
+    // Host writes through copy_into_memory and get_mut_memory, including ones which span pages
+    vm.copy_into_memory(0x8100_0FFE, &[0xAA; 4]).unwrap();
+    assert_eq!(vm.incremental_state_hash(), vm.state_hash());
+    vm.memory.get_mut_memory(0x8100_3000).unwrap()[0x1000] = 0xBB;
+    assert_eq!(vm.incremental_state_hash(), vm.state_hash());
+
+    // Clones keep their own cached hashes
+    let mut clone = vm.clone();
+    clone.memory.set_u32(0x8100_0000, 0x1234).unwrap();
+    assert_eq!(clone.incremental_state_hash(), clone.state_hash());
+    assert_ne!(clone.incremental_state_hash(), vm.incremental_state_hash());
+
+    vm.memory.add_memory(0x2_0000, 0x3000).unwrap();
+    assert_eq!(vm.incremental_state_hash(), vm.state_hash());
+}
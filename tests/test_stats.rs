@@ -0,0 +1,182 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+use narm::stats::*;
+use narm::NarmError;
+
+/*
+
+Integration test for instruction mix statistics
+
+General test cases:
+
+- Executed instructions are counted by name, and by mnemonic across encodings
+- Conditional branches are counted as taken or not taken per condition
+- Loads and stores are counted by width, including each register of multiple transfers
+- Statistics are disabled by default, and instructions which fault are not counted
+- Exports can be imported, and concatenated exports are merged
+- Invalid export lines are rejected
+- The report lists instructions by count
+
+*/
+
+const LOOP: &str = "
+        movs r0, #3
+        movs r1, #0
+    loop:
+        adds r1, r1, r0
+        subs r0, #1
+        bne loop
+        cmp r1, #6
+        beq done
+        movs r1, #0
+    done:
+        svc #1
+        ";
+
+fn collect(vm: &mut NarmVM) -> InstructionStats {
+    vm.set_statistics(true);
+    vm.set_sp(STACK_MEM_START + 0x1000).unwrap();
+    assert_eq!(execute_differential(vm), Ok(1));
+    vm.take_statistics().unwrap()
+}
+
+// Executed instructions are counted by name, and by mnemonic across encodings
+#[test]
+pub fn test_stats_instructions() {
+    let stats = collect(&mut create_vm_from_asm(LOOP));
+    assert_eq!(stats.total(), 2 + 3 * 3 + 2 + 1);
+    assert_eq!(stats.instructions["MovImmT1"], 2);
+    assert_eq!(stats.instructions["AddRegT1"], 3);
+    assert_eq!(stats.instructions["SubImmT2"], 3);
+    assert_eq!(stats.instructions["BCondT1"], 4);
+    assert_eq!(stats.instructions["CmpImmT1"], 1);
+    assert_eq!(stats.instructions["Svc"], 1);
+    assert_eq!(stats.instructions.len(), 6);
+
+    let mnemonics = stats.by_mnemonic();
+    assert_eq!(mnemonics["MovImm"], 2);
+    assert_eq!(mnemonics["BCond"], 4);
+    assert_eq!(mnemonics["Svc"], 1);
+
+    // the counts start from zero after being taken
+    let mut vm = create_vm_from_asm(LOOP);
+    collect(&mut vm);
+    assert_eq!(vm.get_statistics().unwrap().total(), 0);
+}
+
+// Conditional branches are counted as taken or not taken per condition
+#[test]
+pub fn test_stats_branches() {
+    let stats = collect(&mut create_vm_from_asm(LOOP));
+    assert_eq!(stats.branches["ne"], BranchCounts { taken: 2, not_taken: 1 });
+    assert_eq!(stats.branches["eq"], BranchCounts { taken: 1, not_taken: 0 });
+    assert_eq!(stats.branches.len(), 2);
+
+    let mut vm = create_vm_from_asm(
+        "
+        .arch armv7-m
+        movs r0, #0
+        cbnz r0, skip
+        cbz r0, skip
+        nop
+    skip:
+        cmp r0, #1
+        blt.w done
+        nop
+    done:
+        svc #1
+        ",
+    );
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    let stats = collect(&mut vm);
+    assert_eq!(stats.branches["cbnz"], BranchCounts { taken: 0, not_taken: 1 });
+    assert_eq!(stats.branches["cbz"], BranchCounts { taken: 1, not_taken: 0 });
+    assert_eq!(stats.branches["lt"], BranchCounts { taken: 1, not_taken: 0 });
+}
+
+// Loads and stores are counted by width, including each register of multiple transfers
+#[test]
+pub fn test_stats_memory() {
+    let mut vm = create_vm_from_asm(
+        "
+        ldr r0, =0x81000100
+        strb r1, [r0]
+        ldrb r1, [r0]
+        ldrsh r1, [r0, r1]
+        strh r1, [r0, #2]
+        str r1, [r0, #4]
+        push {r1, r2, lr}
+        pop {r1, r2, r3}
+        stm r0!, {r1, r2}
+        svc #1
+        ",
+    );
+    let stats = collect(&mut vm);
+    assert_eq!(stats.loads, WidthCounts { byte: 1, halfword: 1, word: 1 + 3 });
+    assert_eq!(stats.stores, WidthCounts { byte: 1, halfword: 1, word: 1 + 3 + 2 });
+    assert_eq!(stats.loads.total(), 6);
+}
+
+// Statistics are disabled by default, and instructions which fault are not counted
+#[test]
+pub fn test_stats_disabled_and_faults() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #1
+        ldr r1, =0x50000000
+        ldr r2, [r1]
+        svc #1
+        ",
+    );
+    assert!(vm.get_statistics().is_none());
+    assert!(vm.take_statistics().is_none());
+    vm.set_statistics(true);
+    assert_eq!(execute_differential(&mut vm), Err(NarmError::UnloadedMemoryRead(0x5000_0000)));
+    let stats = vm.get_statistics().unwrap();
+    assert_eq!(stats.total(), 2);
+    assert_eq!(stats.loads.word, 1);
+    assert!(!stats.instructions.contains_key("LdrImmT1"));
+
+    vm.set_statistics(false);
+    assert!(vm.get_statistics().is_none());
+}
+
+// Exports can be imported, and concatenated exports are merged
+#[test]
+pub fn test_stats_export() {
+    let stats = collect(&mut create_vm_from_asm(LOOP));
+    let export = stats.export();
+    assert!(export.contains("instruction BCondT1 4\n"));
+    assert!(export.contains("branch ne 2 1\n"));
+    assert!(export.contains("load 4 0\n"));
+    assert_eq!(InstructionStats::import(&export), Ok(stats.clone()));
+
+    let mut merged = stats.clone();
+    merged.merge(&stats);
+    assert_eq!(merged.total(), stats.total() * 2);
+    assert_eq!(merged.branches["ne"], BranchCounts { taken: 4, not_taken: 2 });
+    assert_eq!(InstructionStats::import(&format!("{}\n{}", export, export)), Ok(merged));
+    assert_eq!(InstructionStats::import(""), Ok(InstructionStats::default()));
+}
+
+// Invalid export lines are rejected
+#[test]
+pub fn test_stats_import_invalid() {
+    assert_eq!(InstructionStats::import("instruction MovImmT1 1\ninstruction MovImmT1\n"), Err(2));
+    assert_eq!(InstructionStats::import("branch eq 1 x"), Err(1));
+    assert_eq!(InstructionStats::import("load 8 1"), Err(1));
+    assert_eq!(InstructionStats::import("cycles 10"), Err(1));
+}
+
+// The report lists instructions by count
+#[test]
+pub fn test_stats_report() {
+    let report = collect(&mut create_vm_from_asm(LOOP)).report();
+    assert!(report.starts_with("instructions: 14\n  BCondT1               4  28.57%\n"), "{}", report);
+    assert!(report.contains("  ne                    2          1\n"), "{}", report);
+    assert!(report.contains("loads:  0 (byte 0, halfword 0, word 0)\n"), "{}", report);
+}
@@ -0,0 +1,199 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+
+/*
+
+Integration test for the Thumb-2 branch instructions of the ARMv7-M profile
+
+Included varieties:
+
+B.W <label> T4                  PC <- PC + imm (+/- 16MB), also available in the ARMv8-M baseline profile
+B<c>.W <label> T3               PC <- PC + imm (+/- 1MB) if condition passes
+TBB [<Rn>, <Rm>] T1             PC <- PC + 2 * [Rn + Rm] (byte)
+TBH [<Rn>, <Rm>, LSL #1] T1     PC <- PC + 2 * [Rn + 2 * Rm] (halfword)
+
+General test cases:
+
+- Forward and backward wide branches beyond the range of 16 bit branches
+- Wide conditional branches taken and not taken
+- Table branches with the table following the instruction
+- A function compiled for thumbv7m, mixing 16 and 32 bit instructions
+
+*/
+
+fn create_branch_vm(body: &str, profile: IsaProfile) -> NarmVM {
+    let arch = if profile == IsaProfile::ARMv7M {"armv7-m"} else {"armv8-m.base"};
+    let mut vm = create_vm_from_asm(&format!(
+        "
+        .arch {}
+        {}
+        svc #0xFF
+        ",
+        arch,
+        body
+    ));
+    vm.set_isa_profile(profile);
+    vm
+}
+
+// Forward and backward wide branches beyond the range of 16 bit branches
+#[test]
+pub fn test_thumb2_branch_wide() {
+    for profile in [IsaProfile::ARMv7M, IsaProfile::ARMv8MBaseline] {
+        let mut vm = create_branch_vm(
+            "
+            movs r0, #0
+            b.w forward
+            backward:
+            adds r0, #2
+            b.w end
+            .space 0x1000
+            forward:
+            adds r0, #1
+            b.w backward
+            end:
+            ",
+            profile,
+        );
+        assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+        assert_eq!(vm.external_get_reg(0), 0x03);
+    }
+}
+
+// Wide conditional branches taken and not taken
+#[test]
+pub fn test_thumb2_branch_conditional() {
+    let mut vm = create_branch_vm(
+        "
+        movs r0, #0
+        cmp r1, r2
+        beq.w equal
+        add.w r0, r0, #1
+        blt.w end
+        equal:
+        add.w r0, r0, #2
+        bhi.w end
+        .space 0x200
+        add.w r0, r0, #4
+        end:
+        ",
+        IsaProfile::ARMv7M,
+    );
+    let cases = [(1, 1, 0x06), (1, 2, 0x01), (2, 1, 0x03), (0xFFFF_FFFF, 1, 0x01)];
+    for (r1, r2, expected) in cases {
+        let mut vm = vm.clone();
+        vm.external_set_reg(1, r1);
+        vm.external_set_reg(2, r2);
+        assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+        assert_eq!(vm.external_get_reg(0), expected, "r1 = {:#X}, r2 = {:#X}", r1, r2);
+    }
+    vm.set_isa_profile(IsaProfile::ARMv8MBaseline);
+    assert!(execute_differential(&mut vm).is_err());
+}
+
+// Table branches with the table following the instruction
+#[test]
+pub fn test_thumb2_branch_table() {
+    let tbb = create_branch_vm(
+        "
+        tbb [pc, r1]
+        table:
+        .byte (case0 - table) / 2
+        .byte (case1 - table) / 2
+        .byte (case2 - table) / 2
+        .align 1
+        case0:
+        movs r0, #0x10
+        b end
+        case1:
+        movs r0, #0x11
+        b end
+        case2:
+        movs r0, #0x12
+        end:
+        ",
+        IsaProfile::ARMv7M,
+    );
+    let tbh = create_branch_vm(
+        "
+        tbh [pc, r1, lsl #1]
+        table:
+        .short (case0 - table) / 2
+        .short (case1 - table) / 2
+        .short (case2 - table) / 2
+        case0:
+        movs r0, #0x20
+        b.w end
+        case1:
+        movs r0, #0x21
+        b.w end
+        .space 0x400
+        case2:
+        movs r0, #0x22
+        end:
+        ",
+        IsaProfile::ARMv7M,
+    );
+    for i in 0..3 {
+        let mut vm = tbb.clone();
+        vm.external_set_reg(1, i);
+        assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+        assert_eq!(vm.external_get_reg(0), 0x10 + i);
+        let mut vm = tbh.clone();
+        vm.external_set_reg(1, i);
+        assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+        assert_eq!(vm.external_get_reg(0), 0x20 + i);
+    }
+}
+
+// A function compiled for thumbv7m, mixing 16 and 32 bit instructions
+// The function is written in the style of rustc -O output, summing the bytes of a slice which are below a limit
+#[test]
+pub fn test_thumb2_branch_compiled() {
+    let mut vm = create_branch_vm(
+        "
+        movw r0, #:lower16:data
+        movt r0, #:upper16:data
+        movs r1, #10
+        movs r2, #0x80
+        bl sum_below
+        b.w end
+
+        sum_below:
+        push {r4, r5, r7, lr}
+        add r7, sp, #8
+        cbz r1, empty
+        mov.w r12, #0
+        movs r3, #0
+        loop:
+        ldrb r4, [r0], #1
+        cmp r4, r2
+        it lo
+        addlo.w r12, r12, r4
+        add.w r3, r3, #1
+        subs r1, #1
+        bne loop
+        mov r0, r12
+        pop {r4, r5, r7, pc}
+        empty:
+        movs r0, #0
+        pop {r4, r5, r7, pc}
+
+        .align 2
+        data:
+        .byte 0x01, 0x02, 0x80, 0x03, 0xFF, 0x7F, 0x04, 0x90, 0x05, 0x10
+        .align 1
+        end:
+        ",
+        IsaProfile::ARMv7M,
+    );
+    vm.external_set_reg(13, STACK_MEM_START + 0x1000);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(0), 0x01 + 0x02 + 0x03 + 0x7F + 0x04 + 0x05 + 0x10);
+    assert_eq!(vm.external_get_reg(3), 10);
+    assert_eq!(vm.external_get_reg(13), STACK_MEM_START + 0x1000);
+}
@@ -0,0 +1,235 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+
+/*
+
+Integration test for the Thumb-2 data processing instructions of the ARMv7-M profile
+
+Arithmetic results and flags are compared against a reference AddWithCarry for a set of interesting operands.
+
+Included varieties:
+
+ADD, ADC, SUB, SBC, RSB, CMP, CMN{S}.W with a modified immediate or a shifted register
+AND, BIC, ORR, ORN, EOR, TST, TEQ, MOV, MVN{S}.W with a modified immediate or a shifted register
+ADDW, SUBW, ADR.W with a plain 12 bit immediate
+LSL, LSR, ASR, ROR{S}.W by a register
+UBFX, SBFX, BFI, BFC, CLZ, RBIT, UXTB.W, SXTH.W, MUL.W
+
+General test cases:
+
+- Arithmetic with every combination of interesting operands and carry in, compared against AddWithCarry
+- Carry out of the immediate expansion and of shifted register operands
+- Flags are not affected without the S suffix
+- SP as operand and destination
+- Bitfield and bit counting operations
+
+*/
+
+const OPERANDS: [u32; 8] = [0x0000_0000, 0x0000_0001, 0x0000_FFFF, 0x7FFF_FFFF, 0x8000_0000, 0x8000_0001, 0xDEAD_BEEF, 0xFFFF_FFFF];
+
+// The N, Z, C and V flags
+type Flags = (bool, bool, bool, bool);
+
+// A reference implementation of an arithmetic operation, taking both operands and the carry flag
+type Reference = fn(u32, u32, bool) -> (u32, Flags);
+
+// AddWithCarry from the Architecture Reference Manual, returning the result and the N, Z, C and V flags
+fn add_with_carry(x: u32, y: u32, carry_in: bool) -> (u32, Flags) {
+    let unsigned_sum = x as u64 + y as u64 + carry_in as u64;
+    let signed_sum = x as i32 as i64 + y as i32 as i64 + carry_in as i64;
+    let result = unsigned_sum as u32;
+    let c = result as u64 != unsigned_sum;
+    let v = result as i32 as i64 != signed_sum;
+    (result, (result & 0x8000_0000 != 0, result == 0, c, v))
+}
+
+fn create_thumb2_vm(body: &str) -> NarmVM {
+    let mut vm = create_vm_from_asm(&format!(
+        "
+        .arch armv7-m
+        {}
+        svc #0xFF
+        ",
+        body
+    ));
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    vm
+}
+
+// Runs the code with operands in r1 and r2, returning r0 and the flags
+fn run(vm: &NarmVM, r1: u32, r2: u32, carry: bool) -> (u32, Flags) {
+    let mut vm = vm.clone();
+    vm.external_set_reg(0, 0x1234_5678);
+    vm.external_set_reg(1, r1);
+    vm.external_set_reg(2, r2);
+    vm.cpsr.c = carry;
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    (vm.external_get_reg(0), (vm.cpsr.n, vm.cpsr.z, vm.cpsr.c, vm.cpsr.v))
+}
+
+// Arithmetic with every combination of interesting operands and carry in, compared against AddWithCarry
+#[test]
+pub fn test_thumb2_arithmetic() {
+    let ops: [(&str, Reference); 5] = [
+        ("adds.w r0, r1, r2", |x, y, _| add_with_carry(x, y, false)),
+        ("adcs.w r0, r1, r2", |x, y, c| add_with_carry(x, y, c)),
+        ("subs.w r0, r1, r2", |x, y, _| add_with_carry(x, !y, true)),
+        ("sbcs.w r0, r1, r2", |x, y, c| add_with_carry(x, !y, c)),
+        ("rsbs r0, r1, r2", |x, y, _| add_with_carry(!x, y, true)),
+    ];
+    for (op, reference) in ops.iter() {
+        let vm = create_thumb2_vm(op);
+        for x in OPERANDS.iter().copied() {
+            for y in OPERANDS.iter().copied() {
+                for carry in [false, true].iter().copied() {
+                    assert_eq!(run(&vm, x, y, carry), reference(x, y, carry), "\n\n>>> {} with 0x{}, 0x{}, carry {}\n\n", op, format_padded_hex(x), format_padded_hex(y), carry);
+                }
+            }
+        }
+    }
+}
+
+// Compares set only the flags, and immediates are expanded
+#[test]
+pub fn test_thumb2_compare() {
+    let cmp = create_thumb2_vm("cmp.w r1, #0x00FF00FF");
+    let cmn = create_thumb2_vm("cmn.w r1, r2, lsl #1");
+    for x in OPERANDS.iter().copied() {
+        for y in OPERANDS.iter().copied() {
+            let (_, flags) = add_with_carry(x, !0x00FF_00FF, true);
+            assert_eq!(run(&cmp, x, y, false), (0x1234_5678, flags));
+            let (_, flags) = add_with_carry(x, y << 1, false);
+            assert_eq!(run(&cmn, x, y, false), (0x1234_5678, flags));
+        }
+    }
+}
+
+// Carry out of the immediate expansion and of shifted register operands
+#[test]
+pub fn test_thumb2_logical() {
+    // Immediates which are not rotated leave the carry flag alone
+    let vm = create_thumb2_vm("ands.w r0, r1, #0x00FF00FF");
+    assert_eq!(run(&vm, 0xFFFF_0000, 0x00, true), (0x00FF_0000, (false, false, true, false)));
+    assert_eq!(run(&vm, 0xFF00_FF00, 0x00, false), (0x0000_0000, (false, true, false, false)));
+    // Rotated immediates set carry to the top bit of the immediate
+    let vm = create_thumb2_vm("orrs.w r0, r1, #0x80000000");
+    assert_eq!(run(&vm, 0x0000_0001, 0x00, false), (0x8000_0001, (true, false, true, false)));
+    let vm = create_thumb2_vm("eors.w r0, r1, #0x3FC");
+    assert_eq!(run(&vm, 0x0000_00FF, 0x00, true), (0x0000_0303, (false, false, false, false)));
+    let vm = create_thumb2_vm("bics.w r0, r1, r2, lsr #1");
+    assert_eq!(run(&vm, 0xFFFF_FFFF, 0x0000_0003, false), (0xFFFF_FFFE, (true, false, true, false)));
+    let vm = create_thumb2_vm("orns r0, r1, r2, ror #4");
+    assert_eq!(run(&vm, 0x0000_0000, 0xFFFF_FFF0, true), (0xF000_0000, (true, false, false, false)));
+    let vm = create_thumb2_vm("movs.w r0, r2, asr #31");
+    assert_eq!(run(&vm, 0x00, 0x8000_0000, false), (0xFFFF_FFFF, (true, false, false, false)));
+    let vm = create_thumb2_vm("mvns.w r0, r2, rrx");
+    assert_eq!(run(&vm, 0x00, 0x0000_0003, true), (0x7FFF_FFFE, (false, false, true, false)));
+    let vm = create_thumb2_vm("mvn r0, #0xFF00");
+    assert_eq!(run(&vm, 0x00, 0x00, true), (0xFFFF_00FF, (false, false, true, false)));
+    let vm = create_thumb2_vm("tst.w r1, r2, lsl #31");
+    assert_eq!(run(&vm, 0xFFFF_FFFF, 0x0000_0002, false), (0x1234_5678, (false, true, true, false)));
+    let vm = create_thumb2_vm("teq.w r1, #0x80000000");
+    assert_eq!(run(&vm, 0x8000_0000, 0x00, false), (0x1234_5678, (false, true, true, false)));
+}
+
+// Shifts by a register use the bottom byte, and shift every bit out for amounts of 32 or more
+#[test]
+pub fn test_thumb2_shift_register() {
+    let lsl = create_thumb2_vm("lsls.w r0, r1, r2");
+    assert_eq!(run(&lsl, 0x8000_0001, 0x0000_0101, false), (0x0000_0002, (false, false, true, false)));
+    assert_eq!(run(&lsl, 0x8000_0001, 0x0000_0020, false), (0x0000_0000, (false, true, true, false)));
+    assert_eq!(run(&lsl, 0x8000_0001, 0x0000_0000, true), (0x8000_0001, (true, false, true, false)));
+    let lsr = create_thumb2_vm("lsrs.w r0, r1, r2");
+    assert_eq!(run(&lsr, 0x8000_0001, 0x0000_0021, true), (0x0000_0000, (false, true, false, false)));
+    let asr = create_thumb2_vm("asrs.w r0, r1, r2");
+    assert_eq!(run(&asr, 0x8000_0001, 0x0000_0040, false), (0xFFFF_FFFF, (true, false, true, false)));
+    let ror = create_thumb2_vm("rors.w r0, r1, r2");
+    assert_eq!(run(&ror, 0x8000_0001, 0x0000_0021, false), (0xC000_0000, (true, false, true, false)));
+}
+
+// Flags are not affected without the S suffix
+#[test]
+pub fn test_thumb2_no_flags() {
+    let vm = create_thumb2_vm(
+        "
+        add.w r0, r1, r2
+        sub.w r0, r0, #1
+        and.w r0, r0, r1, lsr #1
+        lsl.w r0, r0, r2
+        ",
+    );
+    assert_eq!(run(&vm, 0xFFFF_FFFF, 0x0000_0001, false), (0xFFFF_FFFE, (false, false, false, false)));
+    assert_eq!(run(&vm, 0xFFFF_FFFF, 0x0000_0001, true), (0xFFFF_FFFE, (false, false, true, false)));
+}
+
+// SP as operand and destination, and the plain 12 bit immediates
+#[test]
+pub fn test_thumb2_sp_and_plain_immediate() {
+    let mut vm = create_thumb2_vm(
+        "
+        sub.w sp, sp, #0x100
+        mov r8, sp
+        addw r9, sp, #0xFFF
+        subw r10, sp, #0x801
+        add.w sp, sp, r1, lsl #2
+        adr.w r11, data
+        b skip
+        .align 2
+    data:
+        .word 0
+    skip:
+        ",
+    );
+    vm.external_set_reg(13, STACK_MEM_START + 0x1000);
+    vm.external_set_reg(1, 0x40);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(8), STACK_MEM_START + 0xF00);
+    assert_eq!(vm.external_get_reg(9), STACK_MEM_START + 0x1EFF);
+    assert_eq!(vm.external_get_reg(10), STACK_MEM_START + 0x6FF);
+    assert_eq!(vm.external_get_reg(13), STACK_MEM_START + 0x1000);
+    assert_eq!(vm.external_get_reg(11) & 0b11, 0x00);
+    assert_eq!(vm.memory.get_u32(vm.external_get_reg(11)).unwrap(), 0x00);
+}
+
+// Bitfield and bit counting operations
+#[test]
+pub fn test_thumb2_bitfield() {
+    let mut vm = create_thumb2_vm(
+        "
+        ubfx r2, r1, #4, #8
+        sbfx r3, r1, #28, #4
+        sbfx r4, r1, #0, #32
+        mov.w r5, #0xFFFFFFFF
+        bfi r5, r1, #8, #12
+        bfc r5, #0, #4
+        clz r6, r1
+        rbit r7, r1
+        uxtb.w r8, r1, ror #8
+        sxth.w r9, r1, ror #16
+        mul r10, r9, r1
+        ",
+    );
+    vm.external_set_reg(1, 0x8765_4321);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(2), 0x0000_0032);
+    assert_eq!(vm.external_get_reg(3), 0xFFFF_FFF8);
+    assert_eq!(vm.external_get_reg(4), 0x8765_4321);
+    assert_eq!(vm.external_get_reg(5), 0xFFF3_21F0);
+    assert_eq!(vm.external_get_reg(6), 0x00);
+    assert_eq!(vm.external_get_reg(7), 0x84C2_A6E1);
+    assert_eq!(vm.external_get_reg(8), 0x0000_0043);
+    assert_eq!(vm.external_get_reg(9), 0xFFFF_8765);
+    assert_eq!(vm.external_get_reg(10), 0xFFFF_8765u32.wrapping_mul(0x8765_4321));
+}
+
+// Thumb-2 data processing is an invalid opcode in the ARMv6-M profile
+#[test]
+pub fn test_thumb2_armv6m() {
+    let mut vm = create_thumb2_vm("add.w r0, r1, #0x00FF00FF");
+    vm.set_isa_profile(IsaProfile::ARMv6M);
+    assert!(execute_differential(&mut vm).is_err());
+}
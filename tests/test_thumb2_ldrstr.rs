@@ -0,0 +1,273 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::IsaProfile;
+use narm::narmvm::*;
+
+/*
+
+Integration test for the Thumb-2 load and store instructions of the ARMv7-M profile
+
+Included varieties:
+
+LDR, LDRB, LDRH, LDRSB, LDRSH, STR, STRB, STRH.W <Rt>, [<Rn>, #+/-<imm>]{!} and [<Rn>], #+/-<imm>
+LDR, LDRB, LDRH, LDRSB, LDRSH, STR, STRB, STRH.W <Rt>, [<Rn>, <Rm>{, LSL #<imm2>}]
+LDR.W <Rt>, <label>
+LDRD, STRD <Rt>, <Rt2>, [<Rn>, #+/-<imm>]{!} and [<Rn>], #+/-<imm>
+LDM.W, LDMDB, STM.W, STMDB <Rn>{!}, <registers> (Including PUSH.W and POP.W)
+
+General test cases:
+
+- 12 bit positive and 8 bit negative offsets
+- Pre-indexed and post-indexed addressing with writeback
+- Register offsets with a shift
+- Sign extension of byte and halfword loads
+- Literal loads
+- Doubleword loads and stores
+- Multiple loads and stores, with and without writeback
+- Loading PC with LDR and POP.W interworks
+- Registers are not written back when the memory access fails
+
+*/
+
+const DATA_ADDRESS: u32 = STACK_MEM_START + 0x100;
+
+// Assembles the code with r0 pointing at DATA_ADDRESS, which holds the given bytes
+fn create_ldrstr_vm(body: &str, data: &[u8]) -> NarmVM {
+    let mut vm = create_vm_from_asm(&format!(
+        "
+        .arch armv7-m
+        movw r0, #0x{:04X}
+        movt r0, #0x{:04X}
+        {}
+        svc #0xFF
+        ",
+        DATA_ADDRESS & 0xFFFF,
+        DATA_ADDRESS >> 16,
+        body
+    ));
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    vm.copy_into_memory(DATA_ADDRESS, data).unwrap();
+    vm
+}
+
+fn get_data(vm: &NarmVM, offset: u32) -> u32 {
+    vm.memory.get_u32(DATA_ADDRESS + offset).unwrap()
+}
+
+// 12 bit positive and 8 bit negative offsets
+#[test]
+pub fn test_thumb2_ldrstr_offset() {
+    let mut data = [0u8; 0x1000];
+    data[0x800..0x804].copy_from_slice(&0xDEAD_BEEFu32.to_le_bytes());
+    let mut vm = create_ldrstr_vm(
+        "
+        ldr.w r1, [r0, #0x800]
+        add r0, #0x10
+        str r1, [r0, #-0x10]
+        strb.w r1, [r0, #0xFFF]
+        strh r1, [r0, #-4]
+        ldrb.w r2, [r0, #0x7F0]
+        ldrh r3, [r0, #-0xE]
+        sub r0, #0x10
+        ",
+        &data,
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(0), DATA_ADDRESS);
+    assert_eq!(vm.external_get_reg(1), 0xDEAD_BEEF);
+    assert_eq!(vm.external_get_reg(2), 0xEF);
+    assert_eq!(vm.external_get_reg(3), 0xDEAD);
+    assert_eq!(get_data(&vm, 0x00), 0xDEAD_BEEF);
+    assert_eq!(get_data(&vm, 0x0C), 0x0000_BEEF);
+    assert_eq!(vm.memory.get_u8(DATA_ADDRESS + 0x100F).unwrap(), 0xEF);
+}
+
+// Pre-indexed and post-indexed addressing with writeback
+#[test]
+pub fn test_thumb2_ldrstr_index() {
+    let mut vm = create_ldrstr_vm(
+        "
+        movs r1, #0x11
+        movs r2, #0x22
+        str r1, [r0, #4]!
+        str r2, [r0], #8
+        ldr r3, [r0, #-8]!
+        ldrb r4, [r0], #-4
+        ",
+        &[0u8; 0x10],
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(0), DATA_ADDRESS);
+    assert_eq!(vm.external_get_reg(3), 0x22);
+    assert_eq!(vm.external_get_reg(4), 0x22);
+    assert_eq!(get_data(&vm, 0x04), 0x22);
+}
+
+// Register offsets with a shift, and sign extension of byte and halfword loads
+#[test]
+pub fn test_thumb2_ldrstr_register() {
+    let mut vm = create_ldrstr_vm(
+        "
+        movs r1, #2
+        ldr.w r2, [r0, r1, lsl #2]
+        ldrsb.w r3, [r0, r1, lsl #2]
+        ldrsh.w r4, [r0, r1, lsl #2]
+        ldrsb r5, [r0, #9]
+        ldrsh r6, [r0, #-0]
+        str.w r2, [r0, r1]
+        strh.w r2, [r0, r1, lsl #3]
+        ",
+        &[0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x85, 0x7F, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(2), 0xFFFF_7F85);
+    assert_eq!(vm.external_get_reg(3), 0xFFFF_FF85);
+    assert_eq!(vm.external_get_reg(4), 0x0000_7F85);
+    assert_eq!(vm.external_get_reg(5), 0x0000_007F);
+    assert_eq!(vm.external_get_reg(6), 0xFFFF_8000);
+    assert_eq!(get_data(&vm, 0x00), 0x7F85_8000);
+    assert_eq!(get_data(&vm, 0x04), 0x0000_FFFF);
+    assert_eq!(get_data(&vm, 0x10), 0x0000_7F85);
+}
+
+// Literal loads
+#[test]
+pub fn test_thumb2_ldrstr_literal() {
+    let mut vm = create_ldrstr_vm(
+        "
+        ldr.w r1, literal
+        ldrh.w r2, literal
+        ldrsb.w r3, literal_end
+        b end
+        .align 2
+        literal:
+        .word 0x89ABCDEF
+        literal_end:
+        .word 0x000000FE
+        end:
+        ",
+        &[],
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(1), 0x89AB_CDEF);
+    assert_eq!(vm.external_get_reg(2), 0x0000_CDEF);
+    assert_eq!(vm.external_get_reg(3), 0xFFFF_FFFE);
+}
+
+// Doubleword loads and stores
+#[test]
+pub fn test_thumb2_ldrstr_dual() {
+    let mut vm = create_ldrstr_vm(
+        "
+        ldrd r1, r2, [r0, #8]
+        strd r2, r1, [r0], #16
+        ldrd r3, r4, [r0, #-16]!
+        strd r1, r2, [r0, #0x20]
+        ",
+        &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x11, 0x11, 0x11, 0x11, 0x22, 0x22, 0x22, 0x22],
+    );
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(0), DATA_ADDRESS);
+    assert_eq!(vm.external_get_reg(3), 0x2222_2222);
+    assert_eq!(vm.external_get_reg(4), 0x1111_1111);
+    assert_eq!(get_data(&vm, 0x00), 0x2222_2222);
+    assert_eq!(get_data(&vm, 0x04), 0x1111_1111);
+    assert_eq!(get_data(&vm, 0x20), 0x1111_1111);
+    assert_eq!(get_data(&vm, 0x24), 0x2222_2222);
+}
+
+// Multiple loads and stores, with and without writeback
+#[test]
+pub fn test_thumb2_ldrstr_multiple() {
+    let mut vm = create_ldrstr_vm(
+        "
+        mov r8, #0x88
+        mov r9, #0x99
+        movs r1, #0x11
+        stmia.w r0!, {r1, r8, r9}
+        stmdb r0, {r1, r8}
+        ldmdb r0!, {r2, r3, r4}
+        ldm.w r0, {r5, r10, r11}
+        push.w {r1, r8, r9, lr}
+        pop.w {r6, r7, r12, lr}
+        ",
+        &[0u8; 0x10],
+    );
+    vm.external_set_reg(13, STACK_MEM_START + 0x1000);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(0), DATA_ADDRESS);
+    assert_eq!(vm.external_get_reg(2), 0x11);
+    assert_eq!(vm.external_get_reg(3), 0x11);
+    assert_eq!(vm.external_get_reg(4), 0x88);
+    assert_eq!(vm.external_get_reg(5), 0x11);
+    assert_eq!(vm.external_get_reg(10), 0x11);
+    assert_eq!(vm.external_get_reg(11), 0x88);
+    assert_eq!(vm.external_get_reg(6), 0x11);
+    assert_eq!(vm.external_get_reg(7), 0x88);
+    assert_eq!(vm.external_get_reg(12), 0x99);
+    assert_eq!(vm.external_get_reg(13), STACK_MEM_START + 0x1000);
+    assert_eq!(get_data(&vm, 0x04), 0x11);
+    assert_eq!(get_data(&vm, 0x08), 0x88);
+}
+
+// Loading PC with LDR and POP.W interworks
+#[test]
+pub fn test_thumb2_ldrstr_load_pc() {
+    let mut vm = create_ldrstr_vm(
+        "
+        adr r1, first
+        adds r1, #1
+        str r1, [r0]
+        ldr.w pc, [r0]
+        movs r4, #0xEE
+        .align 2
+        first:
+        adr r2, second
+        adds r2, #1
+        push.w {r1, r2}
+        pop.w {r3, pc}
+        movs r4, #0xEE
+        .align 2
+        second:
+        ",
+        &[0u8; 0x10],
+    );
+    vm.external_set_reg(4, 0x00);
+    vm.external_set_reg(13, STACK_MEM_START + 0x1000);
+    assert_eq!(execute_differential(&mut vm).unwrap(), 0xFF);
+    assert_eq!(vm.external_get_reg(4), 0x00);
+    assert_eq!(vm.external_get_reg(3), vm.external_get_reg(1));
+    assert_eq!(vm.external_get_reg(13), STACK_MEM_START + 0x1000);
+}
+
+// Registers are not written back when the memory access fails
+#[test]
+pub fn test_thumb2_ldrstr_fault_no_writeback() {
+    let mut vm = create_ldrstr_vm(
+        "
+        movs r1, #0
+        ldr r2, [r1, #4]!
+        ",
+        &[],
+    );
+    vm.external_set_reg(2, 0x1234_5678);
+    assert!(execute_differential(&mut vm).is_err());
+    assert_eq!(vm.external_get_reg(1), 0x00);
+    assert_eq!(vm.external_get_reg(2), 0x1234_5678);
+
+    let mut vm = create_ldrstr_vm("stmia.w r0!, {r1, r2}", &[]);
+    vm.external_set_reg(0, 0x10);
+    vm.set_thumb_pc_address(ASM_ENTRY + 8);
+    assert!(execute_differential(&mut vm).is_err());
+    assert_eq!(vm.external_get_reg(0), 0x10);
+}
+
+// Thumb-2 loads and stores are not decoded for the ARMv6-M profile
+#[test]
+pub fn test_thumb2_ldrstr_armv6m() {
+    let mut vm = create_ldrstr_vm("ldr.w r1, [r0, #0x800]", &[]);
+    vm.set_isa_profile(IsaProfile::ARMv6M);
+    assert!(execute_differential(&mut vm).is_err());
+}
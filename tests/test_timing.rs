@@ -0,0 +1,169 @@
+extern crate narm;
+mod common;
+
+use common::*;
+use narm::instruction::*;
+use narm::narmvm::*;
+use narm::replay::*;
+
+/*
+
+Integration test for counting cycles with the Cortex-M0 timing model
+
+General test cases:
+
+- Data processing takes 1 cycle, and loads and stores take 2
+- Conditional branches take 3 cycles when taken and 1 when not, and unconditional branches take 3
+- BL takes 4 cycles, BX 3, PUSH 1 + N and POP with PC 4 + N
+- LDM and STM take 1 + N cycles
+- Thumb-2 instructions are timed, and instructions skipped by an IT block take 1 cycle
+- Cycles are not counted without a timing model, and the count can be reset
+- The cycle count is rewound along with recorded execution
+
+*/
+
+fn count_cycles(vm: &mut NarmVM) -> u64 {
+    vm.set_timing_model(Some(TimingModel::CortexM0));
+    vm.set_sp(STACK_MEM_START + 0x1000).unwrap();
+    assert_eq!(execute_differential(vm), Ok(1));
+    vm.get_cycle_count()
+}
+
+// Data processing takes 1 cycle, and loads and stores take 2
+#[test]
+pub fn test_timing_data_processing_and_memory() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #1
+        adds r0, r0, r0
+        ldr r1, =0x81000000
+        str r0, [r1]
+        ldr r2, [r1, #0]
+        muls r0, r0
+        svc #1
+        ",
+    );
+    assert_eq!(count_cycles(&mut vm), 1 + 1 + 2 + 2 + 2 + 1 + 1);
+}
+
+// Conditional branches take 3 cycles when taken and 1 when not, and unconditional branches take 3
+#[test]
+pub fn test_timing_branches() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #3
+    loop:
+        subs r0, #1
+        bne loop
+        b skip
+        nop
+    skip:
+        svc #1
+        ",
+    );
+    assert_eq!(count_cycles(&mut vm), 1 + 3 + (3 * 2 + 1) + 3 + 1);
+}
+
+// BL takes 4 cycles, BX 3, PUSH 1 + N and POP with PC 4 + N
+#[test]
+pub fn test_timing_calls() {
+    let mut vm = create_vm_from_asm(
+        "
+        bl func
+        bl leaf
+        svc #1
+    func:
+        push {r4, lr}
+        movs r4, #2
+        pop {r4, pc}
+    leaf:
+        movs r0, #0
+        bx lr
+        ",
+    );
+    assert_eq!(count_cycles(&mut vm), (4 + 3 + 1 + 5) + (4 + 1 + 3) + 1);
+}
+
+// LDM and STM take 1 + N cycles
+#[test]
+pub fn test_timing_load_store_multiple() {
+    let mut vm = create_vm_from_asm(
+        "
+        ldr r0, =0x81000100
+        stm r0!, {r1, r2, r3}
+        subs r0, #12
+        ldm r0!, {r1, r2, r3}
+        svc #1
+        ",
+    );
+    assert_eq!(count_cycles(&mut vm), 2 + 4 + 1 + 4 + 1);
+}
+
+// Thumb-2 instructions are timed, and instructions skipped by an IT block take 1 cycle
+#[test]
+pub fn test_timing_thumb2() {
+    let mut vm = create_vm_from_asm(
+        "
+        .arch armv7-m
+        movs r0, #0
+        cmp r0, #1
+        ite eq
+        moveq r1, #1
+        movne r1, #2
+        cbz r0, target
+        nop
+    target:
+        push.w {r4-r6, lr}
+        pop.w {r4-r6, lr}
+        svc #1
+        ",
+    );
+    vm.set_isa_profile(IsaProfile::ARMv7M);
+    assert_eq!(count_cycles(&mut vm), 1 + 1 + 1 + 1 + 1 + 3 + 5 + 5 + 1);
+    assert_eq!(vm.external_get_reg(1), 2);
+}
+
+// Cycles are not counted without a timing model, and the count can be reset
+#[test]
+pub fn test_timing_disabled() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #1
+        svc #1
+        svc #1
+        ",
+    );
+    assert_eq!(vm.get_timing_model(), None);
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.get_cycle_count(), 0);
+    assert!(!vm.get_diagnostics_message().contains("cycles"));
+
+    vm.set_timing_model(Some(TimingModel::CortexM0));
+    assert_eq!(execute_differential(&mut vm), Ok(1));
+    assert_eq!(vm.get_cycle_count(), 1);
+    assert!(vm.get_diagnostics_message().contains("cycles: 1 (CortexM0)\n"));
+    vm.reset_cycle_count();
+    assert_eq!(vm.get_cycle_count(), 0);
+}
+
+// The cycle count is rewound along with recorded execution
+#[test]
+pub fn test_timing_replay() {
+    let mut vm = create_vm_from_asm(
+        "
+        movs r0, #1
+        ldr r1, =0x81000000
+        str r0, [r1]
+        svc #1
+        ",
+    );
+    vm.set_timing_model(Some(TimingModel::CortexM0));
+    vm.start_recording(RecordingConfig::default());
+    assert_eq!(vm.execute(), Ok(1));
+    assert_eq!(vm.get_cycle_count(), 6);
+    assert!(vm.step_back());
+    assert!(vm.step_back());
+    assert_eq!(vm.get_cycle_count(), 3);
+    assert!(vm.rewind_to(0));
+    assert_eq!(vm.get_cycle_count(), 0);
+}